@@ -0,0 +1,93 @@
+#![allow(non_snake_case)]
+
+// Benchmarks of the hot paths of the interpreter, run with "cargo bench". They're there to measure
+// refactors of the decoding, the instruction dispatch or the memory rather than to guess.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use rust_chip8::emulator::{decode, Emulator, KeyState, PixelChange, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+use rust_chip8::render::{Palette, Rotation, Screen};
+
+// DRAW_HEAVY is a ROM that does nothing but draw font sprites at random positions:
+//
+//   0x200  A050  I = 0x050, the font
+//   0x202  C03F  V0 = random & 0x3F
+//   0x204  C11F  V1 = random & 0x1F
+//   0x206  D01F  draw 15 rows at V0,V1
+//   0x208  6F00  VF = 0
+//   0x20A  1202  jump to 0x202
+const DRAW_HEAVY: [u8; 12] = [0xA0, 0x50, 0xC0, 0x3F, 0xC1, 0x1F, 0xD0, 0x1F, 0x6F, 0x00, 0x12, 0x02];
+
+// ARITHMETIC is a ROM of cheap register instructions only, so that dispatch dominates:
+//
+//   0x200  7001  V0 += 1
+//   0x202  8104  V1 += V0
+//   0x204  8215  V2 -= V1
+//   0x206  3300  skip if V3 == 0
+//   0x208  8306  V3 >>= 1
+//   0x20A  1200  jump to 0x200
+const ARITHMETIC: [u8; 12] = [0x70, 0x01, 0x81, 0x04, 0x82, 0x15, 0x33, 0x00, 0x83, 0x06, 0x12, 0x00];
+
+// decode splits every possible instruction word.
+fn decodeOnly(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decode");
+    group.throughput(Throughput::Elements(0x10000));
+    group.bench_function("all opcodes", |b| {
+        b.iter(|| {
+            for word in 0..=0xFFFFu16 {
+                black_box(decode(black_box(word)));
+            }
+        })
+    });
+    group.finish();
+}
+
+// step runs the draw-heavy ROM one instruction and one frame at a time, and the arithmetic one
+// an instruction at a time. Compare with "cargo bench --features jump-table", and see
+// "cargo bench --features jit" for the JIT.
+fn step(c: &mut Criterion) {
+    let mut arithmetic = Emulator::new();
+    arithmetic.load_rom(&ARITHMETIC).unwrap();
+    let mut emul = Emulator::new();
+    emul.load_rom(&DRAW_HEAVY).unwrap();
+
+    let mut group = c.benchmark_group("step");
+    group.throughput(Throughput::Elements(1));
+    group.bench_function("arithmetic instruction", |b| b.iter(|| arithmetic.run().unwrap()));
+    #[cfg(feature = "jit")]
+    {
+        let mut jit = rust_chip8::jit::Jit::new().unwrap();
+        let mut jitted = Emulator::new();
+        jitted.load_rom(&ARITHMETIC).unwrap();
+        group.bench_function("arithmetic frame (jit)", |b| b.iter(|| black_box(jit.tick_frame(&mut jitted, KeyState::default()).unwrap())));
+        group.bench_function("arithmetic frame", |b| b.iter(|| black_box(arithmetic.tick_frame(KeyState::default()).unwrap())));
+    }
+    group.bench_function("instruction", |b| b.iter(|| emul.run().unwrap()));
+    group.throughput(Throughput::Elements(emul.instructionsPerFrame as u64));
+    group.bench_function("frame", |b| b.iter(|| black_box(emul.tick_frame(KeyState::default()).unwrap())));
+    group.finish();
+}
+
+// blit writes displays into an RGBA frame the way the window frontend does: a whole display, and
+// the pixels changed by a frame of the draw-heavy ROM.
+fn blit(c: &mut Criterion) {
+    let mut emul = Emulator::new();
+    emul.load_rom(&DRAW_HEAVY).unwrap();
+    let diff: Vec<PixelChange> = emul.tick_frame(KeyState::default()).unwrap().diff;
+
+    let mut group = c.benchmark_group("blit");
+    for rotation in [Rotation::R0, Rotation::R90] {
+        let screen = Screen { rotation, cores: 1, keypadRows: 0, scopeRows: 0, graphRows: 0, palette: Palette::DEFAULT };
+        let (width, height) = screen.size();
+        let mut px = vec![0u8; width * height * 4];
+        group.throughput(Throughput::Elements((DISPLAY_WIDTH * DISPLAY_HEIGHT) as u64));
+        group.bench_function(format!("paint {:?}", rotation), |b| {
+            b.iter(|| screen.paint(&mut px, 0, emul.framebuffer(), emul.framebuffer()))
+        });
+        group.throughput(Throughput::Elements(diff.len() as u64));
+        group.bench_function(format!("set_pixels {:?}", rotation), |b| b.iter(|| screen.set_pixels(&mut px, 0, &diff)));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, decodeOnly, step, blit);
+criterion_main!(benches);
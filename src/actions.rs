@@ -0,0 +1,180 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use rust_chip8::quirks::QUIRK_NAMES;
+
+use crate::bundle::BUNDLE_EXTENSION;
+use crate::cart::CART_EXTENSION;
+use crate::i18n::Messages;
+use crate::overlay::Overlay;
+use crate::upscale::{Upscaler, UPSCALER_NAMES};
+
+// SPEEDS are the instructions per second the command palette offers besides the default.
+const SPEEDS: [u32; 6] = [500, 1000, 2000, 5000, 10000, 30000];
+
+// OPACITIES are the opacities of the window the command palette offers, in percent.
+const OPACITIES: [u32; 4] = [100, 75, 50, 25];
+
+// ROM_EXTENSIONS are the files the command palette offers to load, bundles and cartridges included.
+pub const ROM_EXTENSIONS: [&str; 5] = ["ch8", "sc8", "c8", BUNDLE_EXTENSION, CART_EXTENSION];
+
+// the lines of the palette below the search line, as many as fit on the display.
+const VISIBLE_ACTIONS: usize = 9;
+
+// Action is something the window can do at runtime, as listed in the command palette.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Action {
+    Reset,
+    Pause,
+    SlowMotion,
+    Load(PathBuf),
+    OpenTab(PathBuf), // run another ROM in a new tab, next to the current one
+    ToggleQuirk(&'static str),
+    SaveState, // to the first slot, see States
+    LoadState,
+    States,    // the state browser with all the slots
+    Screenshot,
+    ExportSprites, // the display as Octo sprite data
+    Speed(Option<u32>), // instructions per second, None for the default of the variant
+    Upscale(Upscaler),
+    Fullscreen(usize, String), // on a monitor, by its number from 0 and as describeMonitors has it
+    Windowed,
+    AlwaysOnTop,
+    Opacity(u32), // percent
+    About,
+    Quit,
+}
+
+impl Action {
+    // all returns every action, with a Load and an OpenTab for each ROM in the directory of the
+    // running one.
+    pub fn all(romPath: &str) -> Vec<Action> {
+        let mut actions = vec![Action::Reset, Action::Pause, Action::SlowMotion, Action::SaveState, Action::LoadState, Action::States, Action::Screenshot, Action::ExportSprites];
+        actions.push(Action::Speed(None));
+        actions.extend(SPEEDS.map(|ips| Action::Speed(Some(ips))));
+        actions.extend(QUIRK_NAMES.map(Action::ToggleQuirk));
+        actions.extend(UPSCALER_NAMES.iter().filter_map(|name| Upscaler::parse(name).ok()).map(Action::Upscale));
+        actions.push(Action::AlwaysOnTop);
+        actions.extend(OPACITIES.map(Action::Opacity));
+        let dir = Path::new(romPath).parent().filter(|d| !d.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        let mut roms: Vec<PathBuf> = fs::read_dir(dir).into_iter().flatten().flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|e| ROM_EXTENSIONS.iter().any(|r| e.eq_ignore_ascii_case(r))))
+            .collect();
+        roms.sort();
+        actions.extend(roms.iter().cloned().map(Action::Load));
+        actions.extend(roms.into_iter().map(Action::OpenTab));
+        actions.extend([Action::About, Action::Quit]);
+        actions
+    }
+
+    pub fn name(&self, messages: &Messages) -> String {
+        match self {
+            Action::Reset => messages.get("action.reset").to_string(),
+            Action::Pause => messages.get("action.pause").to_string(),
+            Action::SlowMotion => messages.get("action.slow_motion").to_string(),
+            Action::Load(path) => messages.format("action.load", &[&path.file_name().unwrap_or_default().to_string_lossy()]),
+            Action::OpenTab(path) => messages.format("action.open_tab", &[&path.file_name().unwrap_or_default().to_string_lossy()]),
+            Action::ToggleQuirk(name) => messages.format("action.quirk", &[name]),
+            Action::SaveState => messages.get("action.save_state").to_string(),
+            Action::LoadState => messages.get("action.load_state").to_string(),
+            Action::States => messages.get("action.states").to_string(),
+            Action::Screenshot => messages.get("action.screenshot").to_string(),
+            Action::ExportSprites => messages.get("action.export_sprites").to_string(),
+            Action::Speed(Some(ips)) => messages.format("action.speed", &[ips]),
+            Action::Speed(None) => messages.get("action.default_speed").to_string(),
+            Action::Upscale(upscaler) => messages.format("action.upscale", &[&upscaler.name()]),
+            Action::Fullscreen(_, monitor) => messages.format("action.fullscreen", &[monitor]),
+            Action::Windowed => messages.get("action.windowed").to_string(),
+            Action::AlwaysOnTop => messages.get("action.always_on_top").to_string(),
+            Action::Opacity(percent) => messages.format("action.opacity", &[percent]),
+            Action::About => messages.get("action.about").to_string(),
+            Action::Quit => messages.get("action.quit").to_string(),
+        }
+    }
+}
+
+// CommandPalette lists the actions matching what's typed, so nobody needs to remember the hotkeys.
+// The search is fuzzy: the typed letters have to appear in the name in order, but not next to
+// each other, so "ss" finds "Save state" and "scr" a screenshot.
+pub struct CommandPalette {
+    actions: Vec<(Action, String)>, // with their names
+    query: String,
+    matches: Vec<usize>, // indices of the actions matching the query, best first
+    selected: usize,     // index in matches
+}
+
+impl CommandPalette {
+    pub fn new(actions: Vec<Action>, messages: &Messages) -> CommandPalette {
+        let actions = actions.into_iter().map(|a| { let name = a.name(messages); (a, name) }).collect();
+        let mut palette = CommandPalette { actions, query: String::new(), matches: Vec::new(), selected: 0 };
+        palette.search();
+        palette
+    }
+
+    pub fn type_char(&mut self, c: char) {
+        self.query.push(c);
+        self.search();
+    }
+
+    pub fn backspace(&mut self) {
+        self.query.pop();
+        self.search();
+    }
+
+    // up and down move the selection, wrapping around.
+    pub fn up(&mut self) {
+        if !self.matches.is_empty() {
+            self.selected = (self.selected + self.matches.len() - 1) % self.matches.len();
+        }
+    }
+
+    pub fn down(&mut self) {
+        if !self.matches.is_empty() {
+            self.selected = (self.selected + 1) % self.matches.len();
+        }
+    }
+
+    pub fn selected(&self) -> Option<&Action> {
+        self.matches.get(self.selected).map(|i| &self.actions[*i].0)
+    }
+
+    // overlay shows the search and the matching actions around the selected one.
+    pub fn overlay(&self, messages: &Messages) -> Overlay {
+        let mut lines = vec![format!("{} {}_", messages.get("palette.search"), self.query)];
+        let first = (self.selected + 1).saturating_sub(VISIBLE_ACTIONS);
+        for (n, i) in self.matches.iter().enumerate().skip(first).take(VISIBLE_ACTIONS) {
+            lines.push(format!("{} {}", if n == self.selected { ">" } else { " " }, self.actions[*i].1));
+        }
+        Overlay { lines, debug: false, heatmap: false, card: false, tiles: Vec::new() }
+    }
+
+    fn search(&mut self) {
+        let mut scored: Vec<(usize, usize)> = self.actions.iter().enumerate()
+            .filter_map(|(i, (_, name))| fuzzyScore(&self.query, name).map(|score| (score, i)))
+            .collect();
+        // sort_by_key is stable, so equally good matches stay in the order of the list.
+        scored.sort_by_key(|(score, _)| *score);
+        self.matches = scored.into_iter().map(|(_, i)| i).collect();
+        self.selected = 0;
+    }
+}
+
+// fuzzyScore returns how well a name matches the query, lower is better, or None when the letters
+// of the query don't all appear in the name in order. Every letter skipped to find the next one
+// costs a point, so words typed out in full go first, and skipping to the middle of a word costs
+// a point more than skipping to the start of one.
+pub fn fuzzyScore(query: &str, name: &str) -> Option<usize> {
+    let name: Vec<char> = name.to_lowercase().chars().collect();
+    let mut score = 0;
+    let mut at = 0;
+    for c in query.to_lowercase().chars().filter(|c| !c.is_whitespace()) {
+        let found = (at..name.len()).find(|i| name[*i] == c)?;
+        score += found - at;
+        if found > 0 && name[found - 1] != ' ' && found != at {
+            score += 1;
+        }
+        at = found + 1;
+    }
+    Some(score)
+}
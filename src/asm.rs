@@ -0,0 +1,163 @@
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+// An assembler for the syntax of disasm: the mnemonics of Cowgod's technical reference as
+// disasm::mnemonic writes them, one instruction per line, with
+//
+//   L2A4:                a label, on a line of its own or before an instruction
+//       JP L2A4          an address is a number or a label
+//       LD V3, 0x1F      numbers are decimal, or hex with 0x
+//       .byte 0x3C, 60   bytes of data
+//       DW 0x5001        a word that isn't an instruction
+//   ; comment            to the end of the line
+//
+// It's what turns a disasm::program back into the ROM, so it takes exactly what that writes
+// rather than the macros and expressions of the assemblers ROMs are written with.
+
+// assemble translates a source into the bytes to load at an address, or returns the first error
+// with its line.
+pub fn assemble(source: &str, origin: usize) -> Result<Vec<u8>, String> {
+    // the first pass finds the addresses of the labels, the second one assembles.
+    let mut labels = BTreeMap::new();
+    for (n, (line, address)) in source.lines().zip(addresses(source, origin)).enumerate() {
+        if let Some(label) = split(line).0 {
+            if labels.insert(label.to_string(), address).is_some() {
+                return Err(format!("line {}: the label {} is defined twice", n + 1, label));
+            }
+        }
+    }
+
+    let mut bytes = Vec::new();
+    for (n, line) in source.lines().enumerate() {
+        let statement = match split(line).1 {
+            Some(statement) => statement,
+            None => continue,
+        };
+        let error = |e: String| format!("line {}: {}", n + 1, e);
+        match statement {
+            (".byte", operands) => {
+                for operand in operands {
+                    match number(operand) {
+                        Some(b) if b <= 0xFF => bytes.push(b as u8),
+                        _ => return Err(error(format!("expected a byte, got '{}'", operand))),
+                    }
+                }
+            }
+            (op, operands) => bytes.extend(instruction(op, &operands, &labels).map_err(error)?.to_be_bytes()),
+        }
+    }
+    Ok(bytes)
+}
+
+// addresses returns where every line of a source goes when it's assembled to an address, that of
+// what comes next for a line without an instruction or data.
+pub fn addresses(source: &str, origin: usize) -> Vec<usize> {
+    let mut address = origin;
+    source.lines().map(|line| {
+        let at = address;
+        address += match split(line).1 {
+            Some((".byte", operands)) => operands.len(),
+            Some(_) => 2,
+            None => 0,
+        };
+        at
+    }).collect()
+}
+
+// split takes a line apart into its label and its mnemonic and operands, leaving out the comment.
+fn split(line: &str) -> (Option<&str>, Option<(&str, Vec<&str>)>) {
+    let mut line = line.split(';').next().unwrap_or_default().trim();
+    let mut label = None;
+    if let Some((name, rest)) = line.split_once(':') {
+        label = Some(name.trim());
+        line = rest.trim();
+    }
+    if line.is_empty() {
+        return (label, None);
+    }
+    let (op, operands) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let operands = operands.split(',').map(str::trim).filter(|o| !o.is_empty()).collect();
+    (label, Some((op, operands)))
+}
+
+fn number(s: &str) -> Option<u32> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+// Operand is an operand of an instruction, told apart by its form.
+enum Operand<'a> {
+    V(u16),         // V0-VF
+    Number(u32),    // a number, or the address of a label
+    Name(&'a str),  // I, [I], DT, ST, K, F, HF, B or R
+}
+
+fn instruction(op: &str, raw: &[&str], labels: &BTreeMap<String, usize>) -> Result<u16, String> {
+    let operands: Vec<Operand> = raw.iter().map(|o| {
+        let register = o.strip_prefix(['V', 'v']).filter(|r| r.len() == 1).and_then(|r| u16::from_str_radix(r, 16).ok());
+        match (register, number(o), labels.get(*o)) {
+            (Some(x), _, _) => Ok(Operand::V(x)),
+            (_, Some(n), _) => Ok(Operand::Number(n)),
+            (_, _, Some(address)) => Ok(Operand::Number(*address as u32)),
+            _ if ["I", "[I]", "DT", "ST", "K", "F", "HF", "B", "R"].contains(o) => Ok(Operand::Name(o)),
+            _ => Err(format!("unknown operand or label '{}'", o)),
+        }
+    }).collect::<Result<_, _>>()?;
+    // the immediate operands, checked to fit
+    let fits = |n: u32, bits: u32| if n < 1 << bits { Ok(n as u16) } else { Err(format!("{:#X} doesn't fit in {} bits", n, bits)) };
+
+    use Operand::*;
+    let word = match (op.to_uppercase().as_str(), &operands[..]) {
+        ("CLS", []) => 0x00E0,
+        ("RET", []) => 0x00EE,
+        ("SCD", [Number(n)]) => 0x00C0 | fits(*n, 4)?,
+        ("SCR", []) => 0x00FB,
+        ("SCL", []) => 0x00FC,
+        ("EXIT", []) => 0x00FD,
+        ("LOW", []) => 0x00FE,
+        ("HIGH", []) => 0x00FF,
+        ("JP", [Number(a)]) => 0x1000 | fits(*a, 12)?,
+        ("JP", [V(0), Number(a)]) => 0xB000 | fits(*a, 12)?,
+        ("CALL", [Number(a)]) => 0x2000 | fits(*a, 12)?,
+        ("SE", [V(x), Number(n)]) => 0x3000 | x << 8 | fits(*n, 8)?,
+        ("SNE", [V(x), Number(n)]) => 0x4000 | x << 8 | fits(*n, 8)?,
+        ("SE", [V(x), V(y)]) => 0x5000 | x << 8 | y << 4,
+        ("SNE", [V(x), V(y)]) => 0x9000 | x << 8 | y << 4,
+        ("LD", [V(x), Number(n)]) => 0x6000 | x << 8 | fits(*n, 8)?,
+        ("ADD", [V(x), Number(n)]) => 0x7000 | x << 8 | fits(*n, 8)?,
+        ("LD", [V(x), V(y)]) => 0x8000 | x << 8 | y << 4,
+        ("OR", [V(x), V(y)]) => 0x8001 | x << 8 | y << 4,
+        ("AND", [V(x), V(y)]) => 0x8002 | x << 8 | y << 4,
+        ("XOR", [V(x), V(y)]) => 0x8003 | x << 8 | y << 4,
+        ("ADD", [V(x), V(y)]) => 0x8004 | x << 8 | y << 4,
+        ("SUB", [V(x), V(y)]) => 0x8005 | x << 8 | y << 4,
+        ("SHR", [V(x), V(y)]) => 0x8006 | x << 8 | y << 4,
+        ("SUBN", [V(x), V(y)]) => 0x8007 | x << 8 | y << 4,
+        ("SHL", [V(x), V(y)]) => 0x800E | x << 8 | y << 4,
+        ("LD", [Name("I"), Number(a)]) => 0xA000 | fits(*a, 12)?,
+        ("RND", [V(x), Number(n)]) => 0xC000 | x << 8 | fits(*n, 8)?,
+        ("DRW", [V(x), V(y), Number(n)]) => 0xD000 | x << 8 | y << 4 | fits(*n, 4)?,
+        ("SKP", [V(x)]) => 0xE09E | x << 8,
+        ("SKNP", [V(x)]) => 0xE0A1 | x << 8,
+        ("LD", [V(x), Name("DT")]) => 0xF007 | x << 8,
+        ("LD", [V(x), Name("K")]) => 0xF00A | x << 8,
+        ("LD", [Name("DT"), V(x)]) => 0xF015 | x << 8,
+        ("LD", [Name("ST"), V(x)]) => 0xF018 | x << 8,
+        ("ADD", [Name("I"), V(x)]) => 0xF01E | x << 8,
+        ("LD", [Name("F"), V(x)]) => 0xF029 | x << 8,
+        ("LD", [Name("HF"), V(x)]) => 0xF030 | x << 8,
+        ("LD", [Name("B"), V(x)]) => 0xF033 | x << 8,
+        ("LD", [Name("[I]"), V(x)]) => 0xF055 | x << 8,
+        ("LD", [V(x), Name("[I]")]) => 0xF065 | x << 8,
+        ("LD", [Name("R"), V(x)]) => 0xF075 | x << 8,
+        ("LD", [V(x), Name("R")]) => 0xF085 | x << 8,
+        ("DW", [Number(w)]) => fits(*w, 16)?,
+        _ if raw.is_empty() => return Err(format!("unknown instruction '{}'", op)),
+        _ => return Err(format!("unknown instruction '{} {}'", op, raw.join(", "))),
+    };
+    Ok(word)
+}
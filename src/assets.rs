@@ -0,0 +1,31 @@
+use std::path::Path;
+
+// The files the emulator needs at runtime are built into the binary, so that it runs from anywhere
+// without a directory of files next to it. The fonts and palettes are code in the library anyway,
+// these are the rest. A file of the same name in the working directory still beats the built-in
+// one, for trying out a translation without a rebuild.
+
+// ICON is the window icon, "C8" in the CHIP8 font, unless the ROM is a bundle with cover art.
+pub const ICON: &[u8] = include_bytes!("../images/icon.png");
+
+// LANGUAGES are the translations of lang/, by name.
+const LANGUAGES: [(&str, &str); 1] = [
+    ("de", include_str!("../lang/de.toml")),
+];
+
+// language returns the text of a --language: the file if there is one, or else the built-in
+// translation of that name, given as "de" or as the "lang/de.toml" it was built from.
+pub fn language(nameOrPath: &str) -> Result<String, String> {
+    let path = Path::new(nameOrPath);
+    if !path.exists() {
+        let name = path.file_stem().map(|s| s.to_string_lossy()).unwrap_or_default();
+        if let Some((_, text)) = LANGUAGES.iter().find(|(n, _)| *n == name) {
+            return Ok(text.to_string());
+        }
+    }
+    std::fs::read_to_string(path).map_err(|e| format!("unable to read {}: {} (the built-in languages are {})", nameOrPath, e, languages().join(", ")))
+}
+
+pub fn languages() -> Vec<&'static str> {
+    LANGUAGES.iter().map(|(name, _)| *name).collect()
+}
@@ -0,0 +1,62 @@
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+// The files the emulator keeps for the user, the config file, the library, the saved data of the
+// ROMs and the save state slots, are written so that a crash or power loss midway leaves the old
+// file or the new one, never half of one. The new contents go to a temporary file next to it,
+// which is flushed to disk and then renamed over the old file in one step. The old file is kept
+// as a backup, <file>.bak, from before the last write.
+
+fn withSuffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name: OsString = path.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+// backupPath returns where the file as it was before the last write is kept.
+pub fn backupPath(path: &Path) -> PathBuf {
+    withSuffix(path, ".bak")
+}
+
+// write replaces the contents of a file, or creates it, see above.
+pub fn write(path: &Path, data: &[u8]) -> io::Result<()> {
+    let temporary = withSuffix(path, ".tmp");
+    let written = File::create(&temporary).and_then(|mut file| {
+        file.write_all(data)?;
+        file.sync_all()
+    });
+    if let Err(e) = written {
+        let _ = fs::remove_file(&temporary);
+        return Err(e);
+    }
+    // a copy rather than a rename, so that the file itself is there all along.
+    if path.exists() {
+        fs::copy(path, backupPath(path))?;
+    }
+    fs::rename(&temporary, path)?;
+    // the rename is only on disk once the directory is.
+    #[cfg(unix)]
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
+
+// read reads a file, or its backup if what's in the file isn't valid by the check, a file damaged
+// by something else than this. The error is that of the file. A file that's gone stays gone, it
+// may have been deleted to start over.
+pub fn read<T>(path: &Path, valid: impl Fn(&[u8]) -> Option<T>) -> io::Result<T> {
+    let data = fs::read(path)?;
+    if let Some(value) = valid(&data) {
+        return Ok(value);
+    }
+    match fs::read(backupPath(path)).ok().and_then(|backup| valid(&backup)) {
+        Some(value) => {
+            println!("Using {} as {} is damaged", backupPath(path).display(), path.display());
+            Ok(value)
+        }
+        None => Err(io::Error::new(io::ErrorKind::InvalidData, "not a valid file")),
+    }
+}
@@ -0,0 +1,91 @@
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rust_chip8::emulator::{Emulator, KeyState};
+use rust_chip8::hooks::Hooks;
+
+use crate::actions::ROM_EXTENSIONS;
+use crate::commands::batch::romFiles;
+use crate::recording::{self, Player};
+
+// DEMO_SECONDS is how long a ROM without a replay is played with random keys.
+const DEMO_SECONDS: u64 = 30;
+
+// Attract is the attract mode of --attract: after a while without a key pressed the session plays
+// demos of the ROMs of a playlist, one after the other, until a key is pressed and the ROM that
+// was running comes back as it was. A ROM with a replay in paths::replays plays that, the others get
+// random keys for DEMO_SECONDS.
+pub struct Attract {
+    pub idleFrames: u64, // frames without a key pressed before the demos start
+    pub idle: u64,       // frames since the last key press
+    playlist: Vec<PathBuf>,
+    next: usize, // the playlist entry to play next
+    rng: u32,    // xorshift state for the random keys
+    pub demo: Option<Demo>,
+}
+
+// Demo is a ROM of the playlist being played by the attract mode.
+pub struct Demo {
+    pub home: (Vec<Emulator>, String, u64, Hooks), // the cores, ROM, frame and hooks to go back to
+    pub frame: u64,             // frames of the demo played
+    pub frames: u64,            // how long the demo plays
+    pub player: Option<Player>, // the replay of the ROM, if it has one
+    keys: KeyState,             // the random keys held, without a replay
+    holdFrames: u64,            // until the random keys change
+}
+
+impl Attract {
+    // new lists the ROMs of a directory and its subdirectories, or takes the one ROM given, for
+    // the playlist. The ROM the session started with is left out, that's where the demos return
+    // to.
+    pub fn new(idleFrames: u64, dir: &Path, romPath: &str) -> Result<Attract, String> {
+        let playlist: Vec<PathBuf> = romFiles(dir).map_err(|e| format!("Unable to list {}: {}", dir.display(), e))?
+            .into_iter()
+            .filter(|path| path.extension().is_some_and(|e| ROM_EXTENSIONS.iter().any(|r| e.eq_ignore_ascii_case(r))))
+            .filter(|path| path.as_path() != Path::new(romPath))
+            .collect();
+        if playlist.is_empty() {
+            return Err(format!("There are no other ROMs in {} for the attract mode to play", dir.display()));
+        }
+        let rng = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_micros() as u32).unwrap_or(0) | 1;
+        Ok(Attract { idleFrames, idle: 0, playlist, next: 0, rng, demo: None })
+    }
+
+    pub fn playlist_len(&self) -> usize {
+        self.playlist.len()
+    }
+
+    // next_rom returns the next ROM of the playlist, going round and round.
+    pub fn next_rom(&mut self) -> Option<PathBuf> {
+        let rom = self.playlist.get(self.next % self.playlist.len()).cloned();
+        self.next += 1;
+        rom
+    }
+
+    // demo returns the demo of a ROM, with its replay if there's one that was recorded with it.
+    pub fn demo(romPath: &Path, data: &[u8], home: (Vec<Emulator>, String, u64, Hooks), timerHz: u32) -> Demo {
+        let player = Player::open(&recording::replayPath(&romPath.to_string_lossy()), data).ok();
+        let frames = player.as_ref().map(|p| p.replay.frames()).unwrap_or(DEMO_SECONDS * timerHz as u64);
+        Demo { home, frame: 0, frames, player, keys: KeyState::default(), holdFrames: 0 }
+    }
+
+    // keys returns the keys of the next frame of the demo: those of its replay, or a random key
+    // held for a moment followed by a pause, over and over.
+    pub fn keys(&mut self) -> KeyState {
+        let Some(demo) = self.demo.as_mut() else { return KeyState::default() };
+        demo.frame += 1;
+        if let Some(player) = demo.player.as_mut() {
+            return player.keys(demo.frame);
+        }
+        if demo.holdFrames == 0 {
+            self.rng ^= self.rng << 13;
+            self.rng ^= self.rng >> 17;
+            self.rng ^= self.rng << 5;
+            let pressed = demo.keys == KeyState::default();
+            demo.keys = KeyState(if pressed { 1 << (self.rng % 16) } else { 0 });
+            demo.holdFrames = if pressed { 5 + (self.rng >> 8) as u64 % 30 } else { 1 + (self.rng >> 8) as u64 % 10 };
+        }
+        demo.holdFrames -= 1;
+        demo.keys
+    }
+}
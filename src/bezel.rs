@@ -0,0 +1,99 @@
+use std::fs;
+
+// Bezel is artwork around the display in the window, like the bezels of arcade emulators: a PNG
+// that the frame is drawn into, in the area of it that's fully transparent, the screen. The frame
+// is scaled up by the largest whole factor that fits the screen, or down to fit if it's larger,
+// and centered, with the background color around it. The window then scales the whole picture
+// to fit, like it does the frame without a bezel.
+pub struct Bezel {
+    image: Vec<u8>, // RGBA
+    width: usize,
+    height: usize,
+    screen: (usize, usize, usize, usize), // left, top, width and height
+}
+
+impl Bezel {
+    // load reads a bezel from a PNG file.
+    pub fn load(path: &str) -> Result<Bezel, String> {
+        let data = fs::read(path).map_err(|e| format!("Unable to read {}: {}", path, e))?;
+        Bezel::decode(&data).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    // decode reads a bezel from PNG data, like that of a bundle.
+    pub fn decode(png: &[u8]) -> Result<Bezel, String> {
+        let mut decoder = png::Decoder::new(png);
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+        let mut reader = decoder.read_info().map_err(|e| e.to_string())?;
+        let mut data = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut data).map_err(|e| e.to_string())?;
+        let image: Vec<u8> = match info.color_type {
+            png::ColorType::Rgba => data[..info.buffer_size()].to_vec(),
+            png::ColorType::Rgb => data[..info.buffer_size()].chunks(3).flat_map(|p| [p[0], p[1], p[2], 0xff]).collect(),
+            png::ColorType::GrayscaleAlpha => data[..info.buffer_size()].chunks(2).flat_map(|p| [p[0], p[0], p[0], p[1]]).collect(),
+            _ => data[..info.buffer_size()].iter().flat_map(|g| [*g, *g, *g, 0xff]).collect(),
+        };
+        let (width, height) = (info.width as usize, info.height as usize);
+
+        // the screen is the box around the fully transparent pixels.
+        let transparent: Vec<(usize, usize)> = image.chunks(4).enumerate().filter(|(_, p)| p[3] == 0).map(|(i, _)| (i % width, i / width)).collect();
+        let (left, right) = (transparent.iter().map(|p| p.0).min(), transparent.iter().map(|p| p.0).max());
+        let (top, bottom) = (transparent.iter().map(|p| p.1).min(), transparent.iter().map(|p| p.1).max());
+        match (left, right, top, bottom) {
+            (Some(left), Some(right), Some(top), Some(bottom)) => {
+                Ok(Bezel { image, width, height, screen: (left, top, right - left + 1, bottom - top + 1) })
+            }
+            _ => Err("the bezel has no transparent area for the screen".to_string()),
+        }
+    }
+
+    // size returns the width and height of the bezel, and so of the picture the window shows.
+    pub fn size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    // screen_size returns the width and height of its screen.
+    pub fn screen_size(&self) -> (usize, usize) {
+        (self.screen.2, self.screen.3)
+    }
+
+    // placement returns where a frame of a size goes on the screen: its left and top, width and
+    // height there.
+    fn placement(&self, width: usize, height: usize) -> (usize, usize, usize, usize) {
+        let (left, top, screenWidth, screenHeight) = self.screen;
+        let scale = (screenWidth as f64 / width as f64).min(screenHeight as f64 / height as f64);
+        let scale = if scale >= 1.0 { scale.floor() } else { scale };
+        let (w, h) = (((width as f64 * scale) as usize).max(1), ((height as f64 * scale) as usize).max(1));
+        (left + (screenWidth - w) / 2, top + (screenHeight - h) / 2, w, h)
+    }
+
+    // compose draws the bezel with a frame of width x height RGBA pixels on its screen into a
+    // picture of the size of the bezel, the rest of the screen in the background color.
+    pub fn compose(&self, frame: &[u8], width: usize, height: usize, background: [u8; 4], picture: &mut [u8]) {
+        picture.copy_from_slice(&self.image);
+        let (left, top, screenWidth, screenHeight) = self.screen;
+        for y in top..top + screenHeight {
+            for x in left..left + screenWidth {
+                let i = (y * self.width + x) * 4;
+                if self.image[i + 3] == 0 {
+                    picture[i..i + 4].copy_from_slice(&background);
+                }
+            }
+        }
+        let (frameLeft, frameTop, w, h) = self.placement(width, height);
+        for y in 0..h {
+            for x in 0..w {
+                let from = ((y * height / h) * width + x * width / w) * 4;
+                let to = ((frameTop + y) * self.width + frameLeft + x) * 4;
+                picture[to..to + 4].copy_from_slice(&frame[from..from + 4]);
+            }
+        }
+    }
+
+    // frame_pixel returns the pixel of a frame of a size that a pixel of the picture shows, if
+    // it's on the frame, for clicks on the keypad and such.
+    pub fn frame_pixel(&self, x: usize, y: usize, width: usize, height: usize) -> Option<(usize, usize)> {
+        let (left, top, w, h) = self.placement(width, height);
+        let inside = (left..left + w).contains(&x) && (top..top + h).contains(&y);
+        inside.then(|| ((x - left) * width / w, (y - top) * height / h))
+    }
+}
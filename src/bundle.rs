@@ -0,0 +1,216 @@
+use std::fs;
+use std::path::Path;
+
+use miniz_oxide::{deflate, inflate};
+
+use crate::actions::ROM_EXTENSIONS;
+use crate::cart;
+use crate::config;
+
+// A bundle is a zip file, usually named <game>.c8b, with a ROM and how to run it, so a game's
+// author can hand out a game that just works rather than a ROM with instructions for the quirks:
+//
+//   manifest.toml   what the game is and how it runs, see below
+//   <game>.ch8      the ROM, .sc8 or .c8 also do
+//   cover.png       cover art, optional, which becomes the window icon
+//   bezel.png       artwork around the display in the window, optional, see bezel
+//
+// The manifest is in the format of the config file:
+//
+//   title = "Danger Zone"
+//   author = "A. Programmer"
+//   variant = "schip"
+//   quirks = "schip,clip=off"
+//   ips = 12000
+//   keymap = "positional"
+//   palette = "high-contrast"
+//
+// Every key is optional. The options are applied like those of the config file (see
+// cli::parseWithConfig), but before the sections of the ROM, so a player can still change them.
+// With more than one ROM in the zip, rom = "<file>" picks one, and bezel = "<file>" picks the bezel
+// if it isn't bezel.png. background, the color around the display, is an option too. Other keys
+// are left for newer versions.
+//
+// An Octo cartridge, a GIF with the program in it, is also a bundle, see cart.
+pub const BUNDLE_EXTENSION: &str = "c8b";
+
+// MANIFEST_OPTIONS are the keys of the manifest that are options.
+const MANIFEST_OPTIONS: [&str; 6] = ["variant", "quirks", "ips", "keymap", "palette", "background"];
+
+const MANIFEST: &str = "manifest.toml";
+const COVER: &str = "cover.png";
+const BEZEL: &str = "bezel.png";
+
+// ZIP_MAGIC starts the local file headers of a zip, and so every zip file.
+const ZIP_MAGIC: [u8; 4] = [b'P', b'K', 3, 4];
+
+pub struct Bundle {
+    pub rom: Vec<u8>,
+    pub romFile: String, // the name of the ROM in the zip
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub options: Vec<String>, // the options of the manifest as command line arguments
+    pub cover: Option<Vec<u8>>, // PNG
+    pub bezel: Option<Vec<u8>>, // PNG
+}
+
+// isBundle returns whether a file read is a bundle or a cartridge rather than a ROM.
+pub fn isBundle(data: &[u8]) -> bool {
+    data.starts_with(&ZIP_MAGIC) || cart::isCart(data)
+}
+
+// readRom reads a ROM file, or the ROM of a bundle.
+pub fn readRom(path: &str) -> Result<Vec<u8>, String> {
+    let data = fs::read(path).map_err(|e| format!("Unable to read {}: {}", path, e))?;
+    match isBundle(&data) {
+        true => Bundle::parse(&data).map(|bundle| bundle.rom).map_err(|e| format!("{}: {}", path, e)),
+        false => Ok(data),
+    }
+}
+
+impl Bundle {
+    // open reads a bundle, returning None for a file that's a plain ROM. A cartridge is titled
+    // after its file.
+    pub fn open(path: &str) -> Result<Option<Bundle>, String> {
+        let data = fs::read(path).map_err(|e| format!("Unable to read {}: {}", path, e))?;
+        if !isBundle(&data) {
+            return Ok(None);
+        }
+        let mut bundle = Bundle::parse(&data).map_err(|e| format!("{}: {}", path, e))?;
+        if cart::isCart(&data) {
+            bundle.title = Path::new(path).file_stem().map(|stem| stem.to_string_lossy().to_string());
+        }
+        Ok(Some(bundle))
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Bundle, String> {
+        if cart::isCart(data) {
+            return cart::parse(data);
+        }
+        let files = unzip(data)?;
+        let file = |name: &str| files.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|(_, data)| data.clone());
+        let manifest = match file(MANIFEST) {
+            Some(text) => config::parse(&String::from_utf8_lossy(&text)).map_err(|e| format!("{}: {}", MANIFEST, e))?,
+            None => return Err(format!("the bundle has no {}", MANIFEST)),
+        };
+
+        let romFile = match manifest.value("", "rom") {
+            Some(name) => name.to_string(),
+            None => {
+                let roms: Vec<&String> = files.iter().map(|(name, _)| name)
+                    .filter(|name| Path::new(name).extension().is_some_and(|e| ROM_EXTENSIONS.iter().any(|r| e.eq_ignore_ascii_case(r))))
+                    .collect();
+                match roms[..] {
+                    [rom] => rom.clone(),
+                    [] => return Err("the bundle has no ROM".to_string()),
+                    _ => return Err(format!("the bundle has {} ROMs, the manifest needs to pick one with rom = \"<file>\"", roms.len())),
+                }
+            }
+        };
+        let rom = file(&romFile).ok_or_else(|| format!("the bundle has no {}", romFile))?;
+
+        let mut options = Vec::new();
+        for (key, value) in manifest.entries("").into_iter().filter(|(key, _)| MANIFEST_OPTIONS.contains(key)) {
+            options.push(format!("--{}", key));
+            options.push(value.to_string());
+        }
+        Ok(Bundle {
+            rom,
+            romFile,
+            title: manifest.value("", "title").map(String::from),
+            author: manifest.value("", "author").map(String::from),
+            options,
+            cover: file(COVER),
+            bezel: file(manifest.value("", "bezel").unwrap_or(BEZEL)),
+        })
+    }
+
+    // describe returns the title and author for printing when the bundle's loaded.
+    pub fn describe(&self) -> String {
+        let title = self.title.clone().unwrap_or_else(|| self.romFile.clone());
+        match &self.author {
+            Some(author) => format!("{} by {}", title, author),
+            None => title,
+        }
+    }
+}
+
+// unzip returns the names and contents of the files in a zip, which are stored or deflated, from
+// its central directory.
+pub fn unzip(data: &[u8]) -> Result<Vec<(String, Vec<u8>)>, String> {
+    let u16At = |at: usize| data.get(at..at + 2).map(|b| u16::from_le_bytes([b[0], b[1]]) as usize).ok_or("the zip is truncated");
+    let u32At = |at: usize| data.get(at..at + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]])).ok_or("the zip is truncated");
+
+    // the end of central directory record is the last 22 bytes, unless the zip has a comment.
+    let end = (0..data.len().saturating_sub(21)).rev().find(|at| data[*at..].starts_with(&[b'P', b'K', 5, 6]))
+        .ok_or("not a zip file, it has no central directory")?;
+    let (count, mut at) = (u16At(end + 10)?, u32At(end + 16)? as usize);
+
+    let mut files = Vec::new();
+    for _ in 0..count {
+        if u32At(at)? != 0x02014b50 {
+            return Err("the central directory of the zip is corrupt".to_string());
+        }
+        let (method, crc, size, unpacked) = (u16At(at + 10)?, u32At(at + 16)?, u32At(at + 20)? as usize, u32At(at + 24)? as usize);
+        let (nameLength, extraLength, commentLength) = (u16At(at + 28)?, u16At(at + 30)?, u16At(at + 32)?);
+        let local = u32At(at + 42)? as usize;
+        let name = String::from_utf8_lossy(data.get(at + 46..at + 46 + nameLength).ok_or("the zip is truncated")?).to_string();
+        at += 46 + nameLength + extraLength + commentLength;
+        if name.ends_with('/') {
+            continue; // a directory
+        }
+
+        let start = local + 30 + u16At(local + 26)? + u16At(local + 28)?;
+        let packed = data.get(start..start + size).ok_or("the zip is truncated")?;
+        let contents = match method {
+            0 => packed.to_vec(),
+            8 => inflate::decompress_to_vec_with_limit(packed, unpacked).map_err(|e| format!("unable to inflate {}: {}", name, e))?,
+            _ => return Err(format!("{} is compressed with method {}, only stored and deflated files are supported", name, method)),
+        };
+        if crc32fast::hash(&contents) != crc {
+            return Err(format!("{} is corrupt, its checksum doesn't match", name));
+        }
+        // the files of a zip made from a directory are in that directory.
+        let name = name.rsplit('/').next().unwrap_or(&name).to_string();
+        files.push((name, contents));
+    }
+    Ok(files)
+}
+
+// zip packs files into a zip that unzip and the usual tools read, each deflated, with the
+// central directory at the end.
+pub fn zip(files: &[(String, Vec<u8>)]) -> Vec<u8> {
+    let (mut data, mut directory) = (Vec::new(), Vec::new());
+    for (name, contents) in files {
+        let packed = deflate::compress_to_vec(contents, 6);
+        let (crc, local) = (crc32fast::hash(contents), data.len() as u32);
+        // version 2.0, no flags, deflated, no date, then the checksum and the sizes.
+        let mut header = Vec::new();
+        header.extend_from_slice(&[20, 0, 0, 0, 8, 0, 0, 0, 0x21, 0]);
+        header.extend_from_slice(&crc.to_le_bytes());
+        header.extend_from_slice(&(packed.len() as u32).to_le_bytes());
+        header.extend_from_slice(&(contents.len() as u32).to_le_bytes());
+        header.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        header.extend_from_slice(&[0, 0]); // no extra field
+
+        data.extend_from_slice(&ZIP_MAGIC);
+        data.extend_from_slice(&header);
+        data.extend_from_slice(name.as_bytes());
+        data.extend_from_slice(&packed);
+
+        directory.extend_from_slice(&[b'P', b'K', 1, 2, 20, 0]);
+        directory.extend_from_slice(&header);
+        directory.extend_from_slice(&[0; 10]); // no comment, disk 0, no attributes
+        directory.extend_from_slice(&local.to_le_bytes());
+        directory.extend_from_slice(name.as_bytes());
+    }
+    let (start, size) = (data.len() as u32, directory.len() as u32);
+    data.extend_from_slice(&directory);
+    data.extend_from_slice(&[b'P', b'K', 5, 6, 0, 0, 0, 0]);
+    data.extend_from_slice(&(files.len() as u16).to_le_bytes());
+    data.extend_from_slice(&(files.len() as u16).to_le_bytes());
+    data.extend_from_slice(&size.to_le_bytes());
+    data.extend_from_slice(&start.to_le_bytes());
+    data.extend_from_slice(&[0, 0]); // no comment
+    data
+}
@@ -0,0 +1,369 @@
+use std::collections::BTreeMap;
+
+use rust_chip8::octo;
+
+use crate::bundle::Bundle;
+
+// An Octo cartridge is a GIF image, the cart's label, with the program and its settings hidden in
+// the pixels, which is how the Octojam community shares its games. The low 2 bits of each pixel's
+// color index, 4 pixels to the byte with the highest bits first and the frames one after the
+// other, are a 4 byte big endian length and that much JSON:
+//
+//   {"program": "<the Octo source>", "options": {"tickrate": 20, "shiftQuirks": false, ...}}
+//
+// The program is assembled with rust_chip8::octo, and the options that have an equivalent become
+// the options of a bundle, like those of its manifest. The others, the colors and the touch modes,
+// are left out.
+
+pub const CART_EXTENSION: &str = "gif";
+
+// GIF_MAGIC starts every GIF, in either version.
+const GIF_MAGIC: [&[u8]; 2] = [b"GIF87a", b"GIF89a"];
+
+// TIMER_HZ is the rate Octo's tickrate is per, the instructions per frame.
+const TIMER_HZ: i64 = 60;
+
+// CHIP8_MAX_SIZE is the largest program Octo's maxSize allows for a plain CHIP8, anything larger
+// is for the SCHIP or XO-CHIP.
+const CHIP8_MAX_SIZE: f64 = 3232.0;
+
+pub fn isCart(data: &[u8]) -> bool {
+    GIF_MAGIC.iter().any(|magic| data.starts_with(magic))
+}
+
+// parse reads the program of a cartridge and assembles it into the ROM of a bundle.
+pub fn parse(data: &[u8]) -> Result<Bundle, String> {
+    let pixels = pixels(data)?;
+    let payload = payload(&pixels).ok_or("the GIF has no Octo cartridge in it")?;
+    let program = match payload.get("program") {
+        Some(Json::String(program)) => program,
+        _ => return Err("the cartridge has no program".to_string()),
+    };
+    let rom = octo::assemble(program).map_err(|e| format!("unable to assemble the cartridge's program, {}", e))?;
+    let options = match payload.get("options") {
+        Some(Json::Object(options)) => cartOptions(options),
+        _ => Vec::new(),
+    };
+    Ok(Bundle { rom, romFile: "program.8o".to_string(), title: None, author: None, options, cover: None, bezel: None })
+}
+
+// cartOptions turns Octo's options into command line arguments.
+fn cartOptions(options: &BTreeMap<String, Json>) -> Vec<String> {
+    let flag = |key: &str| match options.get(key) {
+        Some(Json::Bool(on)) => Some(*on),
+        _ => None,
+    };
+    let number = |key: &str| match options.get(key) {
+        Some(Json::Number(n)) => Some(*n),
+        _ => None,
+    };
+    let mut args = Vec::new();
+    if let Some(tickrate) = number("tickrate").filter(|t| *t >= 1.0) {
+        args.extend(["--ips".to_string(), (tickrate as i64 * TIMER_HZ).to_string()]);
+    }
+    if let Some(maxSize) = number("maxSize") {
+        let variant = if maxSize <= CHIP8_MAX_SIZE { "chip8" } else { "schip" };
+        args.extend(["--variant".to_string(), variant.to_string()]);
+    }
+    // Octo's shift and load/store quirks are the SCHIP behaviour, ours the original one.
+    let quirks: Vec<String> = [("vfreset", "logicQuirks", false), ("shift", "shiftQuirks", true), ("loadstore", "loadStoreQuirks", true),
+        ("jump", "jumpQuirks", false), ("clip", "clipQuirks", false)].iter()
+        .filter_map(|(quirk, key, inverted)| flag(key).map(|on| format!("{}={}", quirk, if on != *inverted { "on" } else { "off" })))
+        .collect();
+    if !quirks.is_empty() {
+        args.extend(["--quirks".to_string(), quirks.join(",")]);
+    }
+    if let Some(rotation) = number("screenRotation") {
+        args.extend(["--rotate".to_string(), (rotation as i64).to_string()]);
+    }
+    args
+}
+
+// payload returns the JSON object in the low bits of the pixels, if there's one.
+fn payload(pixels: &[u8]) -> Option<BTreeMap<String, Json>> {
+    let mut bytes = pixels.chunks_exact(4).map(|p| p.iter().fold(0, |byte, pixel| byte << 2 | (pixel & 3)));
+    let size = bytes.by_ref().take(4).fold(0usize, |size, byte| size << 8 | byte as usize);
+    let json: Vec<u8> = bytes.take(size).collect();
+    if json.len() < size {
+        return None;
+    }
+    match Json::parse(&String::from_utf8(json).ok()?).ok()? {
+        Json::Object(payload) => Some(payload),
+        _ => None,
+    }
+}
+
+// pixels decodes the color indices of all the frames of a GIF, one after the other.
+fn pixels(data: &[u8]) -> Result<Vec<u8>, String> {
+    let byte = |at: usize| data.get(at).copied().map(usize::from).ok_or("the GIF is truncated");
+    let colorTable = |flags: usize| if flags & 0x80 != 0 { 3 << ((flags & 7) + 1) } else { 0 };
+    let mut at = 13 + colorTable(byte(10)?);
+    let mut pixels = Vec::new();
+    loop {
+        match byte(at)? {
+            0x21 => at = subBlocks(data, at + 2)?.1, // an extension, the label follows the introducer
+            0x2C => {
+                let count = (byte(at + 5)? | byte(at + 6)? << 8) * (byte(at + 7)? | byte(at + 8)? << 8);
+                at += 10 + colorTable(byte(at + 9)?);
+                let minCodeSize = byte(at)?;
+                let (codes, next) = subBlocks(data, at + 1)?;
+                // the size in the header only cuts a frame short: one whose data ends early ends the
+                // pixels there, as those of the frames after it wouldn't line up anyway.
+                let frame = lzw(&codes, minCodeSize)?;
+                let complete = frame.len() >= count;
+                pixels.extend(frame.into_iter().take(count));
+                if !complete {
+                    return Ok(pixels);
+                }
+                at = next;
+            }
+            0x3B => return Ok(pixels),
+            block => return Err(format!("the GIF has an unknown block {:#04X}", block)),
+        }
+    }
+}
+
+// subBlocks returns the data of the sub-blocks from an address and where they end.
+fn subBlocks(data: &[u8], mut at: usize) -> Result<(Vec<u8>, usize), String> {
+    let mut blocks = Vec::new();
+    loop {
+        let size = *data.get(at).ok_or("the GIF is truncated")? as usize;
+        if size == 0 {
+            return Ok((blocks, at + 1));
+        }
+        blocks.extend_from_slice(data.get(at + 1..at + 1 + size).ok_or("the GIF is truncated")?);
+        at += 1 + size;
+    }
+}
+
+// lzw decompresses the image data of a GIF frame into its color indices.
+fn lzw(codes: &[u8], minCodeSize: usize) -> Result<Vec<u8>, String> {
+    if !(1..=11).contains(&minCodeSize) {
+        return Err(format!("the GIF has an invalid code size {}", minCodeSize));
+    }
+    let (clear, end) = (1 << minCodeSize, (1 << minCodeSize) + 1);
+    // each code of the table is the code before it and one more index
+    let (mut prefix, mut suffix, mut length) = ([0usize; 4096], [0u8; 4096], [0usize; 4096]);
+    for code in 0..clear {
+        (suffix[code], length[code]) = (code as u8, 1);
+    }
+    let (mut next, mut size, mut previous) = (end + 1, minCodeSize + 1, None);
+    let mut pixels = Vec::new();
+    let mut bit = 0;
+    while bit + size <= codes.len() * 8 {
+        let code = (bit..bit + size).rev().fold(0, |code, b| code << 1 | (codes[b / 8] >> (b % 8) & 1) as usize);
+        bit += size;
+        if code == clear {
+            (next, size, previous) = (end + 1, minCodeSize + 1, None);
+            continue;
+        }
+        if code == end {
+            break;
+        }
+        let first = |mut code: usize| {
+            while length[code] > 1 {
+                code = prefix[code];
+            }
+            suffix[code]
+        };
+        match previous {
+            Some(previous) if code <= next && next < 4096 => {
+                let added = if code == next { first(previous) } else { first(code) };
+                (prefix[next], suffix[next], length[next]) = (previous, added, length[previous] + 1);
+                next += 1;
+            }
+            None if code >= next => return Err("the GIF's image data is corrupt".to_string()),
+            _ if code > next => return Err("the GIF's image data is corrupt".to_string()),
+            _ => {}
+        }
+        let start = pixels.len();
+        pixels.resize(start + length[code], 0);
+        let mut c = code;
+        for i in (start..pixels.len()).rev() {
+            pixels[i] = suffix[c];
+            c = prefix[c];
+        }
+        previous = Some(code);
+        if next == 1 << size && size < 12 {
+            size += 1;
+        }
+    }
+    Ok(pixels)
+}
+
+// Json is a value of the JSON of a cartridge.
+#[derive(Clone, Debug, PartialEq)]
+enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(BTreeMap<String, Json>),
+}
+
+impl Json {
+    fn parse(text: &str) -> Result<Json, String> {
+        let mut chars = text.chars().peekable();
+        let value = Json::value(&mut chars)?;
+        match chars.find(|c| !c.is_whitespace()) {
+            Some(c) => Err(format!("unexpected '{}' after the JSON", c)),
+            None => Ok(value),
+        }
+    }
+
+    fn value(chars: &mut std::iter::Peekable<std::str::Chars>) -> Result<Json, String> {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let word = |chars: &mut std::iter::Peekable<std::str::Chars>, word: &str, value: Json| match word.chars().all(|c| chars.next() == Some(c)) {
+            true => Ok(value),
+            false => Err(format!("expected {}", word)),
+        };
+        match chars.peek().copied() {
+            Some('n') => word(chars, "null", Json::Null),
+            Some('t') => word(chars, "true", Json::Bool(true)),
+            Some('f') => word(chars, "false", Json::Bool(false)),
+            Some('"') => Json::string(chars).map(Json::String),
+            Some('[') => {
+                chars.next();
+                let mut values = Vec::new();
+                loop {
+                    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+                    if chars.next_if_eq(&']').is_some() {
+                        return Ok(Json::Array(values));
+                    }
+                    if !values.is_empty() && chars.next() != Some(',') {
+                        return Err("expected , or ] in an array".to_string());
+                    }
+                    values.push(Json::value(chars)?);
+                }
+            }
+            Some('{') => {
+                chars.next();
+                let mut members = BTreeMap::new();
+                loop {
+                    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+                    if chars.next_if_eq(&'}').is_some() {
+                        return Ok(Json::Object(members));
+                    }
+                    if !members.is_empty() && chars.next() != Some(',') {
+                        return Err("expected , or } in an object".to_string());
+                    }
+                    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+                    let key = Json::string(chars)?;
+                    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+                    if chars.next() != Some(':') {
+                        return Err(format!("expected : after \"{}\"", key));
+                    }
+                    members.insert(key, Json::value(chars)?);
+                }
+            }
+            Some(c) if c == '-' || c.is_ascii_digit() => {
+                let mut number = String::new();
+                while let Some(c) = chars.next_if(|c| c.is_ascii_digit() || "+-.eE".contains(*c)) {
+                    number.push(c);
+                }
+                number.parse().map(Json::Number).map_err(|_| format!("invalid number {}", number))
+            }
+            Some(c) => Err(format!("unexpected '{}' in the JSON", c)),
+            None => Err("the JSON ends too soon".to_string()),
+        }
+    }
+
+    fn string(chars: &mut std::iter::Peekable<std::str::Chars>) -> Result<String, String> {
+        if chars.next() != Some('"') {
+            return Err("expected a string".to_string());
+        }
+        let mut string = String::new();
+        let mut surrogate = None;
+        loop {
+            let c = match chars.next().ok_or("the JSON ends inside a string")? {
+                '"' => return Ok(string),
+                '\\' => match chars.next().ok_or("the JSON ends inside a string")? {
+                    'n' => '\n',
+                    't' => '\t',
+                    'r' => '\r',
+                    'b' => '\u{8}',
+                    'f' => '\u{c}',
+                    'u' => {
+                        let hex: String = chars.by_ref().take(4).collect();
+                        let unit = u32::from_str_radix(&hex, 16).map_err(|_| format!("invalid escape \\u{}", hex))?;
+                        // characters outside the BMP are escaped as a pair of surrogates
+                        match (surrogate.take(), unit) {
+                            (None, 0xD800..=0xDBFF) => {
+                                surrogate = Some(unit);
+                                continue;
+                            }
+                            (Some(high), 0xDC00..=0xDFFF) => char::from_u32(0x10000 + ((high - 0xD800) << 10) + (unit - 0xDC00)),
+                            (_, unit) => char::from_u32(unit),
+                        }.unwrap_or(char::REPLACEMENT_CHARACTER)
+                    }
+                    c => c,
+                },
+                c => c,
+            };
+            string.push(c);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // gif encodes color indices 0-3 as a GIF of a frame a row high. The LZW data clears the table
+    // after every two pixels, so its codes stay 3 bits.
+    fn gif(pixels: &[u8]) -> Vec<u8> {
+        let codes: Vec<usize> = pixels.chunks(2).flat_map(|pair| [vec![4], pair.iter().map(|p| *p as usize).collect()].concat()).chain([5]).collect();
+        let mut data = vec![0u8; (codes.len() * 3).div_ceil(8)];
+        for (n, code) in codes.iter().enumerate() {
+            for bit in 0..3 {
+                data[(n * 3 + bit) / 8] |= ((code >> bit & 1) as u8) << ((n * 3 + bit) % 8);
+            }
+        }
+        let width = (pixels.len() as u16).to_le_bytes();
+        let mut gif = [b"GIF89a".as_slice(), &width, &[1, 0, 0, 0, 0]].concat();
+        gif.extend([0x2C, 0, 0, 0, 0, width[0], width[1], 1, 0, 0, 2]);
+        for block in data.chunks(255) {
+            gif.push(block.len() as u8);
+            gif.extend(block);
+        }
+        gif.extend([0, 0x3B]);
+        gif
+    }
+
+    // cart hides JSON in the pixels of a GIF like Octo does.
+    fn cart(json: &str) -> Vec<u8> {
+        let payload = [&(json.len() as u32).to_be_bytes(), json.as_bytes()].concat();
+        gif(&payload.iter().flat_map(|byte| [byte >> 6, byte >> 4 & 3, byte >> 2 & 3, byte & 3]).collect::<Vec<u8>>())
+    }
+
+    #[test]
+    fn readsTheProgramAndItsOptions() {
+        let bundle = parse(&cart(r#"{"program": "v0 := 5", "options": {"tickrate": 20, "shiftQuirks": true, "fontStyle": "octo"}}"#)).unwrap();
+        assert_eq!(bundle.rom, vec![0x60, 0x05]);
+        // Octo's shift quirk is the SCHIP behaviour, which turns ours off, and it has no fontStyle
+        assert_eq!(bundle.options, ["--ips", "1200", "--quirks", "shift=off"]);
+    }
+
+    #[test]
+    fn stopsAtTheEndOfTheFrameData() {
+        // a frame of 65535x65535 pixels with the data of one, whose size mustn't be trusted
+        let mut huge = gif(&[0]);
+        huge[18..22].copy_from_slice(&[0xFF, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(pixels(&huge), Ok(vec![0]));
+        let valid = cart(r#"{"program": "v0 := 5"}"#);
+        assert_eq!(parse(&valid[..valid.len() - 1]).map(|b| b.rom), Err("the GIF is truncated".to_string()));
+    }
+
+    #[test]
+    fn explainsWhatItCantRead() {
+        let errors = [
+            (gif(&[0, 1, 2, 3]), "the GIF has no Octo cartridge in it"),
+            (cart(r#"{"options": {}}"#), "the cartridge has no program"),
+            (cart(r#"{"program": "v0 := 256"}"#), "unable to assemble the cartridge's program, line 1: 256 doesn't fit in a byte"),
+        ];
+        for (data, error) in errors {
+            assert_eq!(parse(&data).map(|b| b.rom), Err(error.to_string()));
+        }
+    }
+}
@@ -0,0 +1,208 @@
+use crate::memory::Memory;
+
+// Cdp1802 is the RCA CDP1802 of the COSMAC VIP, as far as the machine code routines of hybrid
+// CHIP8 ROMs need it: all of the instruction set, without the DMA and interrupts that drove the
+// VIP's display, keypad and tape. Input instructions read 0 and the EF flags are never set.
+//
+// It has 16 16-bit registers, any of which can be the program counter (the one P points at) or
+// the index register for memory operations (the one X points at), an 8-bit accumulator D with
+// the carry flag DF, and the Q output that drove the VIP's speaker.
+#[derive(Clone, Debug, Default)]
+pub struct Cdp1802 {
+    pub r: [u16; 16],
+    pub p: u8,
+    pub x: u8,
+    pub d: u8,
+    pub df: bool,
+    pub t: u8, // X and P saved by MARK, or by an interrupt
+    pub ie: bool,
+    pub q: bool,
+}
+
+impl Cdp1802 {
+    // run executes instructions until P is switched to returnP, like the SEP R4 (D4) that hands
+    // back to the VIP's CHIP8 interpreter, and returns how many it ran. It gives up with None after
+    // maxInstructions, for a routine that never returns.
+    pub fn run(&mut self, memory: &mut Memory, returnP: u8, maxInstructions: u32) -> Option<u32> {
+        for count in 1..=maxInstructions {
+            self.step(memory);
+            if self.p == returnP {
+                return Some(count);
+            }
+        }
+        None
+    }
+
+    // step runs a single instruction.
+    pub fn step(&mut self, memory: &mut Memory) {
+        let opcode = self.fetch(memory);
+        let (i, n) = (opcode >> 4, (opcode & 0xF) as usize);
+        let x = self.x as usize;
+        match (i, n) {
+            (0x0, 0) => {} // IDL waits for an interrupt, which never comes, so it goes on at once
+            (0x0, _) => self.d = memory.read_u8(self.r[n]), // LDN
+            (0x1, _) => self.r[n] = self.r[n].wrapping_add(1), // INC
+            (0x2, _) => self.r[n] = self.r[n].wrapping_sub(1), // DEC
+            (0x3, _) => {
+                let taken = self.condition(n & 0x7) != (n & 0x8 != 0);
+                self.short_branch(memory, taken);
+            }
+            (0x4, _) => {
+                // LDA
+                self.d = memory.read_u8(self.r[n]);
+                self.r[n] = self.r[n].wrapping_add(1);
+            }
+            (0x5, _) => memory.write_u8(self.r[n], self.d), // STR
+            (0x6, 0..=7) => self.r[x] = self.r[x].wrapping_add(1), // IRX, and OUT putting M(R(X)) on the bus for nobody
+            (0x6, 8) => {} // not an instruction of the 1802
+            (0x6, _) => {
+                // INP reads 0 from the bus into memory and D
+                self.d = 0;
+                memory.write_u8(self.r[x], 0);
+            }
+            (0x7, 0x0 | 0x1) => {
+                // RET and DIS
+                let xp = memory.read_u8(self.r[x]);
+                self.r[x] = self.r[x].wrapping_add(1);
+                (self.x, self.p) = (xp >> 4, xp & 0xF);
+                self.ie = n == 0x0;
+            }
+            (0x7, 0x2) => {
+                // LDXA
+                self.d = memory.read_u8(self.r[x]);
+                self.r[x] = self.r[x].wrapping_add(1);
+            }
+            (0x7, 0x3) => {
+                // STXD
+                memory.write_u8(self.r[x], self.d);
+                self.r[x] = self.r[x].wrapping_sub(1);
+            }
+            (0x7, 0x4) => { let m = memory.read_u8(self.r[x]); self.add(m, self.df) } // ADC
+            (0x7, 0x5) => { let m = memory.read_u8(self.r[x]); self.subtract(m, self.d, self.df) } // SDB
+            (0x7, 0x6) => self.shift_right(self.df), // SHRC
+            (0x7, 0x7) => { let m = memory.read_u8(self.r[x]); self.subtract(self.d, m, self.df) } // SMB
+            (0x7, 0x8) => memory.write_u8(self.r[x], self.t), // SAV
+            (0x7, 0x9) => {
+                // MARK
+                self.t = self.x << 4 | self.p;
+                memory.write_u8(self.r[2], self.t);
+                self.x = self.p;
+                self.r[2] = self.r[2].wrapping_sub(1);
+            }
+            (0x7, 0xA) => self.q = false, // REQ
+            (0x7, 0xB) => self.q = true,  // SEQ
+            (0x7, 0xC) => { let m = self.fetch(memory); self.add(m, self.df) } // ADCI
+            (0x7, 0xD) => { let m = self.fetch(memory); self.subtract(m, self.d, self.df) } // SDBI
+            (0x7, 0xE) => self.shift_left(self.df), // SHLC
+            (0x7, _) => { let m = self.fetch(memory); self.subtract(self.d, m, self.df) } // SMBI
+            (0x8, _) => self.d = self.r[n] as u8,        // GLO
+            (0x9, _) => self.d = (self.r[n] >> 8) as u8, // GHI
+            (0xA, _) => self.r[n] = self.r[n] & 0xFF00 | self.d as u16, // PLO
+            (0xB, _) => self.r[n] = self.r[n] & 0x00FF | (self.d as u16) << 8, // PHI
+            (0xC, _) => self.long_branch_or_skip(memory, n),
+            (0xD, _) => self.p = n as u8, // SEP
+            (0xE, _) => self.x = n as u8, // SEX
+            (0xF, _) => {
+                // the ALU instructions, on M(R(X)) and from F8 up on the immediate byte
+                let m = match n {
+                    0x6 | 0xE => 0,
+                    0x0..=0x7 => memory.read_u8(self.r[x]),
+                    _ => self.fetch(memory),
+                };
+                match n & 0x7 {
+                    0x0 => self.d = m, // LDX, LDI
+                    0x1 => self.d |= m, // OR, ORI
+                    0x2 => self.d &= m, // AND, ANI
+                    0x3 => self.d ^= m, // XOR, XRI
+                    0x4 => self.add(m, false), // ADD, ADI
+                    0x5 => self.subtract(m, self.d, true), // SD, SDI
+                    0x6 if n == 0x6 => self.shift_right(false), // SHR
+                    0x6 => self.shift_left(false), // SHL
+                    _ => self.subtract(self.d, m, true), // SM, SMI
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    // fetch reads the byte at the program counter and moves past it.
+    fn fetch(&mut self, memory: &mut Memory) -> u8 {
+        let p = self.p as usize;
+        let byte = memory.read_u8(self.r[p]);
+        self.r[p] = self.r[p].wrapping_add(1);
+        byte
+    }
+
+    // condition is what the branches and skips test, by the low 3 bits of the opcode: always, Q,
+    // D zero, DF, and the EF1-EF4 flags, which are always off here.
+    fn condition(&self, n: usize) -> bool {
+        match n {
+            0 => true,
+            1 => self.q,
+            2 => self.d == 0,
+            3 => self.df,
+            _ => false,
+        }
+    }
+
+    // short_branch jumps within the page of the program counter to the byte after the opcode.
+    fn short_branch(&mut self, memory: &mut Memory, taken: bool) {
+        let p = self.p as usize;
+        let target = memory.read_u8(self.r[p]);
+        self.r[p] = if taken { self.r[p] & 0xFF00 | target as u16 } else { self.r[p].wrapping_add(1) };
+    }
+
+    // long_branch_or_skip runs the CN opcodes: C0-C3 and C8-CB are long branches to the two bytes
+    // after the opcode, C4 is NOP, and the others skip those two bytes. The conditions are the
+    // short branch ones for the branches, and Q, D zero, DF and IE for the skips.
+    fn long_branch_or_skip(&mut self, memory: &mut Memory, n: usize) {
+        let p = self.p as usize;
+        let negated = n & 0x8 != 0;
+        match n & 0x7 {
+            0x0..=0x3 => {
+                let taken = self.condition(n & 0x3) != negated;
+                let target = memory.read_u16(self.r[p]);
+                self.r[p] = if taken { target } else { self.r[p].wrapping_add(2) };
+            }
+            0x4 if !negated => {} // NOP
+            skip => {
+                // LSNQ, LSNZ, LSNF, LSKP (C8 is "skip always" and C5-C7 are negated) and LSIE,
+                // LSQ, LSZ, LSDF.
+                let taken = match (n, skip) {
+                    (0x8, _) => true,
+                    (_, 0x4) => self.ie,
+                    (_, s) => self.condition(s - 4) == negated,
+                };
+                if taken {
+                    self.r[p] = self.r[p].wrapping_add(2);
+                }
+            }
+        }
+    }
+
+    // add sets D to D + m, with DF as the carry in and out.
+    fn add(&mut self, m: u8, carry: bool) {
+        let sum = self.d as u16 + m as u16 + carry as u16;
+        self.d = sum as u8;
+        self.df = sum > 0xFF;
+    }
+
+    // subtract sets D to a - b, with DF as the inverted borrow in and out, as the 1802 has it.
+    fn subtract(&mut self, a: u8, b: u8, noBorrow: bool) {
+        let difference = a as i16 - b as i16 - !noBorrow as i16;
+        self.d = difference as u8;
+        self.df = difference >= 0;
+    }
+
+    fn shift_right(&mut self, carry: bool) {
+        let out = self.d & 1 != 0;
+        self.d = self.d >> 1 | (carry as u8) << 7;
+        self.df = out;
+    }
+
+    fn shift_left(&mut self, carry: bool) {
+        let out = self.d & 0x80 != 0;
+        self.d = self.d << 1 | carry as u8;
+        self.df = out;
+    }
+}
@@ -0,0 +1,684 @@
+use std::{env, fs};
+use std::ops::Range;
+
+use pixels::wgpu::PresentMode;
+use rust_chip8::emulator::{MachineCode, Region};
+use rust_chip8::env::Watch;
+use rust_chip8::hash::fnv1a;
+use rust_chip8::history;
+use rust_chip8::hooks::{Condition, Hooks};
+use rust_chip8::quirks::Quirks;
+use rust_chip8::render::{rgb, Palette, Rotation};
+use rust_chip8::variant::Variant;
+
+use crate::bundle::Bundle;
+use crate::config::{self, Config};
+use crate::flashes::FLASHES_PER_SECOND;
+use crate::i18n::Messages;
+use crate::input::{Keymap, Players};
+use crate::pacing::{SLOW_MOTION_PERCENT, TIMER_HZ};
+use crate::paths;
+use crate::saves;
+use crate::session::UnknownOpcodePolicy;
+use crate::upscale::Upscaler;
+
+// DEFAULT_ROM is the ROM run without a ROM argument, from ROM_DIR or --rom-dir.
+pub const DEFAULT_ROM: &str = "spaceinvaders.ch8";
+pub const ROM_DIR: &str = "./roms";
+
+pub const USAGE: &str = "Usage: rust-chip8 [options] [rom...]
+       rust-chip8 diff [options] <rom>
+       rust-chip8 batch [options] <directory>
+       rust-chip8 census [options] <directory>
+       rust-chip8 snapshot [options] <directory | rom>
+       rust-chip8 debug [options] <rom>
+       rust-chip8 record [options] <rom>
+       rust-chip8 play [options] <rom>
+       rust-chip8 coverage [options] <rom>
+       rust-chip8 disasm [options] <rom>
+       rust-chip8 roundtrip [options] <rom>
+       rust-chip8 flow [options] <rom>
+       rust-chip8 explore [options] [rom]
+       rust-chip8 repro [options] <report.zip>
+
+Commands:
+  diff               run the ROM headless in lockstep with a plain reference interpreter, or
+                     against a recorded trace file, and report the first instruction after which
+                     registers, timers, memory or the display differ
+  batch              run every ROM in a directory headless for --cycles instructions, in
+                     parallel, and report the ROMs that fail, crash or run unknown opcodes
+  census             run every ROM in a directory like batch and count which instructions,
+                     instructions of other dialects, unknown opcodes and quirk-sensitive
+                     patterns they run, as a table or JSON, to tell what to support next
+  snapshot           run every ROM for --frames frames and compare the final display with
+                     its golden snapshot, to catch changes in what ROMs draw
+  debug              step through the ROM in a terminal debugger, type help there for the
+                     commands
+  record             play the ROM as usual, recording the keys of every frame to a replay
+  play               play a replay of the ROM back, with the variant, quirks, speed and
+                     random seed it was recorded with
+  coverage           run the ROM headless for --frames frames, or with the keys of its replay,
+                     and show which of its bytes were run, read, written or never touched, to
+                     find dead code and tell code from data
+  disasm             disassemble the ROM into an assembler source that assembles back into
+                     it, with the code told from the data by following the jumps and calls
+  roundtrip          disassemble the ROM like disasm, assemble it again and compare the bytes
+  flow               print the control-flow graph of the ROM, its basic blocks and the jumps,
+                     calls and skips between them, for Graphviz
+  explore            type in instructions and watch what they do on a blank machine, or the
+                     ROM, with lessons explaining them an instruction at a time
+  repro              replay a bug report written when a ROM stopped on an error from a moment
+                     before, with the keys pressed then, and tell whether the error happens
+                     again exactly; a report without that runs on from where it stopped for
+                     --frames frames
+
+Options:
+  --frontend <name>  winit (default) for a window, headless to run without one, fbdev for
+                     the Linux console framebuffer, or sdl2 when built with the sdl2 feature
+  --variant <name>   the CHIP8 dialect of the ROM, chip8 (default) or schip
+  --quirks <spec>    quirks to run the ROM with, e.g. \"schip\" or \"vip,clip=off\", defaults
+                     to those of the variant
+  --compare <spec>   run a second core with these quirks side by side and stop at the
+                     first frame where the two displays differ
+  --rotate <deg>     rotate the display 0, 90, 180 or 270 degrees clockwise
+  --rotate-keys      rotate the 2/4/6/8 direction keys along with the display
+  --sticky-keys      keep key presses for at least a frame and until the ROM has read them
+  --ips <n>          instructions per second, defaults to about 1000 for chip8 and 30000 for
+                     schip
+  --slow-motion <percent>
+                     speed of slow-motion, defaults to 10
+  --vsync <mode>     on (default), off or mailbox
+  --background <color>
+                     the color around the display in the window, as RGB hex, defaults to 000000
+  --bezel <file>     draw a PNG around the display in the window, in its transparent area, none
+                     to not draw that of a bundle
+  --monitor <n>      open the window on monitor n, 1 for the first, full screen going there too
+  --fullscreen       open the window full screen, F11 switches between that and a window
+  --always-on-top    keep the window above the others, F9 switches it on and off
+  --opacity <percent>
+                     how opaque the window is, from 10 to 100 (default), on X11 with a
+                     compositing window manager
+  --upscale <name>   smooth the display in the window, none (default), scale2x, scale4x or lcd,
+                     F8 switches between them
+  --timer-hz <hz>    how often the delay and sound timers tick, and so frames run, defaults to
+                     60, 50 for ROMs made for PAL machines
+  --keypad           show a keypad below the display that can be clicked with the mouse
+  --frame-graph      show a graph of the emulation and render time of each frame
+  --latency          measure how long key presses take to be read by the ROM, change the display
+                     and be presented, reported when the window closes
+  --sound-scope      show a graph of the sound timer and the waveform that plays below the display
+  --palette <name>   default, high-contrast (yellow on black) or colorblind (differences in
+                     compare mode in orange rather than red), or the colors of dark pixels and
+                     pixels lit on the first, second and both XO-CHIP planes, e.g.
+                     101820,F2AA4C,4C9BF2,FFFFFF
+  --palette-cycle <frames>
+                     cycle the colors of the lit pixels every that many frames, for demos, 0
+                     (default) for off
+  --flash-limit <n>  flashes of the display a second to blend faster flashing down to, defaults
+                     to 3, off to show every frame as it is
+  --announce         print when the ROM is loaded, paused, resumed, sped up or slowed down and
+                     when the game is over, for screen readers
+  --language <name>  translate the window title and the About box: de, built in, or a language
+                     file like lang/de.toml
+  --game-over <conditions>
+                     when the game is over, e.g. \"mem[0x1F0]==0 or pc==0x2A4\", see below
+  --score <watch>    where the score is kept, e.g. \"bcd[0x2F0..0x2F3]\"
+  --warn-smc         warn when the ROM runs code it has written itself
+  --break-on-smc     pause when it does
+  --unknown-opcodes <policy>
+                     what to do when the ROM runs an opcode the variant doesn't know: ignore,
+                     warn-once (default), pause or halt. They're listed on exit either way
+  --machine-code <mode>
+                     what the 0NNN machine code calls of the COSMAC VIP do: skip (default) as an
+                     unknown opcode, halt, or vip to run them on an emulated CDP1802
+  --history <n>      number of executed instructions to keep for the debugger and print when
+                     the ROM crashes, defaults to 256, 0 for none, at most 1000000
+  --rom-dir <dir>    where the ROM run without a ROM argument is, defaults to ./roms
+  --keymap <name>    (winit, fbdev) positional (default) for the 1234/QWER/ASDF/ZXCV block, or
+                     numpad for the digits on the numeric keypad and A-F on / * - + Enter .
+  --player1 <pairs>, --player2 <pairs>
+                     (winit, sdl2) the controls of the players of a two-player game, like
+                     \"1=w 4=s\" for CHIP8 key 1 on W and 4 on S, or pad:<button> for a gamepad
+                     button (sdl2), e.g. \"c=pad:dpup d=pad:dpdown\", F6 swaps the sides
+  --players <name>   use the player1 and player2 of a [players.<name>] section of the config file
+  --setup            go through the first-run setup of the window again
+  --library          (winit) open the library of the ROMs in --rom-dir, also on F4
+  --note <text>      keep notes about the ROM in the library
+  --best-score <n>   set the best score the library has for the ROM
+  --preset <name>    use the settings of a [preset.<name>] section of the config file
+  --data-dir <dir>   keep the config file, saves, replays and screenshots in one directory
+                     rather than where the platform keeps them, e.g. \"--data-dir .\"
+  --save-preset <name>
+                     save the variant, quirks, speed, palette and key settings as a preset
+  --assign-preset <name>
+                     have the ROM use a preset from now on, by its hash
+  --kiosk            full screen without a cursor, hotkeys or a way out but the admin combo,
+                     restarting the ROM when it crashes
+  --kiosk-admin <combo>
+                     the key combo that exits kiosk mode, defaults to ctrl+shift+f12
+  --attract <seconds> play demos of the ROMs of --playlist after that long without a key
+                     pressed, until a key is pressed
+  --playlist <dir>   the ROMs of the attract mode, defaults to those next to the ROM
+  --inject-input <file>
+                     press and release keys at the frames the file says, see src/inject.rs
+  --hash-frames <file>
+                     write a hash of the display after every frame, one line per frame, for
+                     diffing two builds or quirk settings
+  --persist-range <from>..<to>
+                     memory range that is saved when it changes and restored on the next
+                     start, e.g. 0xE00..0xF00
+  --load-memory <file>@<addr>
+                     load a binary file into memory at an address after the ROM, and again on
+                     every reset, e.g. patch.bin@0x2A0, can be given more than once
+  --dump-memory <file>@<from>..<to>
+                     (headless, fbdev, sdl2) write a memory range to a binary file when the
+                     ROM stops, can be given more than once
+  --frames <n>       (headless, snapshot, coverage, repro) number of frames to run, defaults to 600
+  --hash-region <x,y,w,h>
+                     (headless) print the screen condition matching that part of the final display
+  --cycles <n>       (diff, batch, census) number of instructions to compare or run, defaults to 1000000
+  --trace <file>     (diff) compare against a trace file rather than the reference interpreter
+  --record <file>    (diff) write a trace file of this core instead of comparing
+  --report <file>    (batch, census) also write the results to a JSON file
+  --replay <file>    (record, play, coverage) the replay file, defaults to <rom>.replay in the
+                     replays directory
+  --checksum-every <frames>
+                     (record) how often to record a checksum of the state, defaults to 60,
+                     0 for never
+  --verify           (play) play the replay headless as fast as possible and exit with 1 if
+                     the state differs from one of its checksums
+  --snapshots <dir>  (snapshot) where the snapshots are, defaults to ./snapshots
+  --update-snapshots (snapshot) write the snapshots rather than comparing with them
+  --snapshot-format <format>
+                     (snapshot) text (default) or png, for --update-snapshots
+  --coverage-map <file>
+                     (coverage) also write the map to a file, as a colored image for a .png
+  --traced           (disasm, roundtrip, flow) also run the ROM like coverage does and take
+                     what ran for code
+  --html             (flow) write the graph as a web page of linked blocks instead
+  -h, --help         print this help
+
+Quirk presets: default, vip, schip. Individual quirks: vfreset, shift, loadstore, jump,
+indexoverflow, clip, rowcollisions, halfscroll, and stack=<depth> for the number of nested calls (12 on the VIP).
+
+Watches: mem[addr], mem16[addr], bcd[from..to] (a decimal digit per byte) or a register V0-VF.
+Conditions: <watch> compared to a number with ==, !=, <, <=, > or >=, pc==<addr> for reaching an
+address, or screen[x,y,w,h]==<hash> for a part of the display, see --hash-region.
+
+Options can also be set in the config file rust-chip8.toml, globally or in a [rom.<name>] section
+for a single ROM. Options given on the command line take precedence. It's in ~/.config/rust-chip8
+on Linux, wherever the platform keeps settings elsewhere, or in the working directory if there's
+one there.";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Command {
+    Run,
+    Diff,
+    Batch,
+    Census,
+    Snapshot,
+    Debug,
+    Record,
+    Play,
+    Coverage,
+    Disasm,
+    Roundtrip,
+    Flow,
+    Explore,
+    Repro,
+}
+
+// Options holds everything that can be set from the command line.
+#[derive(Clone)]
+pub struct Options {
+    pub command: Command,
+    pub frontend: String,
+    pub romPath: String,
+    pub tabs: Vec<String>, // the ROMs after the first, which the window opens in tabs
+    pub variant: Variant,
+    pub quirks: Quirks,
+    pub compare: Option<Quirks>,
+    pub rotation: Rotation,
+    pub rotateKeys: bool,
+    pub stickyKeys: bool,
+    pub slowMotionPercent: u32,
+    pub timerHz: u32,
+    pub instructionsPerFrame: u32, // from --ips, or the default of the variant
+    pub presentMode: PresentMode,
+    pub upscaler: Upscaler,
+    pub monitor: Option<usize>, // from 1
+    pub fullscreen: bool,
+    pub alwaysOnTop: bool,
+    pub opacity: u32, // percent
+    pub background: [u8; 4],
+    pub bezel: Option<String>, // the file, "none" for none, or None for that of a bundle if any
+    pub keypad: bool,
+    pub frameGraph: bool,
+    pub latency: bool,
+    pub soundScope: bool,
+    pub palette: Palette,
+    pub flashLimit: Option<u32>, // flashes per second, None for off
+    pub paletteCycle: Option<u32>, // frames per step of the palette, None for off
+    pub announce: bool,
+    pub messages: Messages, // from --language, English by default
+    pub gameOver: Vec<Condition>,
+    pub score: Option<Watch>,
+    pub warnSmc: bool,
+    pub breakOnSmc: bool,
+    pub unknownOpcodes: UnknownOpcodePolicy,
+    pub machineCode: MachineCode,
+    pub history: usize,
+    pub persistRange: Option<Range<usize>>,
+    pub loadMemory: Vec<(String, usize)>,        // file and address
+    pub dumpMemory: Vec<(String, Range<usize>)>, // file and range
+    pub injectInput: Option<String>,
+    pub hashFrames: Option<String>,
+    pub romDir: String,
+    pub keymap: String, // of input::KEYMAP_NAMES
+    pub players: Players,
+    pub playersName: Option<String>, // of --players, which parseWithConfig replaced by the players
+    pub setup: bool,
+    pub library: bool,
+    pub note: Option<String>,
+    pub bestScore: Option<i64>,
+    pub preset: Option<String>,
+    pub savePreset: Option<String>,
+    pub assignPreset: Option<String>,
+    pub kiosk: bool,
+    pub kioskAdmin: String,
+    pub attract: Option<u64>, // seconds
+    pub playlist: Option<String>,
+    pub frames: u64,
+    pub hashRegion: Option<Region>,
+    pub cycles: u64,
+    pub trace: Option<String>,
+    pub record: Option<String>,
+    pub report: Option<String>,
+    pub replay: Option<String>,
+    pub checksumEvery: u64,
+    pub verify: bool,
+    pub snapshotDir: String,
+    pub updateSnapshots: bool,
+    pub snapshotFormat: String,
+    pub coverageMap: Option<String>,
+    pub traced: bool,
+    pub html: bool,
+}
+
+// parseArgs parses the command line arguments, not including the program name.
+pub fn parseArgs(args: impl IntoIterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options {
+        command: Command::Run,
+        frontend: "winit".to_string(),
+        romPath: String::new(),
+        tabs: Vec::new(),
+        variant: Variant::Chip8,
+        quirks: Quirks::default(),
+        compare: None,
+        rotation: Rotation::R0,
+        rotateKeys: false,
+        stickyKeys: false,
+        slowMotionPercent: SLOW_MOTION_PERCENT,
+        timerHz: TIMER_HZ,
+        instructionsPerFrame: 0,
+        presentMode: PresentMode::Fifo,
+        upscaler: Upscaler::Nearest,
+        monitor: None,
+        fullscreen: false,
+        alwaysOnTop: false,
+        opacity: 100,
+        background: [0, 0, 0, 0xff],
+        bezel: None,
+        keypad: false,
+        frameGraph: false,
+        latency: false,
+        soundScope: false,
+        palette: Palette::DEFAULT,
+        flashLimit: Some(FLASHES_PER_SECOND),
+        paletteCycle: None,
+        announce: false,
+        messages: Messages::english(),
+        gameOver: Vec::new(),
+        score: None,
+        warnSmc: false,
+        breakOnSmc: false,
+        unknownOpcodes: UnknownOpcodePolicy::WarnOnce,
+        machineCode: MachineCode::Skip,
+        history: 256,
+        persistRange: None,
+        loadMemory: Vec::new(),
+        dumpMemory: Vec::new(),
+        injectInput: None,
+        hashFrames: None,
+        romDir: ROM_DIR.to_string(),
+        keymap: "positional".to_string(),
+        players: Players::default(),
+        playersName: None,
+        setup: false,
+        library: false,
+        note: None,
+        bestScore: None,
+        preset: None,
+        savePreset: None,
+        assignPreset: None,
+        kiosk: false,
+        kioskAdmin: "ctrl+shift+f12".to_string(),
+        attract: None,
+        playlist: None,
+        frames: 600,
+        hashRegion: None,
+        cycles: 1_000_000,
+        trace: None,
+        record: None,
+        report: None,
+        replay: None,
+        checksumEvery: 60,
+        verify: false,
+        snapshotDir: "./snapshots".to_string(),
+        updateSnapshots: false,
+        snapshotFormat: "text".to_string(),
+        coverageMap: None,
+        traced: false,
+        html: false,
+    };
+
+    let mut quirks = None;
+    let mut ips: Option<u32> = None;
+    let (mut player1, mut player2) = (String::new(), String::new());
+    let mut roms = Vec::new();
+    let mut args = args.into_iter().peekable();
+    match args.peek().map(|a| a.as_str()) {
+        Some("diff") => options.command = Command::Diff,
+        Some("batch") => options.command = Command::Batch,
+        Some("census") => options.command = Command::Census,
+        Some("snapshot") => options.command = Command::Snapshot,
+        Some("debug") => options.command = Command::Debug,
+        Some("record") => options.command = Command::Record,
+        Some("play") => options.command = Command::Play,
+        Some("coverage") => options.command = Command::Coverage,
+        Some("disasm") => options.command = Command::Disasm,
+        Some("roundtrip") => options.command = Command::Roundtrip,
+        Some("flow") => options.command = Command::Flow,
+        Some("explore") => options.command = Command::Explore,
+        Some("repro") => options.command = Command::Repro,
+        _ => {}
+    }
+    if options.command != Command::Run {
+        args.next();
+    }
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--frontend" => options.frontend = value(&mut args, &arg)?,
+            "--variant" => options.variant = Variant::parse(&value(&mut args, &arg)?)?,
+            "--quirks" => quirks = Some(Quirks::parse(&value(&mut args, &arg)?)?),
+            "--compare" => options.compare = Some(Quirks::parse(&value(&mut args, &arg)?)?),
+            "--rotate" => options.rotation = Rotation::parse(&value(&mut args, &arg)?)?,
+            "--rotate-keys" => options.rotateKeys = true,
+            "--sticky-keys" => options.stickyKeys = true,
+            "--slow-motion" => options.slowMotionPercent = number(&value(&mut args, &arg)?, &arg)?,
+            "--ips" => ips = Some(number(&value(&mut args, &arg)?, &arg)?),
+            "--timer-hz" => options.timerHz = match number(&value(&mut args, &arg)?, &arg)? {
+                hz @ 1..=1000 => hz,
+                hz => return Err(format!("--timer-hz must be from 1 to 1000, got {}", hz)),
+            },
+            "--vsync" => options.presentMode = presentMode(&value(&mut args, &arg)?)?,
+            "--background" => options.background = rgb(&value(&mut args, &arg)?)?,
+            "--bezel" => options.bezel = Some(value(&mut args, &arg)?),
+            "--monitor" => options.monitor = match number(&value(&mut args, &arg)?, &arg)? {
+                0 => return Err("--monitor counts from 1".to_string()),
+                n => Some(n),
+            },
+            "--fullscreen" => options.fullscreen = true,
+            "--always-on-top" => options.alwaysOnTop = true,
+            "--opacity" => options.opacity = match number(&value(&mut args, &arg)?, &arg)? {
+                percent @ 10..=100 => percent,
+                _ => return Err("--opacity must be from 10 to 100".to_string()),
+            },
+            "--upscale" => options.upscaler = Upscaler::parse(&value(&mut args, &arg)?)?,
+            "--keypad" => options.keypad = true,
+            "--frame-graph" => options.frameGraph = true,
+            "--latency" => options.latency = true,
+            "--sound-scope" => options.soundScope = true,
+            "--palette" => options.palette = Palette::parse(&value(&mut args, &arg)?)?,
+            "--flash-limit" => options.flashLimit = match value(&mut args, &arg)?.as_str() {
+                "off" => None,
+                limit => match number(limit, &arg)? {
+                    0 => return Err("--flash-limit must be at least 1, or off".to_string()),
+                    limit => Some(limit),
+                },
+            },
+            "--palette-cycle" => options.paletteCycle = match number(&value(&mut args, &arg)?, &arg)? {
+                0 => None,
+                frames => Some(frames),
+            },
+            "--announce" => options.announce = true,
+            "--language" => options.messages = Messages::load(&value(&mut args, &arg)?)?,
+            "--game-over" => options.gameOver = Hooks::parseConditions(&value(&mut args, &arg)?)?,
+            "--score" => options.score = Some(Watch::parse(&value(&mut args, &arg)?)?),
+            "--warn-smc" => options.warnSmc = true,
+            "--break-on-smc" => options.breakOnSmc = true,
+            "--unknown-opcodes" => options.unknownOpcodes = UnknownOpcodePolicy::parse(&value(&mut args, &arg)?)?,
+            "--machine-code" => options.machineCode = MachineCode::parse(&value(&mut args, &arg)?)?,
+            "--history" => options.history = match number(&value(&mut args, &arg)?, &arg)? {
+                n @ 0..=history::MAX_CAPACITY => n,
+                n => return Err(format!("--history must be at most {}, got {}", history::MAX_CAPACITY, n)),
+            },
+            "--persist-range" => options.persistRange = Some(memoryRange(&value(&mut args, &arg)?)?),
+            "--load-memory" => {
+                let value = value(&mut args, &arg)?;
+                let (path, address) = value.rsplit_once('@').ok_or(format!("invalid --load-memory '{}', expected <file>@<addr>", value))?;
+                options.loadMemory.push((path.to_string(), memoryAddress(address).ok_or(format!("invalid address '{}'", address))?));
+            }
+            "--dump-memory" => {
+                let value = value(&mut args, &arg)?;
+                let (path, range) = value.rsplit_once('@').ok_or(format!("invalid --dump-memory '{}', expected <file>@<from>..<to>", value))?;
+                options.dumpMemory.push((path.to_string(), memoryRange(range)?));
+            }
+            "--inject-input" => options.injectInput = Some(value(&mut args, &arg)?),
+            "--hash-frames" => options.hashFrames = Some(value(&mut args, &arg)?),
+            "--rom-dir" => options.romDir = value(&mut args, &arg)?,
+            "--keymap" => {
+                options.keymap = value(&mut args, &arg)?;
+                Keymap::named(&options.keymap)?;
+            }
+            "--player1" => player1 = value(&mut args, &arg)?,
+            "--player2" => player2 = value(&mut args, &arg)?,
+            "--players" => options.playersName = Some(value(&mut args, &arg)?),
+            "--setup" => options.setup = true,
+            "--library" => options.library = true,
+            "--note" => options.note = Some(value(&mut args, &arg)?),
+            "--best-score" => options.bestScore = Some(number(&value(&mut args, &arg)?, &arg)?),
+            "--preset" => options.preset = Some(value(&mut args, &arg)?),
+            // taken before the config file is read, see dataDir.
+            "--data-dir" => { value(&mut args, &arg)?; }
+            "--save-preset" => options.savePreset = Some(value(&mut args, &arg)?),
+            "--assign-preset" => options.assignPreset = Some(value(&mut args, &arg)?),
+            "--kiosk" => options.kiosk = true,
+            "--kiosk-admin" => options.kioskAdmin = value(&mut args, &arg)?,
+            "--attract" => options.attract = match number(&value(&mut args, &arg)?, &arg)? {
+                0 => return Err("--attract must be at least 1 second".to_string()),
+                seconds => Some(seconds),
+            },
+            "--playlist" => options.playlist = Some(value(&mut args, &arg)?),
+            "--frames" => options.frames = number(&value(&mut args, &arg)?, &arg)?,
+            "--hash-region" => options.hashRegion = Some(region(&value(&mut args, &arg)?)?),
+            "--cycles" => options.cycles = number(&value(&mut args, &arg)?, &arg)?,
+            "--trace" => options.trace = Some(value(&mut args, &arg)?),
+            "--record" => options.record = Some(value(&mut args, &arg)?),
+            "--report" => options.report = Some(value(&mut args, &arg)?),
+            "--replay" => options.replay = Some(value(&mut args, &arg)?),
+            "--checksum-every" => options.checksumEvery = number(&value(&mut args, &arg)?, &arg)?,
+            "--verify" => options.verify = true,
+            "--snapshots" => options.snapshotDir = value(&mut args, &arg)?,
+            "--update-snapshots" => options.updateSnapshots = true,
+            "--snapshot-format" => options.snapshotFormat = match value(&mut args, &arg)?.as_str() {
+                format @ ("text" | "png") => format.to_string(),
+                format => return Err(format!("invalid snapshot format '{}', expected text or png", format)),
+            },
+            "--coverage-map" => options.coverageMap = Some(value(&mut args, &arg)?),
+            "--traced" => options.traced = true,
+            "--html" => options.html = true,
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            _ if arg.starts_with('-') => return Err(format!("unknown option {}\n\n{}", arg, USAGE)),
+            _ => roms.push(arg),
+        }
+    }
+    if roms.is_empty() && options.command == Command::Explore {
+        // explore starts on a blank machine without a ROM, rather than with the default one.
+    } else if roms.is_empty() {
+        options.romPath = format!("{}/{}", options.romDir.trim_end_matches('/'), DEFAULT_ROM);
+    } else {
+        options.romPath = roms.remove(0);
+        options.tabs = roms;
+    }
+    options.quirks = quirks.unwrap_or(options.variant.quirks());
+    options.players = Players::parse(&player1, &player2)?;
+    options.instructionsPerFrame = match ips {
+        Some(ips) => (ips / options.timerHz).max(1),
+        None => options.variant.instructions_per_frame(),
+    };
+    Ok(options)
+}
+
+// dataDir returns the --data-dir of the command line, which decides where the config file is and
+// so is needed before it's read.
+pub fn dataDir(args: &[String]) -> Option<&str> {
+    args.iter().position(|arg| arg == "--data-dir").and_then(|i| args.get(i + 1)).map(|dir| dir.as_str())
+}
+
+// parseWithConfig parses the command line arguments on top of the config file: first the global
+// options, then those of the ROM's section and last the command line itself.
+pub fn parseWithConfig(args: Vec<String>, config: &Config) -> Result<Options, String> {
+    let cli = parseArgs(args.clone())?;
+    let mut args = args.into_iter().peekable();
+
+    let mut combined = Vec::new();
+    if cli.command != Command::Run {
+        combined.extend(args.next());
+    }
+    // the global keys, then those of the ROM's name and of its hash, each followed by the preset
+    // it picks if any, so what a section says itself beats the presets of the sections before it.
+    // A --preset comes last, just before the command line. The options of a bundle's manifest go
+    // right after the global keys.
+    let bundle = Bundle::open(&cli.romPath).ok().flatten();
+    let mut sections = vec![String::new(), format!("rom.{}", saves::romName(&cli.romPath))];
+    let rom = bundle.as_ref().map(|b| b.rom.clone()).or_else(|| fs::read(&cli.romPath).ok());
+    sections.extend(rom.map(|data| format!("hash.{:016X}", fnv1a(&data))));
+    for (i, section) in sections.iter().enumerate() {
+        combined.extend(config.args(section));
+        if let (0, Some(bundle)) = (i, &bundle) {
+            combined.extend(bundle.options.iter().cloned());
+        }
+        if let Some(preset) = config.value(section, "preset") {
+            combined.extend(presetArgs(config, preset)?);
+        }
+    }
+    if let Some(preset) = &cli.preset {
+        combined.extend(presetArgs(config, preset)?);
+    }
+    combined.extend(args);
+    parseArgs(playersArgs(config, combined)?)
+}
+
+// playersArgs replaces every --players <name> of the arguments with the keys of the
+// [players.<name>] section of the config file, its player1 and player2.
+fn playersArgs(config: &Config, args: Vec<String>) -> Result<Vec<String>, String> {
+    let mut expanded = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg != "--players" {
+            expanded.push(arg);
+            continue;
+        }
+        let name = value(&mut args, &arg)?;
+        let section = format!("players.{}", name);
+        if !config.has_section(&section) {
+            return Err(format!("unknown players '{}', the config file has no [{}] section", name, section));
+        }
+        expanded.extend(config.args(&section));
+    }
+    Ok(expanded)
+}
+
+// forRom returns the options to run another ROM with: the command line with its ROMs swapped for
+// that one and the config file read again, so the sections of the ROM and the preset they pick
+// apply to it like they would have on the command line.
+pub fn forRom(options: &Options, romPath: &str) -> Result<Options, String> {
+    let roms: Vec<&String> = options.tabs.iter().chain([&options.romPath]).collect();
+    let mut args: Vec<String> = env::args().skip(1).filter(|arg| !roms.contains(&arg)).collect();
+    args.push(romPath.to_string());
+    parseWithConfig(args, &config::load(paths::config())?)
+}
+
+// presetArgs returns the keys of a [preset.<name>] section as command line arguments.
+fn presetArgs(config: &Config, name: &str) -> Result<Vec<String>, String> {
+    let section = format!("preset.{}", name);
+    if !config.has_section(&section) {
+        return Err(format!("unknown preset '{}', the config file has no [{}] section", name, section));
+    }
+    Ok(config.args(&section))
+}
+
+// presetEntries returns the settings a preset saved with --save-preset holds: the variant, the
+// quirks spelled out, the speed, the palette and how the keys behave.
+pub fn presetEntries(options: &Options) -> Vec<(&'static str, String)> {
+    vec![
+        ("variant", options.variant.spec().to_string()),
+        ("quirks", options.quirks.spec()),
+        ("ips", (options.instructionsPerFrame * options.timerHz).to_string()),
+        ("timer_hz", options.timerHz.to_string()),
+        ("palette", options.palette.spec()),
+        ("sticky_keys", options.stickyKeys.to_string()),
+        ("rotate_keys", options.rotateKeys.to_string()),
+        ("keymap", options.keymap.clone()),
+    ]
+}
+
+// value returns the argument following the option currently being parsed.
+fn value(args: &mut impl Iterator<Item = String>, option: &str) -> Result<String, String> {
+    args.next().ok_or(format!("{} requires a value", option))
+}
+
+// presentMode parses the value of --vsync.
+fn presentMode(value: &str) -> Result<PresentMode, String> {
+    match value {
+        "on" => Ok(PresentMode::Fifo),
+        "off" => Ok(PresentMode::AutoNoVsync),
+        "mailbox" => Ok(PresentMode::Mailbox),
+        _ => Err(format!("invalid vsync mode '{}', expected on, off or mailbox", value)),
+    }
+}
+
+// memoryRange parses a range of memory addresses such as 0xE00..0xF00, the end being exclusive.
+fn memoryRange(value: &str) -> Result<Range<usize>, String> {
+    let invalid = || format!("invalid memory range '{}', expected e.g. 0xE00..0xF00", value);
+    let (from, to) = value.split_once("..").ok_or_else(invalid)?;
+    let range = memoryAddress(from).ok_or_else(invalid)?..memoryAddress(to).ok_or_else(invalid)?;
+    if range.is_empty() || range.end > 4096 {
+        return Err(format!("memory range '{}' must be non-empty and within 0x000..0x1000", value));
+    }
+    Ok(range)
+}
+
+// memoryAddress parses a decimal or 0x hexadecimal address.
+fn memoryAddress(value: &str) -> Option<usize> {
+    match value.trim().strip_prefix("0x").or(value.trim().strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => value.trim().parse().ok(),
+    }
+}
+
+// region parses a part of the display given as x,y,width,height.
+fn region(value: &str) -> Result<Region, String> {
+    let parts: Vec<usize> = value.split(',').map(|p| p.trim().parse().ok()).collect::<Option<_>>()
+        .ok_or(format!("invalid region '{}', expected x,y,width,height", value))?;
+    match parts[..] {
+        [x, y, width, height] if width > 0 && height > 0 => Ok(Region { x, y, width, height }),
+        _ => Err(format!("invalid region '{}', expected x,y,width,height", value)),
+    }
+}
+
+// number parses the value of a numeric option.
+fn number<T: std::str::FromStr>(value: &str, option: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("{} expects a number, got '{}'", option, value))
+}
@@ -0,0 +1,13 @@
+// The subcommands of the rust-chip8 binary, besides the default of opening a window and playing.
+pub mod batch;
+pub mod census;
+pub mod coverage;
+pub mod debug;
+pub mod diff;
+pub mod disasm;
+pub mod explore;
+pub mod flow;
+pub mod play;
+pub mod repro;
+pub mod roundtrip;
+pub mod snapshot;
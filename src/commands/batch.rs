@@ -0,0 +1,194 @@
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use rayon::prelude::*;
+use rust_chip8::difftest::displayHash;
+use rust_chip8::emulator::Emulator;
+use rust_chip8::error::Error;
+
+use crate::cli::Options;
+
+// Outcome is how a ROM's run ended.
+enum Outcome {
+    Completed,         // ran all the instructions
+    Unloadable(String), // the ROM couldn't be read or loaded
+    Stopped(String),   // an instruction failed, e.g. a stack overflow
+    Panicked(String),  // the emulator crashed, which is a bug in the emulator
+}
+
+// RomReport is what batch finds out about a single ROM.
+struct RomReport {
+    path: PathBuf,
+    outcome: Outcome,
+    instructions: u64,
+    unknownOpcodes: Vec<u16>,
+    screenHash: Option<u64>, // of the final display, to tell when a change alters what a ROM draws
+    millis: u128,
+}
+
+// run implements "rust-chip8 batch", which runs every ROM in a directory headless for a number of
+// instructions, in parallel, and reports how each one fared. The exit code is 1 if any ROM
+// stopped on an error or crashed the emulator.
+pub fn run(options: &Options) -> i32 {
+    let roms = match romFiles(Path::new(&options.romPath)) {
+        Ok(roms) => roms,
+        Err(e) => {
+            eprintln!("Unable to list {}: {}", options.romPath, e);
+            return 2;
+        }
+    };
+
+    // panics are caught and reported per ROM, the default hook would only garble the output.
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let reports: Vec<RomReport> = roms.into_par_iter().map(|path| runRom(path, options)).collect();
+    panic::set_hook(hook);
+
+    let mut failed = 0;
+    for report in &reports {
+        let (status, message) = match &report.outcome {
+            Outcome::Completed => ("ok", String::new()),
+            Outcome::Unloadable(e) => ("unloadable", e.clone()),
+            Outcome::Stopped(e) => ("stopped", e.clone()),
+            Outcome::Panicked(e) => ("panicked", e.clone()),
+        };
+        if matches!(report.outcome, Outcome::Stopped(_) | Outcome::Panicked(_)) {
+            failed += 1;
+        }
+        let unknown = if report.unknownOpcodes.is_empty() {
+            String::new()
+        } else {
+            format!(" ({} unknown opcodes)", report.unknownOpcodes.len())
+        };
+        println!("{:<10} {}{} {}", status, report.path.display(), unknown, message);
+    }
+    println!("{} ROMs, {} stopped or crashed", reports.len(), failed);
+
+    if let Some(path) = &options.report {
+        if let Err(e) = fs::write(path, json(&reports, options.cycles)) {
+            eprintln!("Unable to write {}: {}", path, e);
+            return 2;
+        }
+        println!("Wrote {}", path);
+    }
+    if failed > 0 { 1 } else { 0 }
+}
+
+// romFiles lists the files in a directory and its subdirectories, sorted, or just the one file
+// given.
+pub fn romFiles(path: &Path) -> std::io::Result<Vec<PathBuf>> {
+    if !path.is_dir() {
+        fs::metadata(path)?;
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let mut files = Vec::new();
+    for entry in fs::read_dir(path)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            files.extend(romFiles(&path)?);
+        } else {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+// runRom runs a ROM for options.cycles instructions without pressing any keys, ticking the timers
+// every instructionsPerFrame instructions like tick_frame does.
+fn runRom(path: PathBuf, options: &Options) -> RomReport {
+    let start = Instant::now();
+    let mut report = RomReport {
+        path,
+        outcome: Outcome::Completed,
+        instructions: 0,
+        unknownOpcodes: Vec::new(),
+        screenHash: None,
+        millis: 0,
+    };
+
+    let mut emul = Emulator::new();
+    let loaded = fs::read(&report.path).map_err(|e| e.to_string())
+        .and_then(|data| emul.load_rom(&data).map_err(|e| e.to_string()));
+    if let Err(e) = loaded {
+        report.outcome = Outcome::Unloadable(e);
+        return report;
+    }
+    emul.quirks = options.quirks;
+    emul.variant = options.variant;
+    emul.instructionsPerFrame = options.instructionsPerFrame;
+
+    let instructions = &mut report.instructions;
+    let result = panic::catch_unwind(AssertUnwindSafe(|| -> Result<(), Error> {
+        while *instructions < options.cycles {
+            emul.run()?;
+            *instructions += 1;
+            if instructions.is_multiple_of(emul.instructionsPerFrame as u64) {
+                emul.tick_timers();
+            }
+        }
+        Ok(())
+    }));
+    report.outcome = match result {
+        Ok(Ok(())) => Outcome::Completed,
+        Ok(Err(e)) => Outcome::Stopped(e.to_string()),
+        Err(payload) => Outcome::Panicked(panicMessage(payload.as_ref())),
+    };
+    report.unknownOpcodes = emul.unknown_opcodes().iter().map(|u| u.opcode).collect();
+    report.screenHash = Some(displayHash(&emul));
+    report.millis = start.elapsed().as_millis();
+    report
+}
+
+// panicMessage returns what a caught panic said, for the usual payloads of panic! and friends.
+pub fn panicMessage(payload: &(dyn std::any::Any + Send)) -> String {
+    match payload.downcast_ref::<&str>() {
+        Some(message) => message.to_string(),
+        None => payload.downcast_ref::<String>().cloned().unwrap_or_else(|| "unknown panic".to_string()),
+    }
+}
+
+// json formats the reports as a JSON document.
+fn json(reports: &[RomReport], cycles: u64) -> String {
+    let mut out = format!("{{\n  \"cycles\": {},\n  \"roms\": [", cycles);
+    for (i, report) in reports.iter().enumerate() {
+        let (status, message) = match &report.outcome {
+            Outcome::Completed => ("ok", None),
+            Outcome::Unloadable(e) => ("unloadable", Some(e)),
+            Outcome::Stopped(e) => ("stopped", Some(e)),
+            Outcome::Panicked(e) => ("panicked", Some(e)),
+        };
+        let unknown: Vec<String> = report.unknownOpcodes.iter().map(|o| format!("\"{:04X}\"", o)).collect();
+        out.push_str(if i == 0 { "\n" } else { ",\n" });
+        out.push_str(&format!(
+            "    {{\"path\": {}, \"status\": \"{}\", \"message\": {}, \"instructions\": {}, \"unknownOpcodes\": [{}], \"screenHash\": {}, \"millis\": {}}}",
+            string(&report.path.to_string_lossy()),
+            status,
+            message.map(|m| string(m)).unwrap_or_else(|| "null".to_string()),
+            report.instructions,
+            unknown.join(", "),
+            report.screenHash.map(|h| format!("\"{:016X}\"", h)).unwrap_or_else(|| "null".to_string()),
+            report.millis,
+        ));
+    }
+    out.push_str("\n  ]\n}\n");
+    out
+}
+
+// string quotes a string for JSON.
+pub fn string(s: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
@@ -0,0 +1,242 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+
+use rayon::prelude::*;
+use rust_chip8::disasm::{self, REFERENCE};
+use rust_chip8::emulator::Emulator;
+use rust_chip8::error::Error;
+use rust_chip8::variant::Variant;
+
+use crate::bundle;
+use crate::cli::{self, Options};
+use crate::commands::batch::{panicMessage, romFiles, string};
+
+// EXTENSIONS are the instructions of other CHIP8 dialects that this emulator doesn't have, to tell
+// which of the opcodes it doesn't know ROMs would need, the first matching pattern counting.
+const EXTENSIONS: [(&str, &str); 14] = [
+    ("00DN", "XO-CHIP scroll up"),
+    ("5XY2", "XO-CHIP save VX-VY"),
+    ("5XY3", "XO-CHIP load VX-VY"),
+    ("F000", "XO-CHIP long I"),
+    ("FN01", "XO-CHIP plane"),
+    ("F002", "XO-CHIP audio"),
+    ("FX3A", "XO-CHIP pitch"),
+    ("0010", "MegaChip off"),
+    ("0011", "MegaChip on"),
+    ("01NN", "MegaChip long I"),
+    ("02NN", "MegaChip palette"),
+    ("03NN", "MegaChip sprite width"),
+    ("04NN", "MegaChip sprite height"),
+    ("5XY1", "CHIP-8X add"),
+];
+
+// QUIRKS are the quirks a census looks for ROMs depending on, see Census::note.
+const QUIRKS: [&str; 8] = ["vfreset", "shift", "loadstore", "jump", "indexoverflow", "clip", "halfscroll", "stack"];
+
+// VIP_STACK is the shallowest stack of the quirk presets, that of the COSMAC VIP.
+const VIP_STACK: usize = 12;
+
+// Census is what a ROM was seen to run.
+struct Census {
+    path: PathBuf,
+    error: Option<String>, // why it stopped early, if it did
+    instructions: BTreeMap<&'static str, u64>, // executions per pattern of the reference
+    extensions: BTreeMap<&'static str, u64>,   // the same for EXTENSIONS
+    unknown: BTreeMap<u16, u64>,               // opcodes that are neither
+    quirks: BTreeSet<&'static str>,            // of QUIRKS
+}
+
+impl Census {
+    // note counts an instruction about to run, and the quirks it ran differently under.
+    fn note(&mut self, emul: &Emulator, word: u16) {
+        let (x, y, n) = ((word >> 8 & 0xF) as usize, (word >> 4 & 0xF) as usize, (word & 0xF) as usize);
+        match disasm::reference(word, Variant::Schip) {
+            Some(doc) => *self.instructions.entry(doc.pattern).or_default() += 1,
+            None => match EXTENSIONS.iter().find(|(pattern, _)| matches(pattern, word)) {
+                Some((pattern, _)) => *self.extensions.entry(pattern).or_default() += 1,
+                None => *self.unknown.entry(word).or_default() += 1,
+            },
+        }
+        let (width, height) = emul.resolution();
+        let quirk = match (word >> 12, word & 0xFF) {
+            (0x8, _) if (1..=3).contains(&n) => Some("vfreset"),
+            (0x8, _) if (n == 0x6 || n == 0xE) && x != y => Some("shift"),
+            (0xF, 0x55) | (0xF, 0x65) => Some("loadstore"),
+            (0xB, _) if x != 0 => Some("jump"),
+            (0xF, 0x1E) if emul.I as usize + emul.registers[x] as usize > 0xFFF => Some("indexoverflow"),
+            (0xD, _) => {
+                let (size, rows) = if n == 0 && emul.variant == Variant::Schip { (16, 16) } else { (8, n) };
+                let (left, top) = (emul.registers[x] as usize % width, emul.registers[y] as usize % height);
+                (left + size > width || top + rows > height).then_some("clip")
+            }
+            (0x0, 0xC0..=0xCF) | (0x0, 0xFB) | (0x0, 0xFC) if word < 0x0100 && !emul.hires => Some("halfscroll"),
+            (0x2, _) if emul.stack.len() >= VIP_STACK => Some("stack"),
+            _ => None,
+        };
+        self.quirks.extend(quirk);
+    }
+}
+
+// matches returns whether an opcode fits a pattern of EXTENSIONS, with X, Y and N for any digit.
+fn matches(pattern: &str, word: u16) -> bool {
+    pattern.chars().enumerate().all(|(i, c)| matches!(c, 'X' | 'Y' | 'N') || c.to_digit(16) == Some((word >> (12 - 4 * i) & 0xF) as u32))
+}
+
+// run implements "rust-chip8 census": every ROM in a directory runs headless for --cycles
+// instructions like batch runs them, but with the settings the config file has for each, and the
+// instructions they ran are counted up: which instructions how many ROMs use, the instructions of
+// other dialects they'd need, the opcodes nothing knows and the quirks that change how they run.
+// It's all offline, a table for deciding what to support next, and --report writes it as JSON.
+pub fn run(options: &Options) -> i32 {
+    let roms = match romFiles(Path::new(&options.romPath)) {
+        Ok(roms) => roms,
+        Err(e) => {
+            eprintln!("Unable to list {}: {}", options.romPath, e);
+            return 2;
+        }
+    };
+    // panics are caught per ROM, like batch does.
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let censuses: Vec<Census> = roms.into_par_iter().filter_map(|path| census(path, options)).collect();
+    panic::set_hook(hook);
+
+    for line in table(&censuses, options.cycles) {
+        println!("{}", line);
+    }
+    if let Some(path) = &options.report {
+        if let Err(e) = fs::write(path, json(&censuses, options.cycles)) {
+            eprintln!("Unable to write {}: {}", path, e);
+            return 2;
+        }
+        println!("Wrote {}", path);
+    }
+    0
+}
+
+// census runs a ROM for a census, or returns None for a file that isn't a ROM that loads.
+fn census(path: PathBuf, options: &Options) -> Option<Census> {
+    let romOptions = cli::forRom(options, &path.to_string_lossy()).ok();
+    let options = romOptions.as_ref().unwrap_or(options);
+    let mut emul = Emulator::new();
+    emul.load_rom(&bundle::readRom(&path.to_string_lossy()).ok()?).ok()?;
+    emul.quirks = options.quirks;
+    emul.variant = options.variant;
+    emul.instructionsPerFrame = options.instructionsPerFrame;
+    emul.machineCode = options.machineCode;
+
+    let mut census = Census { path, error: None, instructions: BTreeMap::new(), extensions: BTreeMap::new(), unknown: BTreeMap::new(), quirks: BTreeSet::new() };
+    let result = panic::catch_unwind(AssertUnwindSafe(|| -> Result<(), Error> {
+        for cycle in 1..=options.cycles {
+            let pc = emul.pc as usize;
+            let word = emul.memory.bytes().get(pc..pc + 2).map(|w| u16::from_be_bytes([w[0], w[1]])).unwrap_or(0);
+            census.note(&emul, word);
+            emul.run()?;
+            if cycle.is_multiple_of(emul.instructionsPerFrame as u64) {
+                emul.tick_timers();
+            }
+        }
+        Ok(())
+    }));
+    census.error = match result {
+        Ok(Ok(())) => None,
+        Ok(Err(e)) => Some(e.to_string()),
+        Err(payload) => Some(format!("crashed: {}", panicMessage(payload.as_ref()))),
+    };
+    Some(census)
+}
+
+// Tally is how many ROMs ran something and how often it ran in all.
+#[derive(Default)]
+struct Tally<'a> {
+    roms: Vec<&'a Path>,
+    executions: u64,
+}
+
+fn tally<'a, K: Ord + Copy + 'a>(censuses: &'a [Census], counts: impl Fn(&'a Census) -> &'a BTreeMap<K, u64>) -> BTreeMap<K, Tally<'a>> {
+    let mut tallies: BTreeMap<K, Tally> = BTreeMap::new();
+    for census in censuses {
+        for (key, executions) in counts(census) {
+            let tally = tallies.entry(*key).or_default();
+            tally.roms.push(&census.path);
+            tally.executions += executions;
+        }
+    }
+    tallies
+}
+
+// names lists the first few ROMs of a tally, for the table.
+fn names(roms: &[&Path]) -> String {
+    let mut names: Vec<String> = roms.iter().take(3).map(|p| p.file_name().unwrap_or_default().to_string_lossy().to_string()).collect();
+    if roms.len() > 3 {
+        names.push(format!("and {} more", roms.len() - 3));
+    }
+    names.join(", ")
+}
+
+// table returns the census as a table per kind, busiest first for what's missing.
+fn table(censuses: &[Census], cycles: u64) -> Vec<String> {
+    let stopped = censuses.iter().filter(|c| c.error.is_some()).count();
+    let mut lines = vec![format!("Census of {} ROMs run for {} instructions each, {} stopped early", censuses.len(), cycles, stopped)];
+
+    lines.push(String::new());
+    lines.push(format!("{:<6}{:<20}{:>6}{:>14}", "", "instruction", "ROMs", "executions"));
+    let instructions = tally(censuses, |c| &c.instructions);
+    for doc in REFERENCE.iter() {
+        let (roms, executions) = instructions.get(doc.pattern).map_or((0, 0), |t| (t.roms.len(), t.executions));
+        let syntax = if doc.schip { format!("{} (schip)", doc.syntax) } else { doc.syntax.to_string() };
+        lines.push(format!("{:<6}{:<20}{:>6}{:>14}", doc.pattern, syntax, roms, executions));
+    }
+
+    let mut extensions: Vec<(&str, Tally)> = tally(censuses, |c| &c.extensions).into_iter().collect();
+    extensions.sort_by_key(|(_, t)| std::cmp::Reverse(t.roms.len()));
+    if !extensions.is_empty() {
+        lines.push(String::new());
+        lines.push("Instructions of other dialects:".to_string());
+        for (pattern, tally) in &extensions {
+            let name = EXTENSIONS.iter().find(|(p, _)| p == pattern).map_or("", |(_, n)| n);
+            lines.push(format!("{:<6}{:<24}{:>6} ROMs  {}", pattern, name, tally.roms.len(), names(&tally.roms)));
+        }
+    }
+
+    let mut unknown: Vec<(u16, Tally)> = tally(censuses, |c| &c.unknown).into_iter().collect();
+    unknown.sort_by_key(|(_, t)| std::cmp::Reverse(t.roms.len()));
+    if !unknown.is_empty() {
+        lines.push(String::new());
+        lines.push(format!("Unknown opcodes, {} of them, the 20 most common:", unknown.len()));
+        for (opcode, tally) in unknown.iter().take(20) {
+            lines.push(format!("{:04X}{:>8} ROMs  {}", opcode, tally.roms.len(), names(&tally.roms)));
+        }
+    }
+
+    lines.push(String::new());
+    lines.push("Quirks the ROMs ran differently under:".to_string());
+    for quirk in QUIRKS {
+        let roms: Vec<&Path> = censuses.iter().filter(|c| c.quirks.contains(quirk)).map(|c| c.path.as_path()).collect();
+        lines.push(format!("{:<16}{:>6} ROMs  {}", quirk, roms.len(), names(&roms)));
+    }
+    lines
+}
+
+// json formats the census as a JSON document, per ROM like batch's report.
+fn json(censuses: &[Census], cycles: u64) -> String {
+    let counts = |map: Vec<(String, u64)>| map.iter().map(|(k, n)| format!("\"{}\": {}", k, n)).collect::<Vec<_>>().join(", ");
+    let mut out = format!("{{\n  \"cycles\": {},\n  \"roms\": [", cycles);
+    for (i, census) in censuses.iter().enumerate() {
+        let quirks: Vec<String> = census.quirks.iter().map(|q| format!("\"{}\"", q)).collect();
+        out.push_str(if i == 0 { "\n" } else { ",\n" });
+        out.push_str(&format!(
+            "    {{\"path\": {}, \"error\": {}, \"instructions\": {{{}}}, \"extensions\": {{{}}}, \"unknownOpcodes\": {{{}}}, \"quirks\": [{}]}}",
+            string(&census.path.to_string_lossy()),
+            census.error.as_deref().map(string).unwrap_or_else(|| "null".to_string()),
+            counts(census.instructions.iter().map(|(k, n)| (k.to_string(), *n)).collect()),
+            counts(census.extensions.iter().map(|(k, n)| (k.to_string(), *n)).collect()),
+            counts(census.unknown.iter().map(|(k, n)| (format!("{:04X}", k), *n)).collect()),
+            quirks.join(", "),
+        ));
+    }
+    out.push_str("\n  ]\n}\n");
+    out
+}
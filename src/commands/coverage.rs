@@ -0,0 +1,172 @@
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use rust_chip8::emulator::Emulator;
+use rust_chip8::memory::{COVERED_EXECUTED, COVERED_READ, COVERED_WRITTEN};
+
+use crate::cli::Options;
+use crate::memfile;
+use crate::recording::{self, Player};
+
+// ROW_BYTES is how many bytes of memory a row of the coverage map has.
+const ROW_BYTES: usize = 64;
+
+// PIXEL_SIZE is the size of a byte in the image of the map.
+const PIXEL_SIZE: usize = 4;
+
+// Coverage is what a ROM did with memory during a run: the COVERED_ flags of every byte.
+pub struct Coverage {
+    pub flags: Vec<u8>,
+    pub rom: Range<usize>,
+    pub frames: u64, // frames run
+    pub keys: String, // where the keys came from
+    pub stopped: Option<String>, // the error the ROM stopped on, if it did
+}
+
+impl Coverage {
+    // trace runs a ROM headless for --frames frames, or as long as its replay when it has one,
+    // given with --replay or in paths::replays, and keeps which bytes it ran, read and wrote.
+    pub fn trace(options: &Options, data: &[u8]) -> Result<Coverage, String> {
+        let mut emul = Emulator::new();
+        emul.load_rom(data).map_err(|e| format!("Unable to load {}: {}", options.romPath, e))?;
+        emul.quirks = options.quirks;
+        emul.variant = options.variant;
+        emul.instructionsPerFrame = options.instructionsPerFrame;
+        emul.machineCode = options.machineCode;
+        for (path, address) in &options.loadMemory {
+            memfile::load(emul.memory.bytes_mut(), path, *address)?;
+        }
+
+        let replay = match &options.replay {
+            Some(path) => Some(Player::open(Path::new(path), data)?),
+            None => Player::open(&recording::replayPath(&options.romPath), data).ok(),
+        };
+        let (mut player, frames, keys) = match replay {
+            Some(player) => {
+                player.replay.header.apply(&mut emul);
+                let (frames, path) = (player.replay.frames(), player.path.display().to_string());
+                (Some(player), frames, format!("with the keys of {}", path))
+            }
+            None => (None, options.frames, "without keys".to_string()),
+        };
+
+        emul.memory.set_coverage(true);
+        let mut coverage = Coverage { flags: Vec::new(), rom: emul.rom_range(), frames: 0, keys, stopped: None };
+        while coverage.frames < frames {
+            let keys = player.as_mut().map(|p| p.keys(coverage.frames + 1)).unwrap_or_default();
+            if let Err(e) = emul.tick_frame(keys) {
+                coverage.stopped = Some(format!("stopped at frame {}: {}", coverage.frames + 1, e));
+                break;
+            }
+            coverage.frames += 1;
+        }
+        coverage.flags = emul.memory.coverage().unwrap_or_default().to_vec();
+        Ok(coverage)
+    }
+
+    // summary counts the bytes of the ROM that were run, only read or never touched, and the bytes
+    // written anywhere.
+    pub fn summary(&self) -> String {
+        let rom = &self.flags[self.rom.clone()];
+        let count = |f: &dyn Fn(u8) -> bool| rom.iter().filter(|flags| f(**flags)).count();
+        let run = count(&|flags| flags & COVERED_EXECUTED != 0);
+        let read = count(&|flags| flags & COVERED_EXECUTED == 0 && flags & COVERED_READ != 0);
+        let untouched = count(&|flags| flags == 0);
+        let written = self.flags.iter().filter(|flags| *flags & COVERED_WRITTEN != 0).count();
+        let percent = |n: usize| n as f64 * 100.0 / rom.len().max(1) as f64;
+        format!("{} bytes run ({:.1}%), {} only read ({:.1}%) and {} untouched ({:.1}%) of the {} bytes of the ROM, {} bytes written",
+            run, percent(run), read, percent(read), untouched, percent(untouched), rom.len(), written)
+    }
+
+    // text returns the map of the rows of memory with the ROM or anything touched, a character per
+    // byte: X run, r read, w written, * read and written, . untouched.
+    pub fn text(&self) -> String {
+        let mut text = format!("X run, r read, w written, * read and written, . untouched\n       {:<32}{}\n", "+00", "+20");
+        for (row, bytes) in self.flags.chunks(ROW_BYTES).enumerate() {
+            let start = row * ROW_BYTES;
+            let inRom = start < self.rom.end && self.rom.start < start + bytes.len();
+            if !inRom && bytes.iter().all(|flags| *flags == 0) {
+                continue;
+            }
+            let line: String = bytes.iter().map(|flags| symbol(*flags)).collect();
+            text.push_str(&format!("{:#05X}  {}\n", start, line));
+        }
+        text
+    }
+
+    // write_png draws the map of all of memory, a square per byte, in rows of ROW_BYTES: run green,
+    // read blue, written red, read and written purple, the untouched bytes of the ROM gray.
+    pub fn write_png(&self, path: &Path) -> Result<(), String> {
+        let (width, height) = (ROW_BYTES * PIXEL_SIZE, self.flags.len().div_ceil(ROW_BYTES) * PIXEL_SIZE);
+        let mut data = vec![0; width * height * 3];
+        for (address, flags) in self.flags.iter().enumerate() {
+            let color = match *flags {
+                f if f & COVERED_EXECUTED != 0 => [0x30, 0xC0, 0x30],
+                f if f & COVERED_READ != 0 && f & COVERED_WRITTEN != 0 => [0xA0, 0x40, 0xC0],
+                f if f & COVERED_READ != 0 => [0x30, 0x60, 0xE0],
+                f if f & COVERED_WRITTEN != 0 => [0xD0, 0x30, 0x30],
+                _ if self.rom.contains(&address) => [0x40, 0x40, 0x40],
+                _ => [0, 0, 0],
+            };
+            let (x, y) = (address % ROW_BYTES * PIXEL_SIZE, address / ROW_BYTES * PIXEL_SIZE);
+            for row in y..y + PIXEL_SIZE {
+                for column in x..x + PIXEL_SIZE {
+                    data[(row * width + column) * 3..][..3].copy_from_slice(&color);
+                }
+            }
+        }
+        let file = File::create(path).map_err(|e| e.to_string())?;
+        let mut encoder = png::Encoder::new(BufWriter::new(file), width as u32, height as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.write_header().and_then(|mut w| w.write_image_data(&data)).map_err(|e| e.to_string())
+    }
+}
+
+fn symbol(flags: u8) -> char {
+    match flags {
+        f if f & COVERED_EXECUTED != 0 => 'X',
+        f if f & COVERED_READ != 0 && f & COVERED_WRITTEN != 0 => '*',
+        f if f & COVERED_READ != 0 => 'r',
+        f if f & COVERED_WRITTEN != 0 => 'w',
+        _ => '.',
+    }
+}
+
+// run implements "rust-chip8 coverage": the ROM runs headless, see Coverage::trace, and the
+// summary and the map of what it ran, read and wrote are printed, with --coverage-map also
+// written to a file, as an image for a .png. The exit code is 1 if the ROM stopped on an error,
+// with the coverage up to there.
+pub fn run(options: &Options, data: Vec<u8>) -> i32 {
+    let coverage = match Coverage::trace(options, &data) {
+        Ok(coverage) => coverage,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 2;
+        }
+    };
+    println!("Coverage of {} after {} frames {}", options.romPath, coverage.frames, coverage.keys);
+    println!("{}", coverage.summary());
+    print!("{}", coverage.text());
+    if let Some(path) = &options.coverageMap {
+        let path = PathBuf::from(path);
+        let written = match path.extension().is_some_and(|e| e.eq_ignore_ascii_case("png")) {
+            true => coverage.write_png(&path),
+            false => fs::write(&path, coverage.text()).map_err(|e| e.to_string()),
+        };
+        if let Err(e) = written {
+            eprintln!("Unable to write {}: {}", path.display(), e);
+            return 2;
+        }
+        println!("Wrote {}", path.display());
+    }
+    match coverage.stopped {
+        Some(e) => {
+            println!("The ROM {}", e);
+            1
+        }
+        None => 0,
+    }
+}
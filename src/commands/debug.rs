@@ -0,0 +1,787 @@
+use std::fs;
+use std::env;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::Path;
+
+use rust_chip8::disasm::{self, mnemonic};
+use rust_chip8::emulator::{Emulator, Region, MEM_OFFSET};
+use rust_chip8::error::Error;
+use rust_chip8::expr::Expr;
+use rust_chip8::octo;
+use rust_chip8::sprites::{self, Sprite};
+use rust_chip8::variant::Variant;
+
+use crate::cli::Options;
+use crate::commands::snapshot::{writePng, Image};
+use crate::memfile;
+use crate::saves;
+use crate::session::{self, UnknownOpcodePolicy};
+
+const HELP: &str = "Commands:
+  step [n], s        run n instructions, defaults to 1
+  back [n], rs       take back the last n instructions, defaults to 1
+  continue [n], c    run n frames, defaults to 60
+  break <addr> [if <condition>], b
+                     stop when execution reaches an address, if the condition holds
+  break if <condition>
+                     stop before any instruction where the condition holds, e.g.
+                     \"V3 == 0x1F && I > 0x300\" (V0-VF, I, PC, DT, ST, SP and mem[addr])
+  breakpoints        list the breakpoints
+  sprite [addr] [n]  draw n rows of memory from an address (defaults to I) as an 8 pixel wide
+                     sprite, n defaults to that of the next DRW or 15, 16x16 for DRW Vx, Vy, 0
+  sprites [page]     show the sprites the code draws, found by looking for LD I followed by DRW
+  export <sprites> <file>
+                     write sprites from that list, e.g. 1,3,4 or all, to a .png file, or as DB
+                     statements to any other file
+  screen <file> [x y w h]
+                     write the display, or a region of it in pixels of the mode, as sprites of
+                     up to 8x15 to an Octo .8o file with a routine drawing them, or as DB
+                     statements to any other file
+  disasm [addr] [n], d
+                     disassemble n instructions from an address, defaults to 16 around the PC
+  doc [addr]         explain the instruction at an address, defaults to the PC: what it does,
+                     to VF and how the quirks change it
+  follow             switch showing the disassembly around the PC after every step on or off
+  pin [addr], unpin  keep the disassembly at an address rather than following the PC
+  delete [n]         delete breakpoint n, or all of them
+  regs, r            show the registers, I, the timers and the stack
+  mem <addr> [n]     show n bytes of memory from an address, defaults to 64
+  set <target> <value>
+                     change V0-VF, I, PC, DT, ST or mem[addr] to a value, which can be a
+                     condition-like expression such as V3 + 1, e.g. \"set mem[0x2F0] 9\"
+  dump <addr> <n> <file>
+                     write n bytes of memory from an address to a binary file
+  load <file> [addr] load a binary file into memory at an address, defaults to 0x200
+  quirk [name]       switch a quirk on or off right away, or list them
+  history [n], h     show the last n instructions that ran and what they changed, defaults to 16
+  search <value>     narrow down the addresses where memory holds a value, starting from all of
+                     them, to find a score or lives counter
+  search changed|unchanged|up|down
+                     narrow them down to the bytes that changed, didn't, went up or down since
+                     the last search, after \"search start\" when the value isn't known
+  search start|reset start over from all addresses, or forget the search
+  find <bytes>       list where a sequence of hex bytes is in memory, e.g. \"find F0 90 F0\"
+  changes            switch listing what changed after every step or back on or off, what
+                     changed is highlighted in regs and mem either way
+  help               show this help
+  quit, q            leave the debugger
+An empty line repeats the last command.";
+
+// run implements "rust-chip8 debug", returning the process exit code.
+pub fn run(options: &Options, data: Vec<u8>) -> i32 {
+    let mut emul = Emulator::new();
+    if let Err(e) = emul.load_rom(&data) {
+        eprintln!("Unable to load {}: {}", options.romPath, e);
+        return 2;
+    }
+    emul.quirks = options.quirks;
+    emul.variant = options.variant;
+    emul.haltOnUnknownOpcode = options.unknownOpcodes == UnknownOpcodePolicy::Halt;
+    emul.machineCode = options.machineCode;
+    emul.instructionsPerFrame = options.instructionsPerFrame;
+    emul.record_history(options.history);
+    for (path, address) in &options.loadMemory {
+        if let Err(e) = memfile::load(emul.memory.bytes_mut(), path, *address) {
+            eprintln!("{}", e);
+            return 2;
+        }
+    }
+
+    let color = io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none();
+    let mut debugger = Debugger { emul, frame: 0, cycle: 0, breakpoints: Vec::new(), follow: false, pinned: None, before: None, showChanges: true, color, search: None, romName: saves::romName(&options.romPath).to_string() };
+    println!("Debugging {}, type help for the commands", options.romPath);
+    debugger.show_next();
+
+    let stdin = io::stdin();
+    let mut last = String::new();
+    loop {
+        print!("(chip8) ");
+        io::stdout().flush().ok();
+        let mut line = String::new();
+        match stdin.lock().read_line(&mut line) {
+            Ok(0) | Err(_) => return 0, // end of input
+            Ok(_) => {}
+        }
+        let line = if line.trim().is_empty() { last.clone() } else { line.trim().to_string() };
+        match debugger.execute(&line) {
+            Ok(true) => return 0,
+            Ok(false) => {}
+            Err(e) => println!("{}", e),
+        }
+        last = line;
+    }
+}
+
+// Debugger is the state of a debugging session: the emulator, how far it has run and the
+// breakpoints.
+struct Debugger {
+    emul: Emulator,
+    frame: u64,
+    cycle: u32, // instructions run in the current frame
+    breakpoints: Vec<Breakpoint>,
+    follow: bool,        // show the disassembly rather than just the next instruction
+    pinned: Option<u16>, // where the disassembly starts, if not around the PC
+    before: Option<State>, // the state before the last step or back, to tell what they changed
+    showChanges: bool,     // list the changes after every step or back
+    color: bool,           // highlight with terminal colors, unless output isn't a terminal or NO_COLOR is set
+    search: Option<Search>,
+    romName: String, // for naming exports
+}
+
+// Search is a memory search going on, the way cheat tools find a variable: every search keeps the
+// candidates that hold a value, or that changed in a certain way since the search before.
+struct Search {
+    candidates: Vec<usize>, // the addresses still in the running
+    memory: Vec<u8>,        // memory as it was at the last search
+}
+
+// SEARCH_SHOWN is how many of the candidates of a search are listed.
+const SEARCH_SHOWN: usize = 16;
+
+// State is what the debugger compares to tell what stepping changed, everything but the PC, which
+// changes with every instruction anyway.
+#[derive(Clone, PartialEq, Eq)]
+pub struct State {
+    registers: [u8; 16],
+    I: u16,
+    delayTimer: u8,
+    soundTimer: u8,
+    stack: Vec<u16>,
+    hires: bool,
+    memory: Vec<u8>,
+    display: Vec<u8>,
+}
+
+impl State {
+    pub fn of(emul: &Emulator) -> State {
+        State {
+            registers: emul.registers,
+            I: emul.I,
+            delayTimer: emul.delayTimer,
+            soundTimer: emul.soundTimer,
+            stack: emul.stack.to_vec(),
+            hires: emul.hires,
+            memory: emul.memory.bytes().to_vec(),
+            display: emul.display.to_vec(),
+        }
+    }
+
+    // changes lists what differs in another state, like "V3 05 -> 06", at most MAX_CHANGED_BYTES of
+    // the bytes of memory.
+    pub fn changes(&self, now: &State) -> Vec<String> {
+        let mut changes: Vec<String> = (0..16).filter(|&r| self.registers[r] != now.registers[r])
+            .map(|r| format!("V{:X} {:02X} -> {:02X}", r, self.registers[r], now.registers[r]))
+            .collect();
+        if self.I != now.I {
+            changes.push(format!("I {:03X} -> {:03X}", self.I, now.I));
+        }
+        if self.delayTimer != now.delayTimer {
+            changes.push(format!("DT {:02X} -> {:02X}", self.delayTimer, now.delayTimer));
+        }
+        if self.soundTimer != now.soundTimer {
+            changes.push(format!("ST {:02X} -> {:02X}", self.soundTimer, now.soundTimer));
+        }
+        if self.stack != now.stack {
+            let stack = |s: &[u16]| s.iter().map(|a| format!("{:03X}", a)).collect::<Vec<_>>().join(" ");
+            changes.push(format!("stack [{}] -> [{}]", stack(&self.stack), stack(&now.stack)));
+        }
+        if self.hires != now.hires {
+            changes.push(format!("hires {} -> {}", self.hires, now.hires));
+        }
+        let bytes: Vec<usize> = (0..self.memory.len().min(now.memory.len())).filter(|&a| self.memory[a] != now.memory[a]).collect();
+        for &address in bytes.iter().take(MAX_CHANGED_BYTES) {
+            changes.push(format!("mem[{:#05X}] {:02X} -> {:02X}", address, self.memory[address], now.memory[address]));
+        }
+        if bytes.len() > MAX_CHANGED_BYTES {
+            changes.push(format!("{} more bytes", bytes.len() - MAX_CHANGED_BYTES));
+        }
+        if self.display != now.display {
+            changes.push("the display".to_string());
+        }
+        changes
+    }
+}
+
+// MAX_CHANGED_BYTES is how many changed bytes of memory the changes after a step list.
+const MAX_CHANGED_BYTES: usize = 8;
+
+// HIGHLIGHT and RESET are the terminal colors of what changed, bold yellow.
+const HIGHLIGHT: &str = "\x1b[1;33m";
+const RESET: &str = "\x1b[0m";
+
+// SPRITES_PER_PAGE is how many sprites the sprites command shows at a time.
+const SPRITES_PER_PAGE: usize = 12;
+
+// pixelText draws a row of pixels, each two characters wide so that sprites come out roughly square.
+pub fn pixelText(pixels: &[bool]) -> String {
+    pixels.iter().map(|lit| if *lit { "##" } else { ".." }).collect()
+}
+
+// showDoc prints the entry of the reference for an instruction word, see disasm::reference.
+pub fn showDoc(b: u16, variant: Variant) {
+    let Some(doc) = disasm::reference(b, variant) else {
+        return println!("{:04X} isn't an instruction of {}", b, variant.name());
+    };
+    println!("{:04X}  {}  ({} {})", b, mnemonic(b, variant), doc.pattern, doc.syntax);
+    println!("   {}", doc.summary);
+    if !doc.flags.is_empty() {
+        println!("   VF: {}", doc.flags);
+    }
+    if !doc.quirks.is_empty() {
+        println!("   Quirks: {}", doc.quirks);
+    }
+}
+
+// LISTING_LINES is the length of the disassembly, LISTING_BEFORE how many of its lines come before
+// the PC when following it.
+const LISTING_LINES: usize = 16;
+const LISTING_BEFORE: u16 = 4;
+
+// Breakpoint stops execution at an address, when a condition holds, or on both.
+struct Breakpoint {
+    address: Option<u16>,
+    condition: Option<Expr>,
+    text: String, // as it was given, for listing
+}
+
+impl Breakpoint {
+    // parse parses the arguments of the break command: "<addr>", "<addr> if <condition>" or
+    // "if <condition>".
+    fn parse(text: &str) -> Result<Breakpoint, String> {
+        let (address, condition) = match text.split_once("if ") {
+            Some((address, condition)) => (address.trim(), Some(Expr::parse(condition)?)),
+            None => (text.trim(), None),
+        };
+        let address = match address {
+            "" if condition.is_some() => None,
+            _ => Some(number(address).ok_or(format!("expected an address, got '{}'", address))?),
+        };
+        Ok(Breakpoint { address, condition, text: text.trim().to_string() })
+    }
+
+    fn hit(&self, emul: &Emulator) -> bool {
+        self.address.is_none_or(|a| a == emul.pc) && self.condition.as_ref().is_none_or(|c| c.holds(emul))
+    }
+}
+
+// number parses a decimal or 0x hexadecimal address.
+fn number(s: &str) -> Option<u16> {
+    match s.strip_prefix("0x").or(s.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+impl Debugger {
+    // execute runs a single debugger command, returning whether it was quit.
+    fn execute(&mut self, line: &str) -> Result<bool, String> {
+        let mut words = line.split_whitespace();
+        let command = words.next().unwrap_or("");
+        let arguments = line.trim_start().get(command.len()..).unwrap_or("").trim();
+        let count = |default: u64, word: Option<&str>| match word {
+            Some(n) => n.parse().map_err(|_| format!("expected a number, got '{}'", n)),
+            None => Ok(default),
+        };
+        match command {
+            "" => {}
+            "step" | "s" => {
+                let n = count(1, words.next())?;
+                self.before = Some(State::of(&self.emul));
+                self.run_until(n, None);
+                self.show_changes();
+            }
+            "back" | "rs" => {
+                let n = count(1, words.next())?;
+                self.before = Some(State::of(&self.emul));
+                self.step_back(n);
+                self.show_changes();
+            }
+            "continue" | "c" => {
+                // running on for frames is no step to tell the changes of.
+                self.before = None;
+                self.run_until(u64::MAX, Some(self.frame + count(60, words.next())?));
+            }
+            "search" => self.search(arguments)?,
+            "find" => {
+                // the bytes are hex, like mem shows them.
+                let pattern = words.map(|w| u8::from_str_radix(w.trim_start_matches("0x"), 16).map_err(|_| format!("expected a hex byte, got '{}'", w)))
+                    .collect::<Result<Vec<u8>, String>>()?;
+                if pattern.is_empty() {
+                    return Err("find expects one or more bytes".into());
+                }
+                let found: Vec<String> = self.emul.memory.bytes().windows(pattern.len()).enumerate()
+                    .filter(|(_, w)| *w == &pattern[..]).map(|(a, _)| format!("{:#05X}", a)).collect();
+                match found.is_empty() {
+                    true => println!("Not found"),
+                    false => println!("Found at {}", found.join(" ")),
+                }
+            }
+            "changes" => {
+                self.showChanges = !self.showChanges;
+                println!("Listing the changes of a step is {}", if self.showChanges { "on" } else { "off" });
+            }
+            "break" | "b" => {
+                let breakpoint = Breakpoint::parse(arguments)?;
+                println!("Breakpoint {}: {}", self.breakpoints.len() + 1, breakpoint.text);
+                self.breakpoints.push(breakpoint);
+            }
+            "breakpoints" => {
+                for (i, b) in self.breakpoints.iter().enumerate() {
+                    println!("{}: {}", i + 1, b.text);
+                }
+            }
+            "delete" => match words.next() {
+                Some(n) => match n.parse::<usize>().ok().filter(|n| (1..=self.breakpoints.len()).contains(n)) {
+                    Some(n) => drop(self.breakpoints.remove(n - 1)),
+                    None => return Err(format!("no breakpoint {}", n)),
+                },
+                None => self.breakpoints.clear(),
+            },
+            "regs" | "r" => self.show_registers(),
+            "mem" => {
+                let address = words.next().and_then(number).ok_or("mem expects an address")?;
+                self.show_memory(address, count(64, words.next())? as usize);
+            }
+            "set" => self.set(arguments)?,
+            "sprite" => {
+                let address = match words.next() {
+                    Some(a) => number(a).ok_or(format!("expected an address, got '{}'", a))?,
+                    None => self.emul.I,
+                };
+                let next = self.word(self.emul.pc);
+                let drw = (next & 0xF000 == 0xD000).then_some(next & 0xF);
+                let (rows, wide) = match (words.next(), drw) {
+                    (Some(n), _) => (count(15, Some(n))? as usize, false),
+                    (None, Some(0)) => (16, true),
+                    (None, Some(n)) => (n as usize, false),
+                    (None, None) => (15, false),
+                };
+                self.show_sprite(Sprite { address, height: rows, wide });
+            }
+            "sprites" => self.show_sprites(count(1, words.next())? as usize),
+            "export" => {
+                let (which, path) = (words.next(), words.next());
+                let (Some(which), Some(path)) = (which, path) else {
+                    return Err("export expects the sprites and a file".into());
+                };
+                self.export(which, Path::new(path))?;
+            }
+            "screen" => {
+                let path = words.next().ok_or("screen expects a file")?;
+                let region: Vec<usize> = words.map(|w| number(w).map(usize::from).ok_or(format!("expected a number, got '{}'", w))).collect::<Result<_, _>>()?;
+                self.export_screen(Path::new(path), &region)?;
+            }
+            "disasm" | "d" => {
+                let from = match words.next() {
+                    Some(a) => Some(number(a).ok_or(format!("expected an address, got '{}'", a))?),
+                    None => None,
+                };
+                self.show_listing(from, count(LISTING_LINES as u64, words.next())? as usize);
+            }
+            "doc" => {
+                let address = match words.next() {
+                    Some(a) => number(a).ok_or(format!("expected an address, got '{}'", a))?,
+                    None => self.emul.pc,
+                };
+                showDoc(self.word(address), self.emul.variant);
+            }
+            "follow" => {
+                self.follow = !self.follow;
+                println!("Following the PC is {}", if self.follow { "on" } else { "off" });
+            }
+            "pin" => {
+                self.pinned = Some(match words.next() {
+                    Some(a) => number(a).ok_or(format!("expected an address, got '{}'", a))?,
+                    None => self.listing_start(),
+                });
+                self.show_listing(None, LISTING_LINES);
+            }
+            "unpin" => {
+                self.pinned = None;
+                self.show_listing(None, LISTING_LINES);
+            }
+            "dump" => {
+                let (address, n, path) = (words.next().and_then(number), words.next(), words.next());
+                let (Some(address), Some(path)) = (address, path) else {
+                    return Err("dump expects an address, a number of bytes and a file".into());
+                };
+                let range = address as usize..address as usize + count(0, n)? as usize;
+                memfile::dump(self.emul.memory.bytes(), path, range.clone())?;
+                println!("Wrote {:#05X}..{:#05X} to {}", range.start, range.end, path);
+            }
+            "load" => {
+                let path = words.next().ok_or("load expects a file")?;
+                let address = match words.next() {
+                    Some(a) => number(a).ok_or(format!("expected an address, got '{}'", a))?,
+                    None => MEM_OFFSET as u16,
+                };
+                let n = memfile::load(self.emul.memory.bytes_mut(), path, address as usize)?;
+                println!("Loaded {} bytes at {:#05X}", n, address);
+            }
+            "quirk" => match words.next() {
+                Some(name) => {
+                    let quirks = &mut self.emul.quirks;
+                    let on = !quirks.get(name)?;
+                    quirks.set(name, on)?;
+                    println!("{}", session::quirkToggled(name, on, self.frame, self.frame > 0 || self.cycle > 0, quirks));
+                }
+                None => println!("{}", self.emul.quirks.spec().replace(',', " ")),
+            },
+            "history" | "h" => self.show_history(count(16, words.next())? as usize),
+            "help" => println!("{}", HELP),
+            "quit" | "q" => return Ok(true),
+            _ => return Err(format!("unknown command '{}', type help for the commands", command)),
+        }
+        Ok(false)
+    }
+
+    // run_until runs at most n instructions and up to the given frame, stopping early at a
+    // breakpoint or an error. The breakpoints are only checked when there are any, and not for
+    // the first instruction so that it gets past the breakpoint it's stopped at.
+    fn run_until(&mut self, n: u64, frame: Option<u64>) {
+        for i in 0..n {
+            if frame.is_some_and(|f| self.frame >= f) {
+                break;
+            }
+            if i > 0 && !self.breakpoints.is_empty() {
+                if let Some(b) = self.breakpoints.iter().position(|b| b.hit(&self.emul)) {
+                    println!("Breakpoint {}: {}", b + 1, self.breakpoints[b].text);
+                    break;
+                }
+            }
+            if let Err(e) = self.advance() {
+                println!("Stopped at frame {}: {}", self.frame, e);
+                break;
+            }
+        }
+        self.show_next();
+    }
+
+    // advance runs an instruction, ticking the timers after every instructionsPerFrame of them
+    // like tick_frame does.
+    fn advance(&mut self) -> Result<(), Error> {
+        self.emul.run()?;
+        self.cycle += 1;
+        if self.cycle >= self.emul.instructionsPerFrame {
+            self.cycle = 0;
+            self.frame += 1;
+            self.emul.tick_timers();
+        }
+        Ok(())
+    }
+
+    // step_back takes back instructions from the history.
+    fn step_back(&mut self, n: u64) {
+        for i in 0..n {
+            if self.emul.step_back().is_none() {
+                println!("Can't go back further than {} instructions, see --history", i);
+                break;
+            }
+            if self.cycle == 0 {
+                self.frame = self.frame.saturating_sub(1);
+                self.cycle = self.emul.instructionsPerFrame;
+            }
+            self.cycle -= 1;
+        }
+        self.show_next();
+    }
+
+    // listing_start returns where the disassembly starts: the pinned address or a few
+    // instructions before the PC.
+    fn listing_start(&self) -> u16 {
+        self.pinned.unwrap_or(self.emul.pc.saturating_sub(2 * LISTING_BEFORE))
+    }
+
+    // show_listing prints a disassembly with raw bytes and branch arrows, marking the PC with =>
+    // and breakpoints with *.
+    fn show_listing(&self, from: Option<u16>, n: usize) {
+        let lines = disasm::listing(self.emul.memory.bytes(), from.unwrap_or(self.listing_start()), n, self.emul.variant);
+        let arrows = disasm::arrows(&lines, 3);
+        for (line, arrows) in lines.iter().zip(arrows) {
+            let marker = if line.address == self.emul.pc {
+                "=>"
+            } else if self.breakpoints.iter().any(|b| b.address == Some(line.address)) {
+                " *"
+            } else {
+                "  "
+            };
+            println!("{} {:#05X}  {:02X} {:02X}  {} {}", marker, line.address, line.opcode >> 8, line.opcode & 0xFF, arrows, line.mnemonic);
+        }
+    }
+
+    // show_next prints the instruction that runs next, or the disassembly when following.
+    fn show_next(&self) {
+        if self.follow {
+            return self.show_listing(None, LISTING_LINES);
+        }
+        let pc = self.emul.pc;
+        let b = self.word(pc);
+        println!("-> {:#05X}  {:04X}  {}", pc, b, mnemonic(b, self.emul.variant));
+    }
+
+    // word reads an instruction word from memory without it counting as an access.
+    fn word(&self, address: u16) -> u16 {
+        let memory = self.emul.memory.bytes();
+        (memory[address as usize % memory.len()] as u16) << 8 | memory[(address as usize + 1) % memory.len()] as u16
+    }
+
+    // search narrows down the candidates of the memory search, see Search.
+    fn search(&mut self, arguments: &str) -> Result<(), String> {
+        let memory = self.emul.memory.bytes();
+        let all = || Search { candidates: (0..memory.len()).collect(), memory: memory.to_vec() };
+        let keep: Box<dyn Fn(u8, u8) -> bool> = match arguments {
+            "start" => {
+                self.search = Some(all());
+                println!("Started a search of all {} bytes of memory", memory.len());
+                return Ok(());
+            }
+            "reset" => {
+                self.search = None;
+                println!("Forgot the search");
+                return Ok(());
+            }
+            "changed" => Box::new(|old, new| old != new),
+            "unchanged" => Box::new(|old, new| old == new),
+            "up" => Box::new(|old, new| new > old),
+            "down" => Box::new(|old, new| new < old),
+            "" => return Err("search expects a value, changed, unchanged, up, down, start or reset".into()),
+            value => {
+                let value = number(value).filter(|v| *v <= 0xFF).ok_or(format!("expected a byte value, got '{}'", value))? as u8;
+                Box::new(move |_, new| new == value)
+            }
+        };
+        let search = self.search.get_or_insert_with(all);
+        search.candidates.retain(|&a| keep(search.memory[a], memory[a]));
+        search.memory = memory.to_vec();
+        let shown: Vec<String> = search.candidates.iter().take(SEARCH_SHOWN).map(|a| format!("{:#05X}={:02X}", a, memory[*a])).collect();
+        match search.candidates.len() {
+            0 => println!("No addresses left, search start or reset to start over"),
+            n if n > SEARCH_SHOWN => println!("{} addresses, the first {}: {}", n, SEARCH_SHOWN, shown.join(" ")),
+            n => println!("{} addresses: {}", n, shown.join(" ")),
+        }
+        Ok(())
+    }
+
+    // show_changes lists what the last step or back changed, if anything, when that's on.
+    fn show_changes(&self) {
+        let Some(before) = self.before.as_ref().filter(|_| self.showChanges) else { return };
+        let changes = before.changes(&State::of(&self.emul));
+        if !changes.is_empty() {
+            println!("   {}", self.highlight(&changes.join(", "), true));
+        }
+    }
+
+    // highlight colors text that changed in the last step or back, in a terminal.
+    fn highlight(&self, text: &str, changed: bool) -> String {
+        match changed && self.color {
+            true => format!("{}{}{}", HIGHLIGHT, text, RESET),
+            false => text.to_string(),
+        }
+    }
+
+    // show_registers prints the registers, I, the timers and the stack, those that changed in the
+    // last step or back highlighted.
+    fn show_registers(&self) {
+        let emul = &self.emul;
+        let before = self.before.as_ref();
+        for (r, values) in emul.registers.chunks(8).enumerate() {
+            let values: Vec<String> = values.iter().enumerate().map(|(i, v)| {
+                let changed = before.is_some_and(|b| b.registers[r * 8 + i] != *v);
+                self.highlight(&format!("V{:X}={:02X}", r * 8 + i, v), changed)
+            }).collect();
+            println!("{}", values.join(" "));
+        }
+        println!("{} PC={:03X} {} {} frame={}",
+            self.highlight(&format!("I={:03X}", emul.I), before.is_some_and(|b| b.I != emul.I)),
+            emul.pc,
+            self.highlight(&format!("DT={:02X}", emul.delayTimer), before.is_some_and(|b| b.delayTimer != emul.delayTimer)),
+            self.highlight(&format!("ST={:02X}", emul.soundTimer), before.is_some_and(|b| b.soundTimer != emul.soundTimer)),
+            self.frame);
+        let stack: Vec<String> = emul.stack.iter().map(|a| format!("{:03X}", a)).collect();
+        let stackChanged = before.is_some_and(|b| b.stack[..] != emul.stack[..]);
+        println!("{}", self.highlight(&format!("stack [{}]", stack.join(" ")), stackChanged));
+    }
+
+    // show_memory prints memory as rows of 16 bytes, wrapping around at the end, with the bytes
+    // the last step or back changed highlighted.
+    fn show_memory(&self, address: u16, n: usize) {
+        let memory = self.emul.memory.bytes();
+        for row in (0..n).step_by(16) {
+            let bytes: Vec<String> = (row..n.min(row + 16)).map(|i| {
+                let at = (address as usize + i) % memory.len();
+                let changed = self.before.as_ref().is_some_and(|b| b.memory.get(at) != Some(&memory[at]));
+                self.highlight(&format!("{:02X}", memory[at]), changed)
+            }).collect();
+            println!("{:#05X}  {}", (address as usize + row) % memory.len(), bytes.join(" "));
+        }
+    }
+
+    // set changes a register, I, PC, a timer or a byte of memory. Values are expressions, evaluated
+    // before anything changes, and are cut off to the size of what they're stored in.
+    fn set(&mut self, arguments: &str) -> Result<(), String> {
+        // a mem[...] address may have spaces in it, the other targets are a single word.
+        let split = match arguments.find(']') {
+            Some(end) if arguments.to_ascii_lowercase().starts_with("mem[") => Some(arguments.split_at(end + 1)),
+            _ => arguments.split_once(char::is_whitespace),
+        };
+        let (target, value) = split.filter(|(_, v)| !v.trim().is_empty()).ok_or("set expects a target and a value")?;
+        let value = Expr::parse(value)?.value(&self.emul);
+        let target = target.to_ascii_uppercase();
+        let emul = &mut self.emul;
+        match target.as_str() {
+            "I" => emul.I = value as u16 & 0xFFF,
+            "PC" => emul.pc = value as u16 & 0xFFF,
+            "DT" => emul.delayTimer = value as u8,
+            "ST" => emul.soundTimer = value as u8,
+            _ => match target.strip_prefix("MEM[").and_then(|a| a.strip_suffix(']')) {
+                Some(address) => {
+                    let address = Expr::parse(address)?.value(emul);
+                    let memory = emul.memory.bytes_mut();
+                    let len = memory.len() as i64;
+                    memory[address.rem_euclid(len) as usize] = value as u8;
+                }
+                None => match target.strip_prefix('V').filter(|r| r.len() == 1).and_then(|r| usize::from_str_radix(r, 16).ok()) {
+                    Some(r) => emul.registers[r] = value as u8,
+                    None => return Err(format!("can't set '{}', expected V0-VF, I, PC, DT, ST or mem[addr]", target)),
+                },
+            },
+        }
+        Ok(())
+    }
+
+    // show_sprite draws sprite data the way DXYN would, next to the address and bytes of each row.
+    fn show_sprite(&self, sprite: Sprite) {
+        let memory = self.emul.memory.bytes();
+        let bytes = sprite.bytes(memory);
+        let rowBytes = sprite.width() / 8;
+        for (row, pixels) in sprite.pixels(memory).iter().enumerate() {
+            let hex: Vec<String> = bytes[row * rowBytes..(row + 1) * rowBytes].iter().map(|b| format!("{:02X}", b)).collect();
+            let address = (sprite.address as usize + row * rowBytes) % memory.len();
+            println!("{:#05X}  {}  {}", address, hex.join(" "), pixelText(pixels));
+        }
+    }
+
+    fn find_sprites(&self) -> Vec<Sprite> {
+        let memory = self.emul.memory.bytes();
+        sprites::find(memory, MEM_OFFSET as u16, memory.len() as u16 - 1, self.emul.variant)
+    }
+
+    // show_sprites draws a page of the sprites found in memory, side by side as far as they fit
+    // in 80 columns, each under its number and address.
+    fn show_sprites(&self, page: usize) {
+        let found = self.find_sprites();
+        let pages = found.len().div_ceil(SPRITES_PER_PAGE).max(1);
+        if page == 0 || page > pages {
+            return println!("Expected a page from 1 to {}", pages);
+        }
+        let memory = self.emul.memory.bytes();
+        let numbered: Vec<(usize, &Sprite)> = found.iter().enumerate().skip((page - 1) * SPRITES_PER_PAGE).take(SPRITES_PER_PAGE).collect();
+        let mut rest = &numbered[..];
+        while !rest.is_empty() {
+            // as many as fit next to each other, each pixel 2 columns, with 2 in between.
+            let mut columns = 0;
+            let fit = rest.iter().take_while(|(_, s)| { columns += s.width() * 2 + 2; columns <= 80 }).count().max(1);
+            let (shelf, next) = rest.split_at(fit);
+            let labels: Vec<String> = shelf.iter().map(|(i, s)| format!("{:<w$}", format!("{} {:#05X}", i + 1, s.address), w = s.width() * 2)).collect();
+            println!("{}", labels.join("  ").trim_end());
+            let pixels: Vec<Vec<Vec<bool>>> = shelf.iter().map(|(_, s)| s.pixels(memory)).collect();
+            for row in 0..shelf.iter().map(|(_, s)| s.height).max().unwrap_or(0) {
+                let line: Vec<String> = shelf.iter().zip(&pixels)
+                    .map(|((_, s), p)| p.get(row).map(|r| pixelText(r)).unwrap_or(" ".repeat(s.width() * 2)))
+                    .collect();
+                println!("{}", line.join("  ").trim_end());
+            }
+            println!();
+            rest = next;
+        }
+        println!("Page {} of {}, {} sprites", page, pages, found.len());
+    }
+
+    // export writes sprites from the sprites list to a PNG, next to each other with a pixel in
+    // between, or as assembler data with the pixels in comments.
+    fn export(&self, which: &str, path: &Path) -> Result<(), String> {
+        let found = self.find_sprites();
+        let selected: Vec<Sprite> = match which {
+            "all" => found.clone(),
+            _ => which.split(',')
+                .map(|n| n.trim().parse::<usize>().ok().and_then(|n| found.get(n.wrapping_sub(1))).copied().ok_or(format!("no sprite {}, see sprites", n)))
+                .collect::<Result<_, _>>()?,
+        };
+        if selected.is_empty() {
+            return Err("no sprites to export".into());
+        }
+        let memory = self.emul.memory.bytes();
+        let written = if path.extension().is_some_and(|e| e.eq_ignore_ascii_case("png")) {
+            let width = selected.iter().map(|s| s.width() + 1).sum::<usize>() - 1;
+            let height = selected.iter().map(|s| s.height).max().unwrap_or(0);
+            let mut image = Image { width, height, pixels: vec![false; width * height] };
+            let mut left = 0;
+            for sprite in &selected {
+                for (y, row) in sprite.pixels(memory).iter().enumerate() {
+                    for (x, lit) in row.iter().enumerate() {
+                        image.pixels[y * width + left + x] = *lit;
+                    }
+                }
+                left += sprite.width() + 1;
+            }
+            writePng(path, &image)
+        } else {
+            let mut text = String::new();
+            for sprite in &selected {
+                text.push_str(&format!("; {}x{} sprite at {:#05X}\nsprite_{:03X}:\n", sprite.width(), sprite.height, sprite.address, sprite.address));
+                let bytes = sprite.bytes(memory);
+                for (row, pixels) in bytes.chunks(sprite.width() / 8).zip(sprite.pixels(memory)) {
+                    let hex: Vec<String> = row.iter().map(|b| format!("{:#04X}", b)).collect();
+                    let pixels: String = pixels.iter().map(|lit| if *lit { '#' } else { '.' }).collect();
+                    text.push_str(&format!("  DB {} ; {}\n", hex.join(", "), pixels));
+                }
+                text.push('\n');
+            }
+            fs::write(path, text).map_err(|e| e.to_string())
+        };
+        written.map_err(|e| format!("Unable to write {}: {}", path.display(), e))?;
+        println!("Wrote {} sprites to {}", selected.len(), path.display());
+        Ok(())
+    }
+
+    // export_screen writes what's on the display, or a region x y w h of it, as sprites, see
+    // sprites::tiles.
+    fn export_screen(&self, path: &Path, region: &[usize]) -> Result<(), String> {
+        let (width, height) = self.emul.resolution();
+        let region = match *region {
+            [] => Region { x: 0, y: 0, width, height },
+            [x, y, w, h] if x + w <= width && y + h <= height && w > 0 && h > 0 => Region { x, y, width: w, height: h },
+            [_, _, _, _] => return Err(format!("the region is outside of the {}x{} display", width, height)),
+            _ => return Err("screen expects a file and optionally x y w h".into()),
+        };
+        let tiles = sprites::tiles(&self.emul.display, self.emul.hires, region);
+        let comment = format!("{}x{} at {} {} of the {}x{} display of {}", region.width, region.height, region.x, region.y, width, height, self.romName);
+        let text = if path.extension().is_some_and(|e| e.eq_ignore_ascii_case("8o")) {
+            octo::declare(&tiles, &self.romName, &comment)
+        } else {
+            let mut text = format!("; {}\n", comment);
+            for tile in &tiles {
+                text.push_str(&format!("; 8x{} at {} {}\nscreen_{}_{}:\n", tile.rows.len(), tile.x, tile.y, tile.x, tile.y));
+                for row in &tile.rows {
+                    let pixels: String = (0..8).map(|bit| if row & 0x80 >> bit != 0 { '#' } else { '.' }).collect();
+                    text.push_str(&format!("  DB {:#04X} ; {}\n", row, pixels));
+                }
+            }
+            text
+        };
+        fs::write(path, text).map_err(|e| format!("Unable to write {}: {}", path.display(), e))?;
+        println!("Wrote {} sprites of the display to {}", tiles.len(), path.display());
+        Ok(())
+    }
+
+    fn show_history(&self, n: usize) {
+        match self.emul.history() {
+            Some(history) => {
+                for entry in history.iter().skip(history.len().saturating_sub(n)) {
+                    println!("  {}", entry.describe(self.emul.variant));
+                }
+            }
+            None => println!("No history is kept, see --history"),
+        }
+    }
+}
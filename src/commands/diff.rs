@@ -0,0 +1,82 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+
+use rust_chip8::difftest;
+use rust_chip8::emulator::Emulator;
+use rust_chip8::reference::Reference;
+
+use crate::cli::Options;
+
+// run implements "rust-chip8 diff", returning the process exit code.
+pub fn run(options: &Options, data: Vec<u8>) -> i32 {
+    let mut emul = Emulator::new();
+    if let Err(e) = emul.load_rom(&data) {
+        eprintln!("Unable to load {}: {}", options.romPath, e);
+        return 2;
+    }
+    emul.quirks = options.quirks;
+    emul.variant = options.variant;
+    emul.instructionsPerFrame = options.instructionsPerFrame;
+
+    if let Some(path) = &options.record {
+        let file = match File::create(path) {
+            Ok(file) => file,
+            Err(e) => {
+                eprintln!("Unable to create {}: {}", path, e);
+                return 2;
+            }
+        };
+        return match difftest::recordTrace(&mut emul, &mut BufWriter::new(file), options.cycles) {
+            Ok(n) => {
+                println!("Recorded {} instructions to {}", n, path);
+                0
+            }
+            Err(e) => {
+                eprintln!("Unable to write {}: {}", path, e);
+                2
+            }
+        };
+    }
+
+    if let Some(path) = &options.trace {
+        let trace = match File::open(path) {
+            Ok(file) => BufReader::new(file),
+            Err(e) => {
+                eprintln!("Unable to read {}: {}", path, e);
+                return 2;
+            }
+        };
+        return match difftest::againstTrace(&mut emul, trace, options.cycles) {
+            Ok(n) => {
+                println!("No divergence from {} after {} instructions", path, n);
+                0
+            }
+            Err(msg) => {
+                println!("{}", msg);
+                1
+            }
+        };
+    }
+
+    let mut reference = match Reference::new(emul.rom(), options.quirks) {
+        Ok(reference) => reference,
+        Err(e) => {
+            eprintln!("Unable to load {} into the reference interpreter: {}", options.romPath, e);
+            return 2;
+        }
+    };
+    match difftest::againstReference(&mut emul, &mut reference, options.cycles) {
+        Ok(n) if n < options.cycles => {
+            println!("No divergence from the reference interpreter, both stopped on an error after {} instructions", n);
+            0
+        }
+        Ok(n) => {
+            println!("No divergence from the reference interpreter after {} instructions", n);
+            0
+        }
+        Err(divergence) => {
+            print!("{}", divergence);
+            1
+        }
+    }
+}
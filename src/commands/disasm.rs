@@ -0,0 +1,32 @@
+use rust_chip8::disasm;
+use rust_chip8::emulator::Emulator;
+
+use crate::cli::Options;
+use crate::commands::coverage::Coverage;
+
+// run implements "rust-chip8 disasm": the ROM is disassembled into an assembler source, see
+// disasm::program, printed for redirecting to a file. With --traced the ROM also runs like for
+// "rust-chip8 coverage", and what ran counts as code as well.
+pub fn run(options: &Options, data: Vec<u8>) -> i32 {
+    let mut emul = Emulator::new();
+    if let Err(e) = emul.load_rom(&data) {
+        eprintln!("Unable to load {}: {}", options.romPath, e);
+        return 2;
+    }
+    let coverage = match options.traced {
+        true => match Coverage::trace(options, &data) {
+            Ok(coverage) => Some(coverage),
+            Err(e) => {
+                eprintln!("{}", e);
+                return 2;
+            }
+        },
+        false => None,
+    };
+    println!("; {} disassembled by rust-chip8", options.romPath);
+    if let Some(coverage) = &coverage {
+        println!("; with the code that ran in {} frames {}", coverage.frames, coverage.keys);
+    }
+    print!("{}", disasm::program(emul.memory.bytes(), emul.rom_range(), options.variant, coverage.as_ref().map(|c| c.flags.as_slice())));
+    0
+}
@@ -0,0 +1,291 @@
+use std::io::{self, BufRead, Write};
+
+use rust_chip8::asm;
+use rust_chip8::disasm::mnemonic;
+use rust_chip8::emulator::{Emulator, MEM_OFFSET};
+
+use crate::bundle;
+use crate::cli::Options;
+use crate::commands::debug::{pixelText, showDoc, State};
+
+const HELP: &str = "Type an instruction to run it at the PC, as an opcode like D015 or in the syntax of
+disasm like \"DRW V0, V1, 5\", and see what it changed.
+  lesson [n]         list the lessons, or start lesson n
+  next, n            run the next instruction of the lesson, an empty line does too
+  doc [opcode]       explain an instruction, defaults to the one at the PC
+  regs, r            show the registers, I, the timers and the stack
+  screen             show the display
+  mem <addr> [n]     show n bytes of memory from an address, defaults to 16
+  key <key>          press or release a key of the keypad, 0-F, for SKP, SKNP and LD Vx, K
+  reset              start over with a blank machine
+  help               show this help
+  quit, q            leave";
+
+// LESSONS are the tutorials of explore, little programs in the syntax of asm that are run an
+// instruction at a time, the comment of every instruction explaining what it does. They're
+// assembled at 0x200 like a ROM.
+const LESSONS: [(&str, &str); 5] = [
+    ("Registers", "
+    LD V0, 5          ; 6XNN puts a number in a register, there are 16 of them, V0 to VF
+    ADD V0, 3         ; 7XNN adds a number to a register, V0 is now 8
+    LD V1, V0         ; 8XY0 copies one register into another
+    ADD V1, V0        ; 8XY4 adds a register to another, V1 is now 16, 0x10
+    LD V2, 0xFF       ; registers hold a byte, 0 to 255
+    ADD V2, 1         ; so adding 1 to 255 wraps around to 0, and 7XNN doesn't tell
+    LD V3, 0xF0
+    ADD V3, V3        ; 8XY4 does, 0xF0 + 0xF0 is 0x1E0, so V3 is 0xE0 and VF, the flag, 1
+    LD V4, 3
+    SUB V4, V0        ; 8XY5 subtracts, 3 - 8 borrows, so V4 is 0xFB and VF 0 for the borrow
+    SHR V0, V0        ; 8XY6 shifts right, halving, VF gets the bit shifted out
+"),
+    ("Drawing", "
+    LD V0, 7          ; a digit to draw
+    LD F, V0          ; FX29 points I at the built-in sprite of the digit in VX, 5 rows high
+    LD V1, 4          ; x
+    LD V2, 2          ; y
+    DRW V1, V2, 5     ; DXYN draws N rows of the sprite at I at VX, VY, VF is 0
+    DRW V1, V2, 5     ; pixels are XORed on, drawing again erases it and VF is 1 for that
+    ADD V1, 2
+    DRW V1, V2, 5     ; drawn two pixels to the right, nothing's in the way so VF is 0
+    DRW V1, V2, 5     ; and gone again, VF being 1 is how games tell a collision
+    CLS               ; 00E0 clears the display
+"),
+    ("Skips and jumps", "
+    LD V0, 5
+    SE V0, 5          ; 3XNN skips the next instruction if VX is NN, which it is, watch the PC
+    LD V0, 9          ; skipped
+    SNE V0, 5         ; 4XNN skips if VX isn't NN, V0 is 5 so it doesn't
+    ADD V0, 1         ; and this runs, V0 is 6
+    JP on             ; 1NNN jumps, the PC goes to NNN, here over the next instruction
+    LD V0, 0
+on: CALL sub          ; 2NNN calls a subroutine, the address after the call goes on the stack
+end: JP end           ; an endless loop, the way programs end, type reset or lesson to leave
+sub: ADD V0, 1        ; the subroutine, V0 is 7
+    RET               ; 00EE returns to the address on top of the stack
+"),
+    ("Memory", "
+    LD I, 0x300       ; ANNN points I at memory, the ROM starts at 0x200
+    LD V0, 0xFE       ; 254
+    LD B, V0          ; FX33 writes the decimal digits of VX to I, I+1 and I+2, try mem 0x300
+    LD V2, [I]        ; FX65 reads V0 to VX from I on, so V0 is 2, V1 5 and V2 4
+    LD V0, 1
+    LD V1, 2
+    LD [I], V1        ; FX55 writes V0 to VX to memory at I, with the vip quirks I moves too
+    ADD I, V1         ; FX1E adds a register to I
+"),
+    ("Timers and keys", "
+    LD V0, 60
+    LD DT, V0         ; FX15 sets the delay timer, which counts down 60 times a second
+    LD V1, DT         ; FX07 reads it, it's still 60 as time stands still between steps here
+    LD ST, V0         ; FX18 sets the sound timer, which beeps until it's 0
+    LD V2, 5
+    SKP V2            ; EX9E skips if the key in VX is pressed, type key 5 first to see it skip
+    SKNP V2           ; EXA1 skips if it isn't pressed
+    LD V3, K          ; FX0A waits for a key to be pressed and released, use key twice
+"),
+];
+
+// run implements "rust-chip8 explore", a sandbox for learning what the instructions do: an
+// instruction typed in is run on a blank machine, or on the ROM given, and what it changed shown,
+// with lessons of little programs explained an instruction at a time.
+pub fn run(options: &Options) -> i32 {
+    let data = match options.romPath.is_empty() {
+        true => None,
+        false => match bundle::readRom(&options.romPath) {
+            Ok(data) => Some(data),
+            Err(e) => {
+                eprintln!("{}", e);
+                return 2;
+            }
+        },
+    };
+    let mut explorer = Explorer { emul: Emulator::new(), options, data, comments: Vec::new() };
+    if let Err(e) = explorer.reset() {
+        eprintln!("{}", e);
+        return 2;
+    }
+    println!("Exploring {}, type an instruction like 6105 or \"LD V1, 5\", lesson for the lessons or help",
+        explorer.data.as_ref().map(|_| options.romPath.as_str()).unwrap_or("a blank machine"));
+    explorer.show_next();
+
+    let stdin = io::stdin();
+    loop {
+        print!("(explore) ");
+        io::stdout().flush().ok();
+        let mut line = String::new();
+        match stdin.lock().read_line(&mut line) {
+            Ok(0) | Err(_) => return 0, // end of input
+            Ok(_) => {}
+        }
+        match explorer.execute(line.trim()) {
+            Ok(true) => return 0,
+            Ok(false) => {}
+            Err(e) => println!("{}", e),
+        }
+    }
+}
+
+struct Explorer<'a> {
+    emul: Emulator,
+    options: &'a Options,
+    data: Option<Vec<u8>>, // the ROM, if one was given
+    comments: Vec<(usize, String)>, // the explanations of the instructions of the lesson by address
+}
+
+impl Explorer<'_> {
+    // reset starts over with the ROM, or a blank machine, and no lesson.
+    fn reset(&mut self) -> Result<(), String> {
+        self.emul = Emulator::new();
+        self.emul.quirks = self.options.quirks;
+        self.emul.variant = self.options.variant;
+        self.emul.machineCode = self.options.machineCode;
+        if let Some(data) = &self.data {
+            self.emul.load_rom(data).map_err(|e| format!("Unable to load {}: {}", self.options.romPath, e))?;
+        }
+        self.comments.clear();
+        Ok(())
+    }
+
+    // execute runs a line typed in, returning whether to quit.
+    fn execute(&mut self, line: &str) -> Result<bool, String> {
+        let mut words = line.split_whitespace();
+        let command = words.next().unwrap_or_default();
+        match command.to_ascii_lowercase().as_str() {
+            "" | "next" | "n" => self.step()?,
+            "lesson" => match words.next() {
+                Some(n) => self.start_lesson(n)?,
+                None => {
+                    for (n, (title, _)) in LESSONS.iter().enumerate() {
+                        println!("{}  {}", n + 1, title);
+                    }
+                }
+            },
+            "doc" => {
+                let pc = self.emul.pc as usize;
+                let memory = self.emul.memory.bytes();
+                let b = match words.next() {
+                    Some(w) => u16::from_str_radix(w, 16).map_err(|_| format!("expected an opcode, got '{}'", w))?,
+                    None => (memory[pc % memory.len()] as u16) << 8 | memory[(pc + 1) % memory.len()] as u16,
+                };
+                showDoc(b, self.emul.variant);
+            }
+            "regs" | "r" => self.show_registers(),
+            "screen" => self.show_screen(),
+            "mem" => {
+                let address = words.next().and_then(number).ok_or("mem expects an address")?;
+                let n = words.next().map(|n| n.parse().map_err(|_| format!("expected a number, got '{}'", n))).transpose()?.unwrap_or(16);
+                let memory = self.emul.memory.bytes();
+                for row in (0..n).step_by(16) {
+                    let bytes: Vec<String> = (row..n.min(row + 16)).map(|i| format!("{:02X}", memory[(address + i) % memory.len()])).collect();
+                    println!("{:#05X}  {}", (address + row) % memory.len(), bytes.join(" "));
+                }
+            }
+            "key" => {
+                let key = words.next().and_then(|k| u8::from_str_radix(k, 16).ok()).filter(|k| *k < 16).ok_or("key expects a key, 0-F")?;
+                let pressed = !self.emul.keys.is_pressed(key);
+                self.emul.keys.set(key, pressed);
+                println!("Key {:X} {}", key, if pressed { "pressed" } else { "released" });
+            }
+            "reset" => {
+                self.reset()?;
+                self.show_next();
+            }
+            "help" => println!("{}", HELP),
+            "quit" | "q" => return Ok(true),
+            _ => {
+                let word = match (line.len(), u16::from_str_radix(line, 16)) {
+                    (4, Ok(word)) => word,
+                    _ => match asm::assemble(line, self.emul.pc as usize) {
+                        Ok(bytes) if bytes.len() == 2 => (bytes[0] as u16) << 8 | bytes[1] as u16,
+                        Ok(_) => return Err("expected a single instruction".into()),
+                        Err(e) => return Err(format!("{}, type help for the commands", e.trim_start_matches("line 1: "))),
+                    },
+                };
+                println!("   {:04X}  {}", word, mnemonic(word, self.emul.variant));
+                let pc = self.emul.pc as usize;
+                let memory = self.emul.memory.bytes_mut();
+                memory[pc % memory.len()] = (word >> 8) as u8;
+                memory[(pc + 1) % memory.len()] = word as u8;
+                self.step()?;
+            }
+        }
+        Ok(false)
+    }
+
+    // start_lesson loads a lesson in place of the ROM, leaving the quirks and variant.
+    fn start_lesson(&mut self, n: &str) -> Result<(), String> {
+        let (title, source) = n.parse::<usize>().ok().and_then(|n| LESSONS.get(n.wrapping_sub(1)))
+            .ok_or(format!("there's no lesson {}, there are {}", n, LESSONS.len()))?;
+        let rom = asm::assemble(source, MEM_OFFSET)?;
+        self.reset()?;
+        self.emul.load_rom(&rom).map_err(|e| e.to_string())?;
+        self.comments = source.lines().zip(asm::addresses(source, MEM_OFFSET))
+            .filter_map(|(line, address)| line.split_once(';').map(|(_, comment)| (address, comment.trim().to_string())))
+            .collect();
+        println!("Lesson {}: {}, an empty line runs the next instruction", n, title);
+        self.show_next();
+        Ok(())
+    }
+
+    // step runs the instruction at the PC and shows what it changed, and the display when it
+    // changed, then what comes next.
+    fn step(&mut self) -> Result<(), String> {
+        let before = State::of(&self.emul);
+        let (pc, display) = (self.emul.pc, self.emul.display.to_vec());
+        self.emul.run().map_err(|e| e.to_string())?;
+        let changes = before.changes(&State::of(&self.emul));
+        match self.emul.pc == pc {
+            true if changes.is_empty() => println!("   nothing changed, the PC stays at {:#05X}", pc),
+            _ if changes.is_empty() => println!("   nothing changed but the PC, {:#05X} -> {:#05X}", pc, self.emul.pc),
+            _ => println!("   {}, PC {:#05X} -> {:#05X}", changes.join(", "), pc, self.emul.pc),
+        }
+        if self.emul.display[..] != display[..] {
+            self.show_screen();
+        }
+        self.show_next();
+        Ok(())
+    }
+
+    // show_next prints the instruction at the PC, with its explanation in a lesson.
+    fn show_next(&self) {
+        let memory = self.emul.memory.bytes();
+        let pc = self.emul.pc as usize;
+        let b = (memory[pc % memory.len()] as u16) << 8 | memory[(pc + 1) % memory.len()] as u16;
+        match self.comments.iter().find(|(address, _)| *address == pc) {
+            Some((_, comment)) => println!("-> {:#05X}  {:04X}  {:<16}  ; {}", pc, b, mnemonic(b, self.emul.variant), comment),
+            None if self.comments.is_empty() => {}
+            None if !self.emul.rom_range().contains(&pc) => println!("That's the end of the lesson, type lesson for the others"),
+            None => println!("-> {:#05X}  {:04X}  {}", pc, b, mnemonic(b, self.emul.variant)),
+        }
+    }
+
+    fn show_registers(&self) {
+        let emul = &self.emul;
+        for (r, values) in emul.registers.chunks(8).enumerate() {
+            let values: Vec<String> = values.iter().enumerate().map(|(i, v)| format!("V{:X}={:02X}", r * 8 + i, v)).collect();
+            println!("{}", values.join(" "));
+        }
+        let stack: Vec<String> = emul.stack.iter().map(|a| format!("{:03X}", a)).collect();
+        println!("I={:03X} PC={:03X} DT={:02X} ST={:02X} stack [{}]", emul.I, emul.pc, emul.delayTimer, emul.soundTimer, stack.join(" "));
+    }
+
+    // show_screen draws the display, in lores the whole of it and in hires only the rows with
+    // something on them.
+    fn show_screen(&self) {
+        let (width, height) = self.emul.resolution();
+        for y in 0..height {
+            let row: Vec<bool> = (0..width).map(|x| self.emul.pixel(x, y)).collect();
+            if self.emul.hires && !row.contains(&true) {
+                continue;
+            }
+            println!("   {}", pixelText(&row));
+        }
+    }
+}
+
+fn number(s: &str) -> Option<usize> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
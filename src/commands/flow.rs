@@ -0,0 +1,29 @@
+use rust_chip8::emulator::Emulator;
+use rust_chip8::flow;
+
+use crate::cli::Options;
+use crate::commands::coverage::Coverage;
+
+// run implements "rust-chip8 flow": the control-flow graph of the ROM, see flow, printed in the
+// Graphviz language, or with --html as a page of linked blocks. With --traced what ran counts as
+// code as well, like for "rust-chip8 disasm".
+pub fn run(options: &Options, data: Vec<u8>) -> i32 {
+    let mut emul = Emulator::new();
+    if let Err(e) = emul.load_rom(&data) {
+        eprintln!("Unable to load {}: {}", options.romPath, e);
+        return 2;
+    }
+    let coverage = match options.traced {
+        true => match Coverage::trace(options, &data) {
+            Ok(coverage) => Some(coverage.flags),
+            Err(e) => {
+                eprintln!("{}", e);
+                return 2;
+            }
+        },
+        false => None,
+    };
+    let write = if options.html { flow::html } else { flow::dot };
+    print!("{}", write(emul.memory.bytes(), emul.rom_range(), options.variant, coverage.as_deref(), &options.romPath));
+    0
+}
@@ -0,0 +1,34 @@
+use rust_chip8::emulator::KeyState;
+
+use crate::cli::Options;
+use crate::session::Session;
+
+// run implements "rust-chip8 play --verify": the replay plays back headless, as fast as possible,
+// and the state is checked against every checksum it has. The exit code is 1 if the state
+// differs, so a replay is a regression test of how the emulator runs the ROM, and 2 if the replay
+// can't be played at all.
+pub fn run(options: &Options, data: Vec<u8>) -> i32 {
+    let mut session = match Session::new(options, &data) {
+        Ok(session) => session,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 2;
+        }
+    };
+    for line in session.load_info() {
+        println!("{}", line);
+    }
+    while session.playing() {
+        if let Err(e) = session.tick_frame(KeyState::default()).swap_remove(0) {
+            println!("FAILED   stopped at frame {}: {}", session.frame, e);
+            session.dump_history();
+            return 1;
+        }
+        if let Some(divergence) = session.divergence {
+            println!("FAILED   diverged from the replay by frame {}", divergence.frame);
+            return 1;
+        }
+    }
+    println!("ok       played {} frames", session.frame);
+    0
+}
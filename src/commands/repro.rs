@@ -0,0 +1,92 @@
+use std::panic::{self, AssertUnwindSafe};
+
+use rust_chip8::emulator::Emulator;
+use rust_chip8::replay;
+
+use crate::cli::Options;
+use crate::commands::batch::panicMessage;
+use crate::report::{self, Report};
+use crate::session;
+
+// run implements "rust-chip8 repro <report>.zip": the ROM of a bug report runs headless from the
+// state of start.txt with the keys of inputs.txt, see report, and has to stop on the same error at
+// the same frame, in the very state it stopped in before. A report without a start runs on from
+// where it stopped instead, for --frames frames with the keys held then. The exit code is 0 if the
+// fault reproduces, 1 if it doesn't or not exactly and 2 if the report can't be read. A crash of
+// the emulator is caught and is the error "crashed: <message>", as the window reports it.
+pub fn run(options: &Options) -> i32 {
+    let report = match report::read(&options.romPath) {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 2;
+        }
+    };
+    println!("{} stopped at frame {} with rust-chip8 {}: {}", report.romFile, report.frame, report.version, report.error);
+    let trace: Vec<&str> = report.trace.lines().collect();
+    if !trace.is_empty() {
+        println!("Last {} instructions:", trace.len());
+        for line in trace {
+            println!("  {}", line);
+        }
+    }
+
+    let mut emul = Emulator::new();
+    if let Err(e) = emul.load_rom(&report.rom).map_err(|e| e.to_string()).and_then(|_| report.state.check(&report.rom)) {
+        eprintln!("Unable to load {}: {}", report.romFile, e);
+        return 2;
+    }
+    let (start, startFrame) = match (&report.start, report.frame.checked_sub(1)) {
+        (Some((start, frame)), _) => (start, *frame),
+        (None, Some(frame)) => (&report.state, frame),
+        (None, None) => {
+            eprintln!("{} has no frame that {} stopped at", options.romPath, report.romFile);
+            return 2;
+        }
+    };
+    emul.load_state(start);
+    emul.record_history(options.history);
+    println!("Running {} from frame {} with quirks {}, {} instructions per frame and random state {:08X}",
+        emul.variant.name(), startFrame, emul.quirks.spec(), emul.instructionsPerFrame, emul.random_state());
+
+    let exact = report.start.is_some();
+    let frames = if exact { report.inputs.len() as u64 } else { options.frames };
+    for n in 0..frames {
+        let keys = report.inputs.get(n as usize).copied().unwrap_or(report.state.keys);
+        let error = match panic::catch_unwind(AssertUnwindSafe(|| emul.tick_frame(keys))) {
+            Ok(Ok(_)) => continue,
+            Ok(Err(e)) => e.to_string(),
+            Err(payload) => format!("crashed: {}", panicMessage(payload.as_ref())),
+        };
+        let frame = startFrame + n + 1;
+        if report::flatten(&error) != report.error {
+            println!("Stopped at frame {} with another error: {}", frame, error);
+            session::dumpHistory(&emul);
+            return 1;
+        }
+        if !exact {
+            println!("Reproduced: stopped at frame {} with {}", frame, error);
+            return 0;
+        }
+        return match (frame == report.frame, report.checksum.is_none_or(|c| c == replay::checksum(&emul))) {
+            (true, true) => {
+                println!("Reproduced exactly: stopped at frame {} with {}, in the same state", frame, error);
+                0
+            }
+            (true, false) => difference(&report, "at the same frame but in another state"),
+            (false, _) => difference(&report, &format!("at frame {} rather than {}", frame, report.frame)),
+        };
+    }
+    match exact {
+        true => println!("Not reproduced: ran the {} frames of the report up to frame {} without an error", frames, startFrame + frames),
+        false => println!("Not reproduced: ran {} frames on from frame {} without an error", frames, startFrame),
+    }
+    1
+}
+
+// difference says that the fault came back, but didn't play out the same, which is a bug in itself
+// as the emulator is deterministic.
+fn difference(report: &Report, how: &str) -> i32 {
+    println!("Reproduced, but not exactly: stopped with {} {}", report.error, how);
+    1
+}
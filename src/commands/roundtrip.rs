@@ -0,0 +1,52 @@
+use rust_chip8::asm;
+use rust_chip8::disasm;
+use rust_chip8::emulator::Emulator;
+
+use crate::cli::Options;
+use crate::commands::coverage::Coverage;
+
+// run implements "rust-chip8 roundtrip": the ROM is disassembled like "rust-chip8 disasm" does,
+// with --traced the same way, the source assembled again with rust_chip8::asm and the bytes
+// compared with the ROM, which is a test of both. The exit code is 1 if they differ or the source
+// doesn't assemble.
+pub fn run(options: &Options, data: Vec<u8>) -> i32 {
+    let mut emul = Emulator::new();
+    if let Err(e) = emul.load_rom(&data) {
+        eprintln!("Unable to load {}: {}", options.romPath, e);
+        return 2;
+    }
+    let coverage = match options.traced {
+        true => match Coverage::trace(options, &data) {
+            Ok(coverage) => Some(coverage.flags),
+            Err(e) => {
+                eprintln!("{}", e);
+                return 2;
+            }
+        },
+        false => None,
+    };
+    let rom = emul.rom_range();
+    let source = disasm::program(emul.memory.bytes(), rom.clone(), options.variant, coverage.as_deref());
+    let assembled = match asm::assemble(&source, rom.start) {
+        Ok(assembled) => assembled,
+        Err(e) => {
+            println!("FAILED   the disassembly doesn't assemble, {}", e);
+            return 1;
+        }
+    };
+
+    let original = &emul.memory.bytes()[rom.clone()];
+    let differing: Vec<usize> = (0..original.len().max(assembled.len())).filter(|i| original.get(*i) != assembled.get(*i)).collect();
+    match differing.first() {
+        None => {
+            println!("ok       {} bytes disassembled and assembled back the same", original.len());
+            0
+        }
+        Some(i) => {
+            let byte = |b: Option<&u8>| b.map(|b| format!("{:02X}", b)).unwrap_or("nothing".to_string());
+            println!("FAILED   {} of {} bytes differ, the first at {:#05X}: {} in the ROM, {} assembled",
+                differing.len(), original.len(), rom.start + i, byte(original.get(*i)), byte(assembled.get(*i)));
+            1
+        }
+    }
+}
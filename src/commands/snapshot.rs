@@ -0,0 +1,174 @@
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+use rayon::prelude::*;
+use rust_chip8::emulator::{Emulator, KeyState, DISPLAY_WIDTH};
+
+use crate::cli::Options;
+use crate::commands::batch::romFiles;
+use crate::saves;
+
+// Image is a display at the resolution of its mode, one bool per pixel, true = lit.
+#[derive(PartialEq, Eq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<bool>,
+}
+
+// run implements "rust-chip8 snapshot": every ROM runs headless for --frames frames without any
+// keys pressed, and its final display is compared with the golden snapshots of the ROM in the
+// snapshot directory, <rom name>.txt and/or <rom name>.png. With --update-snapshots the snapshots
+// are written instead, in --snapshot-format. The core is deterministic, so a snapshot only changes
+// when a change to the emulator does change what the ROM draws. The exit code is 1 if any ROM
+// doesn't match its snapshots.
+pub fn run(options: &Options) -> i32 {
+    let roms = match romFiles(Path::new(&options.romPath)) {
+        Ok(roms) => roms,
+        Err(e) => {
+            eprintln!("Unable to list {}: {}", options.romPath, e);
+            return 2;
+        }
+    };
+    if options.updateSnapshots {
+        if let Err(e) = fs::create_dir_all(&options.snapshotDir) {
+            eprintln!("Unable to create {}: {}", options.snapshotDir, e);
+            return 2;
+        }
+    }
+
+    let results: Vec<(PathBuf, Result<String, String>)> = roms.into_par_iter()
+        .map(|rom| {
+            let result = check(&rom, options);
+            (rom, result)
+        })
+        .collect();
+
+    let mut failed = 0;
+    for (rom, result) in &results {
+        match result {
+            Ok(message) => println!("ok       {} {}", rom.display(), message),
+            Err(message) => {
+                failed += 1;
+                println!("FAILED   {} {}", rom.display(), message);
+            }
+        }
+    }
+    println!("{} ROMs, {} failed", results.len(), failed);
+    if failed > 0 { 1 } else { 0 }
+}
+
+// check runs a ROM and compares or updates its snapshots, returning what it did.
+fn check(rom: &Path, options: &Options) -> Result<String, String> {
+    let data = fs::read(rom).map_err(|e| format!("unable to read: {}", e))?;
+    let mut emul = Emulator::new();
+    emul.load_rom(&data).map_err(|e| format!("unable to load: {}", e))?;
+    emul.quirks = options.quirks;
+    emul.variant = options.variant;
+    emul.instructionsPerFrame = options.instructionsPerFrame;
+    for frame in 0..options.frames {
+        emul.tick_frame(KeyState::default()).map_err(|e| format!("stopped at frame {}: {}", frame + 1, e))?;
+    }
+    let actual = image(&emul);
+
+    let base = Path::new(&options.snapshotDir).join(saves::romName(&rom.to_string_lossy()));
+    let text = base.with_extension("txt");
+    let png = base.with_extension("png");
+
+    if options.updateSnapshots {
+        let (path, written) = match options.snapshotFormat.as_str() {
+            "png" => (&png, writePng(&png, &actual)),
+            _ => (&text, fs::write(&text, toText(&actual)).map_err(|e| e.to_string())),
+        };
+        written.map_err(|e| format!("unable to write {}: {}", path.display(), e))?;
+        return Ok(format!("wrote {}", path.display()));
+    }
+
+    let mut compared = Vec::new();
+    for path in [text, png] {
+        if !path.exists() {
+            continue;
+        }
+        let golden = if path.extension().is_some_and(|e| e == "png") { readPng(&path) } else { readText(&path) }
+            .map_err(|e| format!("unable to read {}: {}", path.display(), e))?;
+        if golden != actual {
+            return Err(format!("differs from {}: {}", path.display(), difference(&golden, &actual)));
+        }
+        compared.push(path.display().to_string());
+    }
+    if compared.is_empty() {
+        return Err(format!("no snapshot in {}, run with --update-snapshots to create one", options.snapshotDir));
+    }
+    Ok(format!("matches {}", compared.join(" and ")))
+}
+
+// image takes the display of the emulator at the resolution of its mode.
+pub fn image(emul: &Emulator) -> Image {
+    let (width, height) = emul.resolution();
+    let scale = DISPLAY_WIDTH / width;
+    let pixels = (0..width * height)
+        .map(|i| emul.framebuffer()[(i / width) * scale * DISPLAY_WIDTH + (i % width) * scale] == 1)
+        .collect();
+    Image { width, height, pixels }
+}
+
+// difference describes how two images differ.
+fn difference(golden: &Image, actual: &Image) -> String {
+    if (golden.width, golden.height) != (actual.width, actual.height) {
+        return format!("{}x{} instead of {}x{}", actual.width, actual.height, golden.width, golden.height);
+    }
+    let differing: Vec<usize> = (0..golden.pixels.len()).filter(|i| golden.pixels[*i] != actual.pixels[*i]).collect();
+    let first = differing[0];
+    format!("{} pixels, the first at {},{}", differing.len(), first % golden.width, first / golden.width)
+}
+
+// The text format is a line of # (lit) and . (dark) per row, like the headless frontend prints.
+fn toText(image: &Image) -> String {
+    let mut text = String::new();
+    for row in image.pixels.chunks(image.width) {
+        text.extend(row.iter().map(|lit| if *lit { '#' } else { '.' }));
+        text.push('\n');
+    }
+    text
+}
+
+fn readText(path: &Path) -> Result<Image, String> {
+    let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let rows: Vec<&str> = text.lines().filter(|l| !l.is_empty()).collect();
+    let width = rows.first().map(|r| r.len()).unwrap_or(0);
+    if width == 0 || rows.iter().any(|r| r.len() != width || r.chars().any(|c| c != '#' && c != '.')) {
+        return Err("expected rows of # and . of the same length".to_string());
+    }
+    let pixels = rows.iter().flat_map(|r| r.chars().map(|c| c == '#')).collect();
+    Ok(Image { width, height: rows.len(), pixels })
+}
+
+// The PNG format has a pixel per CHIP8 pixel, written as 8 bit grayscale. Any PNG will do as a
+// snapshot, pixels brighter than half are taken as lit.
+pub fn writePng(path: &Path, image: &Image) -> Result<(), String> {
+    let file = File::create(path).map_err(|e| e.to_string())?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), image.width as u32, image.height as u32);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+    let data: Vec<u8> = image.pixels.iter().map(|lit| if *lit { 0xFF } else { 0x0 }).collect();
+    encoder.write_header().and_then(|mut w| w.write_image_data(&data)).map_err(|e| e.to_string())
+}
+
+fn readPng(path: &Path) -> Result<Image, String> {
+    let mut decoder = png::Decoder::new(File::open(path).map_err(|e| e.to_string())?);
+    // expands palettes and low bit depths to 8 bits per channel.
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let mut reader = decoder.read_info().map_err(|e| e.to_string())?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer).map_err(|e| e.to_string())?;
+    let (width, height) = (info.width as usize, info.height as usize);
+    let channels = info.line_size / width;
+    let pixels = (0..width * height)
+        .map(|i| {
+            let row = &buffer[(i / width) * info.line_size..];
+            row[(i % width) * channels] > 0x7F
+        })
+        .collect();
+    Ok(Image { width, height, pixels })
+}
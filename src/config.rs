@@ -0,0 +1,161 @@
+use std::fs;
+use std::path::Path;
+
+use crate::atomicfile;
+
+// Config is the parsed config file. It's a small subset of TOML: "key = value" lines where value
+// is a "string", a number or true/false, grouped in [sections]. Keys before the first section are
+// global, keys in a [rom.<name>] section only apply to the ROM with that file name (without
+// extension). Every key is the name of a command line option with dashes written as underscores,
+// e.g.
+//
+//   quirks = "vip"
+//   vsync = "off"
+//
+//   [rom.spaceinvaders]
+//   quirks = "vip,clip=off"
+//   persist_range = "0xE00..0xF00"
+//
+// A [hash.<hash>] section is the same for the ROM with that FNV-1a hash, as 16 hex digits in
+// capitals, whatever its file is called. A [preset.<name>] section is a bundle of settings that
+// any section, or --preset, can pick with preset = "<name>", see cli::parseWithConfig. A
+// [players.<name>] section has the player1 and player2 of a two-player game, see input::Players,
+// picked with players = "<name>" or --players.
+#[derive(Default)]
+pub struct Config {
+    sections: Vec<(String, Vec<(String, String)>)>,
+}
+
+// load reads a config file, see paths::config, which is empty when there's none.
+pub fn load(path: &Path) -> Result<Config, String> {
+    if !path.exists() {
+        return Ok(Config::default());
+    }
+    let text = fs::read_to_string(path).map_err(|e| format!("unable to read {}: {}", path.display(), e))?;
+    parse(&text).map_err(|e| match atomicfile::backupPath(path) {
+        backup if backup.exists() => format!("{}: {} (the file as it was before it was last written is {})", path.display(), e, backup.display()),
+        _ => format!("{}: {}", path.display(), e),
+    })
+}
+
+pub fn parse(text: &str) -> Result<Config, String> {
+    let mut config = Config::default();
+    config.sections.push((String::new(), Vec::new()));
+
+    for (n, line) in text.lines().enumerate() {
+        let line = stripComment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            config.sections.push((name.trim().to_string(), Vec::new()));
+            continue;
+        }
+        let (key, value) = line.split_once('=').ok_or(format!("line {}: expected key = value", n + 1))?;
+        let value = value.trim();
+        let value = match value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+            Some(s) => s.to_string(),
+            None if value == "true" || value == "false" || value.parse::<i64>().is_ok() => value.to_string(),
+            None => return Err(format!("line {}: expected a \"string\", a number or true/false", n + 1)),
+        };
+        config.sections.last_mut().unwrap().1.push((key.trim().to_string(), value));
+    }
+    Ok(config)
+}
+
+// stripComment removes a # comment, unless the # is inside a string.
+fn stripComment(line: &str) -> &str {
+    let mut inString = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => inString = !inString,
+            '#' if !inString => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+// setKeys sets keys of a section of a config file, "" for the global keys, in place of the lines
+// they were on, adding the section after the rest when there's none yet. All else in the file,
+// comments included, stays as it is. Values other than true, false and numbers are written as
+// strings.
+pub fn setKeys(path: &Path, section: &str, entries: &[(&str, String)]) -> Result<(), String> {
+    let text = if path.exists() { fs::read_to_string(path).map_err(|e| format!("unable to read {}: {}", path.display(), e))? } else { String::new() };
+    let header = |line: &str| stripComment(line).trim().strip_prefix('[').and_then(|l| l.strip_suffix(']')).map(|n| n.trim().to_string());
+    let key = |line: &str| stripComment(line).split_once('=').map(|(k, _)| k.trim().to_string());
+
+    let mut lines: Vec<String> = text.lines().map(String::from).collect();
+    // the global keys start right at the top, without a header.
+    let found = if section.is_empty() { Some(0) } else { lines.iter().position(|l| header(l).as_deref() == Some(section)).map(|i| i + 1) };
+    let start = match found {
+        Some(i) => i,
+        None => {
+            while lines.last().is_some_and(|l| l.trim().is_empty()) {
+                lines.pop();
+            }
+            if !lines.is_empty() {
+                lines.push(String::new());
+            }
+            lines.push(format!("[{}]", section));
+            lines.len()
+        }
+    };
+    let end = lines[start..].iter().position(|l| header(l).is_some()).map(|i| start + i).unwrap_or(lines.len());
+
+    // the new lines go after the last line of the section that isn't blank.
+    let mut kept: Vec<String> = lines[start..end].iter().filter(|l| key(l).is_none_or(|k| !entries.iter().any(|(e, _)| *e == k))).cloned().collect();
+    let at = kept.len() - kept.iter().rev().take_while(|l| l.trim().is_empty()).count();
+    kept.splice(at..at, entries.iter().map(|(key, value)| {
+        let plain = value == "true" || value == "false" || value.parse::<i64>().is_ok();
+        if plain { format!("{} = {}", key, value) } else { format!("{} = \"{}\"", key, value) }
+    }));
+    lines.splice(start..end, kept);
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).map_err(|e| format!("unable to create {}: {}", dir.display(), e))?;
+    }
+    atomicfile::write(path, (lines.join("\n") + "\n").as_bytes()).map_err(|e| format!("unable to write {}: {}", path.display(), e))
+}
+
+impl Config {
+    // entries returns the keys and values of a section, "" for the global keys.
+    pub fn entries(&self, section: &str) -> Vec<(&str, &str)> {
+        self.sections.iter()
+            .filter(|(name, _)| name == section)
+            .flat_map(|(_, entries)| entries.iter().map(|(key, value)| (key.as_str(), value.as_str())))
+            .collect()
+    }
+
+    // value returns the value of a key of a section, the last one if it's there more than once.
+    pub fn value(&self, section: &str, key: &str) -> Option<&str> {
+        self.entries(section).into_iter().rev().find(|(k, _)| *k == key).map(|(_, v)| v)
+    }
+
+    pub fn has_section(&self, section: &str) -> bool {
+        self.sections.iter().any(|(name, _)| name == section)
+    }
+
+    // args turns the keys of a section into command line arguments, so that the config file and
+    // the command line go through the very same parsing. Boolean keys become flags that are only
+    // passed when true.
+    pub fn args(&self, section: &str) -> Vec<String> {
+        let mut args = Vec::new();
+        for (name, entries) in &self.sections {
+            if name != section {
+                continue;
+            }
+            for (key, value) in entries {
+                let option = format!("--{}", key.replace('_', "-"));
+                match value.as_str() {
+                    "true" => args.push(option),
+                    "false" => {}
+                    _ => {
+                        args.push(option);
+                        args.push(value.clone());
+                    }
+                }
+            }
+        }
+        args
+    }
+}
@@ -0,0 +1,219 @@
+use std::fmt;
+use std::io::{BufRead, Write};
+
+use crate::emulator::{Emulator, DISPLAY_WIDTH};
+use crate::error::Error;
+use crate::hash::fnv1a;
+use crate::reference::Reference;
+
+// Snapshot is the machine state that is compared after every single instruction. Memory and the
+// display are only compared through their hashes so snapshots are cheap to record in trace files.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Snapshot {
+    pub pc: u16,
+    pub I: u16,
+    pub registers: [u8; 16],
+    pub stackDepth: usize,
+    pub delayTimer: u8,
+    pub soundTimer: u8,
+    pub memoryHash: u64,
+    pub displayHash: u64,
+}
+
+impl Snapshot {
+    pub fn of(emul: &Emulator) -> Snapshot {
+        Snapshot {
+            pc: emul.pc,
+            I: emul.I,
+            registers: emul.registers,
+            stackDepth: emul.stack.len(),
+            delayTimer: emul.delayTimer,
+            soundTimer: emul.soundTimer,
+            memoryHash: fnv1a(emul.memory.bytes()),
+            displayHash: displayHash(emul),
+        }
+    }
+
+    pub fn of_reference(reference: &Reference) -> Snapshot {
+        Snapshot {
+            pc: reference.pc,
+            I: reference.i,
+            registers: reference.v,
+            stackDepth: reference.stack.len(),
+            delayTimer: reference.delay,
+            soundTimer: reference.sound,
+            memoryHash: fnv1a(&reference.memory),
+            displayHash: fnv1a(&reference.display),
+        }
+    }
+
+    // to_trace_line formats the snapshot as a line of a trace file:
+    // pc I V0..VF stack-depth delay-timer sound-timer memory-hash display-hash, all in hex.
+    pub fn to_trace_line(&self) -> String {
+        let registers: Vec<String> = self.registers.iter().map(|r| format!("{:02X}", r)).collect();
+        format!("{:04X} {:04X} {} {:X} {:02X} {:02X} {:016X} {:016X}",
+            self.pc, self.I, registers.join(""), self.stackDepth, self.delayTimer, self.soundTimer,
+            self.memoryHash, self.displayHash)
+    }
+
+    pub fn parse_trace_line(line: &str) -> Result<Snapshot, String> {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() != 8 || fields[2].len() != 32 {
+            return Err(format!("malformed trace line '{}'", line));
+        }
+        let hex = |s: &str| u64::from_str_radix(s, 16).map_err(|e| format!("malformed trace line '{}': {}", line, e));
+        let mut registers = [0; 16];
+        for (i, r) in registers.iter_mut().enumerate() {
+            *r = hex(&fields[2][i * 2..i * 2 + 2])? as u8;
+        }
+        Ok(Snapshot {
+            pc: hex(fields[0])? as u16,
+            I: hex(fields[1])? as u16,
+            registers,
+            stackDepth: hex(fields[3])? as usize,
+            delayTimer: hex(fields[4])? as u8,
+            soundTimer: hex(fields[5])? as u8,
+            memoryHash: hex(fields[6])?,
+            displayHash: hex(fields[7])?,
+        })
+    }
+
+    // differences lists every field where actual differs from this (expected) snapshot, as
+    // (field, expected, actual).
+    pub fn differences(&self, actual: &Snapshot) -> Vec<(String, String, String)> {
+        let mut diffs = Vec::new();
+        let mut check = |name: String, expected: String, got: String| {
+            if expected != got {
+                diffs.push((name, expected, got));
+            }
+        };
+        check("PC".to_string(), format!("{:#05X}", self.pc), format!("{:#05X}", actual.pc));
+        check("I".to_string(), format!("{:#05X}", self.I), format!("{:#05X}", actual.I));
+        for r in 0..16 {
+            check(format!("V{:X}", r), format!("{:#04X}", self.registers[r]), format!("{:#04X}", actual.registers[r]));
+        }
+        check("stack depth".to_string(), self.stackDepth.to_string(), actual.stackDepth.to_string());
+        check("delay timer".to_string(), self.delayTimer.to_string(), actual.delayTimer.to_string());
+        check("sound timer".to_string(), self.soundTimer.to_string(), actual.soundTimer.to_string());
+        check("memory hash".to_string(), format!("{:016X}", self.memoryHash), format!("{:016X}", actual.memoryHash));
+        check("display hash".to_string(), format!("{:016X}", self.displayHash), format!("{:016X}", actual.displayHash));
+        diffs
+    }
+}
+
+// displayHash hashes the display at the resolution of the current mode, so that a lores display
+// hashes the same as the 64x32 one of the reference interpreter.
+pub fn displayHash(emul: &Emulator) -> u64 {
+    let (width, height) = emul.resolution();
+    let scale = DISPLAY_WIDTH / width;
+    let pixels: Vec<u8> = (0..width * height)
+        .map(|i| emul.display[(i / width) * scale * DISPLAY_WIDTH + (i % width) * scale])
+        .collect();
+    fnv1a(&pixels)
+}
+
+// Divergence describes the first instruction after which the core no longer matched.
+#[derive(Debug)]
+pub struct Divergence {
+    pub step: u64,   // zero-based number of the instruction that caused the divergence
+    pub pc: u16,     // address of that instruction
+    pub opcode: u16,
+    pub differences: Vec<(String, String, String)>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Divergence after instruction {} ({:04X} at {:#05X}):", self.step, self.opcode, self.pc)?;
+        for (name, expected, actual) in &self.differences {
+            writeln!(f, "  {:<14} expected {}, got {}", name, expected, actual)?;
+        }
+        Ok(())
+    }
+}
+
+// step runs one instruction on the emulator, ticking the timers at the end of every frame's worth
+// of instructions like tick_frame does. It returns the pc and opcode of the instruction and
+// whether it ran.
+fn step(emul: &mut Emulator, n: u64) -> (u16, u16, Result<(), Error>) {
+    let pc = emul.pc;
+    let memory = emul.memory.bytes();
+    let opcode = u16::from_be_bytes([memory[pc as usize % memory.len()], memory[(pc as usize + 1) % memory.len()]]);
+    let result = emul.run();
+    if result.is_ok() && (n + 1).is_multiple_of(emul.instructionsPerFrame as u64) {
+        emul.tick_timers();
+    }
+    (pc, opcode, result)
+}
+
+// againstReference steps the emulator and the reference interpreter in lockstep for the given
+// number of instructions, returning the first divergence if there is one. Otherwise it returns
+// the number of instructions compared, which is less than steps if both stopped on an error.
+pub fn againstReference(emul: &mut Emulator, reference: &mut Reference, steps: u64) -> Result<u64, Divergence> {
+    for n in 0..steps {
+        let (pc, opcode, result) = step(emul, n);
+        let expected = reference.step();
+        match (expected, result) {
+            (Ok(()), Ok(())) => {}
+            (Err(_), Err(_)) => return Ok(n),
+            (expected, actual) => {
+                let describe = |r: Result<(), String>| r.err().unwrap_or("none".to_string());
+                let differences = vec![("error".to_string(), describe(expected), describe(actual.map_err(|e| e.to_string())))];
+                return Err(Divergence { step: n, pc, opcode, differences });
+            }
+        }
+        if (n + 1).is_multiple_of(emul.instructionsPerFrame as u64) {
+            reference.tick_timers();
+        }
+
+        let expected = Snapshot::of_reference(reference);
+        let mut differences = expected.differences(&Snapshot::of(emul));
+        if !differences.is_empty() {
+            // we've got the actual memory of both, so point out where it differs.
+            let memory = emul.memory.bytes();
+            if let Some(addr) = (0..4096).find(|a| memory[*a] != reference.memory[*a]) {
+                differences.push((format!("memory[{:#05X}]", addr), format!("{:#04X}", reference.memory[addr]), format!("{:#04X}", memory[addr])));
+            }
+            return Err(Divergence { step: n, pc, opcode, differences });
+        }
+    }
+    Ok(steps)
+}
+
+// againstTrace steps the emulator and compares it to each line of a trace previously written by
+// recordTrace. It stops at the end of the trace or after the given number of instructions, and
+// fails if the emulator stops on an error before that.
+pub fn againstTrace(emul: &mut Emulator, trace: impl BufRead, steps: u64) -> Result<u64, String> {
+    let mut compared = 0;
+    for (n, line) in trace.lines().enumerate() {
+        let n = n as u64;
+        if n >= steps {
+            break;
+        }
+        let expected = Snapshot::parse_trace_line(&line.map_err(|e| e.to_string())?)?;
+        let (pc, opcode, result) = step(emul, n);
+        if let Err(e) = result {
+            return Err(format!("Stopped at instruction {} ({:04X} at {:#05X}): {}", n, opcode, pc, e));
+        }
+        let differences = expected.differences(&Snapshot::of(emul));
+        if !differences.is_empty() {
+            return Err(Divergence { step: n, pc, opcode, differences }.to_string());
+        }
+        compared += 1;
+    }
+    Ok(compared)
+}
+
+// recordTrace runs the emulator for the given number of instructions, writing a trace line with
+// the state after each of them. It returns the number of instructions recorded, which is less
+// than steps if the emulator stopped on an error.
+pub fn recordTrace(emul: &mut Emulator, out: &mut impl Write, steps: u64) -> std::io::Result<u64> {
+    for n in 0..steps {
+        if step(emul, n).2.is_err() {
+            out.flush()?;
+            return Ok(n);
+        }
+        writeln!(out, "{}", Snapshot::of(emul).to_trace_line())?;
+    }
+    out.flush()?;
+    Ok(steps)
+}
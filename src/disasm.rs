@@ -0,0 +1,311 @@
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use crate::emulator::{decode, Opcode};
+use crate::memory::COVERED_EXECUTED;
+use crate::sprites;
+use crate::variant::Variant;
+
+// mnemonic disassembles an instruction word into the usual assembler syntax of Cowgod's technical
+// reference, e.g. "LD V3, 0x1F" or "DRW V0, V1, 5". Words that aren't instructions of the variant
+// come out as "DW 0x1234", like data.
+pub fn mnemonic(b: u16, variant: Variant) -> String {
+    let Opcode { instr, X, Y, N, NN, NNN } = decode(b);
+    let schip = variant == Variant::Schip;
+    match (instr, X, Y, N) {
+        (0x0, 0x0, 0xE, 0x0) => "CLS".into(),
+        (0x0, 0x0, 0xE, 0xE) => "RET".into(),
+        (0x0, 0x0, 0xC, _) if schip => format!("SCD {}", N),
+        (0x0, 0x0, 0xF, 0xB) if schip => "SCR".into(),
+        (0x0, 0x0, 0xF, 0xC) if schip => "SCL".into(),
+        (0x0, 0x0, 0xF, 0xD) if schip => "EXIT".into(),
+        (0x0, 0x0, 0xF, 0xE) if schip => "LOW".into(),
+        (0x0, 0x0, 0xF, 0xF) if schip => "HIGH".into(),
+        (0x1, _, _, _) => format!("JP {:#05X}", NNN),
+        (0x2, _, _, _) => format!("CALL {:#05X}", NNN),
+        (0x3, _, _, _) => format!("SE V{:X}, {:#04X}", X, NN),
+        (0x4, _, _, _) => format!("SNE V{:X}, {:#04X}", X, NN),
+        (0x5, _, _, 0x0) => format!("SE V{:X}, V{:X}", X, Y),
+        (0x6, _, _, _) => format!("LD V{:X}, {:#04X}", X, NN),
+        (0x7, _, _, _) => format!("ADD V{:X}, {:#04X}", X, NN),
+        (0x8, _, _, 0x0) => format!("LD V{:X}, V{:X}", X, Y),
+        (0x8, _, _, 0x1) => format!("OR V{:X}, V{:X}", X, Y),
+        (0x8, _, _, 0x2) => format!("AND V{:X}, V{:X}", X, Y),
+        (0x8, _, _, 0x3) => format!("XOR V{:X}, V{:X}", X, Y),
+        (0x8, _, _, 0x4) => format!("ADD V{:X}, V{:X}", X, Y),
+        (0x8, _, _, 0x5) => format!("SUB V{:X}, V{:X}", X, Y),
+        (0x8, _, _, 0x6) => format!("SHR V{:X}, V{:X}", X, Y),
+        (0x8, _, _, 0x7) => format!("SUBN V{:X}, V{:X}", X, Y),
+        (0x8, _, _, 0xE) => format!("SHL V{:X}, V{:X}", X, Y),
+        (0x9, _, _, 0x0) => format!("SNE V{:X}, V{:X}", X, Y),
+        (0xA, _, _, _) => format!("LD I, {:#05X}", NNN),
+        (0xB, _, _, _) => format!("JP V0, {:#05X}", NNN),
+        (0xC, _, _, _) => format!("RND V{:X}, {:#04X}", X, NN),
+        (0xD, _, _, _) => format!("DRW V{:X}, V{:X}, {}", X, Y, N),
+        (0xE, _, 0x9, 0xE) => format!("SKP V{:X}", X),
+        (0xE, _, 0xA, 0x1) => format!("SKNP V{:X}", X),
+        (0xF, _, 0x0, 0x7) => format!("LD V{:X}, DT", X),
+        (0xF, _, 0x0, 0xA) => format!("LD V{:X}, K", X),
+        (0xF, _, 0x1, 0x5) => format!("LD DT, V{:X}", X),
+        (0xF, _, 0x1, 0x8) => format!("LD ST, V{:X}", X),
+        (0xF, _, 0x1, 0xE) => format!("ADD I, V{:X}", X),
+        (0xF, _, 0x2, 0x9) => format!("LD F, V{:X}", X),
+        (0xF, _, 0x3, 0x0) if schip => format!("LD HF, V{:X}", X),
+        (0xF, _, 0x3, 0x3) => format!("LD B, V{:X}", X),
+        (0xF, _, 0x5, 0x5) => format!("LD [I], V{:X}", X),
+        (0xF, _, 0x6, 0x5) => format!("LD V{:X}, [I]", X),
+        (0xF, _, 0x7, 0x5) => format!("LD R, V{:X}", X),
+        (0xF, _, 0x8, 0x5) => format!("LD V{:X}, R", X),
+        _ => format!("DW {:#06X}", b),
+    }
+}
+
+// Doc is an instruction in the reference: what it does, to VF and under which quirks it works
+// differently, in words short enough for the overlay of the window.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Doc {
+    pub pattern: &'static str, // the opcode, with X, Y and N for its operands
+    pub syntax: &'static str,  // as mnemonic writes it, with the operands as in the pattern
+    pub summary: &'static str,
+    pub flags: &'static str,  // what it does to VF, empty when nothing
+    pub quirks: &'static str, // how quirks change it, empty when they don't
+    pub schip: bool,          // only in the schip variant
+}
+
+const fn doc(pattern: &'static str, syntax: &'static str, summary: &'static str, flags: &'static str, quirks: &'static str, schip: bool) -> Doc {
+    Doc { pattern, syntax, summary, flags, quirks, schip }
+}
+
+// REFERENCE documents the instructions mnemonic knows, the first matching opcode counting, so
+// the more specific patterns of 0NNN come first.
+pub const REFERENCE: [Doc; 44] = [
+    doc("00E0", "CLS", "Clears the display.", "", "", false),
+    doc("00EE", "RET", "Returns from a subroutine, to the address on top of the stack.", "", "", false),
+    doc("00CN", "SCD N", "Scrolls the display down N pixel rows.", "", "halfscroll: N lores pixels in lores.", true),
+    doc("00FB", "SCR", "Scrolls the display right 4 pixels.", "", "halfscroll: 4 lores pixels in lores.", true),
+    doc("00FC", "SCL", "Scrolls the display left 4 pixels.", "", "halfscroll: 4 lores pixels in lores.", true),
+    doc("00FD", "EXIT", "Stops the interpreter.", "", "", true),
+    doc("00FE", "LOW", "Switches to the 64x32 lores mode.", "", "", true),
+    doc("00FF", "HIGH", "Switches to the 128x64 hires mode.", "", "", true),
+    doc("0NNN", "DW 0x0NNN", "Calls the 1802 machine code at NNN on a COSMAC VIP, see --machine-code for what happens here.", "", "", false),
+    doc("1NNN", "JP NNN", "Jumps to NNN.", "", "", false),
+    doc("2NNN", "CALL NNN", "Calls the subroutine at NNN, pushing the address after the call.", "", "stack: how deep calls can nest.", false),
+    doc("3XNN", "SE VX, NN", "Skips the next instruction if VX is NN.", "", "", false),
+    doc("4XNN", "SNE VX, NN", "Skips the next instruction if VX isn't NN.", "", "", false),
+    doc("5XY0", "SE VX, VY", "Skips the next instruction if VX is VY.", "", "", false),
+    doc("6XNN", "LD VX, NN", "Sets VX to NN.", "", "", false),
+    doc("7XNN", "ADD VX, NN", "Adds NN to VX, wrapping around at 256.", "Left alone, there's no carry.", "", false),
+    doc("8XY0", "LD VX, VY", "Sets VX to VY.", "", "", false),
+    doc("8XY1", "OR VX, VY", "Sets VX to VX OR VY.", "", "vfreset: VF is set to 0.", false),
+    doc("8XY2", "AND VX, VY", "Sets VX to VX AND VY.", "", "vfreset: VF is set to 0.", false),
+    doc("8XY3", "XOR VX, VY", "Sets VX to VX XOR VY.", "", "vfreset: VF is set to 0.", false),
+    doc("8XY4", "ADD VX, VY", "Adds VY to VX.", "1 if the sum is over 255, else 0.", "", false),
+    doc("8XY5", "SUB VX, VY", "Sets VX to VX - VY.", "0 if it borrows, else 1.", "", false),
+    doc("8XY6", "SHR VX, VY", "Shifts VX right a bit, halving it.", "The bit shifted out.", "shift: VY is shifted into VX.", false),
+    doc("8XY7", "SUBN VX, VY", "Sets VX to VY - VX.", "0 if it borrows, else 1.", "", false),
+    doc("8XYE", "SHL VX, VY", "Shifts VX left a bit, doubling it.", "The bit shifted out.", "shift: VY is shifted into VX.", false),
+    doc("9XY0", "SNE VX, VY", "Skips the next instruction if VX isn't VY.", "", "", false),
+    doc("ANNN", "LD I, NNN", "Sets I to NNN.", "", "", false),
+    doc("BNNN", "JP V0, NNN", "Jumps to NNN + V0.", "", "jump: to NNN + VX.", false),
+    doc("CXNN", "RND VX, NN", "Sets VX to a random byte AND NN.", "", "", false),
+    doc("DXYN", "DRW VX, VY, N", "Draws N rows of the 8 pixel wide sprite at I at VX, VY, XORed onto the display. N of 0 draws 16x16 in schip.", "1 if a lit pixel was turned off, else 0.", "clip: clipped at the edges, not wrapped. rowcollisions: VF counts rows.", false),
+    doc("EX9E", "SKP VX", "Skips the next instruction if the key in VX is pressed.", "", "", false),
+    doc("EXA1", "SKNP VX", "Skips the next instruction if the key in VX isn't pressed.", "", "", false),
+    doc("FX07", "LD VX, DT", "Sets VX to the delay timer.", "", "", false),
+    doc("FX0A", "LD VX, K", "Waits for a key to be pressed and released, and sets VX to it.", "", "", false),
+    doc("FX15", "LD DT, VX", "Sets the delay timer to VX, which counts down at 60hz.", "", "", false),
+    doc("FX18", "LD ST, VX", "Sets the sound timer to VX, which beeps until it's counted down.", "", "", false),
+    doc("FX1E", "ADD I, VX", "Adds VX to I.", "Left alone.", "indexoverflow: 1 if I passes 0xFFF.", false),
+    doc("FX29", "LD F, VX", "Points I at the 4x5 font sprite of the digit in VX.", "", "", false),
+    doc("FX30", "LD HF, VX", "Points I at the 8x10 big font sprite of the digit in VX.", "", "", true),
+    doc("FX33", "LD B, VX", "Writes the three decimal digits of VX to I, I+1 and I+2.", "", "", false),
+    doc("FX55", "LD [I], VX", "Writes V0 to VX to memory from I on.", "", "loadstore: I ends past the last.", false),
+    doc("FX65", "LD VX, [I]", "Reads V0 to VX from memory from I on.", "", "loadstore: I ends past the last.", false),
+    doc("FX75", "LD R, VX", "Saves V0 to VX in the RPL flags of SCHIP, which are kept for the next run.", "", "", false),
+    doc("FX85", "LD VX, R", "Loads V0 to VX from the RPL flags.", "", "", false),
+];
+
+// reference returns the entry of the reference for an instruction word, or None for a word that
+// isn't an instruction of the variant.
+pub fn reference(b: u16, variant: Variant) -> Option<&'static Doc> {
+    let matches = |pattern: &str| pattern.chars().enumerate().all(|(i, c)| {
+        let digit = (b >> (12 - 4 * i)) & 0xF;
+        matches!(c, 'X' | 'Y' | 'N') || c.to_digit(16) == Some(digit as u32)
+    });
+    REFERENCE.iter().find(|doc| (!doc.schip || variant == Variant::Schip) && matches(doc.pattern))
+}
+
+// branchTarget returns where an instruction can go other than the next one: the address of a
+// jump or call, or the instruction after next for the skips. JP V0 and RET depend on the
+// registers and the stack, so they have none.
+pub fn branchTarget(pc: u16, b: u16) -> Option<u16> {
+    let Opcode { instr, Y, N, NNN, .. } = decode(b);
+    match (instr, Y, N) {
+        (0x1 | 0x2, _, _) => Some(NNN),
+        (0x3 | 0x4, _, _) | (0x5 | 0x9, _, 0x0) | (0xE, 0x9, 0xE) | (0xE, 0xA, 0x1) => Some(pc.wrapping_add(4)),
+        _ => None,
+    }
+}
+
+// Line is an instruction of a disassembly listing.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Line {
+    pub address: u16,
+    pub opcode: u16,
+    pub mnemonic: String,
+    pub target: Option<u16>, // see branchTarget
+}
+
+// listing disassembles count words of memory from an address on, wrapping around at the end. Data
+// between the instructions is disassembled all the same, see program for telling them apart.
+pub fn listing(memory: &[u8], from: u16, count: usize, variant: Variant) -> Vec<Line> {
+    (0..count)
+        .map(|i| {
+            let address = (from as usize + 2 * i) % memory.len();
+            let opcode = (memory[address] as u16) << 8 | memory[(address + 1) % memory.len()] as u16;
+            Line { address: address as u16, opcode, mnemonic: mnemonic(opcode, variant), target: branchTarget(address as u16, opcode) }
+        })
+        .collect()
+}
+
+// arrows draws the branches between the lines of a listing in a gutter of the given number of
+// columns, returning it for every line: a branch runs down a column from a + at its instruction
+// to a + at its target, which gets a >. The shortest branches are nearest to the instructions,
+// and those that leave the listing or don't fit aren't drawn.
+pub fn arrows(lines: &[Line], columns: usize) -> Vec<String> {
+    let row = |address: u16| lines.iter().position(|l| l.address == address);
+    let mut branches: Vec<(usize, usize)> = lines.iter().enumerate()
+        .filter_map(|(from, l)| Some((from, row(l.target?)?)))
+        .collect();
+    branches.sort_by_key(|(from, to)| from.abs_diff(*to));
+
+    let mut gutter = vec![vec![' '; columns + 1]; lines.len()];
+    for (from, to) in branches {
+        let (top, bottom) = (from.min(to), from.max(to));
+        // the column nearest to the instructions that's free all along the branch.
+        let Some(column) = (0..columns).rev().find(|c| (top..=bottom).all(|r| gutter[r][*c] == ' ' || (r != top && r != bottom && gutter[r][*c] == '-'))) else {
+            continue;
+        };
+        for (r, line) in gutter.iter_mut().enumerate().take(bottom + 1).skip(top) {
+            if r == top || r == bottom {
+                line[column] = '+';
+                for c in line.iter_mut().skip(column + 1) {
+                    if *c == ' ' {
+                        *c = '-';
+                    }
+                }
+            } else if line[column] == ' ' || line[column] == '-' {
+                line[column] = '|';
+            }
+        }
+        gutter[to][columns] = '>';
+    }
+    gutter.into_iter().map(|line| line.into_iter().collect()).collect()
+}
+
+// Kind is what a byte of a ROM is to program: the first or second byte of an instruction, or data.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    Code,
+    Operand,
+    Data,
+}
+
+// DATA_BYTES is how many bytes of data other than sprites go on a line of a program.
+const DATA_BYTES: usize = 8;
+
+// classify tells the code of a ROM from its data: the code is what the jumps, calls and skips can
+// reach from where the ROM starts, and with the coverage flags of a run (see Memory::coverage)
+// also what ran, which takes in the code only reached through JP V0 or a return address changed
+// on the stack. Everything else is data.
+pub fn classify(memory: &[u8], rom: Range<usize>, coverage: Option<&[u8]>) -> Vec<Kind> {
+    let mut kinds = vec![Kind::Data; rom.len()];
+    let mut todo = vec![rom.start];
+    // the instructions of a stretch of bytes that ran are taken to start at its first byte.
+    if let Some(coverage) = coverage {
+        todo.extend(rom.clone().filter(|a| coverage[*a] & COVERED_EXECUTED != 0 && (*a == rom.start || coverage[a - 1] & COVERED_EXECUTED == 0)));
+    }
+    while let Some(pc) = todo.pop() {
+        if !(rom.start <= pc && pc + 1 < rom.end) || kinds[pc - rom.start] != Kind::Data || kinds[pc + 1 - rom.start] != Kind::Data {
+            continue;
+        }
+        kinds[pc - rom.start] = Kind::Code;
+        kinds[pc + 1 - rom.start] = Kind::Operand;
+        let b = (memory[pc] as u16) << 8 | memory[pc + 1] as u16;
+        let Opcode { instr, NNN, .. } = decode(b);
+        match (instr, b) {
+            (0x0, 0x00EE | 0x00FD) | (0xB, _) => {}
+            (0x1, _) => todo.push(NNN as usize),
+            (0x2, _) => todo.extend([NNN as usize, pc + 2]),
+            _ => match branchTarget(pc as u16, b) {
+                Some(skip) => todo.extend([skip as usize, pc + 2]),
+                None => todo.push(pc + 2),
+            },
+        }
+    }
+    kinds
+}
+
+// program disassembles a ROM into an assembler source that assembles back into the very same
+// bytes: the code as in mnemonic, at labels L<addr> where it's jumped to or called, and the data
+// as .byte lines at labels D<addr> where I is pointed at it. Data that's pointed at is taken for
+// sprites and gets a row per line, with its pixels in a comment, the rest DATA_BYTES to the line.
+// An address outside of the ROM or in the middle of an instruction stays a number.
+pub fn program(memory: &[u8], rom: Range<usize>, variant: Variant, coverage: Option<&[u8]>) -> String {
+    let kinds = classify(memory, rom.clone(), coverage);
+    let kind = |address: usize| rom.contains(&address).then(|| kinds[address - rom.start]);
+    let word = |address: usize| (memory[address] as u16) << 8 | memory[address + 1] as u16;
+
+    let mut labels = BTreeMap::new();
+    for address in rom.clone().filter(|a| kind(*a) == Some(Kind::Code)) {
+        let Opcode { instr, NNN, .. } = decode(word(address));
+        let target = NNN as usize;
+        match (instr, kind(target)) {
+            (0x1 | 0x2 | 0xA | 0xB, Some(Kind::Code)) => labels.insert(target, format!("L{:03X}", target)),
+            (0xA | 0xB, Some(Kind::Data)) => labels.insert(target, format!("D{:03X}", target)),
+            _ => None,
+        };
+    }
+    let wide: Vec<usize> = sprites::find(memory, rom.start as u16, rom.end as u16, variant).into_iter()
+        .filter(|s| s.wide)
+        .map(|s| s.address as usize)
+        .collect();
+
+    let code = kinds.iter().filter(|k| **k != Kind::Data).count();
+    let mut text = format!("; {} bytes from {:#05X}, {} of code and {} of data\n", rom.len(), rom.start, code, rom.len() - code);
+    let mut address = rom.start;
+    while address < rom.end {
+        if let Some(label) = labels.get(&address) {
+            text.push_str(&format!("{}:\n", label));
+        }
+        if kind(address) == Some(Kind::Code) {
+            let b = word(address);
+            let mut line = mnemonic(b, variant);
+            if let (0x1 | 0x2 | 0xA | 0xB, Some(label)) = (decode(b).instr, labels.get(&(decode(b).NNN as usize))) {
+                line = line.replace(&format!("{:#05X}", decode(b).NNN), label);
+            }
+            text.push_str(&format!("    {}\n", line));
+            address += 2;
+            continue;
+        }
+        // the data up to the next instruction or label
+        let end = (address + 1..rom.end).find(|a| kind(*a) == Some(Kind::Code) || labels.contains_key(a)).unwrap_or(rom.end);
+        let sprite = labels.get(&address).is_some_and(|l| l.starts_with('D'));
+        let rowBytes = if !sprite { DATA_BYTES } else if wide.contains(&address) { 2 } else { 1 };
+        for row in memory[address..end].chunks(rowBytes) {
+            let bytes: Vec<String> = row.iter().map(|b| format!("{:#04X}", b)).collect();
+            text.push_str(&format!("    .byte {}", bytes.join(", ")));
+            if sprite {
+                let pixels: String = row.iter().flat_map(|b| (0..8).rev().map(move |bit| if b >> bit & 1 == 1 { '#' } else { '.' })).collect();
+                text.push_str(&format!("{:width$}; {}", "", pixels, width = (rowBytes - row.len()) * 6 + 2));
+            }
+            text.push('\n');
+        }
+        address = end;
+    }
+    text
+}
@@ -0,0 +1,1557 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use crate::cdp1802::Cdp1802;
+use crate::error::Error;
+use crate::hash::fnv1a;
+use crate::history::{Entry, History, Undo};
+use crate::memory::{Memory, MEMORY_SIZE};
+use crate::quirks::Quirks;
+use crate::savestate::State;
+use crate::variant::Variant;
+
+pub const MEM_OFFSET: usize = 0x200;
+pub const FONT_OFFSET: u16 = 0x50;
+pub const BIG_FONT_OFFSET: u16 = 0xA0; // right after FONT
+
+// the resolution of CHIP8 and SCHIP lores mode
+pub const SCREEN_WIDTH: usize = 64;
+pub const SCREEN_HEIGHT: usize = 32;
+
+// The display always has the SCHIP hires resolution. In lores mode every pixel covers 2x2 display
+// pixels, which is also how SCHIP 1.1 on the HP48 could scroll by half a lores pixel.
+pub const DISPLAY_WIDTH: usize = 128;
+pub const DISPLAY_HEIGHT: usize = 64;
+
+// Langhoffs guide recommends 700 ops/s, but I think that's on the slow side. 16 instructions per
+// 60hz frame gives us approx. 1000 ops/s.
+pub const INSTRUCTIONS_PER_FRAME: u32 = 16;
+
+// the seed used for CXNN until a frontend picks another one through Emulator::seed.
+pub const DEFAULT_SEED: u32 = 0xC8C8C8C8;
+
+// KeyState holds the pressed/released state of the 16 CHIP8 keys (0x0-0xF), one bit per key.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct KeyState(pub u16);
+
+impl KeyState {
+    pub fn set(&mut self, key: u8, pressed: bool) {
+        if pressed {
+            self.0 |= 1 << (key & 0xF);
+        } else {
+            self.0 &= !(1 << (key & 0xF));
+        }
+    }
+
+    pub fn is_pressed(&self, key: u8) -> bool {
+        self.0 & (1 << (key & 0xF)) > 0
+    }
+}
+
+// PixelChange describes a single pixel that was toggled during a frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PixelChange {
+    pub x: u8,
+    pub y: u8,
+    pub on: bool,
+}
+
+// Region is a rectangle of display pixels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Region {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Region {
+    pub fn full() -> Region {
+        Region { x: 0, y: 0, width: DISPLAY_WIDTH, height: DISPLAY_HEIGHT }
+    }
+
+    // union returns the smallest region covering both regions.
+    pub fn union(&self, other: Region) -> Region {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        Region {
+            x,
+            y,
+            width: (self.x + self.width).max(other.x + other.width) - x,
+            height: (self.y + self.height).max(other.y + other.height) - y,
+        }
+    }
+}
+
+// SelfModification is a ROM running code it has written itself, see track_self_modification.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SelfModification {
+    pub pc: u16,     // where the modified instruction ran
+    pub writer: u16, // the instruction that last wrote it, FX33 or FX55
+}
+
+// UnknownOpcode is an opcode that isn't an instruction of the variant, see unknown_opcodes. ROMs
+// running into data run a few of those, a ROM needing instructions the variant doesn't have tends
+// to run the same ones over and over.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UnknownOpcode {
+    pub opcode: u16,
+    pub pc: u16,     // where it first ran
+    pub count: u64,  // how often it ran
+    pub schip: bool, // it's an SCHIP instruction, so the ROM may be for SCHIP
+}
+
+// MachineCode is what 0NNN does. On the COSMAC VIP it calls the 1802 machine code at NNN, which
+// later interpreters don't have, so most ROMs never use it and running one is usually a ROM
+// running into data.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MachineCode {
+    Skip, // do nothing, noting the call as an unknown opcode so it gets warned about
+    Halt, // stop with Error::MachineCode
+    Vip,  // run the routines like the VIP would, on a CDP1802 core but for the well-known ones
+}
+
+impl MachineCode {
+    pub fn parse(name: &str) -> Result<MachineCode, String> {
+        match name {
+            "skip" => Ok(MachineCode::Skip),
+            "halt" => Ok(MachineCode::Halt),
+            "vip" => Ok(MachineCode::Vip),
+            _ => Err(format!("unknown machine code mode '{}', expected skip, halt or vip", name)),
+        }
+    }
+
+    // name returns the mode as parse takes it.
+    pub fn name(&self) -> &'static str {
+        match self {
+            MachineCode::Skip => "skip",
+            MachineCode::Halt => "halt",
+            MachineCode::Vip => "vip",
+        }
+    }
+}
+
+// VIP_CLEAR_HIRES is the routine the hires CHIP8 ROMs of the VIP call to clear their 64x64
+// display. With the display this emulator has it clears the screen like 00E0.
+const VIP_CLEAR_HIRES: u16 = 0x230;
+
+// The VIP's CHIP8 interpreter keeps its state at the end of its 4kb, where machine code routines
+// look for it: the 1802 stack growing down from VIP_STACK, V0-VF from VIP_REGISTERS and the
+// display from VIP_DISPLAY, 8 bytes of 64 pixels per row.
+const VIP_STACK: u16 = 0xECF;
+const VIP_REGISTERS: u16 = 0xEF0;
+const VIP_DISPLAY: u16 = 0xF00;
+
+// MACHINE_CODE_STEPS is how many 1802 instructions a routine gets to return to the interpreter.
+// The routines of hybrid ROMs take some hundreds.
+const MACHINE_CODE_STEPS: u32 = 100_000;
+
+// FrameOutput is what a frontend gets back from tick_frame: the pixels that differ from the
+// previous frame, whether the buzzer should be sounding and which keys the ROM saw pressed.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FrameOutput {
+    pub diff: Vec<PixelChange>,
+    pub sound: bool,
+    pub keysRead: KeyState, // keys that EX9E, EXA1 or FX0A found pressed during the frame
+}
+
+// initEmulator returns an emulator with the ROM loaded at 0x200, panicking if it doesn't fit.
+pub fn initEmulator(data: Vec<u8>) -> Emulator {
+    let mut emul = Emulator::new();
+    emul.load_rom(&data).expect("Unable to load ROM");
+    emul
+}
+
+#[derive(Clone)]
+pub struct Emulator {
+    pub memory: Memory,      // Our 4kb of RAM
+    pub stack: Vec<u16>,     // return addresses of the subroutine calls, at most quirks.stackDepth of them
+    pub I: u16,              // represents Index register
+    pub registers: [u8; 16], // represents the 16 1-byte registers
+    pub pc: u16,             // Program counter, set it to the initial memory offset
+    pub delayTimer: u8,      // represents the delay timer that's decremented at 60hz if > 0
+    pub soundTimer: u8,      // represents the sound timer that's decremented at 60hz and plays a beep if > 0.
+    pub display: [u8; DISPLAY_WIDTH * DISPLAY_HEIGHT], // one byte per pixel, 1 = lit, 0 = dark
+    front: [u8; DISPLAY_WIDTH * DISPLAY_HEIGHT], // the display as of the end of the last frame, see framebuffer
+    pub hires: bool,         // SCHIP 128x64 mode, switched with 00FF and 00FE
+    pub keys: KeyState,      // state of the 16 keys, as passed to the latest tick_frame
+    pub instructionsPerFrame: u32, // how many instructions tick_frame runs before ticking the timers
+    pub haltOnUnknownOpcode: bool, // stop with an error at an unknown opcode rather than skipping it
+    pub machineCode: MachineCode, // what 0NNN does
+    pub quirks: Quirks,      // interpreter behaviours that differ between CHIP8 implementations
+    pub variant: Variant,    // the dialect, which decides whether the SCHIP instructions exist
+    rng: u32,                // xorshift state for CXNN, so two emulators with the same seed behave identically
+    heldKey: Option<u8>,     // the key FX0A saw being pressed and is waiting to be released
+    keysRead: KeyState,      // the keys the ROM has seen pressed during the current frame
+    pub rplFlags: [u8; 8],   // the HP48 "RPL user flags" that SCHIP lets FX75/FX85 save registers to
+    rplChanged: bool,        // set by FX75 until a frontend has picked up the new flags
+    rom: Vec<u8>,            // the loaded ROM, kept for reset
+    dirty: Option<Region>,   // the part of the display changed since the last take_dirty_region
+    loadAddress: u16,        // where the ROM is loaded and execution starts
+    trimmedPadding: usize,   // the zero bytes at the end of the ROM that didn't fit and were left out
+    pcWatches: Vec<u16>,     // addresses to note when execution reaches them, see watch_pc
+    pcHits: Vec<u16>,        // the watched addresses reached since the last take_pc_hits
+    unknownOpcodes: Vec<UnknownOpcode>, // the opcodes without an instruction run since the last reset
+    unknownReported: usize,  // how many of unknownOpcodes take_new_unknown_opcodes returned
+    writers: Vec<Option<u16>>, // per address the instruction that last wrote it, empty unless tracking
+    selfModifications: Vec<SelfModification>, // found since the last take_self_modifications
+    history: Option<History>, // the last instructions that ran, if recording
+    writes: Vec<(u16, u8)>,  // address and old value of the bytes the current instruction wrote, if recording
+}
+
+impl Emulator {
+
+    // new returns an emulator without a ROM, in the state it's in right after power-on.
+    pub fn new() -> Emulator {
+        let mut emul = Emulator {
+            memory: Memory::new(MEMORY_SIZE),
+            stack: Vec::new(),
+            I: 0,
+            registers: [0; 16],
+            pc: MEM_OFFSET as u16,
+            delayTimer: 0x0,
+            soundTimer: 0x0,
+            display: [0; DISPLAY_WIDTH * DISPLAY_HEIGHT],
+            front: [0; DISPLAY_WIDTH * DISPLAY_HEIGHT],
+            hires: false,
+            keys: KeyState::default(),
+            instructionsPerFrame: INSTRUCTIONS_PER_FRAME,
+            haltOnUnknownOpcode: false,
+            machineCode: MachineCode::Skip,
+            quirks: Quirks::default(),
+            variant: Variant::Chip8,
+            rng: DEFAULT_SEED,
+            heldKey: None,
+            keysRead: KeyState::default(),
+            rplFlags: [0; 8],
+            rplChanged: false,
+            rom: Vec::new(),
+            dirty: None,
+            loadAddress: MEM_OFFSET as u16,
+            trimmedPadding: 0,
+            pcWatches: Vec::new(),
+            pcHits: Vec::new(),
+            unknownOpcodes: Vec::new(),
+            unknownReported: 0,
+            writers: Vec::new(),
+            selfModifications: Vec::new(),
+            history: None,
+            writes: Vec::new(),
+        };
+        emul.reset();
+        emul
+    }
+
+    // load_rom replaces the ROM with another one loaded at 0x200 and resets the emulator.
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), Error> {
+        self.load_rom_at(rom, MEM_OFFSET)
+    }
+
+    // load_rom_at is load_rom for ROMs that expect to be loaded elsewhere, e.g. at 0x600 for the
+    // ETI 660. On error the current ROM is left as it was. A ROM that doesn't fit in memory is an
+    // error, unless all that doesn't fit are zeros: dumps of a whole 4kb memory image and ROMs
+    // padded up to some size are, and the padding is left out, see trimmed_padding.
+    pub fn load_rom_at(&mut self, rom: &[u8], address: usize) -> Result<(), Error> {
+        if address < MEM_OFFSET || address >= self.memory.len() || !address.is_multiple_of(2) {
+            return Err(Error::InvalidLoadAddress(address));
+        }
+        if rom.is_empty() {
+            return Err(Error::EmptyRom);
+        }
+        let max = self.memory.len() - address;
+        if rom.len() > max && rom[max..].iter().any(|b| *b != 0) {
+            return Err(Error::RomTooLarge { size: rom.len(), address, max });
+        }
+        self.rom = rom[..rom.len().min(max)].to_vec();
+        self.trimmedPadding = rom.len() - self.rom.len();
+        self.loadAddress = address as u16;
+        self.reset();
+        Ok(())
+    }
+
+    // rom_range returns where in memory the ROM is loaded.
+    pub fn rom_range(&self) -> Range<usize> {
+        self.loadAddress as usize..self.loadAddress as usize + self.rom.len()
+    }
+
+    // rom returns the ROM as loaded, without the padding left out.
+    pub fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    // trimmed_padding returns how many zero bytes at the end of the ROM were left out on loading,
+    // as they didn't fit in memory.
+    pub fn trimmed_padding(&self) -> usize {
+        self.trimmedPadding
+    }
+
+    // reset puts the emulator back in its power-on state with the current ROM loaded, as if it
+    // had just been started. The quirks, speed, random generator and RPL flags are kept, the
+    // latter since they're the HP48's persistent storage rather than part of the CHIP8 machine.
+    pub fn reset(&mut self) {
+        self.memory.clear();
+        let fontStart = FONT_OFFSET as usize;
+        self.memory.bytes_mut()[fontStart..fontStart + FONT.len()].copy_from_slice(&FONT);
+        let bigFontStart = BIG_FONT_OFFSET as usize;
+        self.memory.bytes_mut()[bigFontStart..bigFontStart + BIG_FONT.len()].copy_from_slice(&BIG_FONT);
+        let romStart = self.loadAddress as usize;
+        self.memory.bytes_mut()[romStart..romStart + self.rom.len()].copy_from_slice(&self.rom);
+
+        self.stack.clear();
+        self.I = 0;
+        self.registers = [0; 16];
+        self.pc = self.loadAddress;
+        self.delayTimer = 0;
+        self.soundTimer = 0;
+        self.display = [0; DISPLAY_WIDTH * DISPLAY_HEIGHT];
+        self.front = self.display;
+        self.hires = false;
+        self.mark_dirty(Region::full());
+        self.keys = KeyState::default();
+        self.heldKey = None;
+        self.keysRead = KeyState::default();
+        self.rplChanged = false;
+        self.pcHits.clear();
+        self.unknownOpcodes.clear();
+        self.unknownReported = 0;
+        self.writers.iter_mut().for_each(|w| *w = None);
+        self.selfModifications.clear();
+        if let Some(history) = self.history.as_mut() {
+            history.clear();
+        }
+    }
+
+    // save_state returns the state the emulator is in, to go on from there with load_state.
+    pub fn save_state(&self) -> State {
+        State {
+            romHash: fnv1a(&self.rom),
+            romSize: self.rom.len(),
+            variant: self.variant,
+            quirks: self.quirks,
+            instructionsPerFrame: self.instructionsPerFrame,
+            machineCode: self.machineCode,
+            haltOnUnknownOpcode: self.haltOnUnknownOpcode,
+            pc: self.pc,
+            I: self.I,
+            registers: self.registers,
+            delayTimer: self.delayTimer,
+            soundTimer: self.soundTimer,
+            stack: self.stack.clone(),
+            hires: self.hires,
+            keys: self.keys,
+            random: self.rng,
+            heldKey: self.heldKey,
+            rplFlags: self.rplFlags,
+            memory: self.memory.bytes().to_vec(),
+            display: self.display.to_vec(),
+            shown: self.front.to_vec(),
+        }
+    }
+
+    // load_state puts the emulator in a saved state, with the ROM of the state loaded already, see
+    // State::check. It starts over from there like after a reset, without a history or the
+    // unknown opcodes run so far.
+    pub fn load_state(&mut self, state: &State) {
+        self.reset();
+        self.variant = state.variant;
+        self.quirks = state.quirks;
+        self.instructionsPerFrame = state.instructionsPerFrame;
+        self.machineCode = state.machineCode;
+        self.haltOnUnknownOpcode = state.haltOnUnknownOpcode;
+        self.pc = state.pc;
+        self.I = state.I;
+        self.registers = state.registers;
+        self.delayTimer = state.delayTimer;
+        self.soundTimer = state.soundTimer;
+        self.stack = state.stack.clone();
+        self.hires = state.hires;
+        self.keys = state.keys;
+        self.seed(state.random);
+        self.heldKey = state.heldKey;
+        self.rplFlags = state.rplFlags;
+        let size = self.memory.len().min(state.memory.len());
+        self.memory.bytes_mut()[..size].copy_from_slice(&state.memory[..size]);
+        self.display.copy_from_slice(&state.display);
+        self.front.copy_from_slice(&state.shown);
+    }
+
+    // seed resets the random number generator used by CXNN.
+    pub fn seed(&mut self, seed: u32) {
+        // xorshift gets stuck on zero
+        self.rng = if seed == 0 { DEFAULT_SEED } else { seed };
+    }
+
+    // random_state returns where the random generator is, for telling whether two emulators will
+    // draw the same numbers from here on.
+    pub fn random_state(&self) -> u32 {
+        self.rng
+    }
+
+    // random returns the next number from a xorshift32 generator.
+    fn random(&mut self) -> u8 {
+        let mut x = self.rng;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.rng = x;
+        (x >> 24) as u8
+    }
+
+    // tick_frame runs exactly instructionsPerFrame instructions followed by a single 60hz timer
+    // tick. Given the same starting state and inputs, the output is always the same, so a frontend
+    // is expected to call this once per 1/60th second. If an instruction fails, the frame stops
+    // right there with the emulator still at the failing instruction.
+    pub fn tick_frame(&mut self, inputs: KeyState) -> Result<FrameOutput, Error> {
+        self.tick_frame_with(inputs, |emul, _| emul.run().map(|_| 1))
+    }
+
+    // tick_frame_with is tick_frame with another way of running the instructions: step runs at
+    // least one and at most the given number of instructions, returning how many it ran.
+    pub fn tick_frame_with(&mut self, inputs: KeyState, mut step: impl FnMut(&mut Emulator, u32) -> Result<u32, Error>) -> Result<FrameOutput, Error> {
+        self.keys = inputs;
+        self.keysRead = KeyState::default();
+
+        let mut left = self.instructionsPerFrame;
+        while left > 0 {
+            left -= step(self, left)?.clamp(1, left);
+        }
+        Ok(self.end_frame())
+    }
+
+    // run_until runs instructions until a condition holds, checked before each of them, returning
+    // how many ran, 0 if it held right away. After max instructions it stops with Error::Timeout.
+    // Time goes on like with tick_frame: every instructionsPerFrame instructions, counted from the
+    // call, the timers tick and framebuffer shows the display, with the keys held as they are in
+    // keys. For a test or a script, like
+    //
+    //   emul.run_until(100_000, |emul| emul.pixel(10, 5))?;
+    //   emul.run_until(100_000, |emul| emul.pc == 0x300)?;
+    pub fn run_until(&mut self, max: u64, mut until: impl FnMut(&Emulator) -> bool) -> Result<u64, Error> {
+        let mut cycles = 0;
+        let mut frameCycles = 0;
+        while !until(self) {
+            if cycles == max {
+                return Err(Error::Timeout { pc: self.pc, cycles });
+            }
+            self.run()?;
+            cycles += 1;
+            frameCycles += 1;
+            if frameCycles >= self.instructionsPerFrame {
+                self.end_frame();
+                self.keysRead = KeyState::default();
+                frameCycles = 0;
+            }
+        }
+        Ok(cycles)
+    }
+
+    // end_frame is what happens after the instructions of a frame: the timers tick and the display
+    // is shown.
+    fn end_frame(&mut self) -> FrameOutput {
+        self.tick_timers();
+        self.memory.decay_heat();
+
+        let mut diff = Vec::new();
+        for (i, (old, new)) in self.front.iter().zip(self.display.iter()).enumerate() {
+            if old != new {
+                diff.push(PixelChange {
+                    x: (i % DISPLAY_WIDTH) as u8,
+                    y: (i / DISPLAY_WIDTH) as u8,
+                    on: *new == 1,
+                });
+            }
+        }
+        self.front = self.display;
+
+        FrameOutput {
+            diff,
+            sound: self.soundTimer > 0,
+            keysRead: self.keysRead,
+        }
+    }
+
+    // framebuffer returns the DISPLAY_WIDTH x DISPLAY_HEIGHT display, one byte per pixel row by
+    // row, 1 = lit and 0 = dark, as it was at the end of the last frame. Instructions draw on
+    // display, the back buffer, which is copied over at the end of every complete frame, so a
+    // frontend never shows a sprite half drawn or a frame that stopped halfway on an error. It's a
+    // copy rather than a swap since CHIP8 draws on top of what's already on the display.
+    pub fn framebuffer(&self) -> &[u8] {
+        &self.front
+    }
+
+    // pixel returns whether a pixel of the display is lit, in pixels of the current mode, so 0-63
+    // and 0-31 in lores, as drawn so far rather than as of the last frame.
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        let scale = if self.hires { 1 } else { 2 };
+        self.display.get(y * scale * DISPLAY_WIDTH + x * scale).is_some_and(|p| *p != 0)
+    }
+
+    // resolution returns the width and height of the current mode, 64x32 or 128x64.
+    pub fn resolution(&self) -> (usize, usize) {
+        if self.hires {
+            (DISPLAY_WIDTH, DISPLAY_HEIGHT)
+        } else {
+            (SCREEN_WIDTH, SCREEN_HEIGHT)
+        }
+    }
+
+    // draw implements DXYN, XORing an 8 pixel wide and N lines high sprite from I onto the display
+    // at VX,VY, with VF set if any lit pixel was turned off. SCHIP in hires mode draws a 16x16
+    // sprite for DXY0, two bytes per line, and with the rowCollisions quirk sets VF to the number
+    // of lines that collided plus those clipped off the bottom of the screen.
+    fn draw(&mut self, X: usize, Y: usize, N: u8) {
+        let (width, height) = self.resolution();
+        let (lines, spriteWidth) = if N == 0 && self.hires && self.variant == Variant::Schip { (16, 16) } else { (N as usize, 8) };
+
+        let xCoord = self.registers[X] as usize % width;
+        let yCoord = self.registers[Y] as usize % height;
+
+        let countRows = self.hires && self.quirks.rowCollisions;
+        let mut collisions: u8 = 0;
+
+        for line in 0..lines {
+            let bits: u16 = if spriteWidth == 16 {
+                self.memory.read_u16(self.I.wrapping_add(2 * line as u16))
+            } else {
+                (self.memory.read_u8(self.I.wrapping_add(line as u16)) as u16) << 8
+            };
+            let row = yCoord + line;
+
+            if self.quirks.clipSprites && row >= height {
+                if countRows {
+                    collisions += (lines - line) as u8;
+                }
+                break;
+            }
+
+            let mut collided = false;
+            for bit in 0..spriteWidth {
+                let col = xCoord + bit;
+                if self.quirks.clipSprites && col >= width {
+                    break;
+                }
+                if bits & (0x8000 >> bit) > 0 && self.flip(col % width, row % height) {
+                    collided = true;
+                }
+            }
+            collisions += collided as u8;
+        }
+
+        self.registers[0xF] = if countRows { collisions } else { (collisions > 0) as u8 };
+    }
+
+    // flip toggles a pixel of the current mode, returning whether it was lit.
+    fn flip(&mut self, x: usize, y: usize) -> bool {
+        let scale = DISPLAY_WIDTH / self.resolution().0;
+        let mut wasLit = false;
+        for dy in 0..scale {
+            for dx in 0..scale {
+                let index = (y * scale + dy) * DISPLAY_WIDTH + x * scale + dx;
+                wasLit |= self.display[index] == 0x1;
+                self.display[index] ^= 0x1;
+            }
+        }
+        self.mark_dirty(Region { x: x * scale, y: y * scale, width: scale, height: scale });
+        wasLit
+    }
+
+    // scroll moves the display contents by n pixels of the current mode to the right (dx) and
+    // down (dy), or left and up for negative values. With the halfPixelScroll quirk the distance
+    // is in display pixels whatever the mode, like SCHIP 1.1, so in lores it's half a pixel per n.
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        let scale = if self.quirks.halfPixelScroll { 1 } else { (DISPLAY_WIDTH / self.resolution().0) as isize };
+        let (dx, dy) = (dx * scale, dy * scale);
+        let before = self.display;
+        for y in 0..DISPLAY_HEIGHT {
+            for x in 0..DISPLAY_WIDTH {
+                let (fromX, fromY) = (x as isize - dx, y as isize - dy);
+                let inside = (0..DISPLAY_WIDTH as isize).contains(&fromX) && (0..DISPLAY_HEIGHT as isize).contains(&fromY);
+                self.display[y * DISPLAY_WIDTH + x] = if inside { before[fromY as usize * DISPLAY_WIDTH + fromX as usize] } else { 0x0 };
+            }
+        }
+        self.mark_dirty(Region::full());
+    }
+
+    // take_dirty_region returns the part of the display that has changed since the last call, if
+    // any, so that a frontend only has to redraw that. The region may include pixels that were
+    // toggled back, but never misses one that changed.
+    pub fn take_dirty_region(&mut self) -> Option<Region> {
+        self.dirty.take()
+    }
+
+    fn mark_dirty(&mut self, region: Region) {
+        self.dirty = Some(match self.dirty {
+            Some(dirty) => dirty.union(region),
+            None => region,
+        });
+    }
+
+    // take_rpl_changed returns whether FX75 has written the RPL flags since the last call, so that
+    // a frontend knows when to persist them.
+    pub fn take_rpl_changed(&mut self) -> bool {
+        core::mem::take(&mut self.rplChanged)
+    }
+
+    // watch_pc makes the emulator note whenever an instruction at address is about to run, e.g.
+    // the game over routine of a ROM. The watches survive a reset.
+    pub fn watch_pc(&mut self, address: u16) {
+        self.pcWatches.push(address);
+    }
+
+    pub fn clear_pc_watches(&mut self) {
+        self.pcWatches.clear();
+        self.pcHits.clear();
+    }
+
+    // take_pc_hits returns the watched addresses execution has reached since the last call, each
+    // at most once.
+    pub fn take_pc_hits(&mut self) -> Vec<u16> {
+        core::mem::take(&mut self.pcHits)
+    }
+
+    // unknown_opcodes returns the opcodes that aren't instructions of the variant and ran since
+    // the last reset, each once in the order they first ran.
+    pub fn unknown_opcodes(&self) -> &[UnknownOpcode] {
+        &self.unknownOpcodes
+    }
+
+    // take_new_unknown_opcodes returns the unknown opcodes that first ran since the last call.
+    pub fn take_new_unknown_opcodes(&mut self) -> Vec<UnknownOpcode> {
+        let new = self.unknownOpcodes[self.unknownReported..].to_vec();
+        self.unknownReported = self.unknownOpcodes.len();
+        new
+    }
+
+    // track_self_modification switches on keeping track of which instruction wrote each byte of
+    // memory, so that running an instruction the ROM has written itself is noticed. Some ROMs do
+    // that on purpose, e.g. to patch the address of a jump, but it can also be a ROM running off
+    // into its data, which makes for confusing bugs. It takes 16kb, so it's off by default.
+    pub fn track_self_modification(&mut self, on: bool) {
+        self.writers = if on { alloc::vec![None; self.memory.len()] } else { Vec::new() };
+    }
+
+    // take_self_modifications returns the self-modified instructions that ran since the last call.
+    // Each is reported once, until it's written again.
+    pub fn take_self_modifications(&mut self) -> Vec<SelfModification> {
+        core::mem::take(&mut self.selfModifications)
+    }
+
+    // record_history keeps the last capacity instructions that ran, see History. 0 stops
+    // recording.
+    pub fn record_history(&mut self, capacity: usize) {
+        self.history = (capacity > 0).then(|| History::new(capacity));
+    }
+
+    pub fn history(&self) -> Option<&History> {
+        self.history.as_ref()
+    }
+
+    // step_back takes back the newest instruction of the history, putting the emulator in the
+    // state it was in right before it ran, and returns it. Timers are restored too, so stepping
+    // back across a frame undoes the timer tick. Keys, watches and the self-modification tracking
+    // are left as they are.
+    pub fn step_back(&mut self) -> Option<Entry> {
+        let entry = self.history.as_mut()?.pop()?;
+        let undo = &entry.undo;
+        self.pc = entry.pc;
+        self.registers = entry.registersBefore;
+        self.I = entry.IBefore;
+        if self.stack.len() > undo.stackDepth {
+            self.stack.truncate(undo.stackDepth);
+        } else if self.stack.len() < undo.stackDepth {
+            self.stack.extend(undo.stackTop);
+        }
+        self.delayTimer = undo.delayTimer;
+        self.soundTimer = undo.soundTimer;
+        self.rng = undo.rng;
+        self.heldKey = undo.heldKey;
+        self.rplFlags = undo.rplFlags;
+        for (address, value) in undo.memory.iter().rev() {
+            self.memory.bytes_mut()[*address as usize] = *value;
+        }
+        if self.hires != undo.hires || !undo.display.is_empty() {
+            self.hires = undo.hires;
+            for (i, value) in undo.display.iter() {
+                self.display[*i as usize] = *value;
+            }
+            self.mark_dirty(Region::full());
+        }
+        Some(entry)
+    }
+
+    // undo_point returns the state step_back needs, as it is before an instruction runs.
+    fn undo_point(&self) -> Undo {
+        Undo {
+            stackDepth: self.stack.len(),
+            stackTop: self.stack.last().copied(),
+            delayTimer: self.delayTimer,
+            soundTimer: self.soundTimer,
+            rng: self.rng,
+            heldKey: self.heldKey,
+            hires: self.hires,
+            rplFlags: self.rplFlags,
+            memory: Vec::new(),
+            display: Vec::new(),
+        }
+    }
+
+    // store writes a byte of memory for an instruction, noting the writer if tracking.
+    fn store(&mut self, address: u16, value: u8) {
+        if self.history.is_some() {
+            let address = address as usize % self.memory.len();
+            self.writes.push((address as u16, self.memory.bytes()[address]));
+        }
+        self.memory.write_u8(address, value);
+        if !self.writers.is_empty() {
+            let index = address as usize % self.writers.len();
+            self.writers[index] = Some(self.pc.wrapping_sub(2));
+        }
+    }
+
+    // check_self_modification notes it when the instruction at pc was written by the ROM.
+    fn check_self_modification(&mut self) {
+        let at = self.pc as usize % self.writers.len();
+        let next = (at + 1) % self.writers.len();
+        if let Some(writer) = self.writers[at].or(self.writers[next]) {
+            self.selfModifications.push(SelfModification { pc: self.pc, writer });
+            self.writers[at] = None;
+            self.writers[next] = None;
+        }
+    }
+
+    // is_key_pressed checks a key for EX9E and EXA1, remembering it in keysRead if it's pressed.
+    fn is_key_pressed(&mut self, key: u8) -> bool {
+        let pressed = self.keys.is_pressed(key);
+        if pressed {
+            self.keysRead.set(key, true);
+        }
+        pressed
+    }
+
+    // tick_timers decrements delayTimer and soundTimer, which should happen at 60hz.
+    pub fn tick_timers(&mut self) {
+        if self.delayTimer > 0 {
+            self.delayTimer -= 1;
+        }
+        if self.soundTimer > 0 {
+            self.soundTimer -= 1;
+        }
+    }
+
+    // run runs a single CHP8 instruction. When it fails, pc is left pointing at the instruction.
+    pub fn run(&mut self) -> Result<(), Error> {
+
+        // parse next instruction from memory, using the pc (program counter) value.
+        let b = self.memory.fetch_u16(self.pc);
+        if !self.pcWatches.is_empty() && self.pcWatches.contains(&self.pc) && !self.pcHits.contains(&self.pc) {
+            self.pcHits.push(self.pc);
+        }
+        if !self.writers.is_empty() {
+            self.check_self_modification();
+        }
+
+        //print!("PC: {} B: {:#X}", self.pc, b);
+
+        let pc = self.pc;
+        let before = self.history.is_some().then(|| (self.registers, self.I, self.undo_point()));
+        let displayBefore = (before.is_some() && drawsOnDisplay(b)).then_some(self.display);
+        self.pc = self.pc.wrapping_add(2);
+
+        // with the jump-table feature the instruction is looked up in a table of all 65536 opcodes
+        // that is filled in at compile time, rather than matched every time.
+        #[cfg(feature = "jump-table")]
+        let result = JUMP_TABLE[b as usize](self, decode(b));
+        #[cfg(not(feature = "jump-table"))]
+        let result = handler(b)(self, decode(b));
+
+        if let Some((registersBefore, IBefore, mut undo)) = before {
+            undo.memory = core::mem::take(&mut self.writes);
+            if let Some(displayBefore) = displayBefore {
+                undo.display = displayBefore.iter().zip(self.display.iter()).enumerate()
+                    .filter(|(_, (old, new))| old != new)
+                    .map(|(i, (old, _))| (i as u16, *old))
+                    .collect();
+            }
+            let entry = Entry { pc, opcode: b, registersBefore, registersAfter: self.registers, IBefore, IAfter: self.I, undo };
+            if let Some(history) = self.history.as_mut() {
+                history.push(entry);
+            }
+        }
+        result
+    }
+
+    // The instructions, each taking the decoded opcode, as picked by handler.
+
+    // 0x00E0 Clear screen
+    fn op_00e0(&mut self, _op: Opcode) -> Result<(), Error> {
+        self.display.iter_mut().for_each(|x| *x = 0x0);
+        self.mark_dirty(Region::full());
+        Ok(())
+    }
+
+    // 0x00EE Pop stack
+    fn op_00ee(&mut self, _op: Opcode) -> Result<(), Error> {
+        match self.stack.pop() {
+            Some(pc) => self.pc = pc,
+            None => {
+                self.pc = self.pc.wrapping_sub(2);
+                return Err(Error::StackUnderflow { pc: self.pc });
+            }
+        }
+        Ok(())
+    }
+
+    // 0x0NNN Call the machine code routine at NNN, see MachineCode
+    fn op_0nnn(&mut self, op: Opcode) -> Result<(), Error> {
+        match (self.machineCode, op.NNN) {
+            (MachineCode::Skip, _) => self.unknown(op, false),
+            (MachineCode::Vip, VIP_CLEAR_HIRES) => self.op_00e0(op),
+            (MachineCode::Vip, address) => self.run_machine_code(op, address),
+            (MachineCode::Halt, address) => {
+                self.pc = self.pc.wrapping_sub(2);
+                Err(Error::MachineCode { pc: self.pc, address })
+            }
+        }
+    }
+
+    // run_machine_code runs the 1802 routine at address the way the VIP's interpreter calls it:
+    // with R3 as its program counter, up to the SEP R4 that hands back to the interpreter.
+    // Before the call the registers, the timers and (in lores) the display are put where the VIP
+    // keeps them, and afterwards they're read back, so the routine can change any of them. That
+    // overwrites the end of memory, which ROMs for the VIP leave to the interpreter anyway.
+    fn run_machine_code(&mut self, op: Opcode, address: u16) -> Result<(), Error> {
+        let pc = self.pc.wrapping_sub(2);
+        let memoryBefore = self.history.is_some().then(|| self.memory.bytes().to_vec());
+        let registers = VIP_REGISTERS as usize..VIP_REGISTERS as usize + 16;
+        let display = VIP_DISPLAY as usize..VIP_DISPLAY as usize + 256;
+        self.memory.bytes_mut()[registers.clone()].copy_from_slice(&self.registers);
+        if !self.hires {
+            let page = self.vip_display();
+            self.memory.bytes_mut()[display.clone()].copy_from_slice(&page);
+        }
+
+        // the interpreter's registers, as its fetch loop leaves them for any instruction.
+        let mut cpu = Cdp1802 { p: 3, x: 2, ..Cdp1802::default() };
+        cpu.r[2] = VIP_STACK;
+        cpu.r[3] = address;
+        cpu.r[5] = self.pc;
+        cpu.r[6] = VIP_REGISTERS + op.X as u16;
+        cpu.r[7] = VIP_REGISTERS + op.Y as u16;
+        cpu.r[8] = (self.delayTimer as u16) << 8 | self.soundTimer as u16;
+        cpu.r[0xA] = self.I;
+        cpu.r[0xB] = VIP_DISPLAY;
+        let returned = cpu.run(&mut self.memory, 4, MACHINE_CODE_STEPS);
+
+        self.registers.copy_from_slice(&self.memory.bytes()[registers]);
+        self.I = cpu.r[0xA];
+        self.pc = cpu.r[5];
+        (self.delayTimer, self.soundTimer) = ((cpu.r[8] >> 8) as u8, cpu.r[8] as u8);
+        if !self.hires {
+            let page = self.memory.bytes()[display].to_vec();
+            self.set_vip_display(&page);
+        }
+        if let Some(before) = memoryBefore {
+            let changed = before.iter().zip(self.memory.bytes()).enumerate().filter(|(_, (old, new))| old != new);
+            self.writes.extend(changed.map(|(i, (old, _))| (i as u16, *old)));
+        }
+        if returned.is_none() {
+            self.pc = pc;
+            return Err(Error::MachineCodeRunaway { pc, address });
+        }
+        Ok(())
+    }
+
+    // vip_display returns the lores display as the VIP has it, a bit per pixel.
+    fn vip_display(&self) -> [u8; 256] {
+        let mut page = [0; 256];
+        for y in 0..SCREEN_HEIGHT {
+            for x in 0..SCREEN_WIDTH {
+                if self.display[2 * y * DISPLAY_WIDTH + 2 * x] != 0 {
+                    page[y * 8 + x / 8] |= 0x80 >> (x % 8);
+                }
+            }
+        }
+        page
+    }
+
+    // set_vip_display sets the lores display from the bits of the VIP's display page.
+    fn set_vip_display(&mut self, page: &[u8]) {
+        if *page == self.vip_display() {
+            return;
+        }
+        for y in 0..DISPLAY_HEIGHT {
+            for x in 0..DISPLAY_WIDTH {
+                self.display[y * DISPLAY_WIDTH + x] = (page[y / 2 * 8 + x / 16] >> (7 - x / 2 % 8)) & 1;
+            }
+        }
+        self.mark_dirty(Region::full());
+    }
+
+    // SCHIP 0x00CN: scroll down N pixels
+    fn op_00cn(&mut self, op: Opcode) -> Result<(), Error> {
+        self.scroll(0, op.N as isize);
+        Ok(())
+    }
+
+    // SCHIP 0x00FB: scroll right 4 pixels
+    fn op_00fb(&mut self, _op: Opcode) -> Result<(), Error> {
+        self.scroll(4, 0);
+        Ok(())
+    }
+
+    // SCHIP 0x00FC: scroll left 4 pixels
+    fn op_00fc(&mut self, _op: Opcode) -> Result<(), Error> {
+        self.scroll(-4, 0);
+        Ok(())
+    }
+
+    // SCHIP 0x00FD: exit the interpreter, which here means staying at this instruction
+    fn op_00fd(&mut self, _op: Opcode) -> Result<(), Error> {
+        self.pc = self.pc.wrapping_sub(2);
+        Ok(())
+    }
+
+    // SCHIP 0x00FE: lores mode
+    fn op_00fe(&mut self, _op: Opcode) -> Result<(), Error> {
+        self.hires = false;
+        Ok(())
+    }
+
+    // SCHIP 0x00FF: hires mode
+    fn op_00ff(&mut self, _op: Opcode) -> Result<(), Error> {
+        self.hires = true;
+        Ok(())
+    }
+
+    // 0x1: Jump program counter to NNN
+    fn op_1nnn(&mut self, op: Opcode) -> Result<(), Error> {
+        self.pc = op.NNN;
+        Ok(())
+    }
+
+    // 0x2: Subroutine: Push to stack, then set PC to NNN
+    fn op_2nnn(&mut self, op: Opcode) -> Result<(), Error> {
+        if self.stack.len() >= self.quirks.stackDepth {
+            self.pc = self.pc.wrapping_sub(2);
+            return Err(Error::StackOverflow { pc: self.pc, depth: self.quirks.stackDepth });
+        }
+        self.stack.push(self.pc); // store _current_ program counter to return to.
+        self.pc = op.NNN;
+        Ok(())
+    }
+
+    // 0x3: Skip if value in register X equals NN
+    fn op_3xnn(&mut self, op: Opcode) -> Result<(), Error> {
+        if self.registers[op.X] == op.NN {
+            self.pc = self.pc.wrapping_add(2);
+        }
+        Ok(())
+    }
+
+    // 0x4: Skip if value in register X not equals NN
+    fn op_4xnn(&mut self, op: Opcode) -> Result<(), Error> {
+        if self.registers[op.X] != op.NN {
+            self.pc = self.pc.wrapping_add(2);
+        }
+        Ok(())
+    }
+
+    // 0x5: Skip if values in registers X and Y are equal
+    fn op_5xy0(&mut self, op: Opcode) -> Result<(), Error> {
+        if op.N == 0x0 && self.registers[op.X] == self.registers[op.Y] {
+            self.pc = self.pc.wrapping_add(2);
+        }
+        Ok(())
+    }
+
+    // 0x6: Set register X to NN
+    fn op_6xnn(&mut self, op: Opcode) -> Result<(), Error> {
+        self.registers[op.X] = op.NN;
+        Ok(())
+    }
+
+    // 0x7: Add NN to register X
+    fn op_7xnn(&mut self, op: Opcode) -> Result<(), Error> {
+        self.registers[op.X] = self.registers[op.X].wrapping_add(op.NN);
+        Ok(())
+    }
+
+    // 0x8XY0: Set register X to value of register Y
+    fn op_8xy0(&mut self, op: Opcode) -> Result<(), Error> {
+        self.registers[op.X] = self.registers[op.Y];
+        Ok(())
+    }
+
+    // 0x8XY1: Set register X to OR of registers X and Y
+    fn op_8xy1(&mut self, op: Opcode) -> Result<(), Error> {
+        self.registers[op.X] |= self.registers[op.Y];
+        if self.quirks.vfReset {
+            self.registers[0xF] = 0x0;
+        }
+        Ok(())
+    }
+
+    // 0x8XY2: Set register X to AND of registers X and Y
+    fn op_8xy2(&mut self, op: Opcode) -> Result<(), Error> {
+        self.registers[op.X] &= self.registers[op.Y];
+        if self.quirks.vfReset {
+            self.registers[0xF] = 0x0;
+        }
+        Ok(())
+    }
+
+    // 0x8XY3: Set register X to XOR of registers X and Y
+    fn op_8xy3(&mut self, op: Opcode) -> Result<(), Error> {
+        self.registers[op.X] ^= self.registers[op.Y];
+        if self.quirks.vfReset {
+            self.registers[0xF] = 0x0;
+        }
+        Ok(())
+    }
+
+    // 0x8XY4: Set register X to X + Y, set register F (15) to 1 or 0 depending on overflow
+    fn op_8xy4(&mut self, op: Opcode) -> Result<(), Error> {
+        let vx = self.registers[op.X];
+        let result = vx.wrapping_add(self.registers[op.Y]);
+        self.registers[op.X] = result;
+        if result < vx { // if result is less than original, we've had an overflow
+            self.registers[0xF] = 0x1;
+        } else {
+            self.registers[0xF] = 0x0;
+        }
+        Ok(())
+    }
+
+    // 0x8XY5: Subtract: set register X to the result of registers X - Y.
+    fn op_8xy5(&mut self, op: Opcode) -> Result<(), Error> {
+        let wraps = self.registers[op.X] >= self.registers[op.Y];
+
+        let result = self.registers[op.X].wrapping_sub(self.registers[op.Y]);
+        self.registers[op.X] = result;
+        self.registers[0xF] = if wraps { 0x1 } else { 0x0 };
+        Ok(())
+    }
+
+    // 0x8XY6: Shift register X one step to the right, after setting X to value of Y if the shift quirk is on
+    fn op_8xy6(&mut self, op: Opcode) -> Result<(), Error> {
+        if self.quirks.shiftUsesVY {
+            self.registers[op.X] = self.registers[op.Y];
+        }
+
+        // check if rightmost bit is set (and shifted out)
+        let flag = if (self.registers[op.X]&(1<<0)) > 0 { 0x1 } else {0x0};
+        self.registers[op.X] >>= 1;
+        self.registers[0xF] = flag;
+        Ok(())
+    }
+
+    // 0x8XY7: Subtract: set register X to the result of registers Y - X.
+    fn op_8xy7(&mut self, op: Opcode) -> Result<(), Error> {
+        let notWrapping = self.registers[op.Y] >= self.registers[op.X];
+
+        let result = self.registers[op.Y].wrapping_sub(self.registers[op.X]);
+        self.registers[op.X] = result;
+        self.registers[0xF] = if notWrapping { 0x1 } else { 0x0 };
+        Ok(())
+    }
+
+    // 0x8XYE: Shift register X one step to the left, after setting X to value of Y if the shift quirk is on
+    fn op_8xye(&mut self, op: Opcode) -> Result<(), Error> {
+        if self.quirks.shiftUsesVY {
+            self.registers[op.X] = self.registers[op.Y];
+        }
+        let flag = if (self.registers[op.X]&(1<<7)) > 0 { 0x1 } else {0x0};
+        self.registers[op.X] <<= 1;
+        self.registers[0xF] = flag;
+        Ok(())
+    }
+
+    // 0x9: Skip if values in registers X and Y are not equal
+    fn op_9xy0(&mut self, op: Opcode) -> Result<(), Error> {
+        if self.registers[op.X] != self.registers[op.Y] {
+            self.pc = self.pc.wrapping_add(2);
+        }
+        Ok(())
+    }
+
+    // 0xA: Set Index register to NNN
+    fn op_annn(&mut self, op: Opcode) -> Result<(), Error> {
+        self.I = op.NNN;
+        Ok(())
+    }
+
+    // 0xB: Set PC to NNN + value in register 0
+    fn op_bnnn(&mut self, op: Opcode) -> Result<(), Error> {
+        if self.quirks.jumpUsesVX {
+            // SCHIP behaviour, BXNN jumps to XNN + value in register X.
+            self.pc = op.NNN.wrapping_add(self.registers[op.X] as u16);
+        } else {
+            // original behaviour, assume register 0x0.
+            self.pc = op.NNN.wrapping_add(self.registers[0x0] as u16);
+        }
+        Ok(())
+    }
+
+    // 0xC: Random number into register X anded by NN
+    fn op_cxnn(&mut self, op: Opcode) -> Result<(), Error> {
+        self.registers[op.X] = self.random() & op.NN;
+        Ok(())
+    }
+
+    // 0xD: Draw
+    fn op_dxyn(&mut self, op: Opcode) -> Result<(), Error> {
+        self.draw(op.X, op.Y, op.N);
+        Ok(())
+    }
+
+    // EX9E: handle key pressed
+    fn op_ex9e(&mut self, op: Opcode) -> Result<(), Error> {
+        if self.is_key_pressed(self.registers[op.X]) {
+            self.pc = self.pc.wrapping_add(2);
+        }
+        Ok(())
+    }
+
+    // EXA1: handle key not pressed
+    fn op_exa1(&mut self, op: Opcode) -> Result<(), Error> {
+        if !self.is_key_pressed(self.registers[op.X]) {
+            self.pc = self.pc.wrapping_add(2);
+        }
+        Ok(())
+    }
+
+    // 0xFX07 -  Set register X to current value of delay timer
+    fn op_fx07(&mut self, op: Opcode) -> Result<(), Error> {
+        self.registers[op.X] = self.delayTimer;
+        Ok(())
+    }
+
+    // 0xFX15 -  Set the delay timer to value of register X
+    fn op_fx15(&mut self, op: Opcode) -> Result<(), Error> {
+        self.delayTimer = self.registers[op.X];
+        Ok(())
+    }
+
+    // 0xFX18 -  Set the sound timer to value of register X
+    fn op_fx18(&mut self, op: Opcode) -> Result<(), Error> {
+        self.soundTimer = self.registers[op.X];
+        Ok(())
+    }
+
+    // 0xFX1E - Add to index: Add value of register X to I
+    fn op_fx1e(&mut self, op: Opcode) -> Result<(), Error> {
+        let i = self.I.wrapping_add(self.registers[op.X] as u16);
+        // old-school amiga behaviour
+        if self.quirks.indexOverflowSetsVF {
+            self.registers[0xF] = if i > 0xFFF { 0x1 } else { 0x0 };
+        }
+        self.I = i % 0x1000; //  mod 4096 in case of overflow over original 4kb of RAM
+        Ok(())
+    }
+
+    // 0xFX0A: Get key (blocks until a key has been pressed and released, like the COSMAC VIP)
+    fn op_fx0a(&mut self, op: Opcode) -> Result<(), Error> {
+        match self.heldKey {
+            Some(key) if !self.keys.is_pressed(key) => {
+                self.registers[op.X] = key;
+                self.heldKey = None;
+            }
+            Some(_) => self.pc = self.pc.wrapping_sub(2),
+            None => {
+                self.heldKey = (0..16).find(|k| self.keys.is_pressed(*k));
+                if let Some(key) = self.heldKey {
+                    self.keysRead.set(key, true);
+                }
+                self.pc = self.pc.wrapping_sub(2);
+            }
+        }
+        Ok(())
+    }
+
+    // 0xFX29: font character, sets I to first byte of character per register X
+    fn op_fx29(&mut self, op: Opcode) -> Result<(), Error> {
+        let b = self.registers[op.X] & 0x0F; // just use last nibble of value in register X
+        self.I = FONT_OFFSET + (b*5) as u16; //fontOffsets[b];
+        Ok(())
+    }
+
+    // SCHIP 0xFX30: big font character, sets I to the 8x10 glyph of the digit in register X
+    fn op_fx30(&mut self, op: Opcode) -> Result<(), Error> {
+        let b = self.registers[op.X] & 0x0F;
+        self.I = BIG_FONT_OFFSET + (b as u16) * 10;
+        Ok(())
+    }
+
+    // 0xFX33: binary-coded decimal conversion. Note that "10" is split into 0,1,0 and 4 into 0,0,4.
+    fn op_fx33(&mut self, op: Opcode) -> Result<(), Error> {
+        self.store(self.I, (self.registers[op.X] / 100) % 10);
+        self.store(self.I.wrapping_add(1), (self.registers[op.X] / 10) % 10);
+        self.store(self.I.wrapping_add(2), self.registers[op.X] % 10);
+        Ok(())
+    }
+
+    // 0xFX55: Store register to memory
+    fn op_fx55(&mut self, op: Opcode) -> Result<(), Error> {
+        for i in 0..op.X + 1 {
+            let index = self.I.wrapping_add(i as u16);
+            self.store(index, self.registers[i]);
+        }
+        if self.quirks.loadStoreIncrementsI {
+            self.I = self.I.wrapping_add((op.X+1) as u16);
+        }
+        Ok(())
+    }
+
+    // 0xFX65: Load value from memory into register
+    fn op_fx65(&mut self, op: Opcode) -> Result<(), Error> {
+        for i in 0..op.X + 1 {
+            let index = self.I.wrapping_add(i as u16);
+            self.registers[i] = self.memory.read_u8(index);
+        }
+        if self.quirks.loadStoreIncrementsI {
+            self.I = self.I.wrapping_add((op.X+1) as u16);
+        }
+        Ok(())
+    }
+
+    // 0xFX75: Store registers V0 to VX in the RPL flags (SCHIP, X < 8)
+    fn op_fx75(&mut self, op: Opcode) -> Result<(), Error> {
+        let to = op.X.min(7) + 1;
+        self.rplFlags[..to].copy_from_slice(&self.registers[..to]);
+        self.rplChanged = true;
+        Ok(())
+    }
+
+    // 0xFX85: Load registers V0 to VX from the RPL flags (SCHIP, X < 8)
+    fn op_fx85(&mut self, op: Opcode) -> Result<(), Error> {
+        let to = op.X.min(7) + 1;
+        self.registers[..to].copy_from_slice(&self.rplFlags[..to]);
+        Ok(())
+    }
+
+    // op_schip runs the SCHIP instructions, which are unknown opcodes to plain CHIP8.
+    fn op_schip(&mut self, op: Opcode) -> Result<(), Error> {
+        if self.variant != Variant::Schip {
+            return self.unknown(op, true);
+        }
+        match (op.X, op.Y, op.N) {
+            (0x0, 0xC, _) => self.op_00cn(op),
+            (0x0, 0xF, 0xB) => self.op_00fb(op),
+            (0x0, 0xF, 0xC) => self.op_00fc(op),
+            (0x0, 0xF, 0xD) => self.op_00fd(op),
+            (0x0, 0xF, 0xE) => self.op_00fe(op),
+            (0x0, 0xF, 0xF) => self.op_00ff(op),
+            _ => self.op_fx30(op),
+        }
+    }
+
+    // op_unknown is every opcode that isn't an instruction.
+    fn op_unknown(&mut self, op: Opcode) -> Result<(), Error> {
+        self.unknown(op, false)
+    }
+
+    // unknown notes an unknown opcode and skips it, or stops at it with haltOnUnknownOpcode.
+    fn unknown(&mut self, op: Opcode, schip: bool) -> Result<(), Error> {
+        let opcode = op.instr << 12 | op.NNN;
+        let pc = self.pc.wrapping_sub(2);
+        match self.unknownOpcodes.iter_mut().find(|u| u.opcode == opcode) {
+            Some(unknown) => unknown.count += 1,
+            None => self.unknownOpcodes.push(UnknownOpcode { opcode, pc, count: 1, schip }),
+        }
+        if self.haltOnUnknownOpcode {
+            self.pc = pc;
+            return Err(Error::UnknownOpcode { pc, opcode });
+        }
+        Ok(())
+    }
+}
+
+// drawsOnDisplay returns whether an opcode can change the display: clearing, scrolling, switching
+// modes, machine code or drawing a sprite.
+fn drawsOnDisplay(b: u16) -> bool {
+    b & 0xF000 == 0x0000 || b & 0xF000 == 0xD000
+}
+
+// Handler runs a single instruction.
+type Handler = fn(&mut Emulator, Opcode) -> Result<(), Error>;
+
+// handler picks the instruction an opcode stands for.
+const fn handler(b: u16) -> Handler {
+    let Opcode { instr, X, Y, N, .. } = decode(b);
+
+    // match the instruction
+    match (instr, X, Y, N) {
+        (0x0, 0x0, 0xE, 0x0) => Emulator::op_00e0,
+        (0x0, 0x0, 0xE, 0xE) => Emulator::op_00ee,
+        (0x0, 0x0, 0xC, _) => Emulator::op_schip,
+        (0x0, 0x0, 0xF, 0xB..=0xF) => Emulator::op_schip,
+        (0x0, _, _, _) => Emulator::op_0nnn,
+        (0x1, _, _, _) => Emulator::op_1nnn,
+        (0x2, _, _, _) => Emulator::op_2nnn,
+        (0x3, _, _, _) => Emulator::op_3xnn,
+        (0x4, _, _, _) => Emulator::op_4xnn,
+        (0x5, _, _, _) => Emulator::op_5xy0,
+        (0x6, _, _, _) => Emulator::op_6xnn,
+        (0x7, _, _, _) => Emulator::op_7xnn,
+        (0x8, _, _, 0x0) => Emulator::op_8xy0,
+        (0x8, _, _, 0x1) => Emulator::op_8xy1,
+        (0x8, _, _, 0x2) => Emulator::op_8xy2,
+        (0x8, _, _, 0x3) => Emulator::op_8xy3,
+        (0x8, _, _, 0x4) => Emulator::op_8xy4,
+        (0x8, _, _, 0x5) => Emulator::op_8xy5,
+        (0x8, _, _, 0x6) => Emulator::op_8xy6,
+        (0x8, _, _, 0x7) => Emulator::op_8xy7,
+        (0x8, _, _, 0xE) => Emulator::op_8xye,
+        (0x9, _, _, 0x0) => Emulator::op_9xy0,
+        (0xA, _, _, _) => Emulator::op_annn,
+        (0xB, _, _, _) => Emulator::op_bnnn,
+        (0xC, _, _, _) => Emulator::op_cxnn,
+        (0xD, _, _, _) => Emulator::op_dxyn,
+        (0xE, _, 0x9, 0xE) => Emulator::op_ex9e,
+        (0xE, _, 0xA, 0x1) => Emulator::op_exa1,
+        (0xF, _, 0x0, 0x7) => Emulator::op_fx07,
+        (0xF, _, 0x1, 0x5) => Emulator::op_fx15,
+        (0xF, _, 0x1, 0x8) => Emulator::op_fx18,
+        (0xF, _, 0x1, 0xE) => Emulator::op_fx1e,
+        (0xF, _, 0x0, 0xA) => Emulator::op_fx0a,
+        (0xF, _, 0x2, 0x9) => Emulator::op_fx29,
+        (0xF, _, 0x3, 0x0) => Emulator::op_schip,
+        (0xF, _, 0x3, 0x3) => Emulator::op_fx33,
+        (0xF, _, 0x5, 0x5) => Emulator::op_fx55,
+        (0xF, _, 0x6, 0x5) => Emulator::op_fx65,
+        (0xF, _, 0x7, 0x5) => Emulator::op_fx75,
+        (0xF, _, 0x8, 0x5) => Emulator::op_fx85,
+        _ => Emulator::op_unknown,
+    }
+}
+
+// JUMP_TABLE holds the handler of every opcode, trading 512kb for not decoding the instruction
+// kind on every step.
+#[cfg(feature = "jump-table")]
+static JUMP_TABLE: [Handler; 0x10000] = {
+    let mut table: [Handler; 0x10000] = [Emulator::op_unknown; 0x10000];
+    let mut b = 0;
+    while b < table.len() {
+        table[b] = handler(b as u16);
+        b += 1;
+    }
+    table
+};
+
+impl Default for Emulator {
+    fn default() -> Self {
+        Emulator::new()
+    }
+}
+
+// Opcode is an instruction word split into the nibbles and bytes the instructions are made of.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Opcode {
+    pub instr: u16,
+    pub X: usize,
+    pub Y: usize,
+    pub N: u8,
+    pub NN: u8,
+    pub NNN: u16,
+}
+
+// decode splits an instruction word, as run does before executing it.
+pub const fn decode(b: u16) -> Opcode {
+    let b0 = (b & 0xFF00) >> 8;  // To get first byte, & the 8 leftmost bits which removes the 8 rightsmost, then shift by 8 to the right to make the u8 conversion contain the bits originally on the left.
+    let b1 = (b & 0x00FF) as u8;        // To get the second byte, just & the 8 rightmost bits, which removes the leftmost bits. The remaining bits are already at the correct location so no need to shift before converting to u8.
+
+    let instr = (b0 & 0xF0) >> 4;    // first nibble, the instruction. Keep 4 leftmost bits, then shift them to the right-hand side.
+    let X = (b0 & 0x0F) as usize;        // second nibble, register lookup! Only keep rightmost bits.
+    let Y = ((b1 & 0xF0) >> 4) as usize; // third nibble, register lookup! Keep leftmost bits, shift 4 to left.
+    let N = b1 & 0x0F;                      // fourth nibble, 4 bit number
+    let NN = b1;                            // NN = second byte
+    let NNN = b & 0x0FFF;                   // NNN = second, third and fourth nibbles
+
+    Opcode { instr, X, Y, N, NN, NNN }
+}
+
+pub static FONT: [u8; 80] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x20, 0x60, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+
+// BIG_FONT is the SCHIP 8x10 font for FX30. SCHIP 1.1 only had the digits 0-9, A-F are the glyphs
+// that Octo added.
+pub static BIG_FONT: [u8; 160] = [
+    0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
+    0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
+    0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
+    0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A ROM storing VF over and over while FX55 moves I up: I passes the end of memory, the stores
+    // wrap around and zero the ROM, and pc then runs through zeros to the end of its range.
+    #[test]
+    fn wrapsPcAndIAroundMemory() {
+        let mut emul = initEmulator(vec![0xA3, 0x00, 0x6F, 0x00, 0xFF, 0x55, 0x12, 0x04]);
+        emul.quirks.loadStoreIncrementsI = true;
+        let pcs = core::cell::RefCell::new(Vec::new());
+        let result = emul.run_until(200_000, |emul| {
+            pcs.borrow_mut().push(emul.pc);
+            false
+        });
+        assert!(matches!(result, Err(Error::Timeout { .. })));
+        assert!(pcs.borrow().contains(&0xFFFE), "pc never reached 0xFFFE");
+    }
+
+    // The shifts set VF to the bit shifted out after the result, so with VF as VX the flag wins.
+    #[test]
+    fn shiftsSetVFAfterTheResult() {
+        for (shiftUsesVY, opcode, vf, v1, result) in [
+            (true, [0x8F, 0x16], 0x00, 0x03, 0x01),  // VF := V1 >> 1, then the 1 shifted out
+            (false, [0x8F, 0x16], 0x02, 0x03, 0x00), // VF >>= 1, shifting out a 0
+            (true, [0x8F, 0x1E], 0x00, 0x81, 0x01),  // VF := V1 << 1, then the 1 shifted out
+            (false, [0x8F, 0x1E], 0x81, 0x00, 0x01),
+        ] {
+            let mut emul = initEmulator(opcode.to_vec());
+            emul.quirks.shiftUsesVY = shiftUsesVY;
+            (emul.registers[0xF], emul.registers[1]) = (vf, v1);
+            emul.run().unwrap();
+            assert_eq!(emul.registers[0xF], result, "{:02X}{:02X} with shiftUsesVY {}", opcode[0], opcode[1], shiftUsesVY);
+        }
+
+        let mut emul = initEmulator(vec![0x80, 0x16, 0x80, 0x1E]);
+        emul.quirks.shiftUsesVY = false;
+        emul.registers[0] = 0x81;
+        emul.run().unwrap();
+        assert_eq!((emul.registers[0], emul.registers[0xF]), (0x40, 1));
+        emul.run().unwrap();
+        assert_eq!((emul.registers[0], emul.registers[0xF]), (0x80, 0));
+    }
+
+    // VF of the subtractions is 1 when nothing is borrowed, equal operands included, and is set
+    // after the result, so with VF as VX the flag wins.
+    #[test]
+    fn subtractionsSetVFAfterTheResult() {
+        let mut emul = initEmulator(vec![0x80, 0x15, 0x80, 0x15, 0x80, 0x17, 0x8F, 0x15, 0x8F, 0x17]);
+        (emul.registers[0], emul.registers[1]) = (5, 5);
+        emul.run().unwrap();
+        assert_eq!((emul.registers[0], emul.registers[0xF]), (0, 1));
+        emul.run().unwrap();
+        assert_eq!((emul.registers[0], emul.registers[0xF]), (0xFB, 0));
+        emul.run().unwrap();
+        assert_eq!((emul.registers[0], emul.registers[0xF]), (0x0A, 0));
+        emul.registers[0xF] = 3;
+        emul.run().unwrap();
+        assert_eq!(emul.registers[0xF], 0, "3 - 5 borrows");
+        emul.registers[0xF] = 3;
+        emul.run().unwrap();
+        assert_eq!(emul.registers[0xF], 1, "5 - 3 doesn't borrow");
+    }
+
+    // FX0A only stores the key once it's released, and repeats itself until then.
+    #[test]
+    fn waitsForAKeyToBeReleased() {
+        let mut emul = initEmulator(vec![0xF3, 0x0A, 0x12, 0x02]);
+        emul.run().unwrap();
+        assert_eq!(emul.pc, 0x200, "no key pressed");
+        emul.keys.set(0xA, true);
+        emul.run().unwrap();
+        emul.run().unwrap();
+        assert_eq!((emul.pc, emul.registers[3]), (0x200, 0), "the key is still held");
+        emul.keys.set(0xA, false);
+        emul.keys.set(0x2, true);
+        emul.run().unwrap();
+        assert_eq!((emul.pc, emul.registers[3]), (0x202, 0xA), "the released key counts, not the one pressed since");
+    }
+
+    #[test]
+    fn refusesROMsItCantLoad() {
+        let mut emul = initEmulator(vec![0x60, 0x01]);
+        assert_eq!(emul.load_rom(&[]), Err(Error::EmptyRom));
+        assert_eq!(emul.load_rom_at(&[0x60, 0x02], 0x100), Err(Error::InvalidLoadAddress(0x100)));
+        assert_eq!(emul.load_rom_at(&[0x60, 0x02], 0x601), Err(Error::InvalidLoadAddress(0x601)));
+        assert_eq!(emul.load_rom_at(&[0x60, 0x02], MEMORY_SIZE), Err(Error::InvalidLoadAddress(MEMORY_SIZE)));
+        assert_eq!(emul.load_rom(&[1; MEMORY_SIZE - MEM_OFFSET + 1]), Err(Error::RomTooLarge { size: 3585, address: 0x200, max: 3584 }));
+        // the ROM loaded before is still there
+        assert_eq!((emul.rom(), emul.rom_range()), ([0x60, 0x01].as_slice(), 0x200..0x202));
+
+        emul.load_rom_at(&[0x60, 0x02], 0x600).unwrap();
+        assert_eq!((emul.pc, emul.rom_range()), (0x600, 0x600..0x602));
+        assert_eq!(emul.memory.bytes()[0x600..0x602], [0x60, 0x02]);
+    }
+
+    // A call with all levels of the stack in use stops at the call, as does a return without one.
+    #[test]
+    fn stopsWhenTheStackRunsOut() {
+        let mut emul = initEmulator(vec![0x22, 0x00]);
+        emul.quirks.stackDepth = 12;
+        for _ in 0..12 {
+            emul.run().unwrap();
+        }
+        assert_eq!(emul.run(), Err(Error::StackOverflow { pc: 0x200, depth: 12 }));
+        assert_eq!((emul.pc, emul.stack.len()), (0x200, 12));
+
+        let mut emul = initEmulator(vec![0x00, 0xEE]);
+        assert_eq!(emul.run(), Err(Error::StackUnderflow { pc: 0x200 }));
+        assert_eq!(emul.pc, 0x200);
+    }
+
+    // A dump of all of memory loads with the zeros that don't fit left out, anything else there
+    // doesn't.
+    #[test]
+    fn trimsZeroPadding() {
+        let mut image = vec![0; MEMORY_SIZE];
+        image[..2].copy_from_slice(&[0x12, 0x00]);
+        let mut emul = Emulator::new();
+        emul.load_rom(&image).unwrap();
+        assert_eq!((emul.rom().len(), emul.trimmed_padding()), (MEMORY_SIZE - MEM_OFFSET, MEM_OFFSET));
+        assert_eq!(emul.rom_range(), MEM_OFFSET..MEMORY_SIZE);
+
+        image[MEMORY_SIZE - 1] = 1;
+        assert_eq!(emul.load_rom(&image), Err(Error::RomTooLarge { size: MEMORY_SIZE, address: MEM_OFFSET, max: MEMORY_SIZE - MEM_OFFSET }));
+    }
+
+    // run_until counts the instructions it ran, ticks the timers every instructionsPerFrame of them
+    // and gives up after max.
+    #[test]
+    fn runsUntilTheConditionHolds() {
+        // V0 counts up from 0, with the delay timer set to 10 first
+        let mut emul = initEmulator(vec![0x6F, 0x0A, 0xFF, 0x15, 0x70, 0x01, 0x12, 0x04]);
+        emul.instructionsPerFrame = 4;
+        assert_eq!(emul.run_until(100, |emul| emul.pc == 0x200), Ok(0));
+        // the timer ticked once, after 4 of the 7 instructions
+        assert_eq!(emul.run_until(100, |emul| emul.registers[0] == 3), Ok(7));
+        assert_eq!(emul.delayTimer, 9);
+        // the frames count from the call, so the 3 left of the last one don't
+        assert_eq!(emul.run_until(100, |emul| emul.delayTimer == 7), Ok(8));
+        assert_eq!(emul.run_until(10, |emul| emul.registers[0] == 0), Err(Error::Timeout { pc: 0x206, cycles: 10 }));
+    }
+}
@@ -0,0 +1,163 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::emulator::{Emulator, KeyState};
+use crate::error::Error;
+use crate::hooks::{Event, Hooks};
+
+// Watch is a value the environment reads from the machine after every step, written as
+// "mem[0x2F0]" for a byte, "mem16[0x2F0]" for a big-endian word, "bcd[0x2F0..0x2F3]" for a
+// number stored as one decimal digit per byte (what FX33 writes) or "V3" for a register.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Watch {
+    Byte(usize),
+    Word(usize),
+    Bcd(usize, usize), // from, to (exclusive), most significant digit first
+    Register(usize),
+}
+
+impl Watch {
+    pub fn parse(expr: &str) -> Result<Watch, String> {
+        let expr = expr.trim();
+        let invalid = || format!("invalid watch '{}', expected mem[addr], mem16[addr], bcd[from..to] or V0-VF", expr);
+        let address = |s: &str| {
+            let s = s.trim();
+            match s.strip_prefix("0x").or(s.strip_prefix("0X")) {
+                Some(hex) => usize::from_str_radix(hex, 16).ok(),
+                None => s.parse().ok(),
+            }
+            .filter(|a| *a < 0x10000)
+        };
+        let inside = |prefix: &str| expr.strip_prefix(prefix).and_then(|e| e.strip_suffix(']'));
+
+        if let Some(a) = inside("mem16[") {
+            return address(a).map(Watch::Word).ok_or_else(invalid);
+        }
+        if let Some(a) = inside("mem[") {
+            return address(a).map(Watch::Byte).ok_or_else(invalid);
+        }
+        if let Some(range) = inside("bcd[") {
+            let (from, to) = range.split_once("..").ok_or_else(invalid)?;
+            let (from, to) = (address(from).ok_or_else(invalid)?, address(to).ok_or_else(invalid)?);
+            // more than 9 digits don't fit an i64 anyway
+            if from >= to || to - from > 9 {
+                return Err(invalid());
+            }
+            return Ok(Watch::Bcd(from, to));
+        }
+        match expr.strip_prefix('V').or(expr.strip_prefix('v')) {
+            Some(r) if r.len() == 1 => usize::from_str_radix(r, 16).map(Watch::Register).map_err(|_| invalid()),
+            _ => Err(invalid()),
+        }
+    }
+
+    // value reads the watched value. Addresses past the end of memory wrap around.
+    pub fn value(&self, emul: &Emulator) -> i64 {
+        let memory = emul.memory.bytes();
+        let byte = |a: usize| memory[a % memory.len()] as i64;
+        match self {
+            Watch::Byte(a) => byte(*a),
+            Watch::Word(a) => byte(*a) << 8 | byte(*a + 1),
+            Watch::Bcd(from, to) => (*from..*to).fold(0, |n, a| n * 10 + byte(a) % 10),
+            Watch::Register(r) => emul.registers[*r] as i64,
+        }
+    }
+}
+
+// Step is what Environment::step returns, like a step of an OpenAI Gym environment.
+#[derive(Clone, Debug)]
+pub struct Step {
+    pub observation: Vec<u8>, // the framebuffer, see Emulator::framebuffer
+    pub reward: f64,
+    pub done: bool,           // the game is over or the ROM failed
+    pub error: Option<Error>, // why the ROM failed, if it did
+    pub events: Vec<Event>,   // what the hooks saw during the step
+}
+
+// Environment wraps an emulator as a reinforcement learning environment in the style of OpenAI
+// Gym: reset starts an episode, step applies an action (the keys to hold) for frameSkip frames and
+// returns the observation and the reward. The reward is the change of the watched values, e.g. the
+// score in memory, over the step. The core is deterministic, so an episode with the same seed and
+// the same actions always plays out the same. An episode ends when the game over hooks fire.
+pub struct Environment {
+    pub emul: Emulator,    // with the ROM loaded and configured, reset keeps it that way
+    pub frameSkip: u32,    // frames per step, 4 like the Atari environments
+    rewards: Vec<(Watch, f64)>, // watched value and the reward per unit it changes
+    values: Vec<i64>,      // the watched values after the previous step
+    hooks: Hooks,
+    seed: u32,
+}
+
+impl Environment {
+    pub fn new(emul: Emulator) -> Environment {
+        Environment { emul, frameSkip: 4, rewards: Vec::new(), values: Vec::new(), hooks: Hooks::default(), seed: 0 }
+    }
+
+    // reward_on adds the change of a watched value, times scale, to the reward of every step.
+    // A negative scale punishes instead, e.g. for the number of lives going down.
+    pub fn reward_on(&mut self, watch: Watch, scale: f64) {
+        self.values.push(watch.value(&self.emul));
+        self.rewards.push((watch, scale));
+    }
+
+    // set_hooks sets the conditions that end an episode, typically those of the ROM's config.
+    pub fn set_hooks(&mut self, mut hooks: Hooks) {
+        self.emul.clear_pc_watches();
+        hooks.attach(&mut self.emul);
+        self.hooks = hooks;
+    }
+
+    // reset starts a new episode from power-on, seeding the random generator so that episodes
+    // can be replayed, and returns the first observation.
+    pub fn reset(&mut self, seed: u32) -> Vec<u8> {
+        self.seed = seed;
+        self.emul.reset();
+        self.emul.seed(seed);
+        self.hooks.reset(&self.emul);
+        self.values = self.rewards.iter().map(|(watch, _)| watch.value(&self.emul)).collect();
+        self.emul.framebuffer().to_vec()
+    }
+
+    // step holds the given keys for frameSkip frames.
+    pub fn step(&mut self, action: KeyState) -> Step {
+        let mut error = None;
+        let mut events = Vec::new();
+        for _ in 0..self.frameSkip {
+            if let Err(e) = self.emul.tick_frame(action) {
+                error = Some(e);
+                break;
+            }
+            events.extend(self.hooks.check(&mut self.emul));
+            if self.hooks.is_over() {
+                break;
+            }
+        }
+
+        let mut reward = 0.0;
+        for ((watch, scale), value) in self.rewards.iter().zip(self.values.iter_mut()) {
+            let now = watch.value(&self.emul);
+            reward += (now - *value) as f64 * scale;
+            *value = now;
+        }
+
+        Step {
+            observation: self.emul.framebuffer().to_vec(),
+            reward,
+            done: error.is_some() || self.hooks.is_over(),
+            error,
+            events,
+        }
+    }
+
+    // actions returns the action space: no key at all or a single one of the 16 keys.
+    pub fn actions() -> Vec<KeyState> {
+        let mut actions = Vec::from([KeyState::default()]);
+        for key in 0..16 {
+            let mut keys = KeyState::default();
+            keys.set(key, true);
+            actions.push(keys);
+        }
+        actions
+    }
+}
@@ -0,0 +1,34 @@
+use core::fmt;
+
+// Error is what the emulator core reports when it's asked to do something it can't.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    EmptyRom,
+    RomTooLarge { size: usize, address: usize, max: usize }, // max is what fits between the load address and the end of memory
+    InvalidLoadAddress(usize),
+    StackOverflow { pc: u16, depth: usize }, // 2NNN at pc with all depth levels of the stack in use
+    StackUnderflow { pc: u16 },              // 00EE at pc with an empty stack
+    UnknownOpcode { pc: u16, opcode: u16 },  // with Emulator::haltOnUnknownOpcode
+    MachineCode { pc: u16, address: u16 },   // 0NNN at pc calling a machine code routine that isn't run
+    MachineCodeRunaway { pc: u16, address: u16 }, // the routine 0NNN at pc called never returned
+    Timeout { pc: u16, cycles: u64 },        // Emulator::run_until ran cycles instructions without its condition holding
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::EmptyRom => write!(f, "the ROM is empty"),
+            Error::RomTooLarge { size, address, max } => write!(f, "the ROM is {} bytes, at most {} bytes fit in memory from {:#05X}", size, max, address),
+            Error::StackOverflow { pc, depth } => write!(f, "stack overflow at {:#05X}, all {} levels are in use", pc, depth),
+            Error::StackUnderflow { pc } => write!(f, "return with an empty stack at {:#05X}", pc),
+            Error::UnknownOpcode { pc, opcode } => write!(f, "unknown opcode {:04X} at {:#05X}", opcode, pc),
+            Error::MachineCode { pc, address } => write!(f, "call to the machine code routine at {:#05X} at {:#05X}", address, pc),
+            Error::MachineCodeRunaway { pc, address } => write!(f, "the machine code routine at {:#05X} called at {:#05X} didn't return", address, pc),
+            Error::Timeout { pc, cycles } => write!(f, "the condition didn't hold after {} instructions, stopped at {:#05X}", cycles, pc),
+            Error::InvalidLoadAddress(address) => write!(f, "invalid load address {:#05X}, expected an even address from {:#05X} up", address, crate::emulator::MEM_OFFSET),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
@@ -0,0 +1,327 @@
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use crate::emulator::Emulator;
+
+// Expr is an expression over the state of the emulator, such as "V3 == 0x1F && I > 0x300", for
+// conditional breakpoints. Its values are numbers, comparisons are 1 when true and 0 when false,
+// and anything but 0 counts as true. It knows
+//
+//   numbers    42, 0x2A
+//   state      V0-VF, I, PC, DT, ST, SP (the stack depth), mem[<expr>] (a byte of memory)
+//   operators  ! and - in front, then from loosest to tightest: ||, &&, == != < <= > >=,
+//              + - & | ^, and parentheses to group
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Expr {
+    Number(i64),
+    Register(usize),
+    Index,
+    Pc,
+    DelayTimer,
+    SoundTimer,
+    StackDepth,
+    Memory(Box<Expr>),
+    Not(Box<Expr>),
+    Negate(Box<Expr>),
+    Binary(Op, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Op {
+    Or,
+    And,
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Add,
+    Subtract,
+    BitAnd,
+    BitOr,
+    BitXor,
+}
+
+// the operators of each precedence level, loosest first, longer symbols before their prefixes.
+const LEVELS: [&[(&str, Op)]; 4] = [
+    &[("||", Op::Or)],
+    &[("&&", Op::And)],
+    &[("==", Op::Equal), ("!=", Op::NotEqual), ("<=", Op::LessOrEqual), (">=", Op::GreaterOrEqual), ("<", Op::Less), (">", Op::Greater)],
+    &[("+", Op::Add), ("-", Op::Subtract), ("&", Op::BitAnd), ("|", Op::BitOr), ("^", Op::BitXor)],
+];
+
+const SYMBOLS: [&str; 19] = ["||", "&&", "==", "!=", "<=", ">=", "<", ">", "+", "-", "&", "|", "^", "!", "(", ")", "[", "]", "="];
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(i64),
+    Name(String),
+    Symbol(&'static str),
+}
+
+impl Expr {
+    pub fn parse(text: &str) -> Result<Expr, String> {
+        let mut parser = Parser { tokens: tokenize(text)?, at: 0 };
+        let expr = parser.level(0)?;
+        match parser.tokens.get(parser.at) {
+            None => Ok(expr),
+            Some(token) => Err(format!("unexpected {} in '{}'", describe(token), text)),
+        }
+    }
+
+    // value evaluates the expression for the current state of the emulator.
+    pub fn value(&self, emul: &Emulator) -> i64 {
+        match self {
+            Expr::Number(n) => *n,
+            Expr::Register(r) => emul.registers[*r] as i64,
+            Expr::Index => emul.I as i64,
+            Expr::Pc => emul.pc as i64,
+            Expr::DelayTimer => emul.delayTimer as i64,
+            Expr::SoundTimer => emul.soundTimer as i64,
+            Expr::StackDepth => emul.stack.len() as i64,
+            Expr::Memory(address) => {
+                let memory = emul.memory.bytes();
+                memory[address.value(emul).rem_euclid(memory.len() as i64) as usize] as i64
+            }
+            Expr::Not(e) => (e.value(emul) == 0) as i64,
+            Expr::Negate(e) => e.value(emul).wrapping_neg(),
+            Expr::Binary(op, a, b) => {
+                let a = a.value(emul);
+                // || and && don't look any further than they need to, like in Rust.
+                match op {
+                    Op::Or if a != 0 => return 1,
+                    Op::And if a == 0 => return 0,
+                    _ => {}
+                }
+                let b = b.value(emul);
+                match op {
+                    Op::Or | Op::And => (b != 0) as i64,
+                    Op::Equal => (a == b) as i64,
+                    Op::NotEqual => (a != b) as i64,
+                    Op::Less => (a < b) as i64,
+                    Op::LessOrEqual => (a <= b) as i64,
+                    Op::Greater => (a > b) as i64,
+                    Op::GreaterOrEqual => (a >= b) as i64,
+                    Op::Add => a.wrapping_add(b),
+                    Op::Subtract => a.wrapping_sub(b),
+                    Op::BitAnd => a & b,
+                    Op::BitOr => a | b,
+                    Op::BitXor => a ^ b,
+                }
+            }
+        }
+    }
+
+    // holds returns whether the expression is true for the current state of the emulator.
+    pub fn holds(&self, emul: &Emulator) -> bool {
+        self.value(emul) != 0
+    }
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = text.trim_start();
+    while let Some(c) = rest.chars().next() {
+        if c.is_ascii_alphanumeric() || c == '_' {
+            let end = rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(rest.len());
+            let word = &rest[..end];
+            let number = match word.strip_prefix("0x").or(word.strip_prefix("0X")) {
+                Some(hex) => i64::from_str_radix(hex, 16).ok(),
+                None => word.parse().ok(),
+            };
+            tokens.push(match number {
+                Some(n) => Token::Number(n),
+                None if c.is_ascii_digit() => return Err(format!("invalid number '{}'", word)),
+                None => Token::Name(word.to_ascii_uppercase()),
+            });
+            rest = &rest[end..];
+        } else {
+            let symbol = SYMBOLS.iter().find(|s| rest.starts_with(**s)).ok_or(format!("unexpected '{}' in '{}'", c, text))?;
+            if *symbol == "=" {
+                return Err(format!("'=' in '{}' should be '=='", text));
+            }
+            tokens.push(Token::Symbol(symbol));
+            rest = &rest[symbol.len()..];
+        }
+        rest = rest.trim_start();
+    }
+    Ok(tokens)
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Number(n) => n.to_string(),
+        Token::Name(name) => name.clone(),
+        Token::Symbol(s) => format!("'{}'", s),
+    }
+}
+
+// Parser is a recursive descent parser with a level per precedence.
+struct Parser {
+    tokens: Vec<Token>,
+    at: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.at).cloned();
+        self.at += 1;
+        token
+    }
+
+    // symbol skips the next token if it's the given symbol, returning whether it was.
+    fn symbol(&mut self, symbol: &str) -> bool {
+        let found = matches!(self.tokens.get(self.at), Some(Token::Symbol(s)) if *s == symbol);
+        if found {
+            self.at += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, symbol: &str) -> Result<(), String> {
+        match self.symbol(symbol) {
+            true => Ok(()),
+            false => Err(match self.tokens.get(self.at) {
+                Some(token) => format!("expected '{}', got {}", symbol, describe(token)),
+                None => format!("expected '{}' at the end", symbol),
+            }),
+        }
+    }
+
+    // level parses operators of a precedence level and everything binding tighter. Comparisons
+    // don't chain, the others are left-associative.
+    fn level(&mut self, level: usize) -> Result<Expr, String> {
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+        let mut expr = self.level(level + 1)?;
+        while let Some(op) = LEVELS[level].iter().find(|(s, _)| self.symbol(s)).map(|(_, op)| *op) {
+            expr = Expr::Binary(op, Box::new(expr), Box::new(self.level(level + 1)?));
+            if level == 2 {
+                break;
+            }
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.symbol("!") {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if self.symbol("-") {
+            return Ok(Expr::Negate(Box::new(self.unary()?)));
+        }
+        match self.next() {
+            Some(Token::Number(n)) => Ok(Expr::Number(n)),
+            Some(Token::Symbol("(")) => {
+                let expr = self.level(0)?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Some(Token::Name(name)) => match name.as_str() {
+                "I" => Ok(Expr::Index),
+                "PC" => Ok(Expr::Pc),
+                "DT" => Ok(Expr::DelayTimer),
+                "ST" => Ok(Expr::SoundTimer),
+                "SP" => Ok(Expr::StackDepth),
+                "MEM" => {
+                    self.expect("[")?;
+                    let address = self.level(0)?;
+                    self.expect("]")?;
+                    Ok(Expr::Memory(Box::new(address)))
+                }
+                _ => match name.strip_prefix('V').filter(|r| r.len() == 1).and_then(|r| usize::from_str_radix(r, 16).ok()) {
+                    Some(r) => Ok(Expr::Register(r)),
+                    None => Err(format!("unknown name '{}', expected V0-VF, I, PC, DT, ST, SP or mem[...]", name)),
+                },
+            },
+            Some(token) => Err(format!("unexpected {}", describe(&token))),
+            None => Err("unexpected end of the expression".into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(text: &str, emul: &Emulator) -> i64 {
+        Expr::parse(text).unwrap().value(emul)
+    }
+
+    #[test]
+    fn bindsFromLoosestToTightest() {
+        let emul = Emulator::new();
+        let number = |n| Box::new(Expr::Number(n));
+        assert_eq!(
+            Expr::parse("1 || 0 && 0"),
+            Ok(Expr::Binary(Op::Or, number(1), Box::new(Expr::Binary(Op::And, number(0), number(0)))))
+        );
+        assert_eq!(
+            Expr::parse("1 + 2 == 3"),
+            Ok(Expr::Binary(Op::Equal, Box::new(Expr::Binary(Op::Add, number(1), number(2))), number(3)))
+        );
+        // + - & | ^ share a level and go from left to right, unlike in Rust
+        assert_eq!(value("1 | 2 & 0", &emul), 0);
+        assert_eq!(value("5 - 1 - 1", &emul), 3);
+        assert_eq!(value("(1 || 0) && 0", &emul), 0);
+        assert_eq!(value("!0 + 1", &emul), 2);
+        assert_eq!(value("-1 + 3", &emul), 2);
+    }
+
+    #[test]
+    fn readsTheStateOfTheEmulator() {
+        let mut emul = Emulator::new();
+        emul.load_rom(&[0x12, 0x00]).unwrap();
+        emul.registers[3] = 0x1F;
+        emul.I = 0x301;
+        assert!(Expr::parse("V3 == 0x1F && I > 0x300").unwrap().holds(&emul));
+        assert!(!Expr::parse("v3 == 0x1f && i > 0x301").unwrap().holds(&emul));
+        assert_eq!(value("mem[PC] + mem[pc + 1]", &emul), 0x12);
+        assert_eq!(value("SP", &emul), 0);
+    }
+
+    #[test]
+    fn shortCircuits() {
+        // V16 doesn't exist, so evaluating the right side would panic
+        let emul = Emulator::new();
+        let missing = || Box::new(Expr::Register(16));
+        assert_eq!(Expr::Binary(Op::Or, Box::new(Expr::Number(2)), missing()).value(&emul), 1);
+        assert_eq!(Expr::Binary(Op::And, Box::new(Expr::Number(0)), missing()).value(&emul), 0);
+        assert_eq!(value("0 || 2", &emul), 1);
+        assert_eq!(value("2 && 3", &emul), 1);
+    }
+
+    #[test]
+    fn wrapsMemoryAddresses() {
+        let mut emul = Emulator::new();
+        let size = emul.memory.bytes().len();
+        emul.memory.bytes_mut()[0] = 0xAB;
+        emul.memory.bytes_mut()[size - 1] = 0xCD;
+        assert_eq!(value("mem[-1]", &emul), 0xCD);
+        assert_eq!(value(&format!("mem[{}]", size), &emul), 0xAB);
+        assert_eq!(value(&format!("mem[-{}]", size), &emul), 0xAB);
+    }
+
+    #[test]
+    fn explainsMalformedExpressions() {
+        let errors = [
+            ("V3 = 1", "'=' in 'V3 = 1' should be '=='"),
+            ("V3 ==", "unexpected end of the expression"),
+            ("(V3", "expected ')' at the end"),
+            ("mem 1", "expected '[', got 1"),
+            ("VG", "unknown name 'VG', expected V0-VF, I, PC, DT, ST, SP or mem[...]"),
+            ("1 2", "unexpected 2 in '1 2'"),
+            ("1 == 2 == 3", "unexpected '==' in '1 == 2 == 3'"),
+            ("0x", "invalid number '0x'"),
+            ("$", "unexpected '$' in '$'"),
+            ("&& 1", "unexpected '&&'"),
+        ];
+        for (text, error) in errors {
+            assert_eq!(Expr::parse(text), Err(error.to_string()), "{}", text);
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use std::collections::VecDeque;
+
+// FLASHES_PER_SECOND is the default of --flash-limit, the three flashes a second WCAG puts the
+// limit at for content that may trigger seizures.
+pub const FLASHES_PER_SECOND: u32 = 3;
+
+// FLASH_SHARE is the part of the display that has to change at once for it to count as a flash,
+// a quarter like WCAG's "a quarter of the field of view".
+const FLASH_SHARE: usize = 4;
+
+// FlashLimiter keeps the display from flashing faster than is safe. XOR drawing makes many ROMs
+// invert the whole screen for a hit or an explosion, or flicker big sprites on every frame. While
+// the display changes that much more often than the limit allows, the limiter shows the average
+// of the last frames rather than the frames themselves, which turns an inversion on every frame
+// into steady grey and smooths out anything faster than the limit.
+pub struct FlashLimiter {
+    recent: VecDeque<Vec<u8>>, // the displays of the latest frames, at most blendFrames
+    sum: Vec<u16>,             // the lit pixels of recent added up
+    changes: VecDeque<u64>,    // the frames with a big change in the last second
+    frame: u64,
+    framesPerSecond: u64,
+    maxChanges: usize,  // a flash is a change and a change back
+    blendFrames: usize, // averaging this many frames filters out flashing above the limit
+}
+
+impl FlashLimiter {
+    pub fn new(timerHz: u32, flashesPerSecond: u32) -> FlashLimiter {
+        FlashLimiter {
+            recent: VecDeque::new(),
+            sum: Vec::new(),
+            changes: VecDeque::new(),
+            frame: 0,
+            framesPerSecond: timerHz as u64,
+            maxChanges: 2 * flashesPerSecond as usize,
+            blendFrames: (timerHz / flashesPerSecond).max(1) as usize,
+        }
+    }
+
+    // filter is called with the display of every frame, one byte per pixel with 1 = lit, and returns
+    // what to show: a level per pixel from 0 for dark to 255 for lit.
+    pub fn filter(&mut self, display: &[u8]) -> Vec<u8> {
+        self.frame += 1;
+        while self.changes.front().is_some_and(|f| f + self.framesPerSecond <= self.frame) {
+            self.changes.pop_front();
+        }
+        if let Some(previous) = self.recent.back() {
+            let changed = previous.iter().zip(display).filter(|(a, b)| a != b).count();
+            if changed * FLASH_SHARE >= display.len() {
+                self.changes.push_back(self.frame);
+            }
+        }
+
+        if self.sum.len() != display.len() {
+            self.sum = vec![0; display.len()];
+        }
+        if self.recent.len() == self.blendFrames {
+            let oldest = self.recent.pop_front().unwrap();
+            self.sum.iter_mut().zip(&oldest).for_each(|(s, p)| *s -= *p as u16);
+        }
+        self.sum.iter_mut().zip(display).for_each(|(s, p)| *s += *p as u16);
+        self.recent.push_back(display.to_vec());
+
+        if self.changes.len() <= self.maxChanges {
+            return display.iter().map(|p| if *p == 1 { 255 } else { 0 }).collect();
+        }
+        let frames = self.recent.len() as u32;
+        self.sum.iter().map(|s| (*s as u32 * 255 / frames) as u8).collect()
+    }
+}
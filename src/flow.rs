@@ -0,0 +1,179 @@
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use crate::disasm::{self, Kind};
+use crate::emulator::{decode, Opcode};
+use crate::variant::Variant;
+
+// The control-flow graph of a ROM: its code (see disasm::classify) split into basic blocks, runs
+// of instructions that are only entered at the first and only left at the last, and the ways from
+// one block to the next. It's for finding one's way around a ROM nobody documented, written as a
+// Graphviz graph with dot or as a page of linked blocks with html.
+
+// Edge is a way out of a block.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Edge {
+    Next, // on to the block right after it
+    Jump,
+    Call, // the block after it is where the call returns to
+    Skip, // the instruction after next, when a skip is taken
+}
+
+// Block is a basic block of a ROM. An edge goes to an address rather than a block, as it can lead
+// outside of the ROM.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Block {
+    pub start: usize,
+    pub end: usize, // after its last instruction
+    pub edges: Vec<(Edge, usize)>,
+    pub exit: Option<&'static str>, // how it's left other than by an edge: RET, EXIT or JP V0
+}
+
+// blocks splits the code of a ROM, with the coverage of a run like for disasm::classify, into basic
+// blocks in the order of their addresses. A block starts where the ROM does, at the target of a
+// jump, call or skip, and after an instruction that branches.
+pub fn blocks(memory: &[u8], rom: Range<usize>, coverage: Option<&[u8]>) -> Vec<Block> {
+    let kinds = disasm::classify(memory, rom.clone(), coverage);
+    let isCode = |address: usize| rom.contains(&address) && kinds[address - rom.start] == Kind::Code;
+    let word = |address: usize| (memory[address] as u16) << 8 | memory[address + 1] as u16;
+
+    let mut starts = alloc::vec![false; rom.len()];
+    let code: Vec<usize> = rom.clone().filter(|a| isCode(*a)).collect();
+    for &address in &code {
+        let b = word(address);
+        let branches = decode(b).instr == 0xB || matches!(b, 0x00EE | 0x00FD) || disasm::branchTarget(address as u16, b).is_some();
+        if let Some(target) = disasm::branchTarget(address as u16, b).filter(|t| isCode(*t as usize)) {
+            starts[target as usize - rom.start] = true;
+        }
+        if branches && isCode(address + 2) {
+            starts[address + 2 - rom.start] = true;
+        }
+        // code that doesn't follow on from code is entered some other way, from a JP V0 maybe.
+        if address < 2 || !isCode(address - 2) {
+            starts[address - rom.start] = true;
+        }
+    }
+
+    let mut blocks: Vec<Block> = Vec::new();
+    for &address in &code {
+        if starts[address - rom.start] {
+            blocks.push(Block { start: address, end: address, edges: Vec::new(), exit: None });
+        }
+        let block = blocks.last_mut().expect("the first instruction starts a block");
+        block.end = address + 2;
+        if !isCode(address + 2) || starts[address + 2 - rom.start] {
+            let b = word(address);
+            let Opcode { instr, NNN, .. } = decode(b);
+            match (instr, b) {
+                (0x0, 0x00EE) => block.exit = Some("RET"),
+                (0x0, 0x00FD) => block.exit = Some("EXIT"),
+                (0xB, _) => block.exit = Some("JP V0"),
+                (0x1, _) => block.edges.push((Edge::Jump, NNN as usize)),
+                (0x2, _) => block.edges.extend([(Edge::Call, NNN as usize), (Edge::Next, address + 2)]),
+                _ => {
+                    if let Some(skip) = disasm::branchTarget(address as u16, b) {
+                        block.edges.push((Edge::Skip, skip as usize));
+                    }
+                    block.edges.push((Edge::Next, address + 2));
+                }
+            }
+        }
+    }
+    blocks
+}
+
+// label names the block at an address like disasm::program does.
+fn label(address: usize) -> String {
+    format!("L{:03X}", address)
+}
+
+// dot writes the graph of the blocks in the Graphviz language, a node per block with its
+// instructions, for "dot -Tsvg". Calls are dashed, taken skips dotted, and the places the code
+// goes that aren't blocks of the ROM get nodes of their own.
+pub fn dot(memory: &[u8], rom: Range<usize>, variant: Variant, coverage: Option<&[u8]>, title: &str) -> String {
+    let blocks = blocks(memory, rom, coverage);
+    let mut text = format!("digraph \"{}\" {{\n", title.replace('"', "\\\""));
+    text.push_str("    node [shape=box, fontname=monospace, fontsize=10];\n");
+    let mut outside = BTreeMap::new();
+    for block in &blocks {
+        let mut lines = format!("{}:\\l", label(block.start));
+        for line in disasm::listing(memory, block.start as u16, (block.end - block.start) / 2, variant) {
+            lines.push_str(&format!("{:03X}  {}\\l", line.address, line.mnemonic));
+        }
+        text.push_str(&format!("    {} [label=\"{}\"];\n", label(block.start), lines));
+        for (edge, target) in &block.edges {
+            if !blocks.iter().any(|b| b.start == *target) {
+                outside.insert(*target, ());
+            }
+            let style = match edge {
+                Edge::Next | Edge::Jump => "",
+                Edge::Call => " [style=dashed, label=\"call\"]",
+                Edge::Skip => " [style=dotted, label=\"skip\"]",
+            };
+            text.push_str(&format!("    {} -> {}{};\n", label(block.start), label(*target), style));
+        }
+        if let Some(exit) = block.exit {
+            text.push_str(&format!("    {}_exit [shape=plaintext, label=\"{}\"];\n", label(block.start), exit));
+            text.push_str(&format!("    {} -> {}_exit [style=dashed];\n", label(block.start), label(block.start)));
+        }
+    }
+    for address in outside.keys() {
+        text.push_str(&format!("    {} [shape=ellipse, style=dashed, label=\"{:#05X}\\nnot code\"];\n", label(*address), address));
+    }
+    text.push_str("}\n");
+    text
+}
+
+// html writes the blocks as a page to read in a browser: the instructions of every block under
+// its label, with links to the blocks it goes to and back to those that come to it.
+pub fn html(memory: &[u8], rom: Range<usize>, variant: Variant, coverage: Option<&[u8]>, title: &str) -> String {
+    let blocks = blocks(memory, rom, coverage);
+    let link = |address: usize| match blocks.iter().any(|b| b.start == address) {
+        true => format!("<a href=\"#{0}\">{0}</a>", label(address)),
+        false => format!("{:#05X} (not code)", address),
+    };
+    let mut text = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{0}</title>\n<style>\n\
+         body {{ font-family: monospace; background: #111; color: #ddd; }}\n\
+         a {{ color: #6af; }}\n\
+         section {{ border: 1px solid #444; margin: 1em 0; padding: 0 1em; }}\n\
+         section:target {{ border-color: #6af; background: #1a2230; }}\n\
+         </style>\n</head>\n<body>\n<h1>{0}</h1>\n<p>{1} blocks</p>\n",
+        escape(title), blocks.len());
+    for block in &blocks {
+        text.push_str(&format!("<section id=\"{}\">\n<h2>{}</h2>\n", label(block.start), label(block.start)));
+        let from: Vec<String> = blocks.iter().filter(|b| b.edges.iter().any(|(_, t)| *t == block.start)).map(|b| link(b.start)).collect();
+        if !from.is_empty() {
+            text.push_str(&format!("<p>from {}</p>\n", from.join(", ")));
+        }
+        text.push_str("<pre>\n");
+        for line in disasm::listing(memory, block.start as u16, (block.end - block.start) / 2, variant) {
+            text.push_str(&format!("{:03X}  {:04X}  {}\n", line.address, line.opcode, line.mnemonic));
+        }
+        text.push_str("</pre>\n<p>");
+        let edges: Vec<String> = block.edges.iter().map(|(edge, target)| {
+            let how = match edge {
+                Edge::Next => "then",
+                Edge::Jump => "jump to",
+                Edge::Call => "call",
+                Edge::Skip => "skip to",
+            };
+            format!("{} {}", how, link(*target))
+        }).collect();
+        text.push_str(&edges.join(", "));
+        if let Some(exit) = block.exit {
+            text.push_str(&format!("{}leaves with {}", if edges.is_empty() { "" } else { ", " }, exit));
+        }
+        text.push_str("</p>\n</section>\n");
+    }
+    text.push_str("</body>\n</html>\n");
+    text
+}
+
+// escape makes a text safe in HTML.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
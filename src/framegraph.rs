@@ -0,0 +1,78 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::pacing::FRAME_DURATION;
+
+// GRAPH_HEIGHT is the number of rows the frame-time graph adds below the display. The full height
+// stands for two 60hz frames, so a bar reaching the middle line took a whole frame.
+pub const GRAPH_HEIGHT: usize = 16;
+
+const EMULATION_COLOR: [u8; 4] = [0x30, 0xC0, 0x30, 0xff];
+const RENDER_COLOR: [u8; 4] = [0x30, 0x60, 0xFF, 0xff];
+const LINE_COLOR: [u8; 4] = [0x60, 0x60, 0x60, 0xff];
+const BACKGROUND: [u8; 4] = [0x10, 0x10, 0x10, 0xff];
+
+// RenderTimes is where the event-loop reports how long presenting took. It's lock-free so that
+// measuring the mutex contention doesn't add to it. The event-loop usually presents more often
+// than the interpreter runs frames, so only the slowest render since the last take is kept.
+#[derive(Default)]
+pub struct RenderTimes {
+    slowest: AtomicU64, // nanoseconds
+}
+
+impl RenderTimes {
+    pub fn record(&self, duration: Duration) {
+        self.slowest.fetch_max(duration.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    pub fn take(&self) -> Duration {
+        Duration::from_nanos(self.slowest.swap(0, Ordering::Relaxed))
+    }
+}
+
+// FrameGraph is a small scrolling graph of how long each of the latest frames took, one column
+// per frame: the time spent emulating (including waiting for the screen mutex) in green with the
+// render time stacked on top of it in blue.
+pub struct FrameGraph {
+    samples: VecDeque<(Duration, Duration)>, // emulation, render
+    width: usize,
+}
+
+impl FrameGraph {
+    pub fn new(width: usize) -> FrameGraph {
+        FrameGraph { samples: VecDeque::with_capacity(width), width }
+    }
+
+    pub fn push(&mut self, emulation: Duration, render: Duration) {
+        if self.samples.len() == self.width {
+            self.samples.pop_front();
+        }
+        self.samples.push_back((emulation, render));
+    }
+
+    // draw paints the graph over the bottom GRAPH_HEIGHT rows of a width x height frame.
+    pub fn draw(&self, px: &mut [u8], width: usize, height: usize) {
+        let rows = |d: Duration| (d.as_nanos() * GRAPH_HEIGHT as u128 / (2 * FRAME_DURATION.as_nanos())) as usize;
+        let top = height - GRAPH_HEIGHT;
+        // the newest sample is always in the rightmost column.
+        let offset = width - self.samples.len().min(width);
+        for x in 0..width {
+            let (emulation, render) = match x.checked_sub(offset).and_then(|i| self.samples.get(i)) {
+                Some((e, r)) => (rows(*e), rows(*e + *r)),
+                None => (0, 0),
+            };
+            for row in 0..GRAPH_HEIGHT {
+                // row 0 is the bottom of the graph
+                let color = match row {
+                    _ if row < emulation => EMULATION_COLOR,
+                    _ if row < render => RENDER_COLOR,
+                    _ if row == GRAPH_HEIGHT / 2 => LINE_COLOR,
+                    _ => BACKGROUND,
+                };
+                let index = ((top + GRAPH_HEIGHT - 1 - row) * width + x) * 4;
+                px[index..index + 4].copy_from_slice(&color);
+            }
+        }
+    }
+}
@@ -0,0 +1,215 @@
+#[cfg(target_os = "linux")]
+pub mod fbdev;
+pub mod headless;
+#[cfg(feature = "sdl2")]
+pub mod sdl;
+pub mod window;
+
+use std::time::Instant;
+
+use rust_chip8::emulator::Emulator;
+
+use crate::cli::Options;
+use crate::flashes::FlashLimiter;
+use crate::input::KeyLatch;
+use crate::pacing::{Pacing, SleepWatch};
+use crate::session::{Control, Session};
+use crate::status::Status;
+
+// A frontend is made of three backends: a Display showing the emulator, an Input reading the keys
+// and an Audio playing the buzzer. Most frontends are put together as a Backend, which drive runs
+// in a plain loop on the current thread. Those that need to own the main loop themselves, such as
+// the winit window, implement Frontend directly. Either way they're registered by name in the
+// Registry so that --frontend can pick one at runtime.
+
+// InputEvent is what an Input backend reports.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum InputEvent {
+    Key(u8, bool), // a CHIP8 key was pressed (true) or released
+    Pause,         // toggle pause
+    Reset,
+    Turbo(bool),
+    SlowMotion(bool),
+    Suspend(bool), // the window was hidden or minimized (true), or is back
+    Quit,
+}
+
+pub trait Display {
+    // present shows the current display of the emulator, as levels from 0 for dark to 255 for lit
+    // per pixel. Those are the pixels of Emulator::framebuffer unless the flash limiter is blending
+    // frames.
+    fn present(&mut self, emul: &Emulator, levels: &[u8], status: &Status) -> Result<(), String>;
+
+    // finish is called once with the final state when the session ends.
+    fn finish(&mut self, _emul: &Emulator) {}
+}
+
+pub trait Input {
+    // poll returns what happened since the last call, without blocking.
+    fn poll(&mut self) -> Vec<InputEvent>;
+}
+
+pub trait Audio {
+    fn buzzer(&mut self, on: bool);
+}
+
+// Silence is the Audio of frontends without sound.
+pub struct Silence;
+
+impl Audio for Silence {
+    fn buzzer(&mut self, _on: bool) {}
+}
+
+pub struct Backend {
+    pub display: Box<dyn Display>,
+    pub input: Box<dyn Input>,
+    pub audio: Box<dyn Audio>,
+    pub paced: bool, // whether to run at 60 (or --timer-hz) frames per second, or as fast as possible
+}
+
+pub trait Frontend {
+    // run runs the session until the user quits, returning the process exit code.
+    fn run(self: Box<Self>, session: Session, options: Options) -> i32;
+}
+
+impl Frontend for Backend {
+    fn run(self: Box<Self>, session: Session, options: Options) -> i32 {
+        drive(*self, session, &options)
+    }
+}
+
+// Factory creates a frontend from the options.
+pub type Factory = fn(&Options) -> Result<Box<dyn Frontend>, String>;
+
+pub struct Registry {
+    entries: Vec<(&'static str, &'static str, Factory)>, // name, description, factory
+}
+
+impl Registry {
+    // builtin returns a registry with all frontends that are compiled in.
+    pub fn builtin() -> Registry {
+        let mut registry = Registry { entries: Vec::new() };
+        registry.register("winit", "a window drawn with winit and pixels (default)", window::create);
+        registry.register("headless", "no window, runs --frames frames as fast as possible and prints the display", headless::create);
+        #[cfg(target_os = "linux")]
+        registry.register("fbdev", "the Linux framebuffer /dev/fb0 with evdev keyboards, for a console without X/Wayland", fbdev::create);
+        #[cfg(feature = "sdl2")]
+        registry.register("sdl2", "a window drawn with SDL2, with sound", sdl::create);
+        registry
+    }
+
+    pub fn register(&mut self, name: &'static str, description: &'static str, factory: Factory) {
+        self.entries.push((name, description, factory));
+    }
+
+    pub fn create(&self, name: &str, options: &Options) -> Result<Box<dyn Frontend>, String> {
+        match self.entries.iter().find(|(n, _, _)| *n == name) {
+            Some((_, _, factory)) => factory(options),
+            None => Err(format!("unknown frontend '{}', expected one of {}", name, self.names().join(", "))),
+        }
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        self.entries.iter().map(|(name, _, _)| *name).collect()
+    }
+
+    // describe lists the frontends as "name: description" lines.
+    pub fn describe(&self) -> Vec<String> {
+        self.entries.iter().map(|(name, description, _)| format!("{}: {}", name, description)).collect()
+    }
+}
+
+// drive runs a session on a Backend, one frame at a time: poll the input, run a frame on the cores,
+// sound the buzzer and present the first core's display. It returns when the input asks to quit.
+pub fn drive(mut backend: Backend, mut session: Session, options: &Options) -> i32 {
+    let pacing = Pacing::new(options.slowMotionPercent, options.timerHz);
+    let mut keys = KeyLatch::new(options.stickyKeys);
+    let mut paused = false;
+    let mut nextFrame = Instant::now();
+    let mut flashLimiter = options.flashLimit.map(|limit| FlashLimiter::new(options.timerHz, limit));
+    let mut lastStatus: Option<Status> = None;
+    let mut sleepWatch = SleepWatch::new();
+
+    loop {
+        // after the host slept the frames go on from now, with the buzzer and the keys off.
+        if let Some(slept) = sleepWatch.check() {
+            println!("No frames for {:.0}s, the system was probably asleep, going on from here", slept.as_secs_f64());
+            nextFrame = Instant::now();
+            backend.audio.buzzer(false);
+            (0..16).for_each(|key| keys.set(key, false));
+        }
+        for event in backend.input.poll() {
+            match event {
+                InputEvent::Key(key, pressed) => {
+                    let key = if options.rotateKeys { options.rotation.remap_key(key) } else { key };
+                    keys.set(key, pressed);
+                }
+                InputEvent::Pause => paused = !paused,
+                InputEvent::Reset => {
+                    if let Err(e) = session.control(Control::Reset) {
+                        println!("{}", e);
+                    }
+                }
+                InputEvent::Turbo(on) => pacing.set_turbo(on),
+                InputEvent::SlowMotion(on) => pacing.set_slow_motion_held(on),
+                InputEvent::Suspend(on) => {
+                    pacing.set_suspended(on);
+                    if on {
+                        backend.audio.buzzer(false);
+                        (0..16).for_each(|key| keys.set(key, false));
+                    }
+                }
+                InputEvent::Quit => {
+                    backend.audio.buzzer(false);
+                    backend.display.finish(&session.cores[0]);
+                    session.dump_memory();
+                    session.save_persisted();
+                    for line in session.unknown_opcodes_summary() {
+                        println!("{}", line);
+                    }
+                    return 0;
+                }
+            }
+        }
+
+        if !paused && !pacing.suspended() {
+            match session.tick_frame(keys.frame()).swap_remove(0) {
+                Ok(output) => {
+                    keys.consumed(output.keysRead);
+                    backend.audio.buzzer(output.sound);
+                    if session.take_break() {
+                        println!("Paused at frame {}, press P to continue", session.frame);
+                        paused = true;
+                    }
+                }
+                Err(e) => {
+                    println!("Stopped at frame {}: {}", session.frame, e);
+                    session.dump_history();
+                    session.report(&e.to_string());
+                    backend.audio.buzzer(false);
+                    paused = true;
+                }
+            }
+        }
+
+        let status = session.status(options.variant.name(), paused, &pacing);
+        if options.announce && lastStatus.as_ref() != Some(&status) {
+            for line in status.announcements(lastStatus.as_ref(), &options.messages) {
+                println!("{}", line);
+            }
+        }
+        let display = session.cores[0].framebuffer();
+        let levels = match flashLimiter.as_mut() {
+            Some(limiter) => limiter.filter(display),
+            None => display.iter().map(|p| if *p == 1 { 255 } else { 0 }).collect(),
+        };
+        if let Err(e) = backend.display.present(&session.cores[0], &levels, &status) {
+            eprintln!("{}", e);
+            return 1;
+        }
+        lastStatus = Some(status);
+        if backend.paced {
+            pacing.wait(&mut nextFrame, paused);
+        }
+    }
+}
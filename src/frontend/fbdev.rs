@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::Read;
+use std::os::unix::fs::FileExt;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+use rust_chip8::emulator::{Emulator, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+use rust_chip8::render::Palette;
+
+use crate::cli::Options;
+use crate::frontend::{Backend, Display, Frontend, Input, InputEvent, Silence};
+use crate::input::Keymap;
+use crate::status::Status;
+
+const FB_DEVICE: &str = "/dev/fb0";
+const FB_SYSFS: &str = "/sys/class/graphics/fb0";
+const INPUT_DIR: &str = "/dev/input";
+
+// evdev key codes of the hotkeys
+const KEY_ESC: u16 = 1;
+const KEY_BACKSPACE: u16 = 14;
+const KEY_TAB: u16 = 15;
+const KEY_P: u16 = 25;
+const KEY_GRAVE: u16 = 41;
+
+const EV_KEY: u16 = 1;
+// A struct input_event from linux/input.h is a timeval of two longs followed by a u16 type, u16
+// code and i32 value. A long is as wide as a pointer on Linux, so 32-bit Raspberry Pi OS has
+// 16 byte events rather than 24.
+const TIMEVAL_SIZE: usize = 2 * std::mem::size_of::<usize>();
+const INPUT_EVENT_SIZE: usize = TIMEVAL_SIZE + 8;
+
+// The fbdev frontend runs on a bare Linux console, such as a Raspberry Pi without X or Wayland. It
+// draws the display scaled up and centered straight into the framebuffer and reads the keys from
+// every evdev keyboard, so the user needs to be allowed to write /dev/fb0 and read /dev/input,
+// usually by being in the video and input groups.
+pub fn create(options: &Options) -> Result<Box<dyn Frontend>, String> {
+    Ok(Box::new(Backend {
+        display: Box::new(Framebuffer::open(options.palette)?),
+        input: Box::new(Evdev::open(options)?),
+        audio: Box::new(Silence),
+        paced: true,
+    }))
+}
+
+struct Framebuffer {
+    device: File,
+    width: usize,
+    height: usize,
+    stride: usize,        // bytes per line
+    bytesPerPixel: usize, // 2 for RGB565 or 4 for XRGB8888
+    last: Vec<u8>,        // the display as last drawn, to skip drawing unchanged ones
+    palette: Palette,
+}
+
+impl Framebuffer {
+    fn open(palette: Palette) -> Result<Framebuffer, String> {
+        let sysfs = |name: &str| {
+            let path = format!("{}/{}", FB_SYSFS, name);
+            fs::read_to_string(&path).map(|s| s.trim().to_string()).map_err(|e| format!("unable to read {}: {}", path, e))
+        };
+        let number = |s: &str| s.trim().parse::<usize>().map_err(|_| format!("unexpected framebuffer setting '{}'", s));
+
+        let size = sysfs("virtual_size")?;
+        let (width, height) = size.split_once(',').ok_or(format!("unexpected framebuffer size '{}'", size))?;
+        let bitsPerPixel = number(&sysfs("bits_per_pixel")?)?;
+        if bitsPerPixel != 16 && bitsPerPixel != 32 {
+            return Err(format!("{} bits per pixel framebuffers are not supported, only 16 and 32", bitsPerPixel));
+        }
+        let device = OpenOptions::new().write(true).open(FB_DEVICE).map_err(|e| format!("unable to open {}: {}", FB_DEVICE, e))?;
+        Ok(Framebuffer {
+            device,
+            width: number(width)?,
+            height: number(height)?,
+            stride: number(&sysfs("stride")?)?,
+            bytesPerPixel: bitsPerPixel / 8,
+            last: Vec::new(),
+            palette,
+        })
+    }
+}
+
+impl Display for Framebuffer {
+    fn present(&mut self, _emul: &Emulator, levels: &[u8], _status: &Status) -> Result<(), String> {
+        if levels == &self.last[..] {
+            return Ok(());
+        }
+        self.last = levels.to_vec();
+
+        // the largest whole scale that fits keeps the pixels square.
+        let scale = (self.width / DISPLAY_WIDTH).min(self.height / DISPLAY_HEIGHT).max(1);
+        let left = self.width.saturating_sub(DISPLAY_WIDTH * scale) / 2;
+        let top = self.height.saturating_sub(DISPLAY_HEIGHT * scale) / 2;
+        let pixel = |[r, g, b, _]: [u8; 4]| match self.bytesPerPixel {
+            2 => ((r as u16 >> 3) << 11 | (g as u16 >> 2) << 5 | b as u16 >> 3).to_le_bytes().to_vec(),
+            _ => vec![b, g, r, 0xFF],
+        };
+        // the colors of the levels in use, usually just dark and lit.
+        let mut colors: HashMap<u8, Vec<u8>> = HashMap::new();
+
+        let mut line = Vec::with_capacity(DISPLAY_WIDTH * scale * self.bytesPerPixel);
+        for y in 0..DISPLAY_HEIGHT {
+            line.clear();
+            for x in 0..DISPLAY_WIDTH {
+                let level = levels[y * DISPLAY_WIDTH + x];
+                let color = colors.entry(level).or_insert_with(|| pixel(self.palette.blend(level)));
+                for _ in 0..scale {
+                    line.extend_from_slice(color);
+                }
+            }
+            for row in 0..scale {
+                let offset = (top + y * scale + row) * self.stride + left * self.bytesPerPixel;
+                self.device.write_all_at(&line, offset as u64).map_err(|e| format!("unable to write {}: {}", FB_DEVICE, e))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Evdev reads key events from all keyboards. Reading evdev devices blocks, so each one gets a
+// thread that passes the key events (code, pressed) on through a channel.
+struct Evdev {
+    events: Receiver<(u16, bool)>,
+    keymap: Keymap,
+}
+
+impl Evdev {
+    fn open(options: &Options) -> Result<Evdev, String> {
+        let (sender, receiver) = mpsc::channel();
+        let entries = fs::read_dir(INPUT_DIR).map_err(|e| format!("unable to read {}: {}", INPUT_DIR, e))?;
+        let mut opened = 0;
+        for entry in entries.flatten() {
+            if !entry.file_name().to_string_lossy().starts_with("event") {
+                continue;
+            }
+            let Ok(mut device) = File::open(entry.path()) else { continue };
+            opened += 1;
+            let sender = sender.clone();
+            thread::spawn(move || {
+                let mut event = [0u8; INPUT_EVENT_SIZE];
+                while device.read_exact(&mut event).is_ok() {
+                    let fields = &event[TIMEVAL_SIZE..];
+                    let kind = u16::from_ne_bytes([fields[0], fields[1]]);
+                    let code = u16::from_ne_bytes([fields[2], fields[3]]);
+                    let value = i32::from_ne_bytes([fields[4], fields[5], fields[6], fields[7]]);
+                    // value 2 is auto-repeat, which doesn't change anything.
+                    if kind == EV_KEY && value != 2 && sender.send((code, value == 1)).is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+        if opened == 0 {
+            return Err(format!("unable to open any input device in {}", INPUT_DIR));
+        }
+        Ok(Evdev { events: receiver, keymap: Keymap::named(&options.keymap)? })
+    }
+}
+
+impl Input for Evdev {
+    fn poll(&mut self) -> Vec<InputEvent> {
+        let mut events = Vec::new();
+        for (code, pressed) in self.events.try_iter() {
+            match code {
+                KEY_ESC if pressed => events.push(InputEvent::Quit),
+                KEY_P if pressed => events.push(InputEvent::Pause),
+                KEY_BACKSPACE if pressed => events.push(InputEvent::Reset),
+                KEY_TAB => events.push(InputEvent::Turbo(pressed)),
+                KEY_GRAVE => events.push(InputEvent::SlowMotion(pressed)),
+                _ => {
+                    // evdev key codes are the scancodes the keymap is made of.
+                    if let Some(key) = self.keymap.key(code as u32) {
+                        events.push(InputEvent::Key(key, pressed));
+                    }
+                }
+            }
+        }
+        events
+    }
+}
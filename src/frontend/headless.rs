@@ -0,0 +1,59 @@
+use rust_chip8::emulator::{Emulator, Region, DISPLAY_WIDTH};
+use rust_chip8::hooks::screenHash;
+
+use crate::cli::Options;
+use crate::frontend::{Backend, Display, Frontend, Input, InputEvent, Silence};
+use crate::status::Status;
+
+// The headless frontend runs a ROM without a window for a fixed number of frames, as fast as the
+// host allows, and then prints the display as text. Handy in scripts and for checking what a ROM
+// draws on a machine without a screen.
+pub fn create(options: &Options) -> Result<Box<dyn Frontend>, String> {
+    Ok(Box::new(Backend {
+        display: Box::new(Text { hashRegion: options.hashRegion }),
+        input: Box::new(Frames { left: options.frames }),
+        audio: Box::new(Silence),
+        paced: false,
+    }))
+}
+
+// Frames is an Input that quits after a number of frames.
+struct Frames {
+    left: u64,
+}
+
+impl Input for Frames {
+    fn poll(&mut self) -> Vec<InputEvent> {
+        if self.left == 0 {
+            return vec![InputEvent::Quit];
+        }
+        self.left -= 1;
+        Vec::new()
+    }
+}
+
+// Text is a Display that prints the final display, a # for every lit pixel of the current mode,
+// and the condition for a screen hook on hashRegion if given.
+struct Text {
+    hashRegion: Option<Region>,
+}
+
+impl Display for Text {
+    fn present(&mut self, _emul: &Emulator, _levels: &[u8], _status: &Status) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn finish(&mut self, emul: &Emulator) {
+        let (width, height) = emul.resolution();
+        let scale = DISPLAY_WIDTH / width;
+        for y in 0..height {
+            let row: String = (0..width)
+                .map(|x| if emul.framebuffer()[y * scale * DISPLAY_WIDTH + x * scale] == 1 { '#' } else { '.' })
+                .collect();
+            println!("{}", row);
+        }
+        if let Some(r) = self.hashRegion {
+            println!("screen[{},{},{},{}]==0x{:016X}", r.x, r.y, r.width, r.height, screenHash(emul, r));
+        }
+    }
+}
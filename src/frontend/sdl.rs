@@ -0,0 +1,185 @@
+use std::collections::HashMap;
+
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+use sdl2::controller::GameController;
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::Scancode;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::WindowCanvas;
+use sdl2::EventPump;
+
+use rust_chip8::emulator::{Emulator, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+use rust_chip8::render::Palette;
+
+use crate::cli::Options;
+use crate::frontend::{Audio, Backend, Display, Frontend, Input, InputEvent};
+use crate::i18n::Messages;
+use crate::input::{Players, QWERTY_NAMES};
+use crate::status::Status;
+
+// The SDL2 frontend is an alternative to the winit window for where wgpu is a problem, such as
+// older GPUs or a Raspberry Pi without a desktop. It's a plain Backend run by drive, so it has
+// none of the winit extras like rotation or compare mode.
+pub fn create(options: &Options) -> Result<Box<dyn Frontend>, String> {
+    let sdl = sdl2::init()?;
+    let window = sdl.video()?
+        .window("Rust-CHIP8", 10 * 64, 10 * 32)
+        .position_centered()
+        .resizable()
+        .build()
+        .map_err(|e| e.to_string())?;
+    let mut canvas = window.into_canvas().build().map_err(|e| e.to_string())?;
+    // let SDL scale the display up, by whole factors to keep the pixels square.
+    canvas.set_logical_size(DISPLAY_WIDTH as u32, DISPLAY_HEIGHT as u32).map_err(|e| e.to_string())?;
+    canvas.set_integer_scale(true)?;
+
+    let desired = AudioSpecDesired { freq: Some(44100), channels: Some(1), samples: None };
+    let device = sdl.audio()?.open_playback(None, &desired, |spec| SquareWave {
+        step: 440.0 / spec.freq as f32,
+        phase: 0.0,
+    })?;
+
+    Ok(Box::new(Backend {
+        display: Box::new(Canvas { canvas, messages: options.messages.clone(), palette: options.palette }),
+        input: Box::new(Keys { events: sdl.event_pump()?, players: options.players.clone(), controllers: openControllers(&sdl)? }),
+        audio: Box::new(Buzzer { device }),
+        paced: true,
+    }))
+}
+
+struct Canvas {
+    canvas: WindowCanvas,
+    messages: Messages,
+    palette: Palette,
+}
+
+impl Display for Canvas {
+    fn present(&mut self, _emul: &Emulator, levels: &[u8], status: &Status) -> Result<(), String> {
+        let title = status.title(&self.messages);
+        if self.canvas.window().title() != title {
+            self.canvas.window_mut().set_title(&title).map_err(|e| e.to_string())?;
+        }
+        let [r, g, b, _] = self.palette.off;
+        self.canvas.set_draw_color(Color::RGB(r, g, b));
+        self.canvas.clear();
+        // the pixels of each level but dark, usually just lit.
+        let mut lit: HashMap<u8, Vec<Rect>> = HashMap::new();
+        for (i, level) in levels.iter().enumerate().filter(|(_, level)| **level > 0) {
+            lit.entry(*level).or_default().push(Rect::new((i % DISPLAY_WIDTH) as i32, (i / DISPLAY_WIDTH) as i32, 1, 1));
+        }
+        for (level, rects) in lit {
+            let [r, g, b, _] = self.palette.blend(level);
+            self.canvas.set_draw_color(Color::RGB(r, g, b));
+            self.canvas.fill_rects(&rects)?;
+        }
+        self.canvas.present();
+        Ok(())
+    }
+}
+
+// Keys reads the keyboard, and the gamepads for the players. Like the winit window it matches
+// the keypad on scancodes, which SDL names after the US layout, and the controls of the players on
+// the keys as labeled.
+struct Keys {
+    events: EventPump,
+    players: Players,
+    controllers: Vec<GameController>, // open for their events
+}
+
+// openControllers opens the gamepads SDL knows how to map, to get the events of their buttons.
+fn openControllers(sdl: &sdl2::Sdl) -> Result<Vec<GameController>, String> {
+    let subsystem = sdl.game_controller()?;
+    let controllers: Vec<GameController> = (0..subsystem.num_joysticks()?)
+        .filter(|i| subsystem.is_game_controller(*i))
+        .filter_map(|i| subsystem.open(i).ok())
+        .collect();
+    for controller in &controllers {
+        println!("Gamepad: {}", controller.name());
+    }
+    Ok(controllers)
+}
+
+impl Input for Keys {
+    fn poll(&mut self) -> Vec<InputEvent> {
+        let mut events = Vec::new();
+        for event in self.events.poll_iter() {
+            let (scancode, keycode, pressed) = match event {
+                Event::Quit { .. } => {
+                    events.push(InputEvent::Quit);
+                    continue;
+                }
+                Event::Window { win_event: WindowEvent::Minimized | WindowEvent::Hidden, .. } => {
+                    events.push(InputEvent::Suspend(true));
+                    continue;
+                }
+                Event::Window { win_event: WindowEvent::Restored | WindowEvent::Shown, .. } => {
+                    events.push(InputEvent::Suspend(false));
+                    continue;
+                }
+                Event::ControllerButtonDown { button, .. } | Event::ControllerButtonUp { button, .. } => {
+                    if let Some(key) = self.players.key(&format!("pad:{}", button.string())) {
+                        events.push(InputEvent::Key(key, matches!(event, Event::ControllerButtonDown { .. })));
+                    }
+                    continue;
+                }
+                Event::KeyDown { scancode: Some(scancode), keycode, repeat: false, .. } => (scancode, keycode, true),
+                Event::KeyUp { scancode: Some(scancode), keycode, .. } => (scancode, keycode, false),
+                _ => continue,
+            };
+            if let Some(key) = keycode.and_then(|k| self.players.key(&k.name())) {
+                events.push(InputEvent::Key(key, pressed));
+                continue;
+            }
+            match scancode {
+                Scancode::Escape if pressed => events.push(InputEvent::Quit),
+                Scancode::P if pressed => events.push(InputEvent::Pause),
+                Scancode::Backspace if pressed => events.push(InputEvent::Reset),
+                Scancode::Tab => events.push(InputEvent::Turbo(pressed)),
+                Scancode::Grave => events.push(InputEvent::SlowMotion(pressed)),
+                Scancode::F6 if pressed && !self.players.is_empty() => {
+                    self.players.swap();
+                    (0..16).for_each(|key| events.push(InputEvent::Key(key, false)));
+                    println!("Swapped the players: {}", self.players.describe().join(", "));
+                }
+                _ => {
+                    if let Some(key) = QWERTY_NAMES.iter().position(|name| Scancode::from_name(name) == Some(scancode)) {
+                        events.push(InputEvent::Key(key as u8, pressed));
+                    }
+                }
+            }
+        }
+        events
+    }
+}
+
+// SquareWave generates the buzzer tone.
+struct SquareWave {
+    step: f32, // how far a sample moves through a period of the tone
+    phase: f32,
+}
+
+impl AudioCallback for SquareWave {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        for sample in out.iter_mut() {
+            *sample = if self.phase < 0.5 { 0.15 } else { -0.15 };
+            self.phase = (self.phase + self.step) % 1.0;
+        }
+    }
+}
+
+struct Buzzer {
+    device: AudioDevice<SquareWave>,
+}
+
+impl Audio for Buzzer {
+    fn buzzer(&mut self, on: bool) {
+        if on {
+            self.device.resume();
+        } else {
+            self.device.pause();
+        }
+    }
+}
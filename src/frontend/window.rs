@@ -0,0 +1,1407 @@
+use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, TryRecvError};
+use std::sync::{Mutex, Arc};
+use std::time::{Duration, Instant};
+use std::thread;
+use pixels::{Pixels, PixelsBuilder, SurfaceTexture};
+
+use winit::dpi::{LogicalSize, PhysicalPosition, PhysicalSize};
+use winit::event::{ElementState, Event, MouseButton, Touch, TouchPhase, VirtualKeyCode, WindowEvent};
+use winit::event_loop::{ControlFlow, EventLoopBuilder, EventLoopProxy};
+use winit::monitor::MonitorHandle;
+use winit::window::{Fullscreen, Icon, WindowBuilder};
+use winit_input_helper::WinitInputHelper;
+use rust_chip8::hooks::Hooks;
+use rust_chip8::render::Screen;
+
+use crate::actions::{Action, CommandPalette};
+use crate::assets;
+use crate::bezel::Bezel;
+use crate::bundle::{self, Bundle};
+use crate::cli::{self, Command, Options};
+use crate::commands::batch::panicMessage;
+use crate::flashes::FlashLimiter;
+use crate::framegraph;
+use crate::frontend::Frontend;
+use crate::i18n::Messages;
+use crate::input::{self, KeyLatch, Keymap};
+use crate::keypad::{Keypad, KEYPAD_HEIGHT};
+use crate::latency::Latency;
+use crate::library::{self, Library, Scan};
+use crate::overlay::{self, Overlay};
+use crate::pacing::{Pacing, SleepWatch, FRAME_DURATION};
+use crate::paths;
+use crate::placement::{self, Placement};
+use crate::scope;
+use crate::session::{Control, Session, StateSlot};
+use crate::states::StateBrowser;
+use crate::status::Status;
+use crate::upscale::Upscaler;
+use crate::wizard::Wizard;
+
+// Window is the default frontend: a winit window with the display drawn by pixels. Every ROM runs
+// in a tab with an interpreter thread of its own, so it keeps its pace however the event-loop
+// presents.
+pub struct Window;
+
+pub fn create(_options: &Options) -> Result<Box<dyn Frontend>, String> {
+    Ok(Box::new(Window))
+}
+
+fn icon(png: &[u8]) -> Result<Icon, String> {
+    let mut reader = png::Decoder::new(png).read_info().map_err(|e| e.to_string())?;
+    let mut rgba = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut rgba).map_err(|e| e.to_string())?;
+    Icon::from_rgba(rgba, info.width, info.height).map_err(|e| e.to_string())
+}
+
+// Combo is a key with modifiers, like the admin combo of --kiosk-admin.
+struct Combo {
+    ctrl: bool,
+    alt: bool,
+    shift: bool,
+    key: VirtualKeyCode,
+}
+
+// COMBO_KEYS are the keys a combo can end with, by name.
+const COMBO_KEYS: [(&str, VirtualKeyCode); 40] = [
+    ("a", VirtualKeyCode::A), ("b", VirtualKeyCode::B), ("c", VirtualKeyCode::C), ("d", VirtualKeyCode::D),
+    ("e", VirtualKeyCode::E), ("f", VirtualKeyCode::F), ("g", VirtualKeyCode::G), ("h", VirtualKeyCode::H),
+    ("i", VirtualKeyCode::I), ("j", VirtualKeyCode::J), ("k", VirtualKeyCode::K), ("l", VirtualKeyCode::L),
+    ("m", VirtualKeyCode::M), ("n", VirtualKeyCode::N), ("o", VirtualKeyCode::O), ("p", VirtualKeyCode::P),
+    ("q", VirtualKeyCode::Q), ("r", VirtualKeyCode::R), ("s", VirtualKeyCode::S), ("t", VirtualKeyCode::T),
+    ("u", VirtualKeyCode::U), ("v", VirtualKeyCode::V), ("w", VirtualKeyCode::W), ("x", VirtualKeyCode::X),
+    ("y", VirtualKeyCode::Y), ("z", VirtualKeyCode::Z),
+    ("f1", VirtualKeyCode::F1), ("f2", VirtualKeyCode::F2), ("f3", VirtualKeyCode::F3), ("f4", VirtualKeyCode::F4),
+    ("f5", VirtualKeyCode::F5), ("f6", VirtualKeyCode::F6), ("f7", VirtualKeyCode::F7), ("f8", VirtualKeyCode::F8),
+    ("f9", VirtualKeyCode::F9), ("f10", VirtualKeyCode::F10), ("f11", VirtualKeyCode::F11), ("f12", VirtualKeyCode::F12),
+    ("escape", VirtualKeyCode::Escape), ("delete", VirtualKeyCode::Delete),
+];
+
+impl Combo {
+    // parse reads a combo such as "ctrl+shift+f12": any of ctrl, alt and shift, and a letter, F1-F12,
+    // escape or delete.
+    fn parse(spec: &str) -> Result<Combo, String> {
+        let mut combo = Combo { ctrl: false, alt: false, shift: false, key: VirtualKeyCode::Escape };
+        let parts: Vec<String> = spec.split('+').map(|p| p.trim().to_lowercase()).collect();
+        let (key, modifiers) = parts.split_last().ok_or(format!("invalid key combo '{}'", spec))?;
+        for modifier in modifiers {
+            match modifier.as_str() {
+                "ctrl" => combo.ctrl = true,
+                "alt" => combo.alt = true,
+                "shift" => combo.shift = true,
+                _ => return Err(format!("invalid modifier '{}' in key combo '{}', expected ctrl, alt or shift", modifier, spec)),
+            }
+        }
+        combo.key = COMBO_KEYS.iter().find(|(name, _)| name == key).map(|(_, k)| *k)
+            .ok_or(format!("invalid key '{}' in key combo '{}', expected a letter, f1-f12, escape or delete", key, spec))?;
+        Ok(combo)
+    }
+
+    fn pressed(&self, input: &WinitInputHelper) -> bool {
+        input.key_pressed(self.key) && input.held_control() == self.ctrl && input.held_alt() == self.alt && input.held_shift() == self.shift
+    }
+}
+
+// Tab is a ROM running in the window: a session in an interpreter thread of its own, drawing into
+// a frame of its own. The event-loop presents the frame of the active tab and sends the input
+// there, the other tabs keep running in the background.
+struct Tab {
+    romPath: String,
+    frame: Arc<Mutex<Vec<u8>>>, // RGBA, the size of the "pixels" frame
+    fresh: Arc<AtomicBool>,     // the frame has changed since it was last presented
+    keys: Arc<Mutex<KeyLatch>>,
+    paused: Arc<AtomicBool>,
+    pacing: Arc<Pacing>,
+    // the About box or the command palette, while one is open. The event-loop opens and closes
+    // them, the interpreter thread draws them.
+    overlay: Arc<Mutex<Option<Overlay>>>,
+    renderTimes: Arc<framegraph::RenderTimes>,
+    latency: Option<Arc<Mutex<Latency>>>, // with --latency
+    // the summary of the unknown opcodes the ROM ran, kept up to date by the interpreter thread
+    // for the event-loop to print when the tab closes.
+    unknownOpcodes: Arc<Mutex<Vec<String>>>,
+    states: Arc<Mutex<Vec<Option<StateSlot>>>>, // the save state slots, see Session::slots
+    controls: mpsc::Sender<Control>, // closing the tab drops this, which ends the thread
+    thread: Option<thread::JoinHandle<()>>, // the interpreter thread, which close waits for
+    statuses: mpsc::Receiver<Status>, // the thread sends its status whenever it changes
+    status: Option<Status>,           // the latest of those
+    // the frames the ROM has run since its play time was last added to the library, not counting
+    // those paused or of the attract mode's demos, and the best score since then.
+    played: Arc<AtomicU64>,
+    timerHz: u32,
+    bestScore: Option<i64>,
+}
+
+impl Tab {
+    // send passes a control on to the interpreter thread.
+    fn send(&self, control: Control) {
+        // the interpreter thread only stops when the tab is closed, never mind errors.
+        let _ = self.controls.send(control);
+    }
+
+    // load reads a ROM file and has the tab run it in place of the current one.
+    fn load(&mut self, path: &Path) {
+        match bundle::readRom(&path.to_string_lossy()) {
+            Ok(data) => {
+                self.update_stats();
+                self.romPath = path.to_string_lossy().to_string();
+                self.bestScore = None;
+                launched(&self.romPath);
+                self.send(Control::Load(self.romPath.clone(), data));
+            }
+            Err(e) => println!("{}", e),
+        }
+    }
+
+    // release lets go of the CHIP8 keys and the speed keys, for when the tab loses the keyboard
+    // and would never see the keys held now being released.
+    fn release(&self) {
+        let mut keys = self.keys.lock().unwrap();
+        (0..16).for_each(|key| keys.set(key, false));
+        self.pacing.set_turbo(false);
+        self.pacing.set_slow_motion_held(false);
+    }
+
+    // update_stats adds the time the ROM has run to the stats of the library, and the best score
+    // when it beats that of the library.
+    fn update_stats(&self) {
+        let (frames, hz) = (self.played.swap(0, Ordering::Relaxed), self.timerHz as u64);
+        // what's left of a second is kept for the next time.
+        self.played.fetch_add(frames % hz, Ordering::Relaxed);
+        let updated = library::update(&self.romPath, |stats| {
+            stats.playSeconds += frames / hz;
+            stats.bestScore = stats.bestScore.max(self.bestScore);
+        });
+        if let Err(e) = updated {
+            println!("{}", e);
+        }
+    }
+
+    // close prints the unknown opcodes the ROM ran if any and the input latency with --latency,
+    // updates its stats in the library and ends its thread, waiting for it to save the persisted
+    // memory, as exiting the window wouldn't.
+    fn close(self) {
+        for line in self.unknownOpcodes.lock().unwrap().iter() {
+            println!("{}", line);
+        }
+        if let Some(latency) = &self.latency {
+            for line in latency.lock().unwrap().report() {
+                println!("{}", line);
+            }
+        }
+        self.update_stats();
+        drop(self.controls);
+        // a thread that panicked outside of a frame has nothing left to save.
+        if let Some(thread) = self.thread {
+            let _ = thread.join();
+        }
+    }
+}
+
+// Hidden is why the window can't be seen, if it can't. The ROMs are suspended then.
+#[derive(Clone, Copy, Default)]
+struct Hidden {
+    minimized: bool,
+    occluded: bool,   // covered by other windows, or on another virtual desktop
+    background: bool, // the app was sent to the background, on mobile
+}
+
+impl Hidden {
+    fn any(&self) -> bool {
+        self.minimized || self.occluded || self.background
+    }
+}
+
+// Settings are what the interpreter threads of all tabs run with, taken from the options.
+#[derive(Clone)]
+struct Settings {
+    layout: Screen,
+    keypad: Option<Keypad>,
+    variantName: &'static str,
+    stickyKeys: bool,
+    slowMotionPercent: u32,
+    timerHz: u32,
+    flashLimit: Option<u32>,
+    paletteCycle: Option<u32>,
+    frameGraph: bool,
+    latency: bool,
+    soundScope: bool,
+    announce: Option<Messages>,
+    kiosk: bool,
+    // wakes the event-loop up when a frame is drawn or the status changes, see Tab::fresh.
+    wake: EventLoopProxy<()>,
+}
+
+// spawnTab starts the interpreter thread of a session and returns the tab to control it with.
+// Each 60hz (or --timer-hz) "tick" lets the emulator process one frame worth of instructions,
+// after which the pixels that changed are written to the frame of the tab. The thread keeps its
+// own pace regardless of how often the event-loop presents, so timers run at 60hz on a 144hz
+// display or with vsync off just the same.
+fn spawnTab(mut session: Session, settings: &Settings) -> Tab {
+    let (mut layout, keypad, variantName) = (settings.layout, settings.keypad, settings.variantName);
+    let (palette, paletteCycle) = (layout.palette, settings.paletteCycle);
+    let (screenWidth, screenHeight) = layout.size();
+    let mut pixels = vec![0; screenWidth * screenHeight * 4];
+    if let Some(keypad) = &keypad {
+        keypad.draw(&mut pixels, Default::default());
+    }
+    let (statusSender, statuses) = mpsc::channel::<Status>();
+    let (controls, controlReceiver) = mpsc::channel::<Control>();
+    let mut tab = Tab {
+        romPath: session.romPath.clone(),
+        frame: Arc::new(Mutex::new(pixels)),
+        fresh: Arc::new(AtomicBool::new(true)),
+        keys: Arc::new(Mutex::new(KeyLatch::new(settings.stickyKeys))),
+        paused: Arc::new(AtomicBool::new(false)),
+        pacing: Arc::new(Pacing::new(settings.slowMotionPercent, settings.timerHz)),
+        overlay: Arc::new(Mutex::new(None)),
+        renderTimes: Arc::new(framegraph::RenderTimes::default()),
+        latency: settings.latency.then(|| Arc::new(Mutex::new(Latency::default()))),
+        unknownOpcodes: Arc::new(Mutex::new(Vec::new())),
+        states: Arc::new(Mutex::new(session.slots())),
+        controls,
+        thread: None,
+        statuses,
+        status: None,
+        played: Arc::new(AtomicU64::new(0)),
+        timerHz: settings.timerHz,
+        bestScore: None,
+    };
+    launched(&tab.romPath);
+
+    let (frame, keyLatch, paused, pacing) = (Arc::clone(&tab.frame), Arc::clone(&tab.keys), Arc::clone(&tab.paused), Arc::clone(&tab.pacing));
+    let (overlay, renderTimes, unknownOpcodes) = (Arc::clone(&tab.overlay), Arc::clone(&tab.renderTimes), Arc::clone(&tab.unknownOpcodes));
+    let (latency, fresh, wake) = (tab.latency.clone(), Arc::clone(&tab.fresh), settings.wake.clone());
+    let (played, states) = (Arc::clone(&tab.played), Arc::clone(&tab.states));
+    let announce = settings.announce.clone();
+    let kiosk = settings.kiosk;
+    let mut flashLimiters = settings.flashLimit.map(|limit| session.cores.iter().map(|_| FlashLimiter::new(settings.timerHz, limit)).collect::<Vec<_>>());
+    let mut frameGraph = settings.frameGraph.then(|| framegraph::FrameGraph::new(screenWidth));
+    let mut soundScope = settings.soundScope.then(|| scope::SoundScope::new(screenWidth));
+
+    tab.thread = Some(thread::spawn(move||{
+        let mut diverged = false;
+        let mut overlayShown = false;
+        let mut crashed = false; // the overlay is that of a panic
+
+        // the point in time when the next frame is due. Sleeping until an absolute deadline
+        // rather than for "whatever is left" keeps sleep overshoot from adding up over time.
+        let mut nextFrame = Instant::now();
+        let mut lastStatus: Option<Status> = None;
+        let mut sleepWatch = SleepWatch::new();
+
+        loop {
+            // after the host slept the frames go on from now, and the keys held when it fell
+            // asleep are let go of, as their releases may never have come in.
+            if let Some(slept) = sleepWatch.check() {
+                println!("No frames for {:.0}s, the system was probably asleep, going on from here", slept.as_secs_f64());
+                nextFrame = Instant::now();
+                let mut keys = keyLatch.lock().unwrap();
+                (0..16).for_each(|key| keys.set(key, false));
+            }
+            let mut drew = false; // on the frame, this time around
+            loop {
+                let control = match controlReceiver.try_recv() {
+                    Ok(control) => control,
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        // the tab was closed
+                        session.save_persisted();
+                        return;
+                    }
+                };
+                let saving = matches!(control, Control::SaveState(_));
+                match session.control(control) {
+                    Ok(message) => println!("{}", message),
+                    Err(e) => {
+                        println!("{}", e);
+                        continue;
+                    }
+                }
+                if saving {
+                    *states.lock().unwrap() = session.slots();
+                }
+                diverged = false;
+                if crashed {
+                    *overlay.lock().unwrap() = None;
+                    crashed = false;
+                }
+                let mut px = frame.lock().unwrap();
+                for (i, emul) in session.cores.iter().enumerate() {
+                    layout.paint(&mut px, i, emul.framebuffer(), emul.framebuffer());
+                }
+                drew = true;
+            }
+
+            let status = session.status(variantName, paused.load(Ordering::Relaxed), &pacing);
+            if lastStatus.as_ref() != Some(&status) {
+                if let Some(messages) = &announce {
+                    for line in status.announcements(lastStatus.as_ref(), messages) {
+                        println!("{}", line);
+                    }
+                }
+                // the event-loop is gone when the window has closed, never mind then.
+                let _ = statusSender.send(status.clone());
+                let _ = wake.send_event(());
+                lastStatus = Some(status);
+            }
+
+            if !paused.load(Ordering::Relaxed) && !pacing.suspended() {
+                if crashed {
+                    *overlay.lock().unwrap() = None;
+                    crashed = false;
+                }
+                let frameStart = Instant::now();
+                // Let each emulator process one frame, using the same keys
+                let keys = keyLatch.lock().unwrap().frame();
+                // the cores draw on their own back buffers, so the frame is only locked for
+                // copying the finished frames over. A panic, like an index out of bounds, must
+                // not take the thread down with it and leave the window frozen, so it's caught
+                // and the ROM's either restarted in kiosk mode or paused with what happened.
+                let outputs = match panic::catch_unwind(AssertUnwindSafe(|| session.tick_frame(keys))) {
+                    Ok(outputs) => outputs,
+                    Err(payload) => {
+                        let message = panicMessage(payload.as_ref());
+                        if kiosk {
+                            println!("Crashed at frame {}: {}, restarting", session.frame, message);
+                        } else {
+                            println!("Crashed at frame {}: {}, press Backspace to reset", session.frame, message);
+                        }
+                        session.dump_history();
+                        session.report(&format!("crashed: {}", message));
+                        if kiosk {
+                            match session.control(Control::Reset) {
+                                Ok(message) => println!("{}", message),
+                                Err(e) => println!("{}", e),
+                            }
+                        } else {
+                            paused.store(true, Ordering::Relaxed);
+                            *overlay.lock().unwrap() = Some(Overlay::crashed(session.frame, &message));
+                            crashed = true;
+                        }
+                        let mut px = frame.lock().unwrap();
+                        for (i, emul) in session.cores.iter().enumerate() {
+                            layout.paint(&mut px, i, emul.framebuffer(), emul.framebuffer());
+                        }
+                        if !fresh.swap(true, Ordering::Relaxed) {
+                            let _ = wake.send_event(());
+                        }
+                        continue;
+                    }
+                };
+                if !session.in_demo() {
+                    played.fetch_add(1, Ordering::Relaxed);
+                }
+                // a step of the palette cycle recolors all of the display, not just what changed.
+                let recolor = match paletteCycle {
+                    Some(frames) if session.frame.is_multiple_of(frames as u64) => {
+                        layout.palette = palette.cycled(session.frame / frames as u64);
+                        true
+                    }
+                    _ => false,
+                };
+                let mut px = frame.lock().unwrap();
+                drew = true;
+                let mut restart = false;
+                for (i, output) in outputs.into_iter().enumerate() {
+                    let output = match output {
+                        Ok(output) => output,
+                        Err(e) if kiosk => {
+                            // nobody's there to press Backspace, so the ROM starts over.
+                            println!("Stopped at frame {}: {}, restarting", session.frame, e);
+                            if i == 0 {
+                                session.dump_history();
+                                session.report(&e.to_string());
+                            }
+                            restart = true;
+                            continue;
+                        }
+                        Err(e) => {
+                            // the frame stopped halfway, the last complete one stays on screen.
+                            println!("Stopped at frame {}: {}, press Backspace to reset", session.frame, e);
+                            if i == 0 {
+                                session.dump_history();
+                                session.report(&e.to_string());
+                            }
+                            paused.store(true, Ordering::Relaxed);
+                            continue;
+                        }
+                    };
+                    if i == 0 {
+                        keyLatch.lock().unwrap().consumed(output.keysRead);
+                        // the frame is locked, so the change is in the next copy the event-loop
+                        // presents.
+                        if let Some(latency) = &latency {
+                            latency.lock().unwrap().frame(|k| output.keysRead.is_pressed(k), !output.diff.is_empty(), Instant::now());
+                        }
+                    }
+                    if !diverged {
+                        let display = session.cores[i].framebuffer();
+                        match flashLimiters.as_mut() {
+                            Some(limiters) => layout.paint_levels(&mut px, i, &limiters[i].filter(display)),
+                            None if recolor => layout.paint(&mut px, i, display, display),
+                            None => layout.set_pixels(&mut px, i, &output.diff),
+                        }
+                    }
+                }
+
+                // once the two cores have diverged, keep highlighting the pixels that differ.
+                let cores = &session.cores;
+                if cores.len() == 2 && (diverged || cores[0].framebuffer() != cores[1].framebuffer()) {
+                    if !diverged {
+                        println!("Displays diverged at frame {} (pc {:#05X} vs {:#05X}), press P to continue", session.frame, cores[0].pc, cores[1].pc);
+                        paused.store(true, Ordering::Relaxed);
+                        diverged = true;
+                    }
+                    layout.paint(&mut px, 0, cores[0].framebuffer(), cores[1].framebuffer());
+                    layout.paint(&mut px, 1, cores[1].framebuffer(), cores[0].framebuffer());
+                }
+
+                if restart {
+                    match session.control(Control::Reset) {
+                        Ok(message) => println!("{}", message),
+                        Err(e) => println!("{}", e),
+                    }
+                    for (i, emul) in session.cores.iter().enumerate() {
+                        layout.paint(&mut px, i, emul.framebuffer(), emul.framebuffer());
+                    }
+                }
+                if let Some(keypad) = &keypad {
+                    keypad.draw(&mut px, keys);
+                }
+                if session.take_break() {
+                    println!("Paused at frame {}, press P to continue", session.frame);
+                    paused.store(true, Ordering::Relaxed);
+                }
+                if !session.cores[0].unknown_opcodes().is_empty() {
+                    *unknownOpcodes.lock().unwrap() = session.unknown_opcodes_summary();
+                }
+                if let Some(scope) = soundScope.as_mut() {
+                    scope.push(session.cores[0].soundTimer);
+                    scope.draw(&mut px, screenWidth, layout.scope_top(), &scope::BUZZER_PATTERN);
+                }
+                if let Some(graph) = frameGraph.as_mut() {
+                    graph.push(frameStart.elapsed(), renderTimes.take());
+                    graph.draw(&mut px, screenWidth, screenHeight);
+                }
+            }
+
+            // the overlay goes over every frame while it's open, also when paused, and the
+            // displays are painted again once it's closed.
+            match overlay.lock().unwrap().as_mut() {
+                Some(overlay) => {
+                    let mut px = frame.lock().unwrap();
+                    if overlay.debug || overlay.card {
+                        overlay.refresh(&session.cores[0]);
+                    }
+                    overlay.draw(&mut px, screenWidth, screenHeight);
+                    if overlay.debug {
+                        overlay.draw_thumbnail(&mut px, screenWidth, screenHeight, session.cores[0].framebuffer(), &layout.palette);
+                    }
+                    session.cores[0].memory.set_heatmap(overlay.heatmap);
+                    if overlay.heatmap {
+                        overlay.draw_heatmap(&mut px, screenWidth, screenHeight, &session.cores[0].memory);
+                    }
+                    overlayShown = true;
+                    drew = true;
+                }
+                None if overlayShown => {
+                    session.cores[0].memory.set_heatmap(false);
+                    let mut px = frame.lock().unwrap();
+                    for (i, emul) in session.cores.iter().enumerate() {
+                        layout.paint(&mut px, i, emul.framebuffer(), emul.framebuffer());
+                    }
+                    overlayShown = false;
+                    drew = true;
+                }
+                None => {}
+            }
+
+            // the event-loop only needs waking up for the first frame it hasn't presented yet.
+            if drew && !fresh.swap(true, Ordering::Relaxed) {
+                let _ = wake.send_event(());
+            }
+            pacing.wait(&mut nextFrame, paused.load(Ordering::Relaxed));
+        }
+    }));
+    tab
+}
+
+// openTab starts a session for another ROM file in a new tab. It runs with the options of the
+// first ROM, but without its game over and score hooks.
+fn openTab(path: &str, options: &Options, settings: &Settings) -> Result<Tab, String> {
+    let data = bundle::readRom(path)?;
+    let session = Session::open(options, path, &data, Hooks::default())?;
+    for line in session.load_info() {
+        println!("{}", line);
+    }
+    Ok(spawnTab(session, settings))
+}
+
+impl Frontend for Window {
+    fn run(self: Box<Self>, session: Session, options: Options) -> i32 {
+        let graphRows = if options.frameGraph { framegraph::GRAPH_HEIGHT } else { 0 };
+        let keypadRows = if options.keypad { KEYPAD_HEIGHT } else { 0 };
+        let scopeRows = if options.soundScope { scope::SCOPE_HEIGHT } else { 0 };
+        let layout = Screen { rotation: options.rotation, cores: session.cores.len(), keypadRows, scopeRows, graphRows, palette: options.palette };
+        let (screenWidth, screenHeight) = layout.size();
+        let keypad = options.keypad.then(|| Keypad { top: layout.keypad_top(), width: screenWidth });
+        let (displayWidth, displayHeight) = layout.display_size();
+        let variantName = options.variant.name();
+
+        // in kiosk mode nothing but the admin combo gets out of the ROM.
+        let admin = match options.kiosk.then(|| Combo::parse(&options.kioskAdmin)).transpose() {
+            Ok(admin) => admin,
+            Err(e) => {
+                eprintln!("{}", e);
+                return 2;
+            }
+        };
+
+        // Init window / pixels
+        let event_loop = EventLoopBuilder::<()>::with_user_event().build();
+        let bundle = Bundle::open(&options.romPath).ok().flatten();
+        let cover = bundle.as_ref().and_then(|b| b.cover.clone()).unwrap_or(assets::ICON.to_vec());
+        // the bezel of --bezel, or else that of the bundle, for all tabs.
+        let bezel = match options.bezel.as_deref() {
+            Some("none") => None,
+            Some(path) => match Bezel::load(path) {
+                Ok(bezel) => Some(bezel),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return 2;
+                }
+            },
+            None => bundle.and_then(|b| b.bezel).and_then(|png| Bezel::decode(&png).map_err(|e| println!("Unable to load the bezel of the bundle: {}", e)).ok()),
+        };
+
+        // --monitor picks the monitor the window opens on and goes full screen on, otherwise
+        // it opens where it was when it last closed, if that's still on a monitor.
+        let monitors: Vec<MonitorHandle> = event_loop.available_monitors().collect();
+        let mut fullscreenMonitor = match options.monitor {
+            Some(n) => match monitors.get(n - 1) {
+                Some(monitor) => Some(monitor.clone()),
+                None => {
+                    eprintln!("There's no monitor {}, the monitors are: {}", n, describeMonitors(&monitors).join(", "));
+                    return 2;
+                }
+            },
+            None => None,
+        };
+        let placement = placement::load().filter(|p| options.monitor.is_none() && monitors.iter().any(|m| {
+            let (position, size) = (m.position(), m.size());
+            (position.x..position.x + size.width as i32).contains(&p.x) && (position.y..position.y + size.height as i32).contains(&p.y)
+        }));
+
+        let window = {
+            // 5 window pixels per display pixel makes a CHIP8 lores pixel 10x10, on the screen of
+            // the bezel if there is one.
+            let size = match &bezel {
+                Some(bezel) => {
+                    let ((width, height), (screenW, screenH)) = (bezel.size(), bezel.screen_size());
+                    let scale = (5.0 * screenWidth as f64 / screenW as f64).min(5.0 * screenHeight as f64 / screenH as f64);
+                    LogicalSize::new(scale * width as f64, scale * height as f64)
+                }
+                None => LogicalSize::new(5.0 * screenWidth as f64, 5.0 * screenHeight as f64),
+            };
+            let mut builder = WindowBuilder::new()
+                .with_title("Rust-CHIP8")
+                .with_inner_size(size)
+                .with_min_inner_size(size)
+                .with_window_icon(icon(&cover).map_err(|e| println!("Unable to load the window icon: {}", e)).ok())
+                .with_fullscreen((options.kiosk || options.fullscreen).then(|| Fullscreen::Borderless(fullscreenMonitor.clone())))
+                .with_always_on_top(options.alwaysOnTop);
+            if let Some(monitor) = &fullscreenMonitor {
+                // centered on the monitor.
+                let window: PhysicalSize<i32> = size.to_physical(monitor.scale_factor());
+                let (position, screen) = (monitor.position(), monitor.size());
+                builder = builder.with_position(PhysicalPosition::new(
+                    position.x + (screen.width as i32 - window.width).max(0) / 2,
+                    position.y + (screen.height as i32 - window.height).max(0) / 2,
+                ));
+            } else if let Some(placement) = placement {
+                builder = builder
+                    .with_position(PhysicalPosition::new(placement.x, placement.y))
+                    .with_inner_size(PhysicalSize::new(placement.width, placement.height));
+            }
+            builder.build(&event_loop).unwrap()
+        };
+        window.set_cursor_visible(!options.kiosk);
+        let mut alwaysOnTop = options.alwaysOnTop;
+        if options.opacity < 100 {
+            if let Err(e) = setOpacity(&window, options.opacity) {
+                println!("Unable to make the window see-through: {}", e);
+            }
+        }
+
+        let mut pixels = {
+            let window_size = window.inner_size();
+            let surface_texture = SurfaceTexture::new(window_size.width, window_size.height, &window);
+            let (width, height) = bufferSize(bezel.as_ref(), options.upscaler, screenWidth, screenHeight);
+            let [r, g, b, _] = options.background.map(|c| c as f64 / 255.0);
+            PixelsBuilder::new(width, height, surface_texture)
+                .present_mode(options.presentMode)
+                .clear_color(pixels::wgpu::Color { r, g, b, a: 1.0 })
+                .build()
+        }.unwrap();
+        let mut upscaler = options.upscaler;
+
+        let settings = Settings {
+            layout,
+            keypad,
+            variantName,
+            stickyKeys: options.stickyKeys,
+            slowMotionPercent: options.slowMotionPercent,
+            timerHz: options.timerHz,
+            flashLimit: options.flashLimit,
+            paletteCycle: options.paletteCycle,
+            frameGraph: options.frameGraph,
+            latency: options.latency,
+            soundScope: options.soundScope,
+            announce: options.announce.then(|| options.messages.clone()),
+            kiosk: options.kiosk,
+            wake: event_loop.create_proxy(),
+        };
+        let mut tabs = vec![spawnTab(session, &settings)];
+        for path in options.tabs.iter() {
+            match openTab(path, &options, &settings) {
+                Ok(tab) => tabs.push(tab),
+                Err(e) => println!("{}", e),
+            }
+        }
+        let mut active = 0; // the tab shown, which gets the input
+
+        let mut input = WinitInputHelper::new();
+        let mut keymap = Keymap::named(&options.keymap).unwrap_or_else(|_| Keymap::positional()); // checked by parseArgs
+        let mut players = options.players.clone();
+        let mut cursor = (0.0, 0.0);
+        let mut clickedKey: Option<u8> = None; // the key of the on-screen keypad held down with the mouse
+        let mut touches: HashMap<u64, u8> = HashMap::new(); // the key held by each finger on the screen
+        let mut hidden = Hidden::default();
+        let mut commandPalette: Option<CommandPalette> = None; // while open, it takes the keyboard
+        // the first start, without a config file, goes through the setup, which also takes the keyboard.
+        let firstStart = !paths::config().exists() && options.command == Command::Run && !options.kiosk;
+        let mut wizard = (options.setup || firstStart).then(|| Wizard::new(&options.romPath));
+        if let Some(wizard) = &wizard {
+            *tabs[active].overlay.lock().unwrap() = Some(wizard.overlay());
+        }
+        // the library is scanned when it's first opened, which runs every ROM for a moment, and
+        // kept for the next time. It's scanned on a thread, see Scan, and opens once it's done.
+        let mut library: Option<Library> = None;
+        let mut scan: Option<Scan> = None;
+        let mut browsing = false; // while the library is open, it takes the keyboard
+        let mut stateBrowser: Option<StateBrowser> = None; // the same goes for the state browser
+        if options.library && wizard.is_none() && admin.is_none() {
+            browsing = openLibrary(&mut library, &mut scan, &options, &settings, &tabs[active]);
+        }
+        let mut title = String::new();
+        println!("Keypad:");
+        for row in keymap.describe() {
+            println!("  {}", row);
+        }
+        if !players.is_empty() {
+            println!("Players, F6 swaps them:");
+            for row in players.describe() {
+                println!("  {}", row);
+            }
+        }
+
+        // The event-loop sleeps until there's an event, an interpreter thread wakes it up with a
+        // new frame or status, or the next refresh of the display is due for a frame not presented
+        // yet. Frames are presented at most once per refresh, see refreshInterval.
+        let mut refresh = refreshInterval(&window);
+        let mut nextRedraw = Instant::now();
+        event_loop.run(move |event, _, control_flow| {
+            // the events still coming in once exiting are ignored, so the tabs are closed once.
+            if let ControlFlow::ExitWithCode(_) = control_flow {
+                return;
+            }
+            for tab in tabs.iter_mut() {
+                let romName = Path::new(&tab.romPath).file_name().map(|n| n.to_string_lossy().to_string());
+                for status in tab.statuses.try_iter() {
+                    // the scores of the demos, or of the ROM before one just loaded, don't count.
+                    if !status.demo && romName.as_ref() == Some(&status.romName) {
+                        tab.bestScore = tab.bestScore.max(status.score);
+                    }
+                    tab.status = Some(status);
+                }
+            }
+            // the library opens over the active tab once it's scanned, in place of the message
+            // that it's being scanned.
+            if let Some((scanned, done)) = scan.as_ref().and_then(|s| Some((s, s.done()?))) {
+                for tab in tabs.iter() {
+                    let mut overlay = tab.overlay.lock().unwrap();
+                    if overlay.as_ref().is_some_and(|o| scanned.shown(o)) {
+                        *overlay = None;
+                    }
+                }
+                scan = None;
+                match done {
+                    Ok(browser) => {
+                        library = Some(browser);
+                        browsing = openLibrary(&mut library, &mut scan, &options, &settings, &tabs[active]);
+                    }
+                    Err(e) => println!("{}", e),
+                }
+            }
+            if let Some(status) = &tabs[active].status {
+                let mut newTitle = status.title(&options.messages);
+                if tabs.len() > 1 {
+                    newTitle.push_str(&format!(" ({})", options.messages.format("title.tab", &[&(active + 1), &tabs.len()])));
+                }
+                if newTitle != title {
+                    window.set_title(&newTitle);
+                    title = newTitle;
+                }
+            }
+
+            // switching to another tab, closing the active one or opening a new one, which is done
+            // once the event has been handled for the active tab.
+            let mut switchTo: Option<usize> = None;
+            let mut closeTab = false;
+            let mut newTab: Option<String> = None;
+            let mut launch: Option<(PathBuf, bool)> = None; // a ROM of the library, and whether in a new tab
+
+            // Handle keystrokes including exit through ESC or clicking (x)
+            if input.update(&event) {
+                let tabCount = tabs.len();
+                let tab = &mut tabs[active];
+                if input.quit() && admin.is_none() {
+                    exitRequested(&mut tabs, &window);
+                    control_flow.set_exit();
+                    return;
+                }
+
+                // Keep the surface at the window's size in physical pixels, also when the window
+                // moves to a screen with another scale factor. Pixels scales the display up by the
+                // largest whole factor that fits using nearest-neighbor sampling, so the CHIP8
+                // pixels stay sharp squares at any DPI.
+                if let Some(size) = input.window_resized().filter(|s| s.width > 0 && s.height > 0) {
+                    pixels.resize_surface(size.width, size.height);
+                    tab.fresh.store(true, Ordering::Relaxed);
+                }
+                if input.scale_factor_changed().is_some() {
+                    let size = window.inner_size();
+                    pixels.resize_surface(size.width, size.height);
+                    tab.fresh.store(true, Ordering::Relaxed);
+                }
+
+                // while the setup is open it has the keyboard, see Wizard, and so does the
+                // command palette: Esc closes it, Up and Down select and Enter does the selected
+                // action. What's typed comes in as ReceivedCharacter below. In kiosk mode there
+                // are no hotkeys but the admin combo, which exits.
+                if let Some(admin) = &admin {
+                    if admin.pressed(&input) {
+                        exitRequested(&mut tabs, &window);
+                        control_flow.set_exit();
+                        return;
+                    }
+                } else if let Some(setup) = wizard.as_mut() {
+                    if input.key_pressed(VirtualKeyCode::Up) {
+                        setup.up();
+                    }
+                    if input.key_pressed(VirtualKeyCode::Down) {
+                        setup.down();
+                    }
+                    let skipped = input.key_pressed(VirtualKeyCode::Escape);
+                    if skipped || (input.key_pressed(VirtualKeyCode::Return) && setup.enter()) {
+                        match if skipped { setup.skip() } else { setup.save() } {
+                            Ok(message) | Err(message) => println!("{}", message),
+                        }
+                        wizard = None;
+                        *tab.overlay.lock().unwrap() = None;
+                    } else {
+                        *tab.overlay.lock().unwrap() = Some(setup.overlay());
+                    }
+                } else if let (true, Some(browser)) = (browsing, library.as_mut()) {
+                    if input.key_pressed(VirtualKeyCode::Back) {
+                        browser.backspace();
+                    }
+                    if input.key_pressed(VirtualKeyCode::Left) {
+                        browser.left();
+                    }
+                    if input.key_pressed(VirtualKeyCode::Right) {
+                        browser.right();
+                    }
+                    if input.key_pressed(VirtualKeyCode::Up) {
+                        browser.up();
+                    }
+                    if input.key_pressed(VirtualKeyCode::Down) {
+                        browser.down();
+                    }
+                    launch = input.key_pressed(VirtualKeyCode::Return).then(|| browser.selected().map(|p| (p.to_path_buf(), input.held_control()))).flatten();
+                    if launch.is_some() || input.key_pressed(VirtualKeyCode::Escape) || input.key_pressed(VirtualKeyCode::F4) {
+                        browsing = false;
+                        *tab.overlay.lock().unwrap() = None;
+                    } else {
+                        *tab.overlay.lock().unwrap() = Some(browser.overlay());
+                    }
+                } else if let Some(browser) = stateBrowser.as_mut() {
+                    if input.key_pressed(VirtualKeyCode::Left) {
+                        browser.left();
+                    }
+                    if input.key_pressed(VirtualKeyCode::Right) {
+                        browser.right();
+                    }
+                    if input.key_pressed(VirtualKeyCode::Up) {
+                        browser.up();
+                    }
+                    if input.key_pressed(VirtualKeyCode::Down) {
+                        browser.down();
+                    }
+                    let slot = browser.selected();
+                    let filled = tab.states.lock().unwrap()[slot].is_some();
+                    let chosen = match (input.key_pressed(VirtualKeyCode::S), input.key_pressed(VirtualKeyCode::Return) && filled) {
+                        (true, _) => Some(Control::SaveState(slot)),
+                        (_, true) => Some(Control::LoadState(slot)),
+                        _ => None,
+                    };
+                    if chosen.is_some() || input.key_pressed(VirtualKeyCode::Escape) || input.key_pressed(VirtualKeyCode::F7) {
+                        stateBrowser = None;
+                        *tab.overlay.lock().unwrap() = None;
+                    } else {
+                        *tab.overlay.lock().unwrap() = Some(browser.overlay(&tab.states.lock().unwrap()));
+                    }
+                    if let Some(control) = chosen {
+                        tab.send(control);
+                    }
+                } else if let Some(palette) = commandPalette.as_mut() {
+                    if input.key_pressed(VirtualKeyCode::Back) {
+                        palette.backspace();
+                    }
+                    if input.key_pressed(VirtualKeyCode::Up) {
+                        palette.up();
+                    }
+                    if input.key_pressed(VirtualKeyCode::Down) {
+                        palette.down();
+                    }
+                    let action = input.key_pressed(VirtualKeyCode::Return).then(|| palette.selected().cloned()).flatten();
+                    if action.is_some() || input.key_pressed(VirtualKeyCode::Escape) {
+                        commandPalette = None;
+                        *tab.overlay.lock().unwrap() = None;
+                    } else {
+                        *tab.overlay.lock().unwrap() = Some(palette.overlay(&options.messages));
+                    }
+                    match action {
+                        Some(Action::Reset) => tab.send(Control::Reset),
+                        Some(Action::Pause) => { tab.paused.fetch_xor(true, Ordering::Relaxed); }
+                        Some(Action::SlowMotion) => tab.pacing.toggle_slow_motion(),
+                        Some(Action::Load(path)) => tab.load(&path),
+                        Some(Action::OpenTab(path)) => newTab = Some(path.to_string_lossy().to_string()),
+                        Some(Action::ToggleQuirk(name)) => tab.send(Control::ToggleQuirk(name)),
+                        Some(Action::SaveState) => tab.send(Control::SaveState(0)),
+                        Some(Action::LoadState) => tab.send(Control::LoadState(0)),
+                        Some(Action::States) => {
+                            let browser = StateBrowser::new(screenWidth, screenHeight);
+                            *tab.overlay.lock().unwrap() = Some(browser.overlay(&tab.states.lock().unwrap()));
+                            stateBrowser = Some(browser);
+                        }
+                        Some(Action::Screenshot) => tab.send(Control::Screenshot),
+                        Some(Action::ExportSprites) => tab.send(Control::ExportSprites),
+                        Some(Action::Speed(ips)) => tab.send(Control::Speed(ips)),
+                        Some(Action::Fullscreen(n, _)) => {
+                            fullscreenMonitor = monitors.get(n).cloned();
+                            window.set_fullscreen(Some(Fullscreen::Borderless(fullscreenMonitor.clone())));
+                        }
+                        Some(Action::Windowed) => window.set_fullscreen(None),
+                        Some(Action::AlwaysOnTop) => {
+                            alwaysOnTop = !alwaysOnTop;
+                            window.set_always_on_top(alwaysOnTop);
+                            println!("Always on top {}", if alwaysOnTop { "on" } else { "off" });
+                        }
+                        Some(Action::Opacity(percent)) => match setOpacity(&window, percent) {
+                            Ok(()) => println!("Opacity {}%", percent),
+                            Err(e) => println!("Unable to make the window see-through: {}", e),
+                        },
+                        Some(Action::Upscale(chosen)) => {
+                            upscaler = chosen;
+                            upscale(&mut pixels, bezel.as_ref(), upscaler, screenWidth, screenHeight);
+                            tab.fresh.store(true, Ordering::Relaxed);
+                        }
+                        Some(Action::About) => *tab.overlay.lock().unwrap() = Some(Overlay::about(variantName, keymap.describe(), &options.messages)),
+                        Some(Action::Quit) => {
+                            exitRequested(&mut tabs, &window);
+                            control_flow.set_exit();
+                            return;
+                        }
+                        None => {}
+                    }
+                } else {
+                    if input.key_pressed(VirtualKeyCode::Escape) {
+                        exitRequested(&mut tabs, &window);
+                        control_flow.set_exit();
+                        return;
+                    }
+                    // ctrl+P opens the command palette, P alone pauses.
+                    if input.key_pressed(VirtualKeyCode::P) && input.held_control() {
+                        let mut actions = Action::all(&tab.romPath);
+                        actions.extend(describeMonitors(&monitors).into_iter().enumerate().map(|(n, name)| Action::Fullscreen(n, name)));
+                        actions.push(Action::Windowed);
+                        let palette = CommandPalette::new(actions, &options.messages);
+                        *tab.overlay.lock().unwrap() = Some(palette.overlay(&options.messages));
+                        commandPalette = Some(palette);
+                        // the keys held now would never see their release.
+                        tab.release();
+                    } else if input.key_pressed(VirtualKeyCode::P) {
+                        tab.paused.fetch_xor(true, Ordering::Relaxed);
+                    }
+                    if input.key_pressed(VirtualKeyCode::F1) {
+                        let mut overlay = tab.overlay.lock().unwrap();
+                        *overlay = match overlay.take() {
+                            Some(_) => None,
+                            None => Some(Overlay::about(variantName, keymap.describe(), &options.messages)),
+                        };
+                    }
+                    // F2 opens the debug view, with a live thumbnail of the display to click
+                    // for going back to the game.
+                    if input.key_pressed(VirtualKeyCode::F2) {
+                        let mut overlay = tab.overlay.lock().unwrap();
+                        *overlay = match overlay.take() {
+                            Some(o) if o.debug => None,
+                            _ => Some(Overlay::debug()),
+                        };
+                    }
+                    // F3 opens the memory heatmap, which shows where the ROM reads, writes and
+                    // runs as it goes.
+                    if input.key_pressed(VirtualKeyCode::F3) {
+                        let mut overlay = tab.overlay.lock().unwrap();
+                        *overlay = match overlay.take() {
+                            Some(o) if o.heatmap => None,
+                            _ => Some(Overlay::heatmap()),
+                        };
+                    }
+                    // F5 opens the reference card of the instruction at the PC, which is
+                    // for when paused, as it changes with every frame otherwise.
+                    if input.key_pressed(VirtualKeyCode::F5) {
+                        let mut overlay = tab.overlay.lock().unwrap();
+                        *overlay = match overlay.take() {
+                            Some(o) if o.card => None,
+                            _ => Some(Overlay::card()),
+                        };
+                    }
+                    // F6 swaps the sides of the players.
+                    if input.key_pressed(VirtualKeyCode::F6) && !players.is_empty() {
+                        players.swap();
+                        tab.release();
+                        println!("Swapped the players: {}", players.describe().join(", "));
+                    }
+                    // F4 opens the library of the ROMs in --rom-dir.
+                    if input.key_pressed(VirtualKeyCode::F4) {
+                        tab.release();
+                        browsing = openLibrary(&mut library, &mut scan, &options, &settings, tab);
+                    }
+                    // F7 opens the save state slots.
+                    if input.key_pressed(VirtualKeyCode::F7) {
+                        tab.release();
+                        let browser = StateBrowser::new(screenWidth, screenHeight);
+                        *tab.overlay.lock().unwrap() = Some(browser.overlay(&tab.states.lock().unwrap()));
+                        stateBrowser = Some(browser);
+                    }
+                    // F11 goes full screen on the monitor picked or else the one the window is on,
+                    // or back to a window.
+                    if input.key_pressed(VirtualKeyCode::F11) {
+                        window.set_fullscreen(match window.fullscreen() {
+                            Some(_) => None,
+                            None => Some(Fullscreen::Borderless(fullscreenMonitor.clone().or_else(|| window.current_monitor()))),
+                        });
+                    }
+                    // F9 keeps the window above the others, or not.
+                    if input.key_pressed(VirtualKeyCode::F9) {
+                        alwaysOnTop = !alwaysOnTop;
+                        window.set_always_on_top(alwaysOnTop);
+                        println!("Always on top {}", if alwaysOnTop { "on" } else { "off" });
+                    }
+                    // F8 switches to the next upscaler.
+                    if input.key_pressed(VirtualKeyCode::F8) {
+                        upscaler = upscaler.next();
+                        upscale(&mut pixels, bezel.as_ref(), upscaler, screenWidth, screenHeight);
+                        tab.fresh.store(true, Ordering::Relaxed);
+                    }
+                    if input.key_pressed(VirtualKeyCode::Back) {
+                        tab.send(Control::Reset);
+                    }
+                    // ctrl+Tab and ctrl+shift+Tab switch tabs, holding Tab alone fast-forwards.
+                    if input.key_pressed(VirtualKeyCode::Tab) && input.held_control() {
+                        let step = if input.held_shift() { tabCount - 1 } else { 1 };
+                        switchTo = Some((active + step) % tabCount);
+                    } else if input.key_pressed(VirtualKeyCode::Tab) {
+                        tab.pacing.set_turbo(true);
+                    }
+                    if input.key_released(VirtualKeyCode::Tab) {
+                        tab.pacing.set_turbo(false);
+                    }
+                    // ctrl+W closes the tab, and the window with the last one.
+                    if input.key_pressed(VirtualKeyCode::W) && input.held_control() {
+                        closeTab = true;
+                    }
+                    // holding ` runs in slow-motion, shift+` toggles slow-motion on and off
+                    if input.key_pressed(VirtualKeyCode::Grave) {
+                        if input.held_shift() {
+                            tab.pacing.toggle_slow_motion();
+                        } else {
+                            tab.pacing.set_slow_motion_held(true);
+                        }
+                    }
+                    if input.key_released(VirtualKeyCode::Grave) {
+                        tab.pacing.set_slow_motion_held(false);
+                    }
+                }
+            }
+
+            let tab = &mut tabs[active];
+            if let (Some(palette), Event::WindowEvent { event: WindowEvent::ReceivedCharacter(c), .. }) = (commandPalette.as_mut(), &event) {
+                if !c.is_control() {
+                    palette.type_char(*c);
+                    *tab.overlay.lock().unwrap() = Some(palette.overlay(&options.messages));
+                }
+            }
+            if let (true, Some(browser), Event::WindowEvent { event: WindowEvent::ReceivedCharacter(c), .. }) = (browsing, library.as_mut(), &event) {
+                if !c.is_control() {
+                    browser.type_char(*c);
+                    *tab.overlay.lock().unwrap() = Some(browser.overlay());
+                }
+            }
+
+            // dropping a ROM file on the window runs it in place of the current one, or in a new
+            // tab with ctrl held.
+            if let (Event::WindowEvent { event: WindowEvent::DroppedFile(path), .. }, None) = (&event, &admin) {
+                if input.held_control() {
+                    newTab = Some(path.to_string_lossy().to_string());
+                } else {
+                    tab.load(path);
+                }
+            }
+
+            // CHIP8 keys are matched on scancodes, so the keypad is in the same place on any layout,
+            // and those of the players on the keys as labeled.
+            if let Event::WindowEvent { event: WindowEvent::KeyboardInput { input, .. }, .. } = &event {
+                keymap.learn(input.scancode, input.virtual_keycode);
+                // the controls of the players beat the keymap.
+                let key = input.virtual_keycode.and_then(|k| players.key(&input::controlName(k))).or(keymap.key(input.scancode));
+                if let Some(key) = key.filter(|_| commandPalette.is_none() && wizard.is_none() && !browsing && stateBrowser.is_none()) {
+                    let k = if options.rotateKeys { options.rotation.remap_key(key) } else { key };
+                    let pressed = input.state == ElementState::Pressed;
+                    if let Some(latency) = tab.latency.as_ref().filter(|_| pressed) {
+                        latency.lock().unwrap().press(k, Instant::now());
+                    }
+                    tab.keys.lock().unwrap().set(k, pressed);
+                }
+            }
+
+            if let Event::WindowEvent { event: WindowEvent::CursorMoved { position, .. }, .. } = &event {
+                cursor = (position.x as f32, position.y as f32);
+            }
+
+            // clicking the thumbnail of the debug view closes it. The debug view covers the
+            // on-screen keypad, so that isn't clicked while it's open.
+            let debugShown = tab.overlay.lock().unwrap().as_ref().is_some_and(|o| o.debug);
+            if let Event::WindowEvent { event: WindowEvent::MouseInput { state: ElementState::Pressed, button: MouseButton::Left, .. }, .. } = &event {
+                if debugShown {
+                    let mut overlay = tab.overlay.lock().unwrap();
+                    let (left, top, width, height) = overlay::thumbnail(screenWidth, screenHeight);
+                    if let Some((x, y)) = screenPixel(&pixels, bezel.as_ref(), upscaler, (screenWidth, screenHeight), cursor) {
+                        if (left..left + width).contains(&x) && (top..top + height).contains(&y) {
+                            *overlay = None;
+                        }
+                    }
+                }
+            }
+
+            // clicking a key of the on-screen keypad holds it down until the button is released.
+            if let (Some(keypad), Event::WindowEvent { event, .. }, false) = (&keypad, &event, debugShown) {
+                match event {
+                    WindowEvent::MouseInput { state: ElementState::Pressed, button: MouseButton::Left, .. } => {
+                        let pixel = screenPixel(&pixels, bezel.as_ref(), upscaler, (screenWidth, screenHeight), cursor);
+                        clickedKey = pixel.and_then(|(x, y)| keypad.key_at(x, y));
+                        if let Some(key) = clickedKey {
+                            tab.keys.lock().unwrap().set(key, true);
+                        }
+                    }
+                    WindowEvent::MouseInput { state: ElementState::Released, button: MouseButton::Left, .. } => {
+                        if let Some(key) = clickedKey.take() {
+                            tab.keys.lock().unwrap().set(key, false);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+
+            // on a touch screen each finger holds the key under it, which is a key of the on-screen
+            // keypad or, on the display of the first core, one of a 3x3 grid of keys 1-9 with the
+            // directions 2/4/6/8 around 5. Sliding a finger moves on to the key under it.
+            if let Event::WindowEvent { event: WindowEvent::Touch(Touch { id, phase, location, .. }), .. } = &event {
+                let pixel = screenPixel(&pixels, bezel.as_ref(), upscaler, (screenWidth, screenHeight), (location.x as f32, location.y as f32));
+                let key = pixel.and_then(|(x, y)| match keypad.and_then(|k| k.key_at(x, y)) {
+                    Some(key) => Some(key),
+                    None if x < displayWidth && y < displayHeight => {
+                        let key = input::touchKey(x, y, displayWidth, displayHeight);
+                        Some(if options.rotateKeys { options.rotation.remap_key(key) } else { key })
+                    }
+                    None => None,
+                });
+                let mut keys = tab.keys.lock().unwrap();
+                let previous = match phase {
+                    TouchPhase::Started | TouchPhase::Moved => match key {
+                        Some(key) => touches.insert(*id, key),
+                        None => touches.remove(id),
+                    },
+                    TouchPhase::Ended | TouchPhase::Cancelled => touches.remove(id),
+                };
+                if let Some(previous) = previous {
+                    // another finger may still be holding the key.
+                    if !touches.values().any(|k| *k == previous) {
+                        keys.set(previous, false);
+                    }
+                }
+                if let (Some(key), TouchPhase::Started | TouchPhase::Moved) = (key, phase) {
+                    keys.set(key, true);
+                }
+            }
+
+            if let Some(path) = newTab {
+                match openTab(&path, &options, &settings) {
+                    Ok(tab) => {
+                        tabs.push(tab);
+                        switchTo = Some(tabs.len() - 1);
+                    }
+                    Err(e) => println!("{}", e),
+                }
+            }
+            // a ROM of the library runs with the settings the config file has for it. The cores
+            // are those of the window, which is laid out for them.
+            if let Some((path, inNewTab)) = launch {
+                let path = path.to_string_lossy().to_string();
+                let opened = cli::forRom(&options, &path).and_then(|mut romOptions| {
+                    romOptions.compare = options.compare;
+                    openTab(&path, &romOptions, &settings)
+                });
+                match opened {
+                    Ok(tab) if inNewTab => {
+                        tabs.push(tab);
+                        switchTo = Some(tabs.len() - 1);
+                    }
+                    Ok(tab) => std::mem::replace(&mut tabs[active], tab).close(),
+                    Err(e) => println!("{}", e),
+                }
+            }
+            if closeTab && tabs.len() == 1 {
+                exitRequested(&mut tabs, &window);
+                control_flow.set_exit();
+                return;
+            }
+            // the tab losing the keyboard lets go of the keys held, and of the command palette,
+            // the library and the state browser.
+            if closeTab || switchTo.is_some_and(|i| i != active) {
+                let tab = &tabs[active];
+                tab.release();
+                if commandPalette.take().is_some() || std::mem::take(&mut browsing) || stateBrowser.take().is_some() {
+                    *tab.overlay.lock().unwrap() = None;
+                }
+                clickedKey = None;
+                touches.clear();
+                if closeTab {
+                    tabs.remove(active).close();
+                    active = active.min(tabs.len() - 1);
+                }
+                if let Some(i) = switchTo {
+                    active = i;
+                }
+                tabs[active].fresh.store(true, Ordering::Relaxed);
+            }
+
+            // the window may have moved to a screen with another refresh rate.
+            if let Event::WindowEvent { event: WindowEvent::Moved(_), .. } = &event {
+                refresh = refreshInterval(&window);
+            }
+
+            // the ROMs stop while nobody can see them, and go on from where they were when the
+            // window is back, rather than catching up on the time in between. Windows tells of
+            // being minimized with a size of 0x0.
+            let wasHidden = hidden.any();
+            match &event {
+                Event::WindowEvent { event: WindowEvent::Occluded(occluded), .. } => hidden.occluded = *occluded,
+                Event::WindowEvent { event: WindowEvent::Resized(size), .. } => hidden.minimized = size.width == 0 || size.height == 0,
+                Event::Suspended => hidden.background = true,
+                Event::Resumed => hidden.background = false,
+                _ => {}
+            }
+            if hidden.any() != wasHidden {
+                for tab in &tabs {
+                    tab.pacing.set_suspended(hidden.any());
+                }
+                if hidden.any() {
+                    tabs[active].release();
+                    println!("Suspended while the window is hidden");
+                } else {
+                    tabs[active].fresh.store(true, Ordering::Relaxed);
+                    println!("Resumed");
+                }
+            }
+
+            // once all the events that came in are handled, a frame the active tab drew is
+            // presented if the display is due for one, or else when it is.
+            if let Event::MainEventsCleared = event {
+                let now = Instant::now();
+                if tabs[active].fresh.load(Ordering::Relaxed) && now >= nextRedraw {
+                    window.request_redraw();
+                    nextRedraw += refresh;
+                    if nextRedraw < now {
+                        nextRedraw = now + refresh; // after a while without new frames
+                    }
+                }
+            }
+
+            // Draw the current frame
+            if let Event::RedrawRequested(_) = event {
+                let tab = &tabs[active];
+                let renderStart = Instant::now();
+                // what the interpreter thread draws from now on is for the next redraw.
+                tab.fresh.store(false, Ordering::Relaxed);
+                let frame = tab.frame.lock().unwrap();
+                let copied = Instant::now();
+                match &bezel {
+                    Some(bezel) => {
+                        let factor = upscaler.factor();
+                        let mut scaled = vec![0; frame.len() * factor * factor];
+                        upscaler.apply(&frame, screenWidth, screenHeight, &mut scaled);
+                        bezel.compose(&scaled, screenWidth * factor, screenHeight * factor, options.background, pixels.get_frame_mut());
+                    }
+                    None => upscaler.apply(&frame, screenWidth, screenHeight, pixels.get_frame_mut()),
+                }
+                drop(frame);
+                pixels.render().expect("do not fail");
+                tab.renderTimes.record(renderStart.elapsed());
+                if let Some(latency) = &tab.latency {
+                    latency.lock().unwrap().presented(copied, Instant::now());
+                }
+            }
+
+            match tabs[active].fresh.load(Ordering::Relaxed) {
+                true => control_flow.set_wait_until(nextRedraw),
+                false => control_flow.set_wait(),
+            }
+        });
+    }
+}
+
+// bufferSize returns the size of the picture pixels presents for a frame of a size: that of the
+// bezel, or else of the frame scaled up by the upscaler.
+fn bufferSize(bezel: Option<&Bezel>, upscaler: Upscaler, width: usize, height: usize) -> (u32, u32) {
+    let (width, height) = bezel.map_or((width * upscaler.factor(), height * upscaler.factor()), |b| b.size());
+    (width as u32, height as u32)
+}
+
+// upscale has the frame of pixels scaled up by an upscaler from now on.
+fn upscale(pixels: &mut Pixels, bezel: Option<&Bezel>, upscaler: Upscaler, width: usize, height: usize) {
+    let (width, height) = bufferSize(bezel, upscaler, width, height);
+    pixels.resize_buffer(width, height);
+    println!("Upscaling with {}", upscaler.name());
+}
+
+// screenPixel returns the pixel of the frame of the tab, of a size, at a position in the window,
+// whatever the upscaler and bezel.
+fn screenPixel(pixels: &Pixels, bezel: Option<&Bezel>, upscaler: Upscaler, (width, height): (usize, usize), position: (f32, f32)) -> Option<(usize, usize)> {
+    let (x, y) = pixels.window_pos_to_pixel(position).ok()?;
+    let factor = upscaler.factor();
+    let (x, y) = match bezel {
+        Some(bezel) => bezel.frame_pixel(x, y, width * factor, height * factor)?,
+        None => (x, y),
+    };
+    Some((x / factor, y / factor))
+}
+
+// setOpacity makes the window see-through, from 100 percent for opaque down. winit has no way to
+// do that, and pixels draws opaque, but on X11 a compositing window manager blends the window
+// with what's below by its _NET_WM_WINDOW_OPACITY property, which this sets through winit's own
+// connection to the X server.
+#[cfg(target_os = "linux")]
+fn setOpacity(window: &winit::window::Window, percent: u32) -> Result<(), String> {
+    use std::os::raw::c_ulong;
+    use winit::platform::unix::x11::ffi;
+    use winit::platform::unix::WindowExtUnix;
+
+    let (Some(xconn), Some(id)) = (window.xlib_xconnection(), window.xlib_window()) else {
+        return Err("only X11 has a way to, not Wayland".to_string());
+    };
+    // the property is 32 bits, which Xlib passes as a long, with 0xFFFFFFFF for fully opaque.
+    let opacity = (0xFFFF_FFFF_u64 * percent as u64 / 100) as c_ulong;
+    // SAFETY: the display and the window are those of the winit window, which outlives the calls,
+    // and the property data is a single long as format 32 has it.
+    unsafe {
+        let atom = (xconn.xlib.XInternAtom)(xconn.display, c"_NET_WM_WINDOW_OPACITY".as_ptr(), ffi::False);
+        match percent {
+            100 => (xconn.xlib.XDeleteProperty)(xconn.display, id, atom),
+            _ => (xconn.xlib.XChangeProperty)(xconn.display, id, atom, ffi::XA_CARDINAL, 32, ffi::PropModeReplace, &opacity as *const c_ulong as *const u8, 1),
+        };
+        (xconn.xlib.XFlush)(xconn.display);
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn setOpacity(_window: &winit::window::Window, _percent: u32) -> Result<(), String> {
+    Err("only X11 has a way to".to_string())
+}
+
+// refreshInterval returns how long a refresh of the screen the window is on takes, that of 60hz if
+// it can't be told.
+fn refreshInterval(window: &winit::window::Window) -> Duration {
+    match window.current_monitor().and_then(|m| m.refresh_rate_millihertz()).filter(|mhz| *mhz > 0) {
+        Some(mhz) => Duration::from_secs_f64(1000.0 / mhz as f64),
+        None => FRAME_DURATION,
+    }
+}
+
+// launched counts a ROM being started in the library.
+fn launched(romPath: &str) {
+    if let Err(e) = library::update(romPath, |stats| stats.launches += 1) {
+        println!("{}", e);
+    }
+}
+
+// openLibrary shows the library over a tab and returns whether it's open. The first time the ROMs
+// are scanned, which it only starts: it says so over the tab, and the library opens once the scan
+// is done, see scanned.
+fn openLibrary(library: &mut Option<Library>, scan: &mut Option<Scan>, options: &Options, settings: &Settings, tab: &Tab) -> bool {
+    let browser = match library {
+        Some(browser) => browser,
+        None => {
+            let (width, height) = settings.layout.size();
+            let wake = settings.wake.clone();
+            let scan = scan.get_or_insert_with(|| Scan::start(options, width, height, move || { let _ = wake.send_event(()); }));
+            *tab.overlay.lock().unwrap() = Some(scan.overlay());
+            return false;
+        }
+    };
+    browser.refresh();
+    *tab.overlay.lock().unwrap() = Some(browser.overlay());
+    true
+}
+
+// exitRequested prints that the window is closing, and the unknown opcodes the ROMs ran if any,
+// and keeps where the window is for the next start, see placement.
+fn exitRequested(tabs: &mut Vec<Tab>, window: &winit::window::Window) {
+    println!("Exit requested");
+    for tab in tabs.drain(..) {
+        tab.close();
+    }
+    // a window that's full screen keeps the placement it had before.
+    if let (Ok(position), None) = (window.outer_position(), window.fullscreen()) {
+        let size = window.inner_size();
+        if let Err(e) = placement::save(Placement { x: position.x, y: position.y, width: size.width, height: size.height }) {
+            println!("Unable to keep where the window is: {}", e);
+        }
+    }
+}
+
+// describeMonitors returns the monitors numbered like --monitor takes them, with their names and
+// sizes.
+fn describeMonitors(monitors: &[MonitorHandle]) -> Vec<String> {
+    monitors.iter().enumerate().map(|(n, m)| {
+        format!("{} {} {}x{}", n + 1, m.name().unwrap_or_else(|| "unnamed".to_string()), m.size().width, m.size().height)
+    }).collect()
+}
@@ -0,0 +1,10 @@
+// fnv1a returns the 64 bit FNV-1a hash of some bytes. It's not cryptographic in any way, just a
+// cheap and stable way of telling two blobs of memory apart.
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for b in bytes {
+        hash ^= *b as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
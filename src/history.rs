@@ -0,0 +1,123 @@
+use alloc::collections::VecDeque;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::disasm::mnemonic;
+use crate::variant::Variant;
+
+// Entry is an instruction as it ran, with the registers and I from before and after.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+    pub pc: u16,
+    pub opcode: u16,
+    pub registersBefore: [u8; 16],
+    pub registersAfter: [u8; 16],
+    pub IBefore: u16,
+    pub IAfter: u16,
+    pub undo: Undo,
+}
+
+// Undo is the rest of the state an instruction can change, as it was before the instruction, for
+// Emulator::step_back. Memory and the display are kept as the bytes and pixels that changed.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Undo {
+    pub stackDepth: usize,
+    pub stackTop: Option<u16>, // the return address a 00EE may have popped
+    pub delayTimer: u8,
+    pub soundTimer: u8,
+    pub rng: u32,
+    pub heldKey: Option<u8>,
+    pub hires: bool,
+    pub rplFlags: [u8; 8],
+    pub memory: Vec<(u16, u8)>,  // address and old value of every byte written
+    pub display: Vec<(u16, u8)>, // index and old value of every pixel changed
+}
+
+impl Entry {
+    // describe formats the entry as a line of address, opcode, mnemonic and the changes it made,
+    // e.g. "0x204  8124  ADD V1, V2       V1 05->07 VF 01->00".
+    pub fn describe(&self, variant: Variant) -> String {
+        let mut line = format!("{:#05X}  {:04X}  {:<16}", self.pc, self.opcode, mnemonic(self.opcode, variant));
+        for (r, (before, after)) in self.registersBefore.iter().zip(self.registersAfter.iter()).enumerate() {
+            if before != after {
+                line.push_str(&format!(" V{:X} {:02X}->{:02X}", r, before, after));
+            }
+        }
+        if self.IBefore != self.IAfter {
+            line.push_str(&format!(" I {:03X}->{:03X}", self.IBefore, self.IAfter));
+        }
+        line.trim_end().into()
+    }
+}
+
+// MAX_CAPACITY is the most instructions --history can ask for, a minute or two of most ROMs and
+// some hundred megabytes at worst.
+pub const MAX_CAPACITY: usize = 1_000_000;
+
+// History is a ring buffer of the last instructions that ran, oldest first, for finding out how
+// the emulator got where it is, e.g. why VF ended up wrong. It grows as instructions run rather
+// than taking all of its capacity up front.
+#[derive(Clone, Debug)]
+pub struct History {
+    entries: VecDeque<Entry>,
+    capacity: usize,
+}
+
+impl History {
+    pub fn new(capacity: usize) -> History {
+        History { entries: VecDeque::new(), capacity }
+    }
+
+    pub fn push(&mut self, entry: Entry) {
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    // pop removes the newest entry.
+    pub fn pop(&mut self) -> Option<Entry> {
+        self.entries.pop_back()
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &Entry> + ExactSizeIterator {
+        self.entries.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(pc: u16) -> Entry {
+        Entry { pc, opcode: 0x6001, registersBefore: [0; 16], registersAfter: [0; 16], IBefore: 0, IAfter: 0, undo: Undo::default() }
+    }
+
+    #[test]
+    fn keepsTheLastInstructions() {
+        // a capacity this large would abort if it were allocated up front.
+        let mut unbounded = History::new(usize::MAX);
+        unbounded.push(entry(0x200));
+        assert_eq!(unbounded.len(), 1);
+
+        let mut history = History::new(2);
+        for pc in [0x200, 0x202, 0x204] {
+            history.push(entry(pc));
+        }
+        assert_eq!(history.iter().map(|e| e.pc).collect::<Vec<_>>(), [0x202, 0x204]);
+        assert_eq!(history.pop().map(|e| e.pc), Some(0x204));
+    }
+}
@@ -0,0 +1,189 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::emulator::{Emulator, Region, DISPLAY_WIDTH};
+use crate::env::Watch;
+use crate::hash::fnv1a;
+
+// Condition is something to look out for in a running ROM:
+//
+//   mem[0x1F0]==0                a watched value (see Watch) compared with ==, !=, <, <=, > or >=
+//   pc==0x2A4                    execution reaching an address, e.g. the game over routine
+//   screen[16,8,32,8]==0x1A2B..  the hash of a region of the display (x, y, width, height in
+//                                pixels of the current mode), e.g. while it shows "GAME OVER"
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Condition {
+    Value(Watch, Comparison, i64),
+    Pc(u16),
+    Screen(Region, u64),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+// Event is what the hooks report after a frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    GameOver,
+    ScoreChanged { from: i64, to: i64 },
+}
+
+impl Condition {
+    pub fn parse(expr: &str) -> Result<Condition, String> {
+        let expr = expr.trim();
+        let invalid = || format!("invalid condition '{}', expected e.g. mem[0x1F0]==0, pc==0x2A4 or screen[0,0,64,8]==<hash>", expr);
+
+        // the two character operators go first so that <= isn't taken for <.
+        let operators = [
+            ("==", Comparison::Equal),
+            ("!=", Comparison::NotEqual),
+            ("<=", Comparison::LessOrEqual),
+            (">=", Comparison::GreaterOrEqual),
+            ("<", Comparison::Less),
+            (">", Comparison::Greater),
+        ];
+        let (left, comparison, right) = operators.iter()
+            .find_map(|(op, comparison)| expr.split_once(op).map(|(l, r)| (l.trim(), *comparison, r.trim())))
+            .ok_or_else(invalid)?;
+        let right = number(right).ok_or_else(invalid)?;
+
+        if left == "pc" || left == "PC" {
+            if comparison != Comparison::Equal || right > 0xFFFF {
+                return Err(invalid());
+            }
+            return Ok(Condition::Pc(right as u16));
+        }
+        if let Some(region) = left.strip_prefix("screen[").and_then(|l| l.strip_suffix(']')) {
+            let parts: Vec<usize> = region.split(',').map(|p| number(p).map(|n| n as usize)).collect::<Option<_>>().ok_or_else(invalid)?;
+            if comparison != Comparison::Equal || parts.len() != 4 || parts[2] == 0 || parts[3] == 0 {
+                return Err(invalid());
+            }
+            let region = Region { x: parts[0], y: parts[1], width: parts[2], height: parts[3] };
+            return Ok(Condition::Screen(region, right as u64));
+        }
+        Ok(Condition::Value(Watch::parse(left)?, comparison, right))
+    }
+
+    // holds checks the condition against the emulator after a frame, given the watched addresses
+    // reached during it.
+    fn holds(&self, emul: &Emulator, pcHits: &[u16]) -> bool {
+        match self {
+            Condition::Value(watch, comparison, value) => {
+                let actual = watch.value(emul);
+                match comparison {
+                    Comparison::Equal => actual == *value,
+                    Comparison::NotEqual => actual != *value,
+                    Comparison::Less => actual < *value,
+                    Comparison::LessOrEqual => actual <= *value,
+                    Comparison::Greater => actual > *value,
+                    Comparison::GreaterOrEqual => actual >= *value,
+                }
+            }
+            Condition::Pc(address) => pcHits.contains(address),
+            Condition::Screen(region, hash) => screenHash(emul, *region) == *hash,
+        }
+    }
+}
+
+// screenHash hashes a region of the display in pixels of the current mode, for screen conditions.
+// Pixels outside the display count as dark.
+pub fn screenHash(emul: &Emulator, region: Region) -> u64 {
+    let (width, height) = emul.resolution();
+    let scale = DISPLAY_WIDTH / width;
+    let mut pixels = Vec::with_capacity(region.width * region.height);
+    for y in region.y..region.y + region.height {
+        for x in region.x..region.x + region.width {
+            let lit = x < width && y < height && emul.framebuffer()[y * scale * DISPLAY_WIDTH + x * scale] == 1;
+            pixels.push(lit as u8);
+        }
+    }
+    fnv1a(&pixels)
+}
+
+// number parses a decimal or 0x prefixed hexadecimal number.
+fn number(s: &str) -> Option<i64> {
+    let s = s.trim();
+    match s.strip_prefix("0x").or(s.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok().map(|n| n as i64),
+        None => s.parse().ok(),
+    }
+}
+
+// Hooks watch a running ROM for the end of a game and for changes of the score, which the
+// emulator itself knows nothing about. Game over is reported once, when any of its conditions
+// starts to hold, and stays set until reset.
+#[derive(Clone, Debug, Default)]
+pub struct Hooks {
+    pub gameOver: Vec<Condition>,
+    pub score: Option<Watch>,
+    over: bool,
+    lastScore: Option<i64>,
+}
+
+impl Hooks {
+    pub fn new(gameOver: Vec<Condition>, score: Option<Watch>) -> Hooks {
+        Hooks { gameOver, score, over: false, lastScore: None }
+    }
+
+    // parseConditions parses a list of conditions separated by " or ".
+    pub fn parseConditions(expr: &str) -> Result<Vec<Condition>, String> {
+        expr.split(" or ").map(Condition::parse).collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.gameOver.is_empty() && self.score.is_none()
+    }
+
+    // attach sets up the PC watches of the conditions on an emulator and starts from its current
+    // state, which is what reset does as well.
+    pub fn attach(&mut self, emul: &mut Emulator) {
+        for condition in &self.gameOver {
+            if let Condition::Pc(address) = condition {
+                emul.watch_pc(*address);
+            }
+        }
+        self.reset(emul);
+    }
+
+    // reset starts watching a new game, e.g. after the emulator was reset.
+    pub fn reset(&mut self, emul: &Emulator) {
+        self.over = false;
+        self.lastScore = self.score.as_ref().map(|watch| watch.value(emul));
+    }
+
+    // check looks at the emulator after a frame and returns what happened during it.
+    pub fn check(&mut self, emul: &mut Emulator) -> Vec<Event> {
+        let mut events = Vec::new();
+        let pcHits = emul.take_pc_hits();
+
+        if let (Some(watch), Some(last)) = (&self.score, self.lastScore) {
+            let score = watch.value(emul);
+            if score != last {
+                events.push(Event::ScoreChanged { from: last, to: score });
+                self.lastScore = Some(score);
+            }
+        }
+        if !self.over && self.gameOver.iter().any(|c| c.holds(emul, &pcHits)) {
+            self.over = true;
+            events.push(Event::GameOver);
+        }
+        events
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.over
+    }
+
+    // score returns the score as of the last check, if there's a score to watch.
+    pub fn score(&self) -> Option<i64> {
+        self.lastScore
+    }
+}
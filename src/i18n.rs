@@ -0,0 +1,100 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+
+use crate::assets;
+use crate::config;
+
+// ENGLISH are the texts the window shows, by key. A language file translates some or all of them,
+// the others stay English. {} is where a value goes, in the order the code passes them.
+const ENGLISH: [(&str, &str); 41] = [
+    ("title", "Rust-CHIP8 — {} [{}, {} ips]"),
+    ("title.score", "Score {}"),
+    ("title.game_over", "GAME OVER"),
+    ("title.comparing", "COMPARING"),
+    ("title.demo", "DEMO"),
+    ("title.turbo", "TURBO"),
+    ("title.slow_motion", "SLOW {}%"),
+    ("title.paused", "PAUSED"),
+    ("title.tab", "TAB {}/{}"),
+    ("about.pause_reset", "P PAUSE  BKSP RESET"),
+    ("about.speed", "TAB FAST  ` SLOW"),
+    ("about.drop", "DROP A ROM TO LOAD IT"),
+    ("about.close", "F1 CLOSE  ESC QUIT"),
+    ("announce.loaded", "Loaded {}"),
+    ("announce.paused", "Paused"),
+    ("announce.running", "Running"),
+    ("announce.turbo", "Fast-forward"),
+    ("announce.slow_motion", "Slow-motion at {}%"),
+    ("announce.normal_speed", "Normal speed"),
+    ("announce.game_over", "Game over"),
+    ("palette.search", "DO:"),
+    ("action.reset", "Reset"),
+    ("action.pause", "Pause or resume"),
+    ("action.slow_motion", "Slow-motion on or off"),
+    ("action.load", "Load {}"),
+    ("action.open_tab", "Open {} in a new tab"),
+    ("action.quirk", "Toggle quirk {}"),
+    ("action.save_state", "Save state to slot 1"),
+    ("action.load_state", "Load state from slot 1"),
+    ("action.states", "Save states"),
+    ("action.screenshot", "Screenshot"),
+    ("action.export_sprites", "Export the screen as Octo sprites"),
+    ("action.speed", "Speed {} ips"),
+    ("action.default_speed", "Default speed"),
+    ("action.upscale", "Upscale with {}"),
+    ("action.fullscreen", "Full screen on monitor {}"),
+    ("action.windowed", "Leave full screen"),
+    ("action.always_on_top", "Always on top on or off"),
+    ("action.opacity", "Opacity {}%"),
+    ("action.about", "About"),
+    ("action.quit", "Quit"),
+];
+
+// Messages are the texts of the window title, the About box, the command palette and the
+// --announce lines in one language, picked with --language. Other messages on the console stay
+// English.
+#[derive(Clone)]
+pub struct Messages {
+    texts: HashMap<&'static str, String>,
+}
+
+impl Messages {
+    pub fn english() -> Messages {
+        Messages { texts: ENGLISH.iter().map(|(key, text)| (*key, text.to_string())).collect() }
+    }
+
+    // load reads a language file, or a built-in language by name, see assets::language. It's
+    // written like the config file with a "key = text" line for each text it translates, e.g.
+    //
+    //   title.paused = "PAUSIERT"
+    pub fn load(path: &str) -> Result<Messages, String> {
+        let text = assets::language(path)?;
+        let file = config::parse(&text).map_err(|e| format!("{}: {}", path, e))?;
+        let mut messages = Messages::english();
+        for (key, text) in file.entries("") {
+            match ENGLISH.iter().find(|(k, _)| *k == key) {
+                Some((key, _)) => messages.texts.insert(key, text.to_string()),
+                None => return Err(format!("{}: unknown message '{}'", path, key)),
+            };
+        }
+        Ok(messages)
+    }
+
+    // get returns the text of a key, or the key itself if there's no such text.
+    pub fn get<'a>(&'a self, key: &'a str) -> &'a str {
+        self.texts.get(key).map(|t| t.as_str()).unwrap_or(key)
+    }
+
+    // format returns the text with its {} replaced by the values, in order.
+    pub fn format(&self, key: &str, values: &[&dyn Display]) -> String {
+        let mut parts = self.get(key).split("{}");
+        let mut text = parts.next().unwrap_or_default().to_string();
+        for (i, part) in parts.enumerate() {
+            if let Some(value) = values.get(i) {
+                text.push_str(&value.to_string());
+            }
+            text.push_str(part);
+        }
+        text
+    }
+}
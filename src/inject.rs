@@ -0,0 +1,67 @@
+use std::fs;
+
+use rust_chip8::emulator::KeyState;
+
+// Injection presses and releases keys at given frames, from an --inject-input file, so a script can
+// play a ROM without anyone at the keyboard, e.g. for a test or a demo. Each line is the frame, the
+// CHIP8 key in hex and press or release:
+//
+//   # start the game and move right for a second
+//   30 5 press
+//   32 5 release
+//   40 6 press
+//   100 6 release
+//
+// Frames count from 1, the first frame run, and a key pressed in a frame is down during it. The
+// keys are down on top of those of the frontend, so someone can still play along. Empty lines and
+// lines starting with # are ignored.
+pub struct Injection {
+    events: Vec<(u64, u8, bool)>, // frame, key and pressed, in frame order
+    next: usize,                  // the first event not applied yet
+    keys: KeyState,               // the keys held down by the events applied
+}
+
+impl Injection {
+    pub fn load(path: &str) -> Result<Injection, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("Unable to read {}: {}", path, e))?;
+        Injection::parse(&text).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    pub fn parse(text: &str) -> Result<Injection, String> {
+        let mut events = Vec::new();
+        for (n, line) in text.lines().enumerate().map(|(i, l)| (i + 1, l.trim())) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (frame, key, action) = match fields[..] {
+                [frame, key, action] => (frame, key, action),
+                _ => return Err(format!("line {}: expected <frame> <key> press|release, got '{}'", n, line)),
+            };
+            let frame = frame.parse::<u64>().map_err(|_| format!("line {}: invalid frame '{}'", n, frame))?;
+            let key = match u8::from_str_radix(key.strip_prefix("0x").unwrap_or(key), 16) {
+                Ok(key) if key < 16 => key,
+                _ => return Err(format!("line {}: invalid key '{}', expected 0-F", n, key)),
+            };
+            let pressed = match action {
+                "press" | "down" => true,
+                "release" | "up" => false,
+                _ => return Err(format!("line {}: invalid action '{}', expected press or release", n, action)),
+            };
+            events.push((frame, key, pressed));
+        }
+        // events of the same frame stay in the order of the file.
+        events.sort_by_key(|(frame, _, _)| *frame);
+        Ok(Injection { events, next: 0, keys: KeyState::default() })
+    }
+
+    // keys returns the keys held down during a frame, which must come after the frame asked for
+    // before.
+    pub fn keys(&mut self, frame: u64) -> KeyState {
+        while let Some((_, key, pressed)) = self.events.get(self.next).filter(|(f, _, _)| *f <= frame) {
+            self.keys.set(*key, *pressed);
+            self.next += 1;
+        }
+        self.keys
+    }
+}
@@ -0,0 +1,273 @@
+use std::collections::HashMap;
+
+use rust_chip8::emulator::KeyState;
+use winit::event::VirtualKeyCode;
+
+// LATCH_FRAMES is the longest a sticky key press is kept alive, waiting for the ROM to read it.
+const LATCH_FRAMES: u8 = 10;
+
+// The CHIP8 keypad is a 4x4 grid, which is traditionally mapped to the left-hand block of a
+// keyboard by position rather than by the labels on the keys:
+//
+//   1 2 3 C        1 2 3 4
+//   4 5 6 D   ->   Q W E R
+//   7 8 9 E        A S D F
+//   A 0 B F        Z X C V
+//
+// To keep that grid in the same place on AZERTY, Dvorak and other layouts, keys are matched on
+// their physical scancode. Scancodes differ between platforms, so there's a table for each.
+
+// SCANCODES are the keyboard scancodes (set 1, which evdev codes on Linux share) of the grid
+// above, indexed by CHIP8 key.
+#[cfg(not(target_os = "macos"))]
+const SCANCODES: [u32; 16] = [
+    0x2D, // 0: X
+    0x02, // 1: 1
+    0x03, // 2: 2
+    0x04, // 3: 3
+    0x10, // 4: Q
+    0x11, // 5: W
+    0x12, // 6: E
+    0x1E, // 7: A
+    0x1F, // 8: S
+    0x20, // 9: D
+    0x2C, // A: Z
+    0x2E, // B: C
+    0x05, // C: 4
+    0x13, // D: R
+    0x21, // E: F
+    0x2F, // F: V
+];
+
+// On macOS scancodes are the kVK_ANSI_* virtual key codes of the grid above.
+#[cfg(target_os = "macos")]
+const SCANCODES: [u32; 16] = [
+    0x07, // 0: X
+    0x12, // 1: 1
+    0x13, // 2: 2
+    0x14, // 3: 3
+    0x0C, // 4: Q
+    0x0D, // 5: W
+    0x0E, // 6: E
+    0x00, // 7: A
+    0x01, // 8: S
+    0x02, // 9: D
+    0x06, // A: Z
+    0x08, // B: C
+    0x15, // C: 4
+    0x0F, // D: R
+    0x03, // E: F
+    0x09, // F: V
+];
+
+// QWERTY_NAMES are the labels of the grid keys on a US keyboard, indexed by CHIP8 key. They're
+// used until the actual label on the user's layout is known.
+pub const QWERTY_NAMES: [&str; 16] = ["X", "1", "2", "3", "Q", "W", "E", "A", "S", "D", "Z", "C", "4", "R", "F", "V"];
+
+// the CHIP8 keys in the order they're laid out on the keypad.
+pub const KEYPAD: [[u8; 4]; 4] = [[0x1, 0x2, 0x3, 0xC], [0x4, 0x5, 0x6, 0xD], [0x7, 0x8, 0x9, 0xE], [0xA, 0x0, 0xB, 0xF]];
+
+// The numpad keymap puts the digits on the digits of the numeric keypad, which is how keypad
+// calculator-style ROMs read best, and A-F on the keys around them:
+//
+//   A=/ B=* C=- D=+ E=Enter F=.
+//
+// NUMPAD_SCANCODES are their scancodes, indexed by CHIP8 key: set 1 for the digits, and evdev
+// codes for / and Enter, which set 1 only has as extended codes.
+#[cfg(not(target_os = "macos"))]
+const NUMPAD_SCANCODES: [u32; 16] = [0x52, 0x4F, 0x50, 0x51, 0x4B, 0x4C, 0x4D, 0x47, 0x48, 0x49, 0x62, 0x37, 0x4A, 0x4E, 0x60, 0x53];
+
+// On macOS they're the kVK_ANSI_Keypad* virtual key codes.
+#[cfg(target_os = "macos")]
+const NUMPAD_SCANCODES: [u32; 16] = [0x52, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5B, 0x5C, 0x4B, 0x43, 0x4E, 0x45, 0x4C, 0x41];
+
+const NUMPAD_NAMES: [&str; 16] = ["Num0", "Num1", "Num2", "Num3", "Num4", "Num5", "Num6", "Num7", "Num8", "Num9", "Num/", "Num*", "Num-", "Num+", "Enter", "Num."];
+
+// KEYMAP_NAMES are the keymaps of --keymap.
+pub const KEYMAP_NAMES: [&str; 2] = ["positional", "numpad"];
+
+// Keymap maps physical keys to CHIP8 keys and keeps track of what the keys are called on the
+// current keyboard layout.
+#[derive(Clone)]
+pub struct Keymap {
+    scancodes: [u32; 16],
+    defaultNames: [&'static str; 16], // until the label on the user's layout is known
+    names: HashMap<u32, VirtualKeyCode>, // layout-aware key of each scancode seen so far
+}
+
+impl Keymap {
+    // positional returns the standard 1234/QWER/ASDF/ZXCV mapping.
+    pub fn positional() -> Keymap {
+        Keymap {
+            scancodes: SCANCODES,
+            defaultNames: QWERTY_NAMES,
+            names: HashMap::new(),
+        }
+    }
+
+    // named returns a keymap of KEYMAP_NAMES.
+    pub fn named(name: &str) -> Result<Keymap, String> {
+        match name {
+            "positional" => Ok(Keymap::positional()),
+            "numpad" => Ok(Keymap { scancodes: NUMPAD_SCANCODES, defaultNames: NUMPAD_NAMES, names: HashMap::new() }),
+            _ => Err(format!("invalid keymap '{}', expected {}", name, KEYMAP_NAMES.join(" or "))),
+        }
+    }
+
+    // key returns the CHIP8 key bound to a scancode, if any.
+    pub fn key(&self, scancode: u32) -> Option<u8> {
+        self.scancodes.iter().position(|s| *s == scancode).map(|k| k as u8)
+    }
+
+    // learn records which key of the current layout a scancode produced, for name.
+    pub fn learn(&mut self, scancode: u32, key: Option<VirtualKeyCode>) {
+        if let Some(key) = key {
+            self.names.insert(scancode, key);
+        }
+    }
+
+    // name returns the name of the keyboard key bound to a CHIP8 key, as labeled on the user's
+    // layout if that key has been pressed already.
+    pub fn name(&self, chip8Key: u8) -> String {
+        let index = (chip8Key & 0xF) as usize;
+        match self.names.get(&self.scancodes[index]) {
+            Some(key) => keyName(*key),
+            None => self.defaultNames[index].to_string(),
+        }
+    }
+
+    // describe returns the keypad as four lines of "CHIP8 key=keyboard key" pairs.
+    pub fn describe(&self) -> Vec<String> {
+        KEYPAD.iter()
+            .map(|row| row.iter().map(|k| format!("{:X}={}", k, self.name(*k))).collect::<Vec<String>>().join(" "))
+            .collect()
+    }
+}
+
+// keyName returns a short label for a key, e.g. "Q" or "1".
+fn keyName(key: VirtualKeyCode) -> String {
+    let name = format!("{:?}", key);
+    // VirtualKeyCode::Key1 is shown as 1, the others go by their variant names.
+    match name.strip_prefix("Key") {
+        Some(digit) if digit.len() == 1 => digit.to_string(),
+        _ => name,
+    }
+}
+
+// Players maps the controls of the two players of games like Pong, which give each player a
+// group of keys, to CHIP8 keys. A player's controls are pairs like "1=w 4=s": a CHIP8 key and a key
+// of the keyboard by its name on the user's layout, or a button of a gamepad as pad:<button>, e.g.
+// pad:dpup or pad:a (gamepads need the sdl2 frontend). They beat the keymap, and swap switches
+// the sides, the first control of player 1 then giving the first CHIP8 key of player 2 and so on.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Players {
+    sides: [Vec<(u8, String)>; 2], // the CHIP8 key and control of every pair of each player
+    swapped: bool,
+}
+
+impl Players {
+    pub fn parse(player1: &str, player2: &str) -> Result<Players, String> {
+        let side = |text: &str| text.split_whitespace().map(|pair| {
+            let (key, control) = pair.split_once('=').ok_or(format!("expected <key>=<control> rather than '{}'", pair))?;
+            let key = u8::from_str_radix(key, 16).ok().filter(|k| *k < 16).ok_or(format!("invalid CHIP8 key '{}', expected 0-F", key))?;
+            Ok((key, control.to_lowercase()))
+        }).collect::<Result<Vec<_>, String>>();
+        Ok(Players { sides: [side(player1)?, side(player2)?], swapped: false })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sides.iter().all(|side| side.is_empty())
+    }
+
+    // key returns the CHIP8 key of a control, named like in the pairs.
+    pub fn key(&self, control: &str) -> Option<u8> {
+        let control = control.to_lowercase();
+        for (player, side) in self.sides.iter().enumerate() {
+            if let Some(i) = side.iter().position(|(_, c)| *c == control) {
+                let other = &self.sides[if self.swapped { 1 - player } else { player }];
+                return Some(other.get(i).unwrap_or(&side[i]).0);
+            }
+        }
+        None
+    }
+
+    // swap switches the sides of the players, and back.
+    pub fn swap(&mut self) {
+        self.swapped = !self.swapped;
+    }
+
+    // describe returns which control is which key for each player, e.g. "PLAYER 1 1=W 4=S".
+    pub fn describe(&self) -> Vec<String> {
+        (0..2).map(|player| {
+            let (controls, keys) = (&self.sides[player], &self.sides[if self.swapped { 1 - player } else { player }]);
+            let pairs: Vec<String> = controls.iter().enumerate()
+                .map(|(i, (key, control))| format!("{:X}={}", keys.get(i).map(|(k, _)| *k).unwrap_or(*key), control.to_uppercase()))
+                .collect();
+            format!("PLAYER {} {}", player + 1, pairs.join(" "))
+        }).collect()
+    }
+}
+
+// controlName returns the name of a keyboard key for Players, e.g. "w", "up" or "1".
+pub fn controlName(key: VirtualKeyCode) -> String {
+    keyName(key).to_lowercase()
+}
+
+// TOUCH_PAD are the keys of a 3x3 grid over the display for touch screens without a keyboard:
+// 2/4/6/8 are the directions most ROMs use, 5 (the usual "fire") is the middle and the corners are
+// the diagonals 1, 3, 7 and 9 like on the keypad.
+const TOUCH_PAD: [[u8; 3]; 3] = [[0x1, 0x2, 0x3], [0x4, 0x5, 0x6], [0x7, 0x8, 0x9]];
+
+// touchKey returns the key for a touch at x,y of a width x height display.
+pub fn touchKey(x: usize, y: usize, width: usize, height: usize) -> u8 {
+    TOUCH_PAD[(y * 3 / height).min(2)][(x * 3 / width).min(2)]
+}
+
+// KeyLatch collects the key state between two frames. The emulator only looks at the keys once per
+// frame, so a key that is pressed and released in between would go unnoticed, and ROMs that only
+// poll EX9E briefly can miss presses at low speeds. With sticky keys on, a press is kept for at
+// least one full frame and then until the ROM has read it with EX9E/EXA1/FX0A, at most
+// LATCH_FRAMES frames.
+pub struct KeyLatch {
+    held: KeyState,
+    latched: [u8; 16], // frames left for each latched key
+    sticky: bool,
+}
+
+impl KeyLatch {
+    pub fn new(sticky: bool) -> KeyLatch {
+        KeyLatch {
+            held: KeyState::default(),
+            latched: [0; 16],
+            sticky,
+        }
+    }
+
+    pub fn set(&mut self, key: u8, pressed: bool) {
+        self.held.set(key, pressed);
+        if pressed && self.sticky {
+            self.latched[(key & 0xF) as usize] = LATCH_FRAMES;
+        }
+    }
+
+    // frame returns the keys for the next frame: those held down plus any latched presses.
+    pub fn frame(&mut self) -> KeyState {
+        let mut keys = self.held;
+        for (key, frames) in self.latched.iter_mut().enumerate() {
+            if *frames > 0 {
+                keys.set(key as u8, true);
+                *frames -= 1;
+            }
+        }
+        keys
+    }
+
+    // consumed releases the latches of the keys the ROM read during the last frame.
+    pub fn consumed(&mut self, keys: KeyState) {
+        for (key, frames) in self.latched.iter_mut().enumerate() {
+            if keys.is_pressed(key as u8) {
+                *frames = 0;
+            }
+        }
+    }
+}
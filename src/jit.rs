@@ -0,0 +1,219 @@
+use std::collections::HashMap;
+
+use cranelift_codegen::ir::condcodes::IntCC;
+use cranelift_codegen::ir::{types, AbiParam, InstBuilder, MemFlagsData, Value};
+use cranelift_codegen::settings::{self, Configurable};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{default_libcall_names, Module};
+
+use crate::emulator::{decode, Emulator, FrameOutput, KeyState, Opcode};
+use crate::error::Error;
+use crate::quirks::Quirks;
+
+// MAX_BLOCK is the most instructions translated into a single block.
+const MAX_BLOCK: usize = 64;
+
+// Block is a translated run of instructions: a native function taking pointers to V0..VF and I.
+struct Block {
+    code: extern "C" fn(*mut u8, *mut u16),
+    bytes: Vec<u8>, // the instructions it was translated from, to notice self-modifying code
+}
+
+// Jit is an experimental dynamic recompiler. It translates basic blocks of CHIP8 code, runs of
+// the register instructions 6XNN, 7XNN, 8XYN and ANNN, into native code with Cranelift and runs
+// them in one go. Every other instruction is left to the interpreter, so all the instructions
+// that touch the display, the keys, the timers or the control flow behave exactly the same.
+//
+// A block is checked against memory every time before it runs, and translated anew if the ROM
+// has overwritten it. Changing the quirks throws all blocks away. The code of discarded blocks
+// isn't freed until the Jit is dropped, so a ROM that keeps modifying itself keeps growing it.
+pub struct Jit {
+    module: JITModule,
+    blocks: HashMap<u16, Option<Block>>, // None where the first instruction can't be translated
+    quirks: Quirks,                      // the quirks the blocks were translated with
+}
+
+impl Jit {
+    pub fn new() -> Result<Jit, String> {
+        let mut flags = settings::builder();
+        flags.set("use_colocated_libcalls", "false").map_err(|e| e.to_string())?;
+        flags.set("is_pic", "false").map_err(|e| e.to_string())?;
+        let isa = cranelift_native::builder()?
+            .finish(settings::Flags::new(flags))
+            .map_err(|e| e.to_string())?;
+        Ok(Jit {
+            module: JITModule::new(JITBuilder::with_isa(isa, default_libcall_names())),
+            blocks: HashMap::new(),
+            quirks: Quirks::default(),
+        })
+    }
+
+    // tick_frame is Emulator::tick_frame running translated blocks where possible.
+    pub fn tick_frame(&mut self, emul: &mut Emulator, inputs: KeyState) -> Result<FrameOutput, Error> {
+        emul.tick_frame_with(inputs, |emul, left| self.step(emul, left))
+    }
+
+    // step runs the block at pc, or a single instruction in the interpreter if there's none or it
+    // is longer than the instructions left in the frame, and returns the number of instructions run.
+    pub fn step(&mut self, emul: &mut Emulator, left: u32) -> Result<u32, Error> {
+        // the history needs every instruction to go through the interpreter.
+        if emul.history().is_some() {
+            return emul.run().map(|_| 1);
+        }
+        if emul.quirks != self.quirks {
+            self.blocks.clear();
+            self.quirks = emul.quirks;
+        }
+        let pc = emul.pc;
+        let memory = emul.memory.bytes();
+        let start = pc as usize;
+
+        let stale = match self.blocks.get(&pc) {
+            Some(Some(block)) => memory.get(start..start + block.bytes.len()) != Some(&block.bytes[..]),
+            Some(None) => false,
+            None => true,
+        };
+        if stale {
+            let block = self.translate(memory, start);
+            self.blocks.insert(pc, block);
+        }
+
+        match &self.blocks[&pc] {
+            Some(block) if block.bytes.len() / 2 <= left as usize => {
+                (block.code)(emul.registers.as_mut_ptr(), &mut emul.I);
+                let instructions = block.bytes.len() / 2;
+                emul.pc = emul.pc.wrapping_add(2 * instructions as u16);
+                Ok(instructions as u32)
+            }
+            _ => emul.run().map(|_| 1),
+        }
+    }
+
+    // translate compiles the instructions from start on up to the first one that can't be
+    // translated, or None if that's the very first.
+    fn translate(&mut self, memory: &[u8], start: usize) -> Option<Block> {
+        let mut opcodes = Vec::new();
+        let mut address = start;
+        while opcodes.len() < MAX_BLOCK && address + 1 < memory.len() {
+            let op = decode(u16::from_be_bytes([memory[address], memory[address + 1]]));
+            if !translatable(op) {
+                break;
+            }
+            opcodes.push(op);
+            address += 2;
+        }
+        if opcodes.is_empty() {
+            return None;
+        }
+
+        let mut ctx = self.module.make_context();
+        let pointer = self.module.target_config().pointer_type();
+        ctx.func.signature.params.push(AbiParam::new(pointer));
+        ctx.func.signature.params.push(AbiParam::new(pointer));
+
+        let mut builderContext = FunctionBuilderContext::new();
+        let mut b = FunctionBuilder::new(&mut ctx.func, &mut builderContext);
+        let entry = b.create_block();
+        b.append_block_params_for_function_params(entry);
+        b.switch_to_block(entry);
+        b.seal_block(entry);
+        let (registers, index) = (b.block_params(entry)[0], b.block_params(entry)[1]);
+        for op in &opcodes {
+            emit(&mut b, *op, self.quirks, registers, index);
+        }
+        b.ins().return_(&[]);
+        b.finalize(self.module.target_config());
+
+        // failing to compile just means interpreting.
+        let id = self.module.declare_anonymous_function(&ctx.func.signature).ok()?;
+        self.module.define_function(id, &mut ctx).ok()?;
+        self.module.clear_context(&mut ctx);
+        self.module.finalize_definitions().ok()?;
+        let code = self.module.get_finalized_function(id);
+        Some(Block {
+            // the function has exactly the signature set up above.
+            code: unsafe { std::mem::transmute::<*const u8, extern "C" fn(*mut u8, *mut u16)>(code) },
+            bytes: memory[start..address].to_vec(),
+        })
+    }
+}
+
+// translatable returns whether the JIT handles an instruction.
+fn translatable(op: Opcode) -> bool {
+    matches!((op.instr, op.N), (0x6, _) | (0x7, _) | (0xA, _) | (0x8, 0x0..=0x7) | (0x8, 0xE))
+}
+
+// emit translates a single instruction, with the same semantics as the interpreter.
+fn emit(b: &mut FunctionBuilder, op: Opcode, quirks: Quirks, registers: Value, index: Value) {
+    let flags = MemFlagsData::trusted();
+    let load = |b: &mut FunctionBuilder, r: usize| b.ins().load(types::I8, flags, registers, r as i32);
+    let store = |b: &mut FunctionBuilder, r: usize, v: Value| {
+        b.ins().store(flags, v, registers, r as i32);
+    };
+
+    match (op.instr, op.N) {
+        (0x6, _) => {
+            let v = b.ins().iconst(types::I8, op.NN as i64);
+            store(b, op.X, v);
+        }
+        (0x7, _) => {
+            let vx = load(b, op.X);
+            let v = b.ins().iadd_imm_u(vx, op.NN as i64);
+            store(b, op.X, v);
+        }
+        (0xA, _) => {
+            let v = b.ins().iconst(types::I16, op.NNN as i64);
+            b.ins().store(flags, v, index, 0);
+        }
+        (0x8, 0x0) => {
+            let vy = load(b, op.Y);
+            store(b, op.X, vy);
+        }
+        (0x8, 0x1..=0x3) => {
+            let (vx, vy) = (load(b, op.X), load(b, op.Y));
+            let v = match op.N {
+                0x1 => b.ins().bor(vx, vy),
+                0x2 => b.ins().band(vx, vy),
+                _ => b.ins().bxor(vx, vy),
+            };
+            store(b, op.X, v);
+            if quirks.vfReset {
+                let zero = b.ins().iconst(types::I8, 0);
+                store(b, 0xF, zero);
+            }
+        }
+        (0x8, 0x4) => {
+            let (vx, vy) = (load(b, op.X), load(b, op.Y));
+            let v = b.ins().iadd(vx, vy);
+            let carry = b.ins().icmp(IntCC::UnsignedLessThan, v, vx);
+            store(b, op.X, v);
+            store(b, 0xF, carry);
+        }
+        (0x8, 0x5) | (0x8, 0x7) => {
+            let (vx, vy) = (load(b, op.X), load(b, op.Y));
+            let (from, subtract) = if op.N == 0x5 { (vx, vy) } else { (vy, vx) };
+            let noBorrow = b.ins().icmp(IntCC::UnsignedGreaterThanOrEqual, from, subtract);
+            let v = b.ins().isub(from, subtract);
+            store(b, op.X, v);
+            store(b, 0xF, noBorrow);
+        }
+        (0x8, 0x6) | (0x8, 0xE) => {
+            let v = if quirks.shiftUsesVY {
+                let vy = load(b, op.Y);
+                store(b, op.X, vy);
+                vy
+            } else {
+                load(b, op.X)
+            };
+            let (flag, shifted) = if op.N == 0x6 {
+                (b.ins().band_imm_u(v, 1), b.ins().ushr_imm_u(v, 1))
+            } else {
+                (b.ins().ushr_imm_u(v, 7), b.ins().ishl_imm_u(v, 1))
+            };
+            store(b, op.X, shifted);
+            store(b, 0xF, flag);
+        }
+        _ => unreachable!("not translatable"),
+    }
+}
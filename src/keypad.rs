@@ -0,0 +1,70 @@
+use rust_chip8::emulator::{KeyState, FONT};
+
+use crate::input::KEYPAD;
+
+// KEYPAD_HEIGHT is the number of frame rows taken by the on-screen keypad, 12 for each row of keys.
+pub const KEYPAD_HEIGHT: usize = 48;
+
+const ROW_HEIGHT: usize = KEYPAD_HEIGHT / 4;
+const GLYPH_SCALE: usize = 2; // the 4x5 font glyphs are drawn at 8x10
+
+const BACKGROUND: [u8; 4] = [0x30, 0x30, 0x30, 0xff];
+const PRESSED: [u8; 4] = [0x20, 0x80, 0x20, 0xff];
+const BORDER: [u8; 4] = [0x0, 0x0, 0x0, 0xff];
+const LABEL: [u8; 4] = [0xFF, 0xFF, 0xFF, 0xff];
+
+// Keypad is a clickable CHIP8 keypad drawn into the frame below the displays, laid out like the
+// original 4x4 hex keypad and labeled with the CHIP8 font. Keys light up while pressed, also when
+// pressed on the keyboard, which makes it easy to find out which keys a ROM uses.
+#[derive(Clone, Copy)]
+pub struct Keypad {
+    pub top: usize,   // the first frame row of the keypad
+    pub width: usize, // the width of the frame, the keypad spans all of it
+}
+
+impl Keypad {
+    // key_at returns the key under pixel x,y of the frame, if any.
+    pub fn key_at(&self, x: usize, y: usize) -> Option<u8> {
+        if y < self.top || y >= self.top + KEYPAD_HEIGHT || x >= self.width {
+            return None;
+        }
+        let row = (y - self.top) / ROW_HEIGHT;
+        let column = x * 4 / self.width;
+        Some(KEYPAD[row][column])
+    }
+
+    // draw paints the keypad with the given keys lit.
+    pub fn draw(&self, px: &mut [u8], pressed: KeyState) {
+        for y in 0..KEYPAD_HEIGHT {
+            for x in 0..self.width {
+                let row = y / ROW_HEIGHT;
+                let column = x * 4 / self.width;
+                let key = KEYPAD[row][column];
+
+                // position within the key, whose left and top edges are the border.
+                let cellX = x - column * self.width / 4;
+                let cellY = y - row * ROW_HEIGHT;
+                let cellWidth = (column + 1) * self.width / 4 - column * self.width / 4;
+                let glyphX = (cellWidth - 4 * GLYPH_SCALE) / 2;
+                let glyphY = (ROW_HEIGHT - 5 * GLYPH_SCALE) / 2;
+
+                let color = if cellX == 0 || cellY == 0 {
+                    BORDER
+                } else if label(key, cellX.wrapping_sub(glyphX) / GLYPH_SCALE, cellY.wrapping_sub(glyphY) / GLYPH_SCALE) {
+                    LABEL
+                } else if pressed.is_pressed(key) {
+                    PRESSED
+                } else {
+                    BACKGROUND
+                };
+                let index = ((self.top + y) * self.width + x) * 4;
+                px[index..index + 4].copy_from_slice(&color);
+            }
+        }
+    }
+}
+
+// label returns whether pixel x,y of the font glyph of a key is lit.
+fn label(key: u8, x: usize, y: usize) -> bool {
+    x < 4 && y < 5 && FONT[key as usize * 5 + y] & (0x80 >> x) != 0
+}
@@ -0,0 +1,103 @@
+use std::time::{Duration, Instant};
+
+// UNSEEN_AFTER is how long a press can go without the ROM reading it or drawing after it before
+// it's given up on. Menus that wait for a key release, or presses nothing reacts to, would keep
+// it open forever.
+const UNSEEN_AFTER: Duration = Duration::from_secs(2);
+
+// Press is a key press on its way through the emulator: the host key event, the frame the ROM
+// read the key with EX9E, EXA1 or FX0A, the frame that changed the display after that, and the
+// frame presented with the change.
+#[derive(Clone, Copy)]
+struct Press {
+    key: u8,
+    pressed: Instant,
+    read: Option<Instant>,
+    drawn: Option<Instant>,
+}
+
+// Latency measures how long --latency presses take from the host key event to the screen, in
+// three stages: until the ROM reads the key, until the display next changes, and until that
+// change is presented. The event-loop reports the key events and the presents, the interpreter
+// thread what each frame read and drew.
+#[derive(Default)]
+pub struct Latency {
+    pending: Vec<Press>,
+    samples: Vec<[Duration; 3]>, // read, drawn and presented, each after the stage before
+    unseen: usize,               // presses given up on, see UNSEEN_AFTER
+}
+
+impl Latency {
+    // press starts measuring a key press, in place of one of the same key still pending.
+    pub fn press(&mut self, key: u8, at: Instant) {
+        self.pending.retain(|p| p.key != key || p.read.is_some());
+        self.pending.push(Press { key, pressed: at, read: None, drawn: None });
+    }
+
+    // frame takes in a frame the interpreter finished at a point in time: whether the ROM read
+    // each key and whether the display changed.
+    pub fn frame(&mut self, read: impl Fn(u8) -> bool, changed: bool, at: Instant) {
+        let before = self.pending.len();
+        self.pending.retain(|p| p.read.is_some() || at - p.pressed < UNSEEN_AFTER);
+        self.unseen += before - self.pending.len();
+        for press in &mut self.pending {
+            match (press.read, press.drawn) {
+                (None, _) if read(press.key) => {
+                    press.read = Some(at);
+                    // the frame that read the key can have drawn what it did already.
+                    if changed {
+                        press.drawn = Some(at);
+                    }
+                }
+                (Some(_), None) if changed => press.drawn = Some(at),
+                _ => {}
+            }
+        }
+    }
+
+    // presented finishes the presses whose change is on the screen now, that of a frame copied
+    // for presenting at a point in time. A change drawn after the copy waits for the next one.
+    pub fn presented(&mut self, copied: Instant, at: Instant) {
+        let before = self.pending.len();
+        let mut samples = Vec::new();
+        self.pending.retain(|p| match (p.read, p.drawn) {
+            (Some(read), Some(drawn)) if drawn <= copied => {
+                samples.push([read - p.pressed, drawn - read, at - drawn]);
+                false
+            }
+            (Some(read), None) => at - read < UNSEEN_AFTER,
+            (Some(_), Some(_)) => true,
+            _ => true,
+        });
+        self.unseen += before - self.pending.len() - samples.len();
+        self.samples.extend(samples);
+    }
+
+    // report returns the minimum, median, 95th percentile and maximum of each stage and of the
+    // whole way, or nothing before the first press made it to the screen.
+    pub fn report(&self) -> Vec<String> {
+        if self.samples.is_empty() {
+            return match self.unseen {
+                0 => Vec::new(),
+                n => vec![format!("Input latency: none of the {} presses made it to the screen", n)],
+            };
+        }
+        let mut lines = vec![
+            format!("Input latency of {} presses, {} given up on:", self.samples.len(), self.unseen),
+            format!("  {:<40}{:>7}{:>8}{:>8}{:>8}", "ms", "min", "median", "p95", "max"),
+        ];
+        let stages = ["key event to read by the ROM", "read to display change", "display change to presented", "key event to presented"];
+        for (stage, name) in stages.iter().enumerate() {
+            // the last stage is the whole way.
+            let mut times: Vec<Duration> = self.samples.iter().map(|s| s.get(stage).copied().unwrap_or_else(|| s.iter().sum())).collect();
+            times.sort();
+            let at = |fraction: f64| ms(times[((times.len() - 1) as f64 * fraction).round() as usize]);
+            lines.push(format!("  {:<40}{:>7}{:>8}{:>8}{:>8}", name, at(0.0), at(0.5), at(0.95), at(1.0)));
+        }
+        lines
+    }
+}
+
+fn ms(duration: Duration) -> String {
+    format!("{:.1}", duration.as_secs_f64() * 1000.0)
+}
@@ -0,0 +1,32 @@
+#![allow(dead_code, non_snake_case)]
+#![cfg_attr(not(feature = "std"), no_std)]
+
+// The emulator core. Everything in here is free of windowing, input and threading concerns so it
+// can be driven one frame at a time by any frontend (the winit window, tests, replays etc). Without
+// the std feature it's no_std, needing nothing but an allocator, so it runs on microcontrollers.
+extern crate alloc;
+
+pub mod asm;
+pub mod cdp1802;
+pub mod disasm;
+pub mod emulator;
+pub mod env;
+pub mod error;
+pub mod expr;
+pub mod flow;
+pub mod memory;
+pub mod octo;
+pub mod quirks;
+pub mod reference;
+pub mod render;
+pub mod replay;
+pub mod savestate;
+pub mod sprites;
+#[cfg(feature = "std")]
+pub mod difftest;
+pub mod hash;
+pub mod history;
+pub mod hooks;
+#[cfg(feature = "jit")]
+pub mod jit;
+pub mod variant;
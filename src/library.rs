@@ -0,0 +1,297 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, TryRecvError};
+use std::sync::Once;
+use std::thread;
+
+use rust_chip8::emulator::{Emulator, KeyState};
+
+use crate::actions::{fuzzyScore, ROM_EXTENSIONS};
+use crate::bundle;
+use crate::cli::{self, Options};
+use crate::commands::batch::romFiles;
+use crate::config::{self, Config};
+use crate::overlay::{self, Overlay, Tile};
+use crate::paths;
+use crate::saves;
+
+// paths::library keeps what the library knows about each ROM, see Stats, in the format of the config
+// file with a [rom.<name>] section per ROM:
+//
+//   [rom.pong]
+//   launches = 12
+//   play_seconds = 754
+//   best_score = 9
+//   notes = "beat the CPU once, keep the paddle high"
+
+// THUMBNAIL_SECONDS is how long each ROM runs for its thumbnail, long enough for most to have
+// drawn their title screen.
+const THUMBNAIL_SECONDS: u32 = 1;
+
+// the lines above the grid: the search and two of the selected ROM.
+const LINES: usize = 3;
+
+// SCANNING is shown over the tab while the library is scanned.
+const SCANNING: &str = "SCANNING THE LIBRARY";
+
+// SCAN_THREAD is the name of the thread of a Scan, whose panics quietPanics leaves out.
+const SCAN_THREAD: &str = "library scan";
+
+// Library is the browser of the ROMs in --rom-dir, on F4 or with --library: a grid of thumbnails of
+// their title screens, which is searched by typing like the command palette, and the stats of the
+// selected ROM. Enter runs the selected ROM in place of the current one, ctrl+Enter in a tab
+// of its own, either with the settings the config file has for it.
+pub struct Library {
+    roms: Vec<Rom>,
+    query: String,
+    matches: Vec<usize>, // indices of the ROMs matching the query, best first
+    selected: usize,     // index in matches
+    columns: usize,
+    rows: usize,
+}
+
+struct Rom {
+    path: PathBuf,
+    name: String,
+    thumbnail: Vec<u8>, // see overlay::shrink
+    stats: Stats,
+}
+
+// Stats are what the library keeps of a ROM: how often it was started and how long it's been
+// played in the window, not counting the time paused or in attract mode demos, the best score of
+// the --score hook or of --best-score, and the notes of --note.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub launches: u64,
+    pub playSeconds: u64,
+    pub bestScore: Option<i64>,
+    pub notes: String,
+}
+
+impl Stats {
+    // of returns the stats of a ROM in the library file, all 0 for a ROM it doesn't have.
+    fn of(library: &Config, romName: &str) -> Stats {
+        let section = format!("rom.{}", romName);
+        let number = |key: &str| library.value(&section, key).and_then(|v| v.parse::<u64>().ok()).unwrap_or(0);
+        Stats {
+            launches: number("launches"),
+            playSeconds: number("play_seconds"),
+            bestScore: library.value(&section, "best_score").and_then(|v| v.parse().ok()),
+            notes: library.value(&section, "notes").unwrap_or_default().to_string(),
+        }
+    }
+
+    // entries returns the keys of the stats, with the " of the notes made ' so they stay a string.
+    fn entries(&self) -> Vec<(&'static str, String)> {
+        let mut entries = vec![("launches", self.launches.to_string()), ("play_seconds", self.playSeconds.to_string())];
+        entries.extend(self.bestScore.map(|score| ("best_score", score.to_string())));
+        if !self.notes.is_empty() {
+            entries.push(("notes", self.notes.replace('"', "'")));
+        }
+        entries
+    }
+}
+
+impl Library {
+    // scan lists the ROMs in the ROM directory and its subdirectories and runs each of them without
+    // any keys pressed for its thumbnail, to show in a frame width x height pixels.
+    fn scan(options: &Options, width: usize, height: usize) -> Result<Library, String> {
+        let dir = Path::new(&options.romDir);
+        let paths: Vec<PathBuf> = romFiles(dir).map_err(|e| format!("Unable to list {}: {}", dir.display(), e))?
+            .into_iter()
+            .filter(|path| path.extension().is_some_and(|e| ROM_EXTENSIONS.iter().any(|r| e.eq_ignore_ascii_case(r))))
+            .collect();
+        if paths.is_empty() {
+            return Err(format!("There are no ROMs in {} for the library", dir.display()));
+        }
+
+        let roms = paths.into_iter().map(|path| {
+            let name = saves::romName(&path.to_string_lossy()).to_string();
+            let thumbnail = thumbnail(&path, options);
+            Rom { path, name, thumbnail, stats: Stats::default() }
+        }).collect();
+
+        let (columns, rows) = overlay::grid(width, height, LINES);
+        let mut library = Library { roms, query: String::new(), matches: Vec::new(), selected: 0, columns: columns.max(1), rows: rows.max(1) };
+        library.refresh();
+        library.search();
+        Ok(library)
+    }
+
+    // refresh reads the stats again, which have changed since the ROMs were scanned.
+    pub fn refresh(&mut self) {
+        let library = config::load(&paths::library()).unwrap_or_default();
+        for rom in self.roms.iter_mut() {
+            rom.stats = Stats::of(&library, &rom.name);
+        }
+    }
+
+    pub fn type_char(&mut self, c: char) {
+        self.query.push(c);
+        self.search();
+    }
+
+    pub fn backspace(&mut self) {
+        self.query.pop();
+        self.search();
+    }
+
+    // left, right, up and down move the selection through the grid, stopping at its ends.
+    pub fn left(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    pub fn right(&mut self) {
+        self.selected = (self.selected + 1).min(self.matches.len().saturating_sub(1));
+    }
+
+    pub fn up(&mut self) {
+        self.selected = self.selected.checked_sub(self.columns).unwrap_or(self.selected);
+    }
+
+    pub fn down(&mut self) {
+        if self.selected + self.columns < self.matches.len() {
+            self.selected += self.columns;
+        }
+    }
+
+    pub fn selected(&self) -> Option<&Path> {
+        self.matches.get(self.selected).map(|i| self.roms[*i].path.as_path())
+    }
+
+    // overlay shows the search, the name, play time, launches, best score and notes of the
+    // selected ROM and the page of the grid with the selected ROM on it.
+    pub fn overlay(&self) -> Overlay {
+        let search = match self.query.is_empty() {
+            true => format!("LIBRARY {} ROMS", self.roms.len()),
+            false => format!("FIND {}_", self.query),
+        };
+        let (selected, notes) = match self.matches.get(self.selected).map(|i| &self.roms[*i]) {
+            Some(rom) => {
+                let stats = &rom.stats;
+                let notes = match stats.bestScore {
+                    Some(score) => format!("BEST {} {}", score, stats.notes),
+                    None => stats.notes.clone(),
+                };
+                (format!("{} {} {}X", rom.name, playTime(stats.playSeconds), stats.launches), notes)
+            }
+            None => ("NO MATCHES".to_string(), String::new()),
+        };
+        let first = (self.selected / self.columns).saturating_sub(self.rows - 1) * self.columns;
+        let tiles = self.matches.iter().enumerate().skip(first).take(self.columns * self.rows).map(|(n, i)| Tile {
+            thumbnail: self.roms[*i].thumbnail.clone(),
+            name: self.roms[*i].name.clone(),
+            selected: n == self.selected,
+        }).collect();
+        Overlay { lines: vec![search, selected, notes], debug: false, heatmap: false, card: false, tiles }
+    }
+
+    fn search(&mut self) {
+        let mut scored: Vec<(usize, usize)> = self.roms.iter().enumerate()
+            .filter_map(|(i, rom)| fuzzyScore(&self.query, &rom.name).map(|score| (score, i)))
+            .collect();
+        scored.sort_by_key(|(score, _)| *score);
+        self.matches = scored.into_iter().map(|(_, i)| i).collect();
+        self.selected = 0;
+    }
+}
+
+// Scan is a Library::scan on a thread of its own, so that the window goes on while every ROM runs
+// for its thumbnail, which takes a while for a large library.
+pub struct Scan {
+    result: mpsc::Receiver<Result<Library, String>>,
+}
+
+impl Scan {
+    // start starts scanning the library for a frame width x height pixels, calling done on the
+    // thread once it's scanned.
+    pub fn start(options: &Options, width: usize, height: usize, done: impl FnOnce() + Send + 'static) -> Scan {
+        quietPanics();
+        let (sender, result) = mpsc::channel();
+        let options = options.clone();
+        // if the thread doesn't start, the sender is dropped with it, which done reports.
+        let _ = thread::Builder::new().name(SCAN_THREAD.to_string()).spawn(move || {
+            let _ = sender.send(Library::scan(&options, width, height));
+            done();
+        });
+        Scan { result }
+    }
+
+    // done returns the library once it's scanned, or why it couldn't be.
+    pub fn done(&self) -> Option<Result<Library, String>> {
+        match self.result.try_recv() {
+            Ok(result) => Some(result),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Err("Unable to scan the library, the scan stopped".to_string())),
+        }
+    }
+
+    // overlay says that the library is being scanned.
+    pub fn overlay(&self) -> Overlay {
+        Overlay { lines: vec![SCANNING.to_string()], debug: false, heatmap: false, card: false, tiles: Vec::new() }
+    }
+
+    // shown returns whether an overlay is the one saying so.
+    pub fn shown(&self, overlay: &Overlay) -> bool {
+        overlay.tiles.is_empty() && overlay.lines == [SCANNING]
+    }
+}
+
+// quietPanics sets a panic hook, once, that leaves out the panics of scan threads: a ROM that
+// crashes the emulator gets a blank thumbnail, and the default hook would only print the panic.
+// The panics of the other threads, those of the tabs too, go to the hook there was.
+fn quietPanics() {
+    static QUIET: Once = Once::new();
+    QUIET.call_once(|| {
+        let hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if thread::current().name() != Some(SCAN_THREAD) {
+                hook(info);
+            }
+        }));
+    });
+}
+
+// thumbnail runs a ROM for THUMBNAIL_SECONDS with the settings the config file has for it and
+// returns what it drew, scaled down. A ROM that doesn't run gets what it drew until then.
+fn thumbnail(path: &Path, options: &Options) -> Vec<u8> {
+    let romOptions = cli::forRom(options, &path.to_string_lossy()).ok();
+    let options = romOptions.as_ref().unwrap_or(options);
+    let mut emul = Emulator::new();
+    if bundle::readRom(&path.to_string_lossy()).ok().is_some_and(|data| emul.load_rom(&data).is_ok()) {
+        emul.quirks = options.quirks;
+        emul.variant = options.variant;
+        emul.instructionsPerFrame = options.instructionsPerFrame;
+        emul.machineCode = options.machineCode;
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| {
+            for _ in 0..THUMBNAIL_SECONDS * options.timerHz {
+                if emul.tick_frame(KeyState::default()).is_err() {
+                    break;
+                }
+            }
+        }));
+    }
+    overlay::shrink(emul.framebuffer())
+}
+
+// update changes the stats of a ROM in paths::library, writing them back when they changed.
+pub fn update(romPath: &str, change: impl FnOnce(&mut Stats)) -> Result<(), String> {
+    let name = saves::romName(romPath);
+    let mut stats = Stats::of(&config::load(&paths::library())?, name);
+    let before = stats.clone();
+    change(&mut stats);
+    if stats == before {
+        return Ok(());
+    }
+    config::setKeys(&paths::library(), &format!("rom.{}", name), &stats.entries())
+}
+
+// playTime formats a play time for the overlay, like 1H05M.
+fn playTime(seconds: u64) -> String {
+    match seconds {
+        0 => "NOT PLAYED".to_string(),
+        1..=59 => "<1M".to_string(),
+        60..=3599 => format!("{}M", seconds / 60),
+        _ => format!("{}H{:02}M", seconds / 3600, seconds / 60 % 60),
+    }
+}
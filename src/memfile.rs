@@ -0,0 +1,21 @@
+use std::fs;
+use std::ops::Range;
+
+// load reads a binary file into memory at an address, e.g. to patch a ROM in place or to set up a
+// test scenario, and returns how many bytes it took. The file must fit in memory from there on,
+// it doesn't wrap around.
+pub fn load(memory: &mut [u8], path: &str, address: usize) -> Result<usize, String> {
+    let data = fs::read(path).map_err(|e| format!("Unable to read {}: {}", path, e))?;
+    let end = address + data.len();
+    if end > memory.len() {
+        return Err(format!("{} is {} bytes, which don't fit in memory at {:#05X}", path, data.len(), address));
+    }
+    memory[address..end].copy_from_slice(&data);
+    Ok(data.len())
+}
+
+// dump writes a range of memory to a binary file, as is.
+pub fn dump(memory: &[u8], path: &str, range: Range<usize>) -> Result<(), String> {
+    let bytes = memory.get(range.clone()).ok_or(format!("{:#05X}..{:#05X} is outside of memory", range.start, range.end))?;
+    fs::write(path, bytes).map_err(|e| format!("Unable to write {}: {}", path, e))
+}
@@ -0,0 +1,203 @@
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+// MEMORY_SIZE is the 4kb of RAM of the original CHIP8 and SCHIP.
+pub const MEMORY_SIZE: usize = 4096;
+
+// Access is a single memory access seen by a watchpoint or the access log.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Access {
+    pub address: usize,
+    pub value: u8, // the byte read, or the byte written
+    pub write: bool,
+}
+
+// HEAT_DECAY is how much the heat of a byte cools down each frame, from 255 when it's accessed,
+// which makes it fade out over a second at 60 frames a second.
+pub const HEAT_DECAY: u8 = 4;
+
+// Heatmap is how recently each byte of memory was read, written and run as an instruction, from
+// 255 right when it was down to 0 once it's been a while, for seeing where a ROM keeps its
+// variables, sprites and code.
+#[derive(Clone)]
+pub struct Heatmap {
+    pub reads: Vec<u8>,
+    pub writes: Vec<u8>,
+    pub executes: Vec<u8>,
+}
+
+impl Heatmap {
+    fn new(size: usize) -> Heatmap {
+        Heatmap { reads: vec![0; size], writes: vec![0; size], executes: vec![0; size] }
+    }
+
+    // decay cools every byte down by HEAT_DECAY.
+    pub fn decay(&mut self) {
+        for heat in self.reads.iter_mut().chain(self.writes.iter_mut()).chain(self.executes.iter_mut()) {
+            *heat = heat.saturating_sub(HEAT_DECAY);
+        }
+    }
+}
+
+// COVERED_EXECUTED, COVERED_READ and COVERED_WRITTEN are the flags of a byte of the coverage,
+// whether it was ever run as an instruction, read or written since the coverage started.
+pub const COVERED_EXECUTED: u8 = 1;
+pub const COVERED_READ: u8 = 2;
+pub const COVERED_WRITTEN: u8 = 4;
+
+// Memory is the emulator's RAM. Instructions go through read_u8, write_u8 and read_u16, which wrap
+// addresses around at the end of memory, like I does, rather than indexing out of bounds. They also
+// record the accesses to watched ranges, or all of them while logging, for debuggers and tools to
+// pick up with take_accesses, and heat up the bytes of the heatmap while there's one. Instructions
+// are fetched with fetch_u16, which counts as running rather than reading them. bytes and
+// bytes_mut give direct access for loading, saving and hashing, without any of that. The size
+// isn't fixed, so that XO-CHIP's 64kb fit as well.
+#[derive(Clone)]
+pub struct Memory {
+    bytes: Vec<u8>,
+    watchpoints: Vec<Range<usize>>,
+    logging: bool,
+    accesses: Vec<Access>,
+    heat: Option<Heatmap>,
+    coverage: Option<Vec<u8>>, // the COVERED_ flags of every byte
+}
+
+impl Memory {
+    pub fn new(size: usize) -> Memory {
+        Memory {
+            bytes: vec![0; size],
+            watchpoints: Vec::new(),
+            logging: false,
+            accesses: Vec::new(),
+            heat: None,
+            coverage: None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn bytes_mut(&mut self) -> &mut [u8] {
+        &mut self.bytes
+    }
+
+    // clear zeroes all of memory, leaving watchpoints and logging as they are.
+    pub fn clear(&mut self) {
+        self.bytes.iter_mut().for_each(|b| *b = 0);
+    }
+
+    pub fn read_u8(&mut self, address: u16) -> u8 {
+        let address = address as usize % self.bytes.len();
+        let value = self.bytes[address];
+        self.record(address, value, false);
+        if let Some(heat) = self.heat.as_mut() {
+            heat.reads[address] = u8::MAX;
+        }
+        self.cover(address, COVERED_READ);
+        value
+    }
+
+    pub fn write_u8(&mut self, address: u16, value: u8) {
+        let address = address as usize % self.bytes.len();
+        self.bytes[address] = value;
+        self.record(address, value, true);
+        if let Some(heat) = self.heat.as_mut() {
+            heat.writes[address] = u8::MAX;
+        }
+        self.cover(address, COVERED_WRITTEN);
+    }
+
+    // read_u16 reads a big-endian word, such as a sprite row of SCHIP's 16x16 sprites.
+    pub fn read_u16(&mut self, address: u16) -> u16 {
+        u16::from_be_bytes([self.read_u8(address), self.read_u8(address.wrapping_add(1))])
+    }
+
+    // fetch_u16 reads the instruction at an address, which the heatmap counts as running the two
+    // bytes and watchpoints and the access log as reading them.
+    pub fn fetch_u16(&mut self, address: u16) -> u16 {
+        let mut word = [0; 2];
+        for (i, byte) in word.iter_mut().enumerate() {
+            let address = address.wrapping_add(i as u16) as usize % self.bytes.len();
+            *byte = self.bytes[address];
+            self.record(address, *byte, false);
+            if let Some(heat) = self.heat.as_mut() {
+                heat.executes[address] = u8::MAX;
+            }
+            self.cover(address, COVERED_EXECUTED);
+        }
+        u16::from_be_bytes(word)
+    }
+
+    // watch records every access to a range of addresses from now on.
+    pub fn watch(&mut self, range: Range<usize>) {
+        self.watchpoints.push(range);
+    }
+
+    pub fn clear_watchpoints(&mut self) {
+        self.watchpoints.clear();
+    }
+
+    // set_logging switches recording every single access on or off.
+    pub fn set_logging(&mut self, on: bool) {
+        self.logging = on;
+    }
+
+    // set_heatmap starts a heatmap, all cold, or drops it. Keeping one costs a little on every
+    // access and frame, so it's only there while shown.
+    pub fn set_heatmap(&mut self, on: bool) {
+        if on != self.heat.is_some() {
+            self.heat = on.then(|| Heatmap::new(self.bytes.len()));
+        }
+    }
+
+    pub fn heatmap(&self) -> Option<&Heatmap> {
+        self.heat.as_ref()
+    }
+
+    // set_coverage starts keeping which bytes were ever run, read or written, all untouched, or
+    // stops it. Like the heatmap it costs a little on every access.
+    pub fn set_coverage(&mut self, on: bool) {
+        if on != self.coverage.is_some() {
+            self.coverage = on.then(|| vec![0; self.bytes.len()]);
+        }
+    }
+
+    // coverage returns the COVERED_ flags of every byte, if coverage is kept.
+    pub fn coverage(&self) -> Option<&[u8]> {
+        self.coverage.as_deref()
+    }
+
+    fn cover(&mut self, address: usize, flag: u8) {
+        if let Some(coverage) = self.coverage.as_mut() {
+            coverage[address] |= flag;
+        }
+    }
+
+    // decay_heat cools the heatmap down, if there is one, once a frame.
+    pub fn decay_heat(&mut self) {
+        if let Some(heat) = self.heat.as_mut() {
+            heat.decay();
+        }
+    }
+
+    // take_accesses returns the accesses recorded since the last call.
+    pub fn take_accesses(&mut self) -> Vec<Access> {
+        core::mem::take(&mut self.accesses)
+    }
+
+    fn record(&mut self, address: usize, value: u8, write: bool) {
+        if self.logging || self.watchpoints.iter().any(|r| r.contains(&address)) {
+            self.accesses.push(Access { address, value, write });
+        }
+    }
+}
@@ -0,0 +1,871 @@
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::sprites::Tile;
+
+// An assembler for Octo, the CHIP8 assembly language of John Earnest's Octo IDE, which is what Octo
+// cartridges carry rather than a compiled ROM. It knows the language of the Octo manual:
+//
+//   : main             labels, and :next for one at the second byte of the next instruction
+//     v0 := 5          the register instructions, :=, +=, -=, =-, |=, &=, ^=, >>= and <<=
+//     i := sprite      i := <addr>|hex vx|bighex vx|long <addr> and i += vx
+//     sprite v0 v1 5
+//     loop
+//       v0 += -1
+//       if v0 == 0 then return
+//       if v0 > 3 begin ... else ... end
+//       while v1 != 0
+//     again
+//   : sprite 0x3C 0x42 0x42 0x3C
+//
+// with the SCHIP and XO-CHIP instructions, :const, :alias, :unpack, :org, :byte, :pointer, :call,
+// :macro, :calc, :stringmode and :assert. Like in Octo, :calc and { } expressions are evaluated
+// from right to left, so 2 * 3 + 1 is 8, but only with what integer arithmetic does: + - * / %
+// & | ^ << >> min max and the comparisons, with unary - ~ ! and @ for a byte already assembled.
+// :breakpoint, :monitor and :proto are skipped.
+//
+// declare goes the other way, writing tiles of the display as Octo sprite data.
+
+// START is where Octo programs, and ROMs, are loaded.
+const START: usize = 0x200;
+
+// MAX_EXPANDED is how many tokens macros and string modes may expand to in all, far more than a
+// program filling the 64K needs.
+const MAX_EXPANDED: usize = 1 << 20;
+
+#[derive(Clone, Debug)]
+struct Token {
+    text: String,
+    line: usize,
+}
+
+// Fixup is how a label that isn't defined yet goes into the ROM once it is.
+#[derive(Clone, Copy, Debug)]
+enum Fixup {
+    Addr12, // the NNN of the instruction at the address
+    Addr16, // the two bytes at the address
+    Low8,   // the byte at the address
+    High8,  // or'ed into the byte at the address
+}
+
+// Value is an operand: a number, or a label to come.
+enum Value {
+    Known(i64),
+    Label(String),
+}
+
+// Condition is the comparison of an if or while: a register, the comparison and a register
+// (Err) or a number (Ok).
+struct Condition {
+    x: u16,
+    op: String,
+    operand: Result<u16, u16>,
+}
+
+// Block is an open if ... begin or loop.
+enum Block {
+    Branch(usize),             // the jump over the block, to patch
+    Loop(usize, Vec<usize>),   // where the loop starts and the jumps out of its whiles
+}
+
+struct Assembler {
+    tokens: VecDeque<Token>,
+    memory: Vec<u8>,
+    here: usize,
+    end: usize, // the first address after everything assembled
+    names: BTreeMap<String, i64>, // labels, constants and :calc results
+    aliases: BTreeMap<String, u8>,
+    macros: BTreeMap<String, (Vec<String>, Vec<Token>)>,
+    stringModes: BTreeMap<String, Vec<(char, Vec<Token>)>>,
+    fixups: BTreeMap<String, Vec<(usize, Fixup, usize)>>, // where labels still need to go, with the line
+    blocks: Vec<Block>,
+    expanded: usize, // tokens expanded so far, see expand
+    line: usize, // of the last token taken, for errors
+}
+
+// assemble translates an Octo program into a ROM to load at 0x200, or returns the first error with
+// its line.
+pub fn assemble(source: &str) -> Result<Vec<u8>, String> {
+    let mut assembler = Assembler {
+        tokens: tokenize(source),
+        memory: vec![0; 0x10000],
+        here: START,
+        end: START,
+        names: BTreeMap::new(),
+        aliases: BTreeMap::new(),
+        macros: BTreeMap::new(),
+        stringModes: BTreeMap::new(),
+        fixups: BTreeMap::new(),
+        blocks: Vec::new(),
+        expanded: 0,
+        line: 1,
+    };
+    while !assembler.tokens.is_empty() {
+        assembler.statement().map_err(|e| format!("line {}: {}", assembler.line, e))?;
+    }
+    if !assembler.blocks.is_empty() {
+        return Err("the program ends inside an if ... begin or a loop".to_string());
+    }
+    if let Some((name, uses)) = assembler.fixups.iter().next() {
+        return Err(format!("line {}: undefined name '{}'", uses[0].2, name));
+    }
+    Ok(assembler.memory[START..assembler.end.max(START)].to_vec())
+}
+
+// tokenize splits a program into words, dropping # comments. A "string" is a single token.
+fn tokenize(source: &str) -> VecDeque<Token> {
+    let mut tokens = VecDeque::new();
+    for (n, line) in source.lines().enumerate() {
+        let mut chars = line.char_indices().peekable();
+        while let Some((start, c)) = chars.next() {
+            if c.is_whitespace() {
+                continue;
+            }
+            if c == '#' {
+                break;
+            }
+            let mut end = start + c.len_utf8();
+            if c == '"' {
+                for (i, c) in chars.by_ref() {
+                    end = i + c.len_utf8();
+                    if c == '"' {
+                        break;
+                    }
+                }
+            } else {
+                while let Some((i, c)) = chars.peek().copied().filter(|(_, c)| !c.is_whitespace()) {
+                    end = i + c.len_utf8();
+                    chars.next();
+                }
+            }
+            tokens.push_back(Token { text: line[start..end].to_string(), line: n + 1 });
+        }
+    }
+    tokens
+}
+
+// number parses a decimal, 0x hex or 0b binary number, optionally negative.
+fn number(text: &str) -> Option<i64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+        i64::from_str_radix(hex, 16).ok()
+    } else if let Some(binary) = digits.strip_prefix("0b") {
+        i64::from_str_radix(binary, 2).ok()
+    } else if digits.starts_with(|c: char| c.is_ascii_digit()) {
+        digits.parse::<i64>().ok()
+    } else {
+        None
+    }?;
+    Some(if negative { -value } else { value })
+}
+
+impl Assembler {
+    fn next(&mut self) -> Result<String, String> {
+        let token = self.tokens.pop_front().ok_or("unexpected end of the program")?;
+        self.line = token.line;
+        Ok(token.text)
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.front().map(|t| t.text.as_str())
+    }
+
+    fn expect(&mut self, text: &str) -> Result<(), String> {
+        match self.next()? {
+            t if t == text => Ok(()),
+            t => Err(format!("expected '{}', got '{}'", text, t)),
+        }
+    }
+
+    fn emit(&mut self, byte: u8) -> Result<(), String> {
+        if self.here >= self.memory.len() {
+            return Err("the program doesn't fit in 64K".to_string());
+        }
+        self.memory[self.here] = byte;
+        self.here += 1;
+        self.end = self.end.max(self.here);
+        Ok(())
+    }
+
+    fn emit16(&mut self, word: u16) -> Result<(), String> {
+        self.emit((word >> 8) as u8)?;
+        self.emit(word as u8)
+    }
+
+    // register parses v0-vf or an :alias.
+    fn register(&mut self) -> Result<u8, String> {
+        let text = self.next()?;
+        self.as_register(&text).ok_or_else(|| format!("expected a register, got '{}'", text))
+    }
+
+    fn as_register(&self, text: &str) -> Option<u8> {
+        if let Some(register) = self.aliases.get(text) {
+            return Some(*register);
+        }
+        match text.strip_prefix(['v', 'V']) {
+            Some(digit) if digit.len() == 1 => u8::from_str_radix(digit, 16).ok(),
+            _ => None,
+        }
+    }
+
+    // value parses a number, a name or a { } expression, which may be a label defined further on.
+    fn value(&mut self) -> Result<Value, String> {
+        let text = self.next()?;
+        if text == "{" {
+            return Ok(Value::Known(self.calc()?));
+        }
+        if let Some(n) = number(&text).or_else(|| self.names.get(&text).copied()) {
+            return Ok(Value::Known(n));
+        }
+        if self.as_register(&text).is_some() || text.starts_with(':') {
+            return Err(format!("expected a number or a name, got '{}'", text));
+        }
+        Ok(Value::Label(text))
+    }
+
+    fn known(&mut self) -> Result<i64, String> {
+        match self.value()? {
+            Value::Known(n) => Ok(n),
+            Value::Label(name) => Err(format!("undefined name '{}'", name)),
+        }
+    }
+
+    fn byte(&mut self) -> Result<u8, String> {
+        match self.known()? {
+            n if (-128..=255).contains(&n) => Ok(n as u8),
+            n => Err(format!("{} doesn't fit in a byte", n)),
+        }
+    }
+
+    fn nibble(&mut self) -> Result<u16, String> {
+        match self.known()? {
+            n if (0..=15).contains(&n) => Ok(n as u16),
+            n => Err(format!("{} doesn't fit in a nibble", n)),
+        }
+    }
+
+    // address emits an instruction with an address operand of the given bits, patched in later
+    // for a label that's yet to come.
+    fn address(&mut self, op: u16, fixup: Fixup) -> Result<(), String> {
+        let at = self.here;
+        let value = self.value()?;
+        let known = match value {
+            Value::Known(n) => n,
+            Value::Label(name) => {
+                self.fixups.entry(name).or_default().push((at, fixup, self.line));
+                0
+            }
+        };
+        match fixup {
+            Fixup::Addr12 if !(0..=0xFFF).contains(&known) => Err(format!("address {:#X} doesn't fit in 12 bits", known)),
+            Fixup::Addr12 => self.emit16(op | known as u16),
+            _ if !(0..=0xFFFF).contains(&known) => Err(format!("address {:#X} doesn't fit in 16 bits", known)),
+            _ => self.emit16(known as u16),
+        }
+    }
+
+    // define sets a label or constant and patches where it was used before.
+    fn define(&mut self, name: String, value: i64, label: bool) -> Result<(), String> {
+        if label && self.names.contains_key(&name) {
+            return Err(format!("the name '{}' is already defined", name));
+        }
+        if self.as_register(&name).is_some() || number(&name).is_some() {
+            return Err(format!("'{}' can't be a name", name));
+        }
+        for (at, fixup, line) in self.fixups.remove(&name).unwrap_or_default() {
+            match fixup {
+                Fixup::Addr12 if value > 0xFFF => return Err(format!("line {}: {} is at {:#X}, out of reach of 12 bits", line, name, value)),
+                Fixup::Addr12 => {
+                    self.memory[at] |= (value >> 8) as u8 & 0xF;
+                    self.memory[at + 1] = value as u8;
+                }
+                Fixup::Addr16 => {
+                    self.memory[at] = (value >> 8) as u8;
+                    self.memory[at + 1] = value as u8;
+                }
+                Fixup::Low8 => self.memory[at] = value as u8,
+                Fixup::High8 => self.memory[at] |= (value >> 8) as u8,
+            }
+        }
+        self.names.insert(name, value);
+        Ok(())
+    }
+
+    // block reads the tokens up to the } matching the { just read.
+    fn block(&mut self) -> Result<Vec<Token>, String> {
+        let mut depth = 0;
+        let mut body = Vec::new();
+        loop {
+            let token = self.tokens.pop_front().ok_or("unexpected end of the program, expected '}'")?;
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" if depth == 0 => return Ok(body),
+                "}" => depth -= 1,
+                _ => {}
+            }
+            body.push(token);
+        }
+    }
+
+    // calc evaluates the expression up to the } matching the { just read.
+    fn calc(&mut self) -> Result<i64, String> {
+        let tokens = self.block()?;
+        let mut expression: VecDeque<String> = tokens.into_iter().map(|t| t.text).collect();
+        let value = self.expression(&mut expression)?;
+        match expression.front() {
+            Some(t) => Err(format!("unexpected '{}' in an expression", t)),
+            None => Ok(value),
+        }
+    }
+
+    // expression evaluates a term and, right to left, whatever operator and expression follow it.
+    fn expression(&self, tokens: &mut VecDeque<String>) -> Result<i64, String> {
+        let left = self.term(tokens)?;
+        let op = match tokens.front().map(String::as_str) {
+            Some(op) if op != ")" => tokens.pop_front().unwrap(),
+            _ => return Ok(left),
+        };
+        let right = self.expression(tokens)?;
+        let truth = |b: bool| b as i64;
+        Ok(match op.as_str() {
+            "+" => left.wrapping_add(right),
+            "-" => left.wrapping_sub(right),
+            "*" => left.wrapping_mul(right),
+            "/" | "%" if right == 0 => return Err("division by zero".to_string()),
+            "/" => left.checked_div(right).ok_or("the division overflows")?,
+            "%" => left.checked_rem(right).ok_or("the division overflows")?,
+            "&" => left & right,
+            "|" => left | right,
+            "^" => left ^ right,
+            "<<" => left.wrapping_shl(right as u32),
+            ">>" => left.wrapping_shr(right as u32),
+            "min" => left.min(right),
+            "max" => left.max(right),
+            "<" => truth(left < right),
+            ">" => truth(left > right),
+            "<=" => truth(left <= right),
+            ">=" => truth(left >= right),
+            "==" => truth(left == right),
+            "!=" => truth(left != right),
+            _ => return Err(format!("unknown operator '{}'", op)),
+        })
+    }
+
+    fn term(&self, tokens: &mut VecDeque<String>) -> Result<i64, String> {
+        let token = tokens.pop_front().ok_or("the expression ends too soon")?;
+        match token.as_str() {
+            "(" => {
+                let value = self.expression(tokens)?;
+                match tokens.pop_front().as_deref() {
+                    Some(")") => Ok(value),
+                    _ => Err("expected ')'".to_string()),
+                }
+            }
+            "-" => Ok(self.term(tokens)?.wrapping_neg()),
+            "~" => Ok(!self.term(tokens)?),
+            "!" => Ok((self.term(tokens)? == 0) as i64),
+            "@" => {
+                let address = self.term(tokens)?;
+                self.memory.get(address as usize).map(|b| *b as i64).ok_or_else(|| format!("no byte at {:#X}", address))
+            }
+            "HERE" => Ok(self.here as i64),
+            _ => number(&token).or_else(|| self.names.get(&token).copied()).ok_or_else(|| format!("undefined name '{}'", token)),
+        }
+    }
+
+    // condition parses the comparison of an if or while.
+    fn condition(&mut self) -> Result<Condition, String> {
+        let x = self.register()? as u16;
+        let op = self.next()?;
+        if op == "key" || op == "-key" {
+            return Ok(Condition { x, op, operand: Ok(0) });
+        }
+        let operand = match self.peek().and_then(|t| self.as_register(t)) {
+            Some(y) => {
+                self.next()?;
+                Err(y as u16)
+            }
+            None => Ok(self.byte()? as u16),
+        };
+        Ok(Condition { x, op, operand })
+    }
+
+    // emit_skip emits the instructions that skip the next one unless a condition holds, or unless
+    // it doesn't when negated.
+    fn emit_skip(&mut self, condition: &Condition, negated: bool) -> Result<(), String> {
+        let Condition { x, operand, .. } = *condition;
+        let mut op = condition.op.as_str();
+        if negated {
+            op = match op {
+                "==" => "!=", "!=" => "==", "<" => ">=", ">=" => "<", ">" => "<=", "<=" => ">",
+                "key" => "-key", "-key" => "key",
+                _ => op,
+            };
+        }
+        // the orderings subtract in vf, whose flag is then 1 without a borrow: vf := x, vf -= y for
+        // x >= y and x < y, vf := y, vf -= x for x > y and x <= y.
+        let xMinusY = match operand {
+            Ok(n) => [0x6F00 | n, 0x8F07 | x << 4],
+            Err(y) => [0x8F00 | x << 4, 0x8F05 | y << 4],
+        };
+        let yMinusX = match operand {
+            Ok(n) => [0x6F00 | n, 0x8F05 | x << 4],
+            Err(y) => [0x8F00 | y << 4, 0x8F05 | x << 4],
+        };
+        let ops = match (op, operand) {
+            ("key", _) => vec![0xE0A1 | x << 8],
+            ("-key", _) => vec![0xE09E | x << 8],
+            ("==", Ok(n)) => vec![0x4000 | x << 8 | n],
+            ("==", Err(y)) => vec![0x9000 | x << 8 | y << 4],
+            ("!=", Ok(n)) => vec![0x3000 | x << 8 | n],
+            ("!=", Err(y)) => vec![0x5000 | x << 8 | y << 4],
+            (">=" | "<", _) => vec![xMinusY[0], xMinusY[1], if op == ">=" { 0x4F01 } else { 0x4F00 }],
+            (">" | "<=", _) => vec![yMinusX[0], yMinusX[1], if op == "<=" { 0x4F01 } else { 0x4F00 }],
+            _ => return Err(format!("expected a comparison, got '{}'", op)),
+        };
+        ops.into_iter().try_for_each(|op| self.emit16(op))
+    }
+
+    // jump_placeholder emits a jump to be patched once its target is known, returning its address.
+    fn jump_placeholder(&mut self) -> Result<usize, String> {
+        let at = self.here;
+        self.emit16(0x1000)?;
+        Ok(at)
+    }
+
+    fn patch_jump(&mut self, at: usize) -> Result<(), String> {
+        if self.here > 0xFFF {
+            return Err(format!("the end of the block at {:#X} can't be jumped to", self.here));
+        }
+        self.memory[at] = 0x10 | (self.here >> 8) as u8;
+        self.memory[at + 1] = self.here as u8;
+        Ok(())
+    }
+
+    fn statement(&mut self) -> Result<(), String> {
+        let token = self.next()?;
+        if let Some(x) = self.as_register(&token) {
+            return self.register_statement(x as u16);
+        }
+        match token.as_str() {
+            ":" => {
+                let name = self.next()?;
+                self.define(name, self.here as i64, true)
+            }
+            ":next" => {
+                let name = self.next()?;
+                self.define(name, self.here as i64 + 1, true)
+            }
+            ":const" => {
+                let name = self.next()?;
+                let value = self.known()?;
+                self.define(name, value, false)
+            }
+            ":calc" => {
+                let name = self.next()?;
+                self.expect("{")?;
+                let value = self.calc()?;
+                self.define(name, value, false)
+            }
+            ":alias" => {
+                let name = self.next()?;
+                let register = match self.peek() {
+                    Some("{") => {
+                        self.next()?;
+                        self.calc()?
+                    }
+                    _ => self.register()? as i64,
+                };
+                if !(0..16).contains(&register) {
+                    return Err(format!("there's no register {}", register));
+                }
+                self.aliases.insert(name, register as u8);
+                Ok(())
+            }
+            ":unpack" => {
+                let long = self.peek() == Some("long");
+                if long {
+                    self.next()?;
+                }
+                let nibble = if long { 0 } else { self.nibble()? as u8 };
+                // v0 := the nibble and the high bits of the address, v1 := the low byte
+                let (at, value) = (self.here, self.value()?);
+                self.emit16(0x6000 | (nibble as u16) << 4)?;
+                self.emit16(0x6100)?;
+                match value {
+                    Value::Known(n) => {
+                        self.memory[at + 1] |= (n >> 8) as u8;
+                        self.memory[at + 3] = n as u8;
+                    }
+                    Value::Label(name) => {
+                        let uses = self.fixups.entry(name).or_default();
+                        uses.push((at + 1, Fixup::High8, self.line));
+                        uses.push((at + 3, Fixup::Low8, self.line));
+                    }
+                }
+                Ok(())
+            }
+            ":org" => {
+                self.here = self.known()? as usize;
+                if !(START..0x10000).contains(&self.here) {
+                    return Err(format!(":org {:#X} is outside of memory after 0x200", self.here));
+                }
+                Ok(())
+            }
+            ":byte" => {
+                let at = self.here;
+                match self.value()? {
+                    Value::Known(n) => self.emit(n as u8),
+                    Value::Label(name) => {
+                        self.fixups.entry(name).or_default().push((at, Fixup::Low8, self.line));
+                        self.emit(0)
+                    }
+                }
+            }
+            ":pointer" => self.address(0, Fixup::Addr16),
+            ":call" => self.address(0x2000, Fixup::Addr12),
+            ":macro" => {
+                let name = self.next()?;
+                let mut args = Vec::new();
+                loop {
+                    match self.next()? {
+                        t if t == "{" => break,
+                        t => args.push(t),
+                    }
+                }
+                let body = self.block()?;
+                self.macros.insert(name, (args, body));
+                Ok(())
+            }
+            ":stringmode" => {
+                let name = self.next()?;
+                let alphabet = self.next()?;
+                let alphabet = alphabet.strip_prefix('"').and_then(|a| a.strip_suffix('"')).ok_or("expected the characters of the :stringmode as a \"string\"")?.to_string();
+                self.expect("{")?;
+                let body = self.block()?;
+                let modes = self.stringModes.entry(name).or_default();
+                modes.extend(alphabet.chars().map(|c| (c, body.clone())));
+                Ok(())
+            }
+            ":assert" => {
+                if self.peek().is_some_and(|t| t.starts_with('"')) {
+                    self.next()?;
+                }
+                self.expect("{")?;
+                match self.calc()? {
+                    0 => Err("assertion failed".to_string()),
+                    _ => Ok(()),
+                }
+            }
+            ":breakpoint" | ":proto" => self.next().map(|_| ()),
+            ":monitor" => {
+                self.next()?;
+                self.next().map(|_| ())
+            }
+            "return" | ";" => self.emit16(0x00EE),
+            "clear" => self.emit16(0x00E0),
+            "hires" => self.emit16(0x00FF),
+            "lores" => self.emit16(0x00FE),
+            "exit" => self.emit16(0x00FD),
+            "scroll-left" => self.emit16(0x00FC),
+            "scroll-right" => self.emit16(0x00FB),
+            "scroll-down" => {
+                let n = self.nibble()?;
+                self.emit16(0x00C0 | n)
+            }
+            "scroll-up" => {
+                let n = self.nibble()?;
+                self.emit16(0x00D0 | n)
+            }
+            "audio" => self.emit16(0xF002),
+            "plane" => {
+                let n = self.nibble()?;
+                self.emit16(0xF001 | n << 8)
+            }
+            "bcd" => {
+                let x = self.register()? as u16;
+                self.emit16(0xF033 | x << 8)
+            }
+            "save" | "load" => {
+                let x = self.register()? as u16;
+                if self.peek() == Some("-") {
+                    self.next()?;
+                    let y = self.register()? as u16;
+                    return self.emit16(if token == "save" { 0x5002 } else { 0x5003 } | x << 8 | y << 4);
+                }
+                self.emit16(if token == "save" { 0xF055 } else { 0xF065 } | x << 8)
+            }
+            "saveflags" => {
+                let x = self.register()? as u16;
+                self.emit16(0xF075 | x << 8)
+            }
+            "loadflags" => {
+                let x = self.register()? as u16;
+                self.emit16(0xF085 | x << 8)
+            }
+            "sprite" => {
+                let (x, y) = (self.register()? as u16, self.register()? as u16);
+                let n = self.nibble()?;
+                self.emit16(0xD000 | x << 8 | y << 4 | n)
+            }
+            "jump" => self.address(0x1000, Fixup::Addr12),
+            "jump0" => self.address(0xB000, Fixup::Addr12),
+            "native" => self.address(0x0000, Fixup::Addr12),
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let x = self.register()? as u16;
+                let op = match token.as_str() {
+                    "delay" => 0xF015,
+                    "buzzer" => 0xF018,
+                    _ => 0xF03A,
+                };
+                self.emit16(op | x << 8)
+            }
+            "i" => match self.next()?.as_str() {
+                ":=" => match self.peek() {
+                    Some("hex") => {
+                        self.next()?;
+                        let x = self.register()? as u16;
+                        self.emit16(0xF029 | x << 8)
+                    }
+                    Some("bighex") => {
+                        self.next()?;
+                        let x = self.register()? as u16;
+                        self.emit16(0xF030 | x << 8)
+                    }
+                    Some("long") => {
+                        self.next()?;
+                        self.emit16(0xF000)?;
+                        self.address(0, Fixup::Addr16)
+                    }
+                    _ => self.address(0xA000, Fixup::Addr12),
+                },
+                "+=" => {
+                    let x = self.register()? as u16;
+                    self.emit16(0xF01E | x << 8)
+                }
+                op => Err(format!("expected i := or i +=, got i {}", op)),
+            },
+            "if" => {
+                // if <condition> then <statement> skips the statement unless the condition holds,
+                // if <condition> begin jumps over the block when it doesn't.
+                let condition = self.condition()?;
+                match self.next()?.as_str() {
+                    "then" => self.emit_skip(&condition, false),
+                    "begin" => {
+                        self.emit_skip(&condition, true)?;
+                        let jump = self.jump_placeholder()?;
+                        self.blocks.push(Block::Branch(jump));
+                        Ok(())
+                    }
+                    t => Err(format!("expected then or begin, got '{}'", t)),
+                }
+            }
+            "else" => match self.blocks.pop() {
+                Some(Block::Branch(jump)) => {
+                    let end = self.jump_placeholder()?;
+                    self.patch_jump(jump)?;
+                    self.blocks.push(Block::Branch(end));
+                    Ok(())
+                }
+                _ => Err("else without if ... begin".to_string()),
+            },
+            "end" => match self.blocks.pop() {
+                Some(Block::Branch(jump)) => self.patch_jump(jump),
+                _ => Err("end without if ... begin".to_string()),
+            },
+            "loop" => {
+                self.blocks.push(Block::Loop(self.here, Vec::new()));
+                Ok(())
+            }
+            "while" => {
+                let condition = self.condition()?;
+                self.emit_skip(&condition, true)?;
+                let jump = self.jump_placeholder()?;
+                match self.blocks.iter_mut().rev().find(|b| matches!(b, Block::Loop(..))) {
+                    Some(Block::Loop(_, whiles)) => whiles.push(jump),
+                    _ => return Err("while outside of a loop".to_string()),
+                }
+                Ok(())
+            }
+            "again" => match self.blocks.pop() {
+                Some(Block::Loop(start, _)) if start > 0xFFF => Err(format!("the loop at {:#X} can't be jumped to", start)),
+                Some(Block::Loop(start, whiles)) => {
+                    self.emit16(0x1000 | start as u16)?;
+                    whiles.into_iter().try_for_each(|jump| self.patch_jump(jump))
+                }
+                _ => Err("again without loop".to_string()),
+            },
+            _ if self.macros.contains_key(&token) => {
+                let (args, body) = self.macros[&token].clone();
+                let mut values = BTreeMap::new();
+                for arg in args {
+                    values.insert(arg, self.next()?);
+                }
+                self.expand(body, &values)
+            }
+            _ if self.stringModes.contains_key(&token) => {
+                let text = self.next()?;
+                let text = text.strip_prefix('"').and_then(|t| t.strip_suffix('"')).ok_or_else(|| format!("expected a \"string\" for {}", token))?.to_string();
+                let modes = self.stringModes[&token].clone();
+                let mut expansion = Vec::new();
+                for (index, c) in text.chars().enumerate() {
+                    let (value, body) = modes.iter().enumerate().find(|(_, (m, _))| *m == c).map(|(v, (_, body))| (v, body))
+                        .ok_or_else(|| format!("{} has no '{}'", token, c))?;
+                    let values: BTreeMap<String, String> = [("CHAR", c as u32 as usize), ("INDEX", index), ("VALUE", value)].into_iter()
+                        .map(|(k, v)| (k.to_string(), v.to_string())).collect();
+                    expansion.extend(body.iter().map(|t| Token { text: values.get(&t.text).cloned().unwrap_or_else(|| t.text.clone()), line: t.line }));
+                }
+                self.expand(expansion, &BTreeMap::new())
+            }
+            // a number by itself is a byte of data, a name by itself calls it.
+            _ => match number(&token) {
+                Some(n) if (-128..=255).contains(&n) => self.emit(n as u8),
+                Some(n) => Err(format!("{} doesn't fit in a byte", n)),
+                None if token.starts_with(':') => Err(format!("unknown directive '{}'", token)),
+                None => {
+                    self.tokens.push_front(Token { text: token, line: self.line });
+                    self.address(0x2000, Fixup::Addr12)
+                }
+            },
+        }
+    }
+
+    // expand puts tokens in front of the rest of the program, with the arguments of a macro
+    // replaced. There are at most MAX_EXPANDED of them in all, so that a macro that expands to
+    // itself fails rather than goes on forever.
+    fn expand(&mut self, body: Vec<Token>, values: &BTreeMap<String, String>) -> Result<(), String> {
+        self.expanded += body.len();
+        if self.expanded > MAX_EXPANDED {
+            return Err(format!("the macros expand to more than {} tokens, is one of them recursive?", MAX_EXPANDED));
+        }
+        for token in body.into_iter().rev() {
+            let text = values.get(&token.text).cloned().unwrap_or(token.text);
+            self.tokens.push_front(Token { text, line: token.line });
+        }
+        Ok(())
+    }
+
+    fn register_statement(&mut self, x: u16) -> Result<(), String> {
+        let op = self.next()?;
+        let y = self.peek().and_then(|t| self.as_register(t)).map(|y| y as u16);
+        if y.is_some() {
+            self.next()?;
+        }
+        let alu = |n: u16| Ok::<u16, String>(0x8000 | x << 8 | y.unwrap_or(0) << 4 | n);
+        let word = match (op.as_str(), y) {
+            (":=", Some(_)) => alu(0)?,
+            (":=", None) => match self.peek() {
+                Some("random") => {
+                    self.next()?;
+                    0xC000 | x << 8 | self.byte()? as u16
+                }
+                Some("key") => {
+                    self.next()?;
+                    0xF00A | x << 8
+                }
+                Some("delay") => {
+                    self.next()?;
+                    0xF007 | x << 8
+                }
+                _ => 0x6000 | x << 8 | self.byte()? as u16,
+            },
+            ("+=", Some(_)) => alu(4)?,
+            ("+=", None) => 0x7000 | x << 8 | self.byte()? as u16,
+            ("-=", Some(_)) => alu(5)?,
+            ("-=", None) => 0x7000 | x << 8 | (self.byte()?.wrapping_neg()) as u16,
+            ("=-", Some(_)) => alu(7)?,
+            ("|=", Some(_)) => alu(1)?,
+            ("&=", Some(_)) => alu(2)?,
+            ("^=", Some(_)) => alu(3)?,
+            (">>=", Some(_)) => alu(6)?,
+            ("<<=", Some(_)) => alu(0xE)?,
+            (op, _) => return Err(format!("unknown register operation '{}'", op)),
+        };
+        self.emit16(word)
+    }
+}
+
+// declare writes tiles of the display as Octo: a : draw-<name> routine drawing them all where
+// they were, with v0 and v1, followed by a : <name>-<n> label and the bytes of each, commented
+// with its pixels. The comment goes first.
+pub fn declare(tiles: &[Tile], name: &str, comment: &str) -> String {
+    // anything but letters, digits, - and _ would end or break a name
+    let name: String = name.chars().map(|c| if c.is_ascii_alphanumeric() || c == '_' { c } else { '-' }).collect();
+    let mut text = format!("# {}\n: draw-{}\n", comment, name);
+    for (n, tile) in tiles.iter().enumerate() {
+        text.push_str(&format!("  i := {}-{}  v0 := {}  v1 := {}  sprite v0 v1 {}\n", name, n, tile.x, tile.y, tile.rows.len()));
+    }
+    text.push_str("  return\n");
+    for (n, tile) in tiles.iter().enumerate() {
+        text.push_str(&format!("\n: {}-{}  # 8x{} at {} {}\n", name, n, tile.rows.len(), tile.x, tile.y));
+        for row in &tile.rows {
+            let pixels: String = (0..8).map(|bit| if row & 0x80 >> bit != 0 { '#' } else { '.' }).collect();
+            text.push_str(&format!("  {:#04X}  # {}\n", row, pixels));
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn assemblesInstructions() {
+        assert_eq!(assemble("v0 := 5  v1 += v0  v2 -= 1"), Ok(vec![0x60, 0x05, 0x81, 0x04, 0x72, 0xFF]));
+        assert_eq!(assemble(":alias counter v3  counter := random 0x0F  bcd counter  load counter"), Ok(vec![0xC3, 0x0F, 0xF3, 0x33, 0xF3, 0x65]));
+    }
+
+    #[test]
+    fn resolvesLabelsWhereverTheyAre() {
+        // sprite is used before it's defined, main is where the program starts
+        assert_eq!(assemble("i := sprite  sprite v0 v1 2  : sprite 0xFF 0x81"), Ok(vec![0xA2, 0x04, 0xD0, 0x12, 0xFF, 0x81]));
+        assert_eq!(assemble(": main loop v0 += 1 again"), Ok(vec![0x70, 0x01, 0x12, 0x00]));
+    }
+
+    #[test]
+    fn compilesConditionals() {
+        // the skip before the instruction of an if tests the opposite of its condition
+        assert_eq!(assemble("if v0 == 3 then v1 := 2"), Ok(vec![0x40, 0x03, 0x61, 0x02]));
+        assert_eq!(
+            assemble("if v0 != v1 begin clear else return end"),
+            Ok(vec![0x90, 0x10, 0x12, 0x08, 0x00, 0xE0, 0x12, 0x0A, 0x00, 0xEE])
+        );
+    }
+
+    #[test]
+    fn evaluatesRightToLeft() {
+        // like in Octo: 2 * (3 + 1)
+        assert_eq!(assemble(":calc x { 2 * 3 + 1 }  :byte x"), Ok(vec![0x08]));
+        assert_eq!(assemble(":calc x { ( 2 * 3 ) + 1 }  :byte x"), Ok(vec![0x07]));
+    }
+
+    #[test]
+    fn expandsMacros() {
+        assert_eq!(assemble(":macro twice r { r += 1 r += 1 }  twice v2  twice v3"), Ok(vec![0x72, 0x01, 0x72, 0x01, 0x73, 0x01, 0x73, 0x01]));
+    }
+
+    #[test]
+    fn explainsWhatItCantAssemble() {
+        let errors = [
+            (":calc x { 1 / 0 }", "line 1: division by zero"),
+            (":calc x { ( 0x7FFFFFFFFFFFFFFF + 1 ) / -1 }", "line 1: the division overflows"),
+            (":macro m { m } m", "line 1: the macros expand to more than 1048576 tokens, is one of them recursive?"),
+            (":org 0x1000 loop again", "line 1: the loop at 0x1000 can't be jumped to"),
+            ("jump nowhere", "line 1: undefined name 'nowhere'"),
+            ("loop v0 += 1", "the program ends inside an if ... begin or a loop"),
+            ("v0 := 256", "line 1: 256 doesn't fit in a byte"),
+            (": main\n: main", "line 2: the name 'main' is already defined"),
+        ];
+        for (source, error) in errors {
+            assert_eq!(assemble(source), Err(error.to_string()), "{}", source);
+        }
+    }
+}
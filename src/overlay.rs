@@ -0,0 +1,340 @@
+use rust_chip8::disasm;
+use rust_chip8::emulator::{Emulator, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+use rust_chip8::memory::Memory;
+use rust_chip8::render::Palette;
+
+use crate::i18n::Messages;
+
+// GLYPH_WIDTH and LINE_HEIGHT are the room a character of overlay text takes in the frame: a 3x5
+// glyph with a column and a row of space around it.
+pub const GLYPH_WIDTH: usize = 4;
+pub const LINE_HEIGHT: usize = 6;
+
+const MARGIN: usize = 2;
+
+const BACKGROUND: [u8; 4] = [0x10, 0x10, 0x30, 0xff];
+const BORDER: [u8; 4] = [0x60, 0x60, 0xA0, 0xff];
+const TEXT: [u8; 4] = [0xFF, 0xFF, 0xFF, 0xff];
+const COLD: u8 = 0x30; // of a byte of the heatmap that's not 0, in every channel
+
+// THUMBNAIL_SCALE is how many display pixels each way make a pixel of the thumbnail of the debug
+// view, which makes it 32x16 in the bottom right of the frame.
+const THUMBNAIL_SCALE: usize = 4;
+
+// TILE_WIDTH and TILE_HEIGHT are the room a tile of the library takes: a thumbnail the size of that
+// of the debug view with a line of its name below.
+pub const TILE_WIDTH: usize = DISPLAY_WIDTH / THUMBNAIL_SCALE;
+pub const TILE_HEIGHT: usize = DISPLAY_HEIGHT / THUMBNAIL_SCALE + LINE_HEIGHT;
+
+// Overlay is a box of text drawn over the top left of the frame, like the About box on F1. The
+// frame is the size of the CHIP8 display, so text is in a tiny 3x5 font of capitals, digits and
+// some punctuation, and lines longer than the frame is wide are cut off.
+pub struct Overlay {
+    pub lines: Vec<String>,
+    pub debug: bool, // the debug view, with lines refreshed every frame and a live thumbnail
+    pub heatmap: bool, // the memory heatmap, drawn under the lines every frame
+    pub card: bool, // the reference card of the instruction at the PC, refreshed every frame
+    pub tiles: Vec<Tile>, // the grid of the library, drawn under the lines
+}
+
+// Tile is a ROM in the grid of the library: its thumbnail, see shrink, and its name.
+#[derive(Clone)]
+pub struct Tile {
+    pub thumbnail: Vec<u8>,
+    pub name: String,
+    pub selected: bool,
+}
+
+impl Overlay {
+    // about returns the About box: the version, the variant and a summary of the keys, with the
+    // keypad rows as Keymap::describe gives them.
+    pub fn about(variant: &str, keypad: Vec<String>, messages: &Messages) -> Overlay {
+        let mut lines = vec![format!("RUST-CHIP8 {}  {}", env!("CARGO_PKG_VERSION"), variant)];
+        lines.extend(keypad);
+        lines.extend(["about.pause_reset", "about.speed", "about.drop", "about.close"].map(|key| messages.get(key).to_string()));
+        Overlay { lines, debug: false, heatmap: false, card: false, tiles: Vec::new() }
+    }
+
+    // crashed returns the box shown when the emulator panicked: the frame, as much of the panic
+    // message as fits, wrapped to the width of the display, and how to go on.
+    pub fn crashed(frame: u64, message: &str) -> Overlay {
+        let mut lines = vec![format!("CRASH AT {}", frame)];
+        lines.extend(wrap(message));
+        lines.truncate(4);
+        lines.push("BKSP RESETS".to_string());
+        Overlay { lines, debug: false, heatmap: false, card: false, tiles: Vec::new() }
+    }
+
+    // debug returns the debug view, which takes the whole frame. Its lines are filled in by
+    // refresh, from the emulator as it runs.
+    pub fn debug() -> Overlay {
+        Overlay { lines: Vec::new(), debug: true, heatmap: false, card: false, tiles: Vec::new() }
+    }
+
+    // heatmap returns the memory heatmap view, which takes the whole frame like the debug view. Each
+    // byte of memory is a pixel, row after row from address 0, red for as recently as it was
+    // written, green read and blue run, and dimly lit when it's not 0 but cold.
+    pub fn heatmap() -> Overlay {
+        let lines = vec!["R WRITE G READ B RUN".to_string()];
+        Overlay { lines, debug: false, heatmap: true, card: false, tiles: Vec::new() }
+    }
+
+    // card returns the reference card of the instruction at the PC, see disasm::reference, for
+    // looking up what it does when paused. Its lines are filled in by refresh.
+    pub fn card() -> Overlay {
+        Overlay { lines: Vec::new(), debug: false, heatmap: false, card: true, tiles: Vec::new() }
+    }
+
+    // refresh sets the lines of the debug view to the registers, I, the timers, the stack and the
+    // instructions from the program counter on, or those of the card to the reference of the
+    // instruction at the program counter.
+    pub fn refresh(&mut self, emul: &Emulator) {
+        if self.card {
+            let memory = emul.memory.bytes();
+            let pc = emul.pc as usize;
+            let b = (memory[pc % memory.len()] as u16) << 8 | memory[(pc + 1) % memory.len()] as u16;
+            let mut lines = vec![format!("{:03X} {:04X} {}", pc, b, disasm::mnemonic(b, emul.variant))];
+            match disasm::reference(b, emul.variant) {
+                Some(doc) => {
+                    lines.extend(wrap(doc.summary));
+                    if !doc.flags.is_empty() {
+                        lines.extend(wrap(&format!("VF: {}", doc.flags)));
+                    }
+                    if !doc.quirks.is_empty() {
+                        lines.extend(wrap(&format!("QUIRKS: {}", doc.quirks)));
+                    }
+                }
+                None => lines.push("NOT AN INSTRUCTION".to_string()),
+            }
+            lines.truncate(DISPLAY_HEIGHT / LINE_HEIGHT);
+            self.lines = lines;
+            return;
+        }
+        let mut lines: Vec<String> = emul.registers.chunks(4).enumerate().map(|(r, values)| {
+            let values: Vec<String> = values.iter().enumerate().map(|(i, v)| format!("V{:X}={:02X}", r * 4 + i, v)).collect();
+            values.join(" ")
+        }).collect();
+        lines.push(format!("I={:03X} DT={:02X} ST={:02X}", emul.I, emul.delayTimer, emul.soundTimer));
+        let stack: Vec<String> = emul.stack.iter().rev().take(4).map(|a| format!("{:03X}", a)).collect();
+        lines.push(format!("STACK {}", stack.join(" ")));
+        for line in disasm::listing(emul.memory.bytes(), emul.pc, 4, emul.variant) {
+            let marker = if line.address == emul.pc { '>' } else { ' ' };
+            lines.push(format!("{}{:03X} {}", marker, line.address, line.mnemonic));
+        }
+        self.lines = lines;
+    }
+
+    // draw paints the overlay into a frame width x height pixels.
+    pub fn draw(&self, px: &mut [u8], width: usize, height: usize) {
+        let columns = self.lines.iter().map(|l| l.chars().count()).max().unwrap_or(0);
+        let (boxWidth, boxHeight) = match self.debug || self.heatmap || !self.tiles.is_empty() {
+            true => (width, height),
+            false => ((columns * GLYPH_WIDTH + 2 * MARGIN - 1).min(width), (self.lines.len() * LINE_HEIGHT + 2 * MARGIN - 1).min(height)),
+        };
+        for y in 0..boxHeight {
+            for x in 0..boxWidth {
+                let edge = x == 0 || y == 0 || x == boxWidth - 1 || y == boxHeight - 1;
+                let color = if edge { BORDER } else if self.lit(x, y) { TEXT } else { BACKGROUND };
+                let index = (y * width + x) * 4;
+                px[index..index + 4].copy_from_slice(&color);
+            }
+        }
+        self.draw_tiles(px, width, height);
+    }
+
+    // draw_tiles paints the tiles below the lines, row after row of as many as fit across, with a
+    // border that's lit around the selected one. Rows that don't fit are left out.
+    fn draw_tiles(&self, px: &mut [u8], width: usize, height: usize) {
+        let (columns, _) = grid(width, height, self.lines.len());
+        let top = self.lines.len() * LINE_HEIGHT + MARGIN;
+        let (thumbWidth, thumbHeight) = (TILE_WIDTH, TILE_HEIGHT - LINE_HEIGHT);
+        for (i, tile) in self.tiles.iter().enumerate() {
+            let (left, tileTop) = ((i % columns.max(1)) * TILE_WIDTH, top + (i / columns.max(1)) * TILE_HEIGHT);
+            if tileTop + TILE_HEIGHT > height {
+                break;
+            }
+            for y in 0..thumbHeight {
+                for x in 0..thumbWidth {
+                    let edge = x == 0 || y == 0 || x == thumbWidth - 1 || y == thumbHeight - 1;
+                    let color = match edge {
+                        true if tile.selected => TEXT,
+                        true => BORDER,
+                        false if tile.thumbnail.get(y * thumbWidth + x) == Some(&1) => TEXT,
+                        false => BACKGROUND,
+                    };
+                    let index = ((tileTop + y) * width + left + x) * 4;
+                    px[index..index + 4].copy_from_slice(&color);
+                }
+            }
+            // the name is cut off at the width of the tile.
+            for (n, c) in tile.name.chars().take(TILE_WIDTH / GLYPH_WIDTH).enumerate() {
+                let rows = glyph(c);
+                for (glyphY, row) in rows.iter().enumerate() {
+                    for glyphX in 0..3 {
+                        if row & (0b100 >> glyphX) != 0 {
+                            let (x, y) = (left + n * GLYPH_WIDTH + glyphX, tileTop + thumbHeight + 1 + glyphY);
+                            let index = (y * width + x) * 4;
+                            px[index..index + 4].copy_from_slice(&TEXT);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // draw_heatmap paints the heat of every byte of memory below the lines, a row of the width of
+    // the frame for each as many bytes. What doesn't fit is left out.
+    pub fn draw_heatmap(&self, px: &mut [u8], width: usize, height: usize, memory: &Memory) {
+        let Some(heat) = memory.heatmap() else { return };
+        let top = self.lines.len() * LINE_HEIGHT + 2 * MARGIN;
+        for (address, value) in memory.bytes().iter().enumerate() {
+            let (x, y) = (address % width, top + address / width);
+            if y >= height {
+                break;
+            }
+            let floor = if *value != 0 { COLD } else { BACKGROUND[0] };
+            let color = [heat.writes[address].max(floor), heat.reads[address].max(floor), heat.executes[address].max(floor), 0xff];
+            let index = (y * width + x) * 4;
+            px[index..index + 4].copy_from_slice(&color);
+        }
+    }
+
+    // draw_thumbnail paints a display scaled down into the bottom right of a frame width x height
+    // pixels, see shrink.
+    pub fn draw_thumbnail(&self, px: &mut [u8], width: usize, height: usize, display: &[u8], palette: &Palette) {
+        let (left, top, thumbWidth, thumbHeight) = thumbnail(width, height);
+        let small = shrink(display);
+        for y in 0..thumbHeight {
+            for x in 0..thumbWidth {
+                let color = if x == 0 || y == 0 { BORDER } else { palette.color(small[y * thumbWidth + x] == 1) };
+                let index = ((top + y) * width + left + x) * 4;
+                px[index..index + 4].copy_from_slice(&color);
+            }
+        }
+    }
+
+    // lit returns whether pixel x,y of the box is part of a glyph.
+    fn lit(&self, x: usize, y: usize) -> bool {
+        let (x, y) = match (x.checked_sub(MARGIN), y.checked_sub(MARGIN)) {
+            (Some(x), Some(y)) => (x, y),
+            _ => return false,
+        };
+        let (glyphX, glyphY) = (x % GLYPH_WIDTH, y % LINE_HEIGHT);
+        let c = self.lines.get(y / LINE_HEIGHT).and_then(|l| l.chars().nth(x / GLYPH_WIDTH));
+        match c {
+            Some(c) if glyphX < 3 && glyphY < 5 => glyph(c)[glyphY] & (0b100 >> glyphX) != 0,
+            _ => false,
+        }
+    }
+}
+
+// thumbnail returns the left, top, width and height of the thumbnail of the debug view in a frame
+// width x height pixels, for drawing it and for telling whether a click hit it.
+pub fn thumbnail(width: usize, height: usize) -> (usize, usize, usize, usize) {
+    let (thumbWidth, thumbHeight) = (DISPLAY_WIDTH / THUMBNAIL_SCALE, DISPLAY_HEIGHT / THUMBNAIL_SCALE);
+    (width.saturating_sub(thumbWidth), height.saturating_sub(thumbHeight), thumbWidth.min(width), thumbHeight.min(height))
+}
+
+// shrink scales a display down by THUMBNAIL_SCALE each way, to a pixel of 1 for every block of the
+// display with any pixel lit in it, so thin lines don't drop out.
+pub fn shrink(display: &[u8]) -> Vec<u8> {
+    let (thumbWidth, thumbHeight) = (DISPLAY_WIDTH / THUMBNAIL_SCALE, DISPLAY_HEIGHT / THUMBNAIL_SCALE);
+    let mut small = vec![0; thumbWidth * thumbHeight];
+    for (i, pixel) in small.iter_mut().enumerate() {
+        let (x, y) = (i % thumbWidth, i / thumbWidth);
+        let lit = (0..THUMBNAIL_SCALE * THUMBNAIL_SCALE).any(|n| {
+            let (dx, dy) = (x * THUMBNAIL_SCALE + n % THUMBNAIL_SCALE, y * THUMBNAIL_SCALE + n / THUMBNAIL_SCALE);
+            display[dy * DISPLAY_WIDTH + dx] == 1
+        });
+        *pixel = lit as u8;
+    }
+    small
+}
+
+// grid returns how many columns and rows of tiles fit in a frame width x height pixels below the
+// given number of lines.
+pub fn grid(width: usize, height: usize, lines: usize) -> (usize, usize) {
+    (width / TILE_WIDTH, height.saturating_sub(lines * LINE_HEIGHT + MARGIN) / TILE_HEIGHT)
+}
+
+// wrap breaks a text into lines at spaces, to fit across the display.
+fn wrap(text: &str) -> Vec<String> {
+    let columns = (DISPLAY_WIDTH - 2 * MARGIN) / GLYPH_WIDTH;
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        if !line.is_empty() && line.len() + 1 + word.len() > columns {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    lines.push(line);
+    lines
+}
+
+// glyph returns the rows of the 3x5 glyph of a character, the left pixel in bit 2. Lowercase is
+// drawn as capitals and anything without a glyph as a block.
+fn glyph(c: char) -> [u8; 5] {
+    match c.to_ascii_uppercase() {
+        ' ' => [0b000, 0b000, 0b000, 0b000, 0b000],
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b011, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b010, 0b010, 0b010],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        ',' => [0b000, 0b000, 0b000, 0b010, 0b100],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '_' => [0b000, 0b000, 0b000, 0b000, 0b111],
+        '+' => [0b000, 0b010, 0b111, 0b010, 0b000],
+        '=' => [0b000, 0b111, 0b000, 0b111, 0b000],
+        '/' => [0b001, 0b001, 0b010, 0b100, 0b100],
+        '(' => [0b010, 0b100, 0b100, 0b100, 0b010],
+        ')' => [0b010, 0b001, 0b001, 0b001, 0b010],
+        '!' => [0b010, 0b010, 0b010, 0b000, 0b010],
+        '?' => [0b110, 0b001, 0b010, 0b000, 0b010],
+        '\'' => [0b010, 0b010, 0b000, 0b000, 0b000],
+        '`' => [0b100, 0b010, 0b000, 0b000, 0b000],
+        '%' => [0b101, 0b001, 0b010, 0b100, 0b101],
+        '>' => [0b100, 0b010, 0b001, 0b010, 0b100],
+        '<' => [0b001, 0b010, 0b100, 0b010, 0b001],
+        '[' => [0b110, 0b100, 0b100, 0b100, 0b110],
+        ']' => [0b011, 0b001, 0b001, 0b001, 0b011],
+        _ => [0b111, 0b111, 0b111, 0b111, 0b111],
+    }
+}
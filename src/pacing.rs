@@ -0,0 +1,146 @@
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+// one 60hz frame, i.e. 16.6 ms
+pub const FRAME_DURATION: Duration = Duration::from_nanos(16_666_667);
+
+// the default rate of the timers, and so of the frames.
+pub const TIMER_HZ: u32 = 60;
+
+// if a frontend falls further behind than this, it stops trying to catch up.
+const MAX_FRAME_LAG: Duration = Duration::from_millis(100);
+
+// a frame taking longer than this means the host was asleep, or the process stopped, in between.
+const SLEEP_GAP: Duration = Duration::from_secs(2);
+
+// the default speed of slow-motion, in percent of normal speed.
+pub const SLOW_MOTION_PERCENT: u32 = 10;
+
+// Pacing decides how long each emulated frame takes. It's shared between the event-loop, which
+// changes it from hotkeys, and the interpreter thread, which sleeps accordingly. Since the timers
+// tick once per frame, they speed up and slow down along with the instructions. At the default
+// 60hz a frame is FRAME_DURATION, for ROMs that expect 50hz timers frames take 20ms instead.
+pub struct Pacing {
+    frame: Duration, // how long a frame takes at normal speed
+    turbo: AtomicBool,
+    slowHeld: AtomicBool,    // slow-motion while its key is held down
+    slowToggled: AtomicBool, // slow-motion until toggled off again
+    slowPercent: AtomicU32,
+    // while the window is minimized or hidden, no frames run, see set_suspended.
+    suspended: AtomicBool,
+}
+
+impl Pacing {
+    pub fn new(slowPercent: u32, timerHz: u32) -> Pacing {
+        Pacing {
+            frame: Duration::from_secs(1) / timerHz.max(1),
+            turbo: AtomicBool::new(false),
+            slowHeld: AtomicBool::new(false),
+            slowToggled: AtomicBool::new(false),
+            slowPercent: AtomicU32::new(slowPercent.clamp(1, 100)),
+            suspended: AtomicBool::new(false),
+        }
+    }
+
+    pub fn set_turbo(&self, on: bool) {
+        self.turbo.store(on, Ordering::Relaxed);
+    }
+
+    pub fn turbo(&self) -> bool {
+        self.turbo.load(Ordering::Relaxed)
+    }
+
+    // set_suspended stops the frames while nobody can see them, unlike pausing without saying so in
+    // the title. The frames go on from when they're resumed, rather than catching up on the time
+    // suspended.
+    pub fn set_suspended(&self, on: bool) {
+        self.suspended.store(on, Ordering::Relaxed);
+    }
+
+    pub fn suspended(&self) -> bool {
+        self.suspended.load(Ordering::Relaxed)
+    }
+
+    pub fn set_slow_motion_held(&self, on: bool) {
+        self.slowHeld.store(on, Ordering::Relaxed);
+    }
+
+    pub fn toggle_slow_motion(&self) {
+        self.slowToggled.fetch_xor(true, Ordering::Relaxed);
+    }
+
+    // slow_motion returns the slow-motion speed in percent, if slow-motion is on.
+    pub fn slow_motion(&self) -> Option<u32> {
+        if self.slowHeld.load(Ordering::Relaxed) || self.slowToggled.load(Ordering::Relaxed) {
+            Some(self.slowPercent.load(Ordering::Relaxed))
+        } else {
+            None
+        }
+    }
+
+    // frame_duration returns how long a frame should take, or None to run frames back to back as
+    // fast as the host allows.
+    pub fn frame_duration(&self) -> Option<Duration> {
+        if self.turbo() {
+            return None;
+        }
+        match self.slow_motion() {
+            Some(percent) => Some(self.frame * 100 / percent),
+            None => Some(self.frame),
+        }
+    }
+
+    // wait sleeps until the next frame is due. nextFrame is the point in time the current frame
+    // was due, sleeping until an absolute deadline rather than for "whatever is left" keeps sleep
+    // overshoot from adding up over time.
+    pub fn wait(&self, nextFrame: &mut Instant, paused: bool) {
+        let now = Instant::now();
+        if self.suspended() {
+            thread::sleep(self.frame);
+            *nextFrame = Instant::now();
+            return;
+        }
+        // there's nothing to fast-forward while paused
+        let duration = if paused { Some(self.frame) } else { self.frame_duration() };
+        match duration {
+            Some(duration) => {
+                *nextFrame += duration;
+                if *nextFrame > now {
+                    thread::sleep(*nextFrame - now);
+                } else if now - *nextFrame > MAX_FRAME_LAG {
+                    *nextFrame = now;
+                }
+            }
+            None => {
+                // give other threads a chance at the locks they share with this one
+                *nextFrame = now;
+                thread::yield_now();
+            }
+        }
+    }
+}
+
+// SleepWatch notices the host having been asleep, a laptop's lid closed say, from a frame that
+// took far longer than any frame does. Neither the monotonic clock nor the wall clock can be relied
+// on to count the time asleep on every OS, so it goes by whichever of them moved on the most.
+pub struct SleepWatch {
+    instant: Instant,
+    wall: SystemTime,
+}
+
+impl SleepWatch {
+    pub fn new() -> SleepWatch {
+        SleepWatch { instant: Instant::now(), wall: SystemTime::now() }
+    }
+
+    // check returns how long it's been since the last check if that was SLEEP_GAP or longer. It's
+    // called once per frame.
+    pub fn check(&mut self) -> Option<Duration> {
+        let (instant, wall) = (Instant::now(), SystemTime::now());
+        // the wall clock can be set back, then it's only the monotonic clock.
+        let gap = (instant - self.instant).max(wall.duration_since(self.wall).unwrap_or_default());
+        (self.instant, self.wall) = (instant, wall);
+        (gap >= SLEEP_GAP).then_some(gap)
+    }
+}
@@ -0,0 +1,107 @@
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use directories::{ProjectDirs, UserDirs};
+
+// Where the emulator keeps its files. By default that's where the platform wants them, found with
+// the directories crate, e.g. on Linux
+//
+//   ~/.config/rust-chip8/rust-chip8.toml   the config file
+//   ~/.local/share/rust-chip8/saves        the data ROMs save, the stats of the library and where
+//                                          the window was
+//   ~/.local/share/rust-chip8/replays
+//   ~/.local/share/rust-chip8/reports      the bug reports of ROMs that stopped on an error
+//   ~/Pictures/rust-chip8                  screenshots and exported sprites
+//
+// --data-dir puts them all in one directory instead: a rust-chip8.toml and directories saves,
+// replays, reports and screenshots, the way they used to be in the working directory. A working
+// directory that has a rust-chip8.toml is still used like that, so the setups from before keep
+// their files.
+
+// CONFIG_FILE is the name of the config file, read at startup if it exists.
+pub const CONFIG_FILE: &str = "rust-chip8.toml";
+
+struct Dirs {
+    config: PathBuf,
+    saves: PathBuf,
+    replays: PathBuf,
+    reports: PathBuf,
+    screenshots: PathBuf,
+}
+
+static DIRS: OnceLock<Dirs> = OnceLock::new();
+
+impl Dirs {
+    fn under(dir: &Path) -> Dirs {
+        Dirs {
+            config: dir.join(CONFIG_FILE),
+            saves: dir.join("saves"),
+            replays: dir.join("replays"),
+            reports: dir.join("reports"),
+            screenshots: dir.join("screenshots"),
+        }
+    }
+
+    fn platform() -> Dirs {
+        let project = match ProjectDirs::from("", "", "rust-chip8") {
+            Some(project) if !Path::new(CONFIG_FILE).exists() => project,
+            // without a home directory, there's only the working directory.
+            _ => return Dirs::under(Path::new(".")),
+        };
+        let pictures = UserDirs::new().and_then(|dirs| dirs.picture_dir().map(|d| d.join("rust-chip8")));
+        Dirs {
+            config: project.config_dir().join(CONFIG_FILE),
+            saves: project.data_dir().join("saves"),
+            replays: project.data_dir().join("replays"),
+            reports: project.data_dir().join("reports"),
+            screenshots: pictures.unwrap_or_else(|| project.data_dir().join("screenshots")),
+        }
+    }
+}
+
+// init picks the directories, all in that of --data-dir if it's given. It's called before the
+// config file is read, as that's where the config file is.
+pub fn init(dataDir: Option<&str>) {
+    let _ = DIRS.set(match dataDir {
+        Some(dir) => Dirs::under(Path::new(dir)),
+        None => Dirs::platform(),
+    });
+}
+
+fn dirs() -> &'static Dirs {
+    DIRS.get_or_init(Dirs::platform)
+}
+
+pub fn config() -> &'static Path {
+    &dirs().config
+}
+
+// saves is the directory of the files ROMs persist between sessions, see saves::savePath.
+pub fn saves() -> &'static Path {
+    &dirs().saves
+}
+
+// library is the file with the stats of the library, among the saves.
+pub fn library() -> PathBuf {
+    saves().join("library.toml")
+}
+
+// window is the file with where the window was when it last closed, among the saves, see
+// placement.
+pub fn window() -> PathBuf {
+    saves().join("window.toml")
+}
+
+// replays is where replays are kept unless --replay names another file.
+pub fn replays() -> &'static Path {
+    &dirs().replays
+}
+
+// reports is where the bug reports go, see report.
+pub fn reports() -> &'static Path {
+    &dirs().reports
+}
+
+pub fn screenshots() -> &'static Path {
+    &dirs().screenshots
+}
@@ -0,0 +1,39 @@
+use crate::config;
+use crate::paths;
+
+// Placement is where the window was and how large when it last closed, in physical pixels, kept in
+// paths::window so that the next session opens it there again:
+//
+//   x = 120
+//   y = 80
+//   width = 640
+//   height = 320
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Placement {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+// load returns the placement the window closed with, if any.
+pub fn load() -> Option<Placement> {
+    let config = config::load(&paths::window()).ok()?;
+    let value = |key: &str| config.value("", key).and_then(|v| v.parse::<i64>().ok());
+    Some(Placement {
+        x: value("x")? as i32,
+        y: value("y")? as i32,
+        width: value("width")?.max(1) as u32,
+        height: value("height")?.max(1) as u32,
+    })
+}
+
+// save keeps the placement of a window that's closing.
+pub fn save(placement: Placement) -> Result<(), String> {
+    config::setKeys(&paths::window(), "", &[
+        ("x", placement.x.to_string()),
+        ("y", placement.y.to_string()),
+        ("width", placement.width.to_string()),
+        ("height", placement.height.to_string()),
+    ])
+}
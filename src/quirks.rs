@@ -0,0 +1,157 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+// Quirks are the behaviours that differ between CHIP8 interpreters over the years. See
+// https://tobiasvl.github.io/blog/write-a-chip-8-emulator/ for a description of each of them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quirks {
+    pub vfReset: bool,             // 8XY1, 8XY2 and 8XY3 reset VF to 0 (COSMAC VIP)
+    pub shiftUsesVY: bool,         // 8XY6 and 8XYE copy VY into VX before shifting (COSMAC VIP)
+    pub loadStoreIncrementsI: bool, // FX55 and FX65 leave I incremented past the last register (COSMAC VIP)
+    pub jumpUsesVX: bool,          // BNNN jumps to XNN + VX rather than NNN + V0 (SCHIP)
+    pub indexOverflowSetsVF: bool, // FX1E sets VF when I overflows past 0xFFF (Amiga)
+    pub clipSprites: bool,         // sprites are clipped at the screen edges rather than wrapped
+    pub rowCollisions: bool,       // DXYN in hires mode sets VF to the number of colliding and clipped rows (SCHIP)
+    pub halfPixelScroll: bool,     // SCHIP scroll distances are in hires pixels even in lores mode (SCHIP 1.1)
+    pub stackDepth: usize,         // how many nested subroutine calls fit on the stack (12 on the COSMAC VIP)
+}
+
+// MAX_STACK_DEPTH is the deepest stack a quirk spec can ask for.
+pub const MAX_STACK_DEPTH: usize = 256;
+
+// QUIRK_NAMES lists the names accepted in a quirk spec, see Quirks::parse.
+pub static QUIRK_NAMES: [&str; 8] = ["vfreset", "shift", "loadstore", "jump", "indexoverflow", "clip", "rowcollisions", "halfscroll"];
+
+impl Default for Quirks {
+    // The defaults are what this interpreter has always done.
+    fn default() -> Self {
+        Quirks {
+            vfReset: false,
+            shiftUsesVY: true,
+            loadStoreIncrementsI: true,
+            jumpUsesVX: false,
+            indexOverflowSetsVF: true,
+            clipSprites: false,
+            rowCollisions: false,
+            halfPixelScroll: false,
+            stackDepth: 32,
+        }
+    }
+}
+
+impl Quirks {
+    // vip returns the quirks of the original COSMAC VIP interpreter.
+    pub fn vip() -> Quirks {
+        Quirks {
+            vfReset: true,
+            shiftUsesVY: true,
+            loadStoreIncrementsI: true,
+            jumpUsesVX: false,
+            indexOverflowSetsVF: false,
+            clipSprites: true,
+            rowCollisions: false,
+            halfPixelScroll: false,
+            stackDepth: 12,
+        }
+    }
+
+    // schip returns the quirks of SUPER-CHIP 1.1 on the HP48.
+    pub fn schip() -> Quirks {
+        Quirks {
+            vfReset: false,
+            shiftUsesVY: false,
+            loadStoreIncrementsI: false,
+            jumpUsesVX: true,
+            indexOverflowSetsVF: false,
+            clipSprites: true,
+            rowCollisions: true,
+            halfPixelScroll: true,
+            stackDepth: 16,
+        }
+    }
+
+    // preset looks up a named set of quirks.
+    pub fn preset(name: &str) -> Option<Quirks> {
+        match name {
+            "default" => Some(Quirks::default()),
+            "vip" | "chip8" => Some(Quirks::vip()),
+            "schip" => Some(Quirks::schip()),
+            _ => None,
+        }
+    }
+
+    // set turns a single quirk, named as in QUIRK_NAMES, on or off.
+    pub fn set(&mut self, name: &str, on: bool) -> Result<(), String> {
+        match name {
+            "vfreset" => self.vfReset = on,
+            "shift" => self.shiftUsesVY = on,
+            "loadstore" => self.loadStoreIncrementsI = on,
+            "jump" => self.jumpUsesVX = on,
+            "indexoverflow" => self.indexOverflowSetsVF = on,
+            "clip" => self.clipSprites = on,
+            "rowcollisions" => self.rowCollisions = on,
+            "halfscroll" => self.halfPixelScroll = on,
+            _ => return Err(format!("unknown quirk '{}', expected one of {}", name, QUIRK_NAMES.join(", "))),
+        }
+        Ok(())
+    }
+
+    // get returns whether a single quirk, named as in QUIRK_NAMES, is on.
+    pub fn get(&self, name: &str) -> Result<bool, String> {
+        match name {
+            "vfreset" => Ok(self.vfReset),
+            "shift" => Ok(self.shiftUsesVY),
+            "loadstore" => Ok(self.loadStoreIncrementsI),
+            "jump" => Ok(self.jumpUsesVX),
+            "indexoverflow" => Ok(self.indexOverflowSetsVF),
+            "clip" => Ok(self.clipSprites),
+            "rowcollisions" => Ok(self.rowCollisions),
+            "halfscroll" => Ok(self.halfPixelScroll),
+            _ => Err(format!("unknown quirk '{}', expected one of {}", name, QUIRK_NAMES.join(", "))),
+        }
+    }
+
+    // spec returns a quirk spec that parses back to exactly these quirks, naming every single
+    // quirk rather than a preset, so it means the same whatever the defaults and presets become.
+    pub fn spec(&self) -> String {
+        let mut parts: Vec<String> = QUIRK_NAMES.iter().map(|name| {
+            let on = self.get(name).unwrap_or_default();
+            format!("{}={}", name, if on { "on" } else { "off" })
+        }).collect();
+        parts.push(format!("stack={}", self.stackDepth));
+        parts.join(",")
+    }
+
+    // parse reads a quirk spec such as "schip" or "vip,clip=off,jump=on": an optional preset
+    // followed by comma-separated overrides of individual quirks, and/or stack=<depth>.
+    pub fn parse(spec: &str) -> Result<Quirks, String> {
+        let mut quirks = Quirks::default();
+        for (i, part) in spec.split(',').map(|p| p.trim()).enumerate() {
+            if part.is_empty() {
+                continue;
+            }
+            match part.split_once('=') {
+                Some(("stack", value)) => {
+                    quirks.stackDepth = match value.parse() {
+                        Ok(depth) if (1..=MAX_STACK_DEPTH).contains(&depth) => depth,
+                        _ => return Err(format!("invalid stack depth '{}', expected 1 to {}", value, MAX_STACK_DEPTH)),
+                    };
+                }
+                Some((name, value)) => {
+                    let on = match value {
+                        "on" | "true" | "1" => true,
+                        "off" | "false" | "0" => false,
+                        _ => return Err(format!("invalid value '{}' for quirk '{}', expected on or off", value, name)),
+                    };
+                    quirks.set(name, on)?;
+                }
+                None if i == 0 => {
+                    quirks = Quirks::preset(part).ok_or(format!("unknown quirk preset '{}'", part))?;
+                }
+                None => return Err(format!("expected <quirk>=on|off, got '{}'", part)),
+            }
+        }
+        Ok(quirks)
+    }
+}
@@ -0,0 +1,122 @@
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use rust_chip8::emulator::{Emulator, KeyState};
+use rust_chip8::replay::{self, Entry, Header, Replay};
+
+use crate::paths;
+use crate::saves;
+
+// replayPath returns the replay file of a ROM in paths::replays, pong.replay for ./roms/pong.ch8.
+pub fn replayPath(romPath: &str) -> PathBuf {
+    paths::replays().join(format!("{}.replay", saves::romName(romPath)))
+}
+
+// Recorder writes a replay as the ROM runs, see replay.rs for the format. Every line is written out
+// as soon as it's known, so the replay is complete up to the last frame recorded however the
+// emulator is closed.
+pub struct Recorder {
+    pub path: PathBuf,
+    file: File,
+    keys: KeyState, // the keys of the last keys line
+    checksumEvery: u64,
+}
+
+impl Recorder {
+    pub fn create(path: &Path, header: &Header) -> Result<Recorder, String> {
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            fs::create_dir_all(dir).map_err(|e| format!("Unable to create {}: {}", dir.display(), e))?;
+        }
+        let mut file = File::create(path).map_err(|e| format!("Unable to create {}: {}", path.display(), e))?;
+        file.write_all(header.to_text().as_bytes()).map_err(|e| format!("Unable to write {}: {}", path.display(), e))?;
+        Ok(Recorder { path: path.to_path_buf(), file, keys: KeyState::default(), checksumEvery: header.checksumEvery })
+    }
+
+    // record adds a frame that ran with the given keys, leaving the emulator as it is now.
+    pub fn record(&mut self, frame: u64, keys: KeyState, emul: &Emulator) -> Result<(), String> {
+        let mut lines = String::new();
+        if keys != self.keys {
+            lines.push_str(&Entry::Keys { frame, keys }.to_line());
+            self.keys = keys;
+        }
+        if self.checksumEvery > 0 && frame.is_multiple_of(self.checksumEvery) {
+            lines.push_str(&Entry::Check { frame, checksum: replay::checksum(emul) }.to_line());
+        }
+        self.file.write_all(lines.as_bytes()).map_err(|e| format!("Unable to write {}: {}", self.path.display(), e))
+    }
+}
+
+// Player feeds the keys of a replay to a session, frame by frame, and checks the state against the
+// checksums of the replay. The first checksum that differs tells the emulator doesn't run the ROM
+// the way it did when recording, e.g. as an opcode does something else now.
+pub struct Player {
+    pub path: PathBuf,
+    pub replay: Replay,
+    next: usize,      // the first entry whose keys haven't been played yet
+    nextCheck: usize, // the first entry that hasn't been checked yet
+    keys: KeyState,   // the keys of the last keys entry played
+    pub checked: usize, // checksums that matched
+    pub lastMatched: u64, // the frame of the last of those, 0 for none
+}
+
+// Divergence is where the state first differed from a replay.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Divergence {
+    pub frame: u64,       // the frame whose checksum differed
+    pub lastMatched: u64, // the last frame whose checksum did match, 0 for none
+}
+
+impl Divergence {
+    // describe says where the state diverged, which is the exact frame when the replay has a
+    // checksum for every frame and otherwise one of those since the last match.
+    pub fn describe(&self) -> String {
+        if self.frame == self.lastMatched + 1 {
+            format!("the state no longer matches the replay, it diverged in frame {}", self.frame)
+        } else {
+            format!("the state no longer matches the replay, it diverged in frames {}-{} as the replay has no checksums in between (record with --checksum-every 1 for the exact frame)",
+                self.lastMatched + 1, self.frame)
+        }
+    }
+}
+
+impl Player {
+    // open reads a replay, which must have been recorded with the ROM given.
+    pub fn open(path: &Path, rom: &[u8]) -> Result<Player, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("Unable to read {}: {}", path.display(), e))?;
+        let replay = Replay::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+        replay.header.check(rom).map_err(|e| format!("Unable to play {}: {}", path.display(), e))?;
+        Ok(Player { path: path.to_path_buf(), replay, next: 0, nextCheck: 0, keys: KeyState::default(), checked: 0, lastMatched: 0 })
+    }
+
+    // keys returns the keys of a frame, which must come after the frame asked for before.
+    pub fn keys(&mut self, frame: u64) -> KeyState {
+        while let Some(entry) = self.replay.entries.get(self.next).filter(|e| e.frame() <= frame) {
+            if let Entry::Keys { keys, .. } = entry {
+                self.keys = *keys;
+            }
+            self.next += 1;
+        }
+        self.keys
+    }
+
+    // check compares the state after a frame with the checksum the replay has for it, if any.
+    pub fn check(&mut self, frame: u64, emul: &Emulator) -> Result<(), Divergence> {
+        while let Some(entry) = self.replay.entries.get(self.nextCheck).filter(|e| e.frame() <= frame) {
+            self.nextCheck += 1;
+            if let Entry::Check { frame, checksum } = *entry {
+                if replay::checksum(emul) != checksum {
+                    return Err(Divergence { frame, lastMatched: self.lastMatched });
+                }
+                self.checked += 1;
+                self.lastMatched = frame;
+            }
+        }
+        Ok(())
+    }
+
+    // finished returns whether the replay has no more frames after this one.
+    pub fn finished(&self, frame: u64) -> bool {
+        frame >= self.replay.frames()
+    }
+}
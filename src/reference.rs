@@ -0,0 +1,251 @@
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use crate::emulator::{BIG_FONT, BIG_FONT_OFFSET, DEFAULT_SEED, FONT, FONT_OFFSET, MEM_OFFSET, SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::error::Error;
+use crate::quirks::Quirks;
+
+// Reference is a deliberately plain CHIP8 interpreter written straight from the spec, without any
+// of the tricks or extensions of the real core. It exists only to be stepped in lockstep with
+// the Emulator (see difftest), so that changes to the real core that alter opcode semantics
+// show up as a divergence between the two.
+pub struct Reference {
+    pub memory: [u8; 4096],
+    pub v: [u8; 16],
+    pub i: u16,
+    pub pc: u16,
+    pub stack: Vec<u16>,
+    pub delay: u8,
+    pub sound: u8,
+    pub display: [u8; SCREEN_WIDTH * SCREEN_HEIGHT],
+    pub keys: u16,
+    pub quirks: Quirks,
+    pub rpl: [u8; 8],
+    heldKey: Option<u8>,
+    rng: u32,
+}
+
+impl Reference {
+    // new loads a ROM at 0x200, which has to fit in the 4kb of memory from there.
+    pub fn new(rom: &[u8], quirks: Quirks) -> Result<Reference, Error> {
+        let mut memory = [0; 4096];
+        if rom.len() > memory.len() - MEM_OFFSET {
+            return Err(Error::RomTooLarge { size: rom.len(), address: MEM_OFFSET, max: memory.len() - MEM_OFFSET });
+        }
+        memory[MEM_OFFSET..MEM_OFFSET + rom.len()].copy_from_slice(rom);
+        memory[FONT_OFFSET as usize..FONT_OFFSET as usize + FONT.len()].copy_from_slice(&FONT);
+        // not used by any CHIP8 instruction, but it's part of the memory image that is compared.
+        memory[BIG_FONT_OFFSET as usize..BIG_FONT_OFFSET as usize + BIG_FONT.len()].copy_from_slice(&BIG_FONT);
+        Ok(Reference {
+            memory,
+            v: [0; 16],
+            i: 0,
+            pc: MEM_OFFSET as u16,
+            stack: Vec::new(),
+            delay: 0,
+            sound: 0,
+            display: [0; SCREEN_WIDTH * SCREEN_HEIGHT],
+            keys: 0,
+            quirks,
+            rpl: [0; 8],
+            heldKey: None,
+            rng: DEFAULT_SEED,
+        })
+    }
+
+    // seed must be called with the same seed as Emulator::seed, both use the same xorshift32.
+    pub fn seed(&mut self, seed: u32) {
+        self.rng = if seed == 0 { DEFAULT_SEED } else { seed };
+    }
+
+    fn random(&mut self) -> u8 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 17;
+        self.rng ^= self.rng << 5;
+        (self.rng >> 24) as u8
+    }
+
+    fn skip(&mut self, condition: bool) {
+        if condition {
+            self.pc = self.pc.wrapping_add(2);
+        }
+    }
+
+    fn pressed(&self, key: u8) -> bool {
+        self.keys & (1 << (key & 0xF)) != 0
+    }
+
+    pub fn tick_timers(&mut self) {
+        self.delay = self.delay.saturating_sub(1);
+        self.sound = self.sound.saturating_sub(1);
+    }
+
+    // step executes the instruction at pc. Running out of stack in either direction stops the
+    // machine, leaving pc at the call or return.
+    pub fn step(&mut self) -> Result<(), String> {
+        let pc = self.pc as usize;
+        let op = u16::from_be_bytes([self.memory[pc % 4096], self.memory[(pc + 1) % 4096]]);
+        self.pc = self.pc.wrapping_add(2);
+
+        let x = ((op >> 8) & 0xF) as usize;
+        let y = ((op >> 4) & 0xF) as usize;
+        let n = (op & 0xF) as u8;
+        let nn = (op & 0xFF) as u8;
+        let nnn = op & 0xFFF;
+
+        match op >> 12 {
+            0x0 if op == 0x00E0 => self.display = [0; SCREEN_WIDTH * SCREEN_HEIGHT],
+            0x0 if op == 0x00EE => match self.stack.pop() {
+                Some(ret) => self.pc = ret,
+                None => {
+                    self.pc = pc as u16;
+                    return Err("stack underflow".to_string());
+                }
+            },
+            0x0 => {} // machine code routines are not supported
+            0x1 => self.pc = nnn,
+            0x2 => {
+                if self.stack.len() >= self.quirks.stackDepth {
+                    self.pc = pc as u16;
+                    return Err("stack overflow".to_string());
+                }
+                self.stack.push(self.pc);
+                self.pc = nnn;
+            }
+            0x3 => self.skip(self.v[x] == nn),
+            0x4 => self.skip(self.v[x] != nn),
+            0x5 if n == 0 => self.skip(self.v[x] == self.v[y]),
+            0x6 => self.v[x] = nn,
+            0x7 => self.v[x] = self.v[x].wrapping_add(nn),
+            0x8 => self.alu(x, y, n),
+            0x9 if n == 0 => self.skip(self.v[x] != self.v[y]),
+            0xA => self.i = nnn,
+            0xB => {
+                let offset = if self.quirks.jumpUsesVX { self.v[x] } else { self.v[0] };
+                self.pc = nnn + offset as u16;
+            }
+            0xC => self.v[x] = self.random() & nn,
+            0xD => self.draw(self.v[x] as usize, self.v[y] as usize, n as usize),
+            0xE if nn == 0x9E => self.skip(self.pressed(self.v[x])),
+            0xE if nn == 0xA1 => self.skip(!self.pressed(self.v[x])),
+            0xF => self.misc(x, nn),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    // alu executes the 8XYN arithmetic and logic instructions. VF is always written last.
+    fn alu(&mut self, x: usize, y: usize, n: u8) {
+        let (vx, vy) = (self.v[x], self.v[y]);
+        let (result, flag) = match n {
+            0x0 => (vy, None),
+            0x1 => (vx | vy, self.quirks.vfReset.then_some(0)),
+            0x2 => (vx & vy, self.quirks.vfReset.then_some(0)),
+            0x3 => (vx ^ vy, self.quirks.vfReset.then_some(0)),
+            0x4 => {
+                let (r, carry) = vx.overflowing_add(vy);
+                (r, Some(carry as u8))
+            }
+            0x5 => (vx.wrapping_sub(vy), Some((vx >= vy) as u8)),
+            0x6 => {
+                let source = if self.quirks.shiftUsesVY { vy } else { vx };
+                (source >> 1, Some(source & 0x1))
+            }
+            0x7 => (vy.wrapping_sub(vx), Some((vy >= vx) as u8)),
+            0xE => {
+                let source = if self.quirks.shiftUsesVY { vy } else { vx };
+                (source << 1, Some(source >> 7))
+            }
+            _ => return,
+        };
+        self.v[x] = result;
+        if let Some(f) = flag {
+            self.v[0xF] = f;
+        }
+    }
+
+    fn draw(&mut self, x: usize, y: usize, height: usize) {
+        let (x, y) = (x % SCREEN_WIDTH, y % SCREEN_HEIGHT);
+        self.v[0xF] = 0;
+        for row in 0..height {
+            let sprite = self.memory[(self.i as usize + row) % 4096];
+            for col in 0..8 {
+                if sprite & (0x80 >> col) == 0 {
+                    continue;
+                }
+                let (px, py) = (x + col, y + row);
+                if self.quirks.clipSprites && (px >= SCREEN_WIDTH || py >= SCREEN_HEIGHT) {
+                    continue;
+                }
+                let index = (py % SCREEN_HEIGHT) * SCREEN_WIDTH + px % SCREEN_WIDTH;
+                if self.display[index] == 1 {
+                    self.v[0xF] = 1;
+                }
+                self.display[index] ^= 1;
+            }
+        }
+    }
+
+    fn misc(&mut self, x: usize, nn: u8) {
+        match nn {
+            0x07 => self.v[x] = self.delay,
+            0x0A => {
+                // wait for a key to be pressed and then released
+                match self.heldKey {
+                    Some(key) if !self.pressed(key) => {
+                        self.v[x] = key;
+                        self.heldKey = None;
+                    }
+                    Some(_) => self.pc = self.pc.wrapping_sub(2),
+                    None => {
+                        self.heldKey = (0..16).find(|k| self.pressed(*k));
+                        self.pc = self.pc.wrapping_sub(2);
+                    }
+                }
+            }
+            0x15 => self.delay = self.v[x],
+            0x18 => self.sound = self.v[x],
+            0x1E => {
+                let sum = self.i.wrapping_add(self.v[x] as u16);
+                if self.quirks.indexOverflowSetsVF {
+                    self.v[0xF] = (sum > 0xFFF) as u8;
+                }
+                self.i = sum % 0x1000;
+            }
+            0x29 => self.i = FONT_OFFSET + (self.v[x] & 0xF) as u16 * 5,
+            0x33 => {
+                let i = self.i as usize;
+                self.memory[i % 4096] = self.v[x] / 100;
+                self.memory[(i + 1) % 4096] = self.v[x] / 10 % 10;
+                self.memory[(i + 2) % 4096] = self.v[x] % 10;
+            }
+            0x55 => {
+                for r in 0..=x {
+                    self.memory[(self.i as usize + r) % 4096] = self.v[r];
+                }
+                if self.quirks.loadStoreIncrementsI {
+                    self.i = self.i.wrapping_add(x as u16 + 1);
+                }
+            }
+            0x65 => {
+                for r in 0..=x {
+                    self.v[r] = self.memory[(self.i as usize + r) % 4096];
+                }
+                if self.quirks.loadStoreIncrementsI {
+                    self.i = self.i.wrapping_add(x as u16 + 1);
+                }
+            }
+            0x75 => {
+                for r in 0..=x.min(7) {
+                    self.rpl[r] = self.v[r];
+                }
+            }
+            0x85 => {
+                for r in 0..=x.min(7) {
+                    self.v[r] = self.rpl[r];
+                }
+            }
+            _ => {}
+        }
+    }
+}
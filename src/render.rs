@@ -0,0 +1,249 @@
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use crate::emulator::{PixelChange, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+
+// Rotation of the emulator display as it's blitted to the window, clockwise.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rotation {
+    R0,
+    R90,
+    R180,
+    R270,
+}
+
+impl Rotation {
+    pub fn parse(degrees: &str) -> Result<Rotation, String> {
+        match degrees {
+            "0" => Ok(Rotation::R0),
+            "90" => Ok(Rotation::R90),
+            "180" => Ok(Rotation::R180),
+            "270" => Ok(Rotation::R270),
+            _ => Err(format!("invalid rotation '{}', expected 0, 90, 180 or 270", degrees)),
+        }
+    }
+
+    // size returns the width and height of a width x height image after rotating it.
+    pub fn size(&self, width: usize, height: usize) -> (usize, usize) {
+        match self {
+            Rotation::R0 | Rotation::R180 => (width, height),
+            Rotation::R90 | Rotation::R270 => (height, width),
+        }
+    }
+
+    // apply maps pixel x,y of a width x height image to where it ends up in the rotated image.
+    pub fn apply(&self, x: usize, y: usize, width: usize, height: usize) -> (usize, usize) {
+        match self {
+            Rotation::R0 => (x, y),
+            Rotation::R90 => (height - 1 - y, x),
+            Rotation::R180 => (width - 1 - x, height - 1 - y),
+            Rotation::R270 => (y, width - 1 - x),
+        }
+    }
+
+    // remap_key is the input hook matching the rotation. Many ROMs use 2, 4, 6 and 8 as up, left,
+    // right and down, so with a rotated display those keys are rotated back the other way to keep
+    // e.g. "up" on the keypad moving things up on the screen. Other keys are left alone.
+    pub fn remap_key(&self, key: u8) -> u8 {
+        let directions: [u8; 4] = [0x2, 0x6, 0x8, 0x4]; // up, right, down, left - clockwise order
+        let steps = match self {
+            Rotation::R0 => 0,
+            Rotation::R90 => 3,
+            Rotation::R180 => 2,
+            Rotation::R270 => 1,
+        };
+        match directions.iter().position(|d| *d == key) {
+            Some(i) => directions[(i + steps) % 4],
+            None => key,
+        }
+    }
+}
+
+// Palette is the colors the display is drawn in: dark pixels, lit pixels, and pixels that differ
+// between the two cores in compare mode. A pixel is a bit per XO-CHIP plane, so there are colors
+// for pixels lit on the second plane only and on both planes too, which only show once there are
+// ROMs drawing on the second plane.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Palette {
+    pub on: [u8; 4],     // lit on the first plane, the only one of CHIP8 and SCHIP
+    pub off: [u8; 4],
+    pub diff: [u8; 4],
+    pub plane2: [u8; 4], // lit on the second plane only
+    pub both: [u8; 4],   // lit on both planes
+}
+
+// PALETTE_NAMES are the palettes parse takes by name.
+pub const PALETTE_NAMES: [&str; 3] = ["default", "high-contrast", "colorblind"];
+
+impl Palette {
+    pub const DEFAULT: Palette = Palette {
+        on: [0xFF, 0xFF, 0xFF, 0xff],
+        off: [0x0, 0x0, 0x0, 0xff],
+        diff: [0xFF, 0x0, 0x0, 0xff],
+        plane2: [0xAA, 0xAA, 0xAA, 0xff],
+        both: [0x55, 0x55, 0x55, 0xff],
+    };
+
+    // HIGH_CONTRAST is yellow on black, the brightest color there is against the darkest, with
+    // differences in cyan.
+    pub const HIGH_CONTRAST: Palette = Palette {
+        on: [0xFF, 0xFF, 0x0, 0xff],
+        off: [0x0, 0x0, 0x0, 0xff],
+        diff: [0x0, 0xFF, 0xFF, 0xff],
+        plane2: [0xFF, 0x0, 0xFF, 0xff],
+        both: [0xFF, 0xFF, 0xFF, 0xff],
+    };
+
+    // COLORBLIND shows differences in the orange of the Okabe-Ito palette, which is told apart
+    // from white and black with any kind of color blindness. The red of the default palette is
+    // hard to see against black for the red-green color blind, who are most of them. The planes
+    // are in its sky blue and bluish green.
+    pub const COLORBLIND: Palette = Palette {
+        on: [0xFF, 0xFF, 0xFF, 0xff],
+        off: [0x0, 0x0, 0x0, 0xff],
+        diff: [0xE6, 0x9F, 0x0, 0xff],
+        plane2: [0x56, 0xB4, 0xE9, 0xff],
+        both: [0x0, 0x9E, 0x73, 0xff],
+    };
+
+    // parse takes one of PALETTE_NAMES, or the colors of dark pixels, pixels lit on the first
+    // plane, on the second and on both as RGB hex, e.g. "101820,F2AA4C,4C9BF2,FFFFFF", of which
+    // those left out are the default palette's.
+    pub fn parse(name: &str) -> Result<Palette, String> {
+        match name {
+            "default" => Ok(Palette::DEFAULT),
+            "high-contrast" => Ok(Palette::HIGH_CONTRAST),
+            "colorblind" => Ok(Palette::COLORBLIND),
+            _ if name.contains(',') => {
+                let colors = name.split(',').map(|c| rgb(c.trim())).collect::<Result<Vec<_>, _>>()?;
+                if colors.len() > 4 {
+                    return Err(format!("invalid palette '{}', expected at most four colors", name));
+                }
+                let mut palette = Palette::DEFAULT;
+                for (color, field) in colors.into_iter().zip([&mut palette.off, &mut palette.on, &mut palette.plane2, &mut palette.both]) {
+                    *field = color;
+                }
+                Ok(palette)
+            }
+            _ => Err(format!("invalid palette '{}', expected default, high-contrast, colorblind or colors like 000000,FFFFFF", name)),
+        }
+    }
+
+    // spec returns what parse takes for the palette: its name, or its colors for one of its own.
+    pub fn spec(&self) -> String {
+        match *self {
+            Palette::DEFAULT => "default".to_string(),
+            Palette::HIGH_CONTRAST => "high-contrast".to_string(),
+            Palette::COLORBLIND => "colorblind".to_string(),
+            _ => {
+                let hex = |c: [u8; 4]| format!("{:02X}{:02X}{:02X}", c[0], c[1], c[2]);
+                format!("{},{},{},{}", hex(self.off), hex(self.on), hex(self.plane2), hex(self.both))
+            }
+        }
+    }
+
+    // color returns the color of a lit or dark pixel.
+    pub fn color(&self, lit: bool) -> [u8; 4] {
+        if lit { self.on } else { self.off }
+    }
+
+    // pixel returns the color of a pixel of the display, a bit per plane.
+    pub fn pixel(&self, planes: u8) -> [u8; 4] {
+        [self.off, self.on, self.plane2, self.both][(planes & 3) as usize]
+    }
+
+    // cycled returns the palette with the colors of the lit pixels moved on by a number of steps,
+    // those of the first plane going to the second, the second to both and both to the first, for
+    // the color cycling of demos, see --palette-cycle.
+    pub fn cycled(&self, steps: u64) -> Palette {
+        let colors = [self.on, self.plane2, self.both];
+        let color = |n: usize| colors[(n + 3 - (steps % 3) as usize) % 3];
+        Palette { on: color(0), plane2: color(1), both: color(2), ..*self }
+    }
+
+    // blend returns the color of a pixel level from 0 for dark to 255 for lit, in between for the
+    // frames the flash limiter blends.
+    pub fn blend(&self, level: u8) -> [u8; 4] {
+        let mix = |off: u8, on: u8| ((off as u32 * (255 - level as u32) + on as u32 * level as u32) / 255) as u8;
+        [mix(self.off[0], self.on[0]), mix(self.off[1], self.on[1]), mix(self.off[2], self.on[2]), 0xff]
+    }
+}
+
+// rgb parses a color as six hex digits.
+pub fn rgb(value: &str) -> Result<[u8; 4], String> {
+    let digits = value.strip_prefix('#').unwrap_or(value);
+    match u32::from_str_radix(digits, 16) {
+        Ok(color) if digits.len() == 6 => Ok([(color >> 16) as u8, (color >> 8) as u8, color as u8, 0xff]),
+        _ => Err(format!("invalid color '{}', expected six hex digits like FFAA00", value)),
+    }
+}
+
+// Screen describes how the displays of one or more emulator cores are laid out side by side in
+// the "pixels" frame.
+#[derive(Clone, Copy)]
+pub struct Screen {
+    pub rotation: Rotation,
+    pub cores: usize,
+    pub keypadRows: usize, // rows below the displays for the on-screen keypad, if shown
+    pub scopeRows: usize, // rows below those for the sound scope, if shown
+    pub graphRows: usize, // rows below those for the frame-time graph, if shown
+    pub palette: Palette,
+}
+
+impl Screen {
+    // size returns the width and height of the whole frame.
+    pub fn size(&self) -> (usize, usize) {
+        let (w, h) = self.rotation.size(DISPLAY_WIDTH, DISPLAY_HEIGHT);
+        (w * self.cores, h + self.keypadRows + self.scopeRows + self.graphRows)
+    }
+
+    // display_size returns the width and height of the display of a single core in the frame.
+    pub fn display_size(&self) -> (usize, usize) {
+        self.rotation.size(DISPLAY_WIDTH, DISPLAY_HEIGHT)
+    }
+
+    // keypad_top returns the first row below the displays.
+    pub fn keypad_top(&self) -> usize {
+        self.display_size().1
+    }
+
+    // scope_top returns the first row below the displays and keypad.
+    pub fn scope_top(&self) -> usize {
+        self.keypad_top() + self.keypadRows
+    }
+
+    // index returns the offset in the frame of pixel x,y of the display of the given core.
+    fn index(&self, core: usize, x: usize, y: usize) -> usize {
+        let (width, _) = self.size();
+        let (coreWidth, _) = self.rotation.size(DISPLAY_WIDTH, DISPLAY_HEIGHT);
+        let (rx, ry) = self.rotation.apply(x, y, DISPLAY_WIDTH, DISPLAY_HEIGHT);
+        (ry * width + core * coreWidth + rx) * 4
+    }
+
+    // set_pixels applies the pixel changes of a frame to the frame.
+    pub fn set_pixels(&self, px: &mut [u8], core: usize, diff: &[PixelChange]) {
+        for change in diff {
+            let index = self.index(core, change.x as usize, change.y as usize);
+            px[index..index + 4].copy_from_slice(&self.palette.color(change.on));
+        }
+    }
+
+    // paint_levels redraws a complete emulator display from pixel levels, see Palette::blend.
+    pub fn paint_levels(&self, px: &mut [u8], core: usize, levels: &[u8]) {
+        for (i, level) in levels.iter().enumerate() {
+            let index = self.index(core, i % DISPLAY_WIDTH, i / DISPLAY_WIDTH);
+            px[index..index + 4].copy_from_slice(&self.palette.blend(*level));
+        }
+    }
+
+    // paint redraws a complete emulator display, coloring the pixels that differ from the other
+    // display in the diff color of the palette.
+    pub fn paint(&self, px: &mut [u8], core: usize, display: &[u8], other: &[u8]) {
+        for (i, (pixel, otherPixel)) in display.iter().zip(other.iter()).enumerate() {
+            let index = self.index(core, i % DISPLAY_WIDTH, i / DISPLAY_WIDTH);
+            let color = if pixel != otherPixel { self.palette.diff } else { self.palette.pixel(*pixel) };
+            px[index..index + 4].copy_from_slice(&color);
+        }
+    }
+}
@@ -0,0 +1,301 @@
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use crate::emulator::{Emulator, KeyState, MachineCode};
+use crate::hash::fnv1a;
+use crate::quirks::Quirks;
+use crate::variant::Variant;
+
+// A replay is a recording of a ROM being played: what the emulator ran it with and the keys of
+// every frame, so that running the ROM again the same way gives the very same game. It's a text
+// file, written as the ROM runs:
+//
+//   rust-chip8 replay 1
+//   rom 5A1F3C0D9E2B7746 3232
+//   variant schip
+//   quirks vfreset=off,shift=off,loadstore=off,jump=on,indexoverflow=off,clip=on,rowcollisions=on,halfscroll=on,stack=16
+//   instructions-per-frame 500
+//   machine-code skip
+//   seed 1B2C3D4E
+//   checksum-every 60
+//   keys 12 0010
+//   keys 15 0000
+//   check 60 9C3E0F2A11D4B7E8
+//
+// The first line names the format and its version, which only goes up for changes that older
+// versions can't play. Then come the header lines, in any order and all required:
+//
+//   rom <hash> <size>          the FNV-1a hash of the ROM file (see hash::fnv1a) and its size
+//   variant <name>             chip8 or schip
+//   quirks <spec>              every quirk spelled out, see Quirks::spec
+//   instructions-per-frame <n>
+//   machine-code <mode>        skip, halt or vip
+//   seed <hex>                 the seed of the random generator, see Emulator::seed
+//   checksum-every <frames>    how often the state checksum is recorded, 0 for never
+//
+// Header lines a version doesn't know are skipped, so newer versions can add information that
+// doesn't change how the ROM runs. After the header, in frame order:
+//
+//   keys <frame> <hex>         from this frame on the keys are down, one bit per key as KeyState
+//   check <frame> <hex>        the checksum of the state after this frame, see checksum
+//
+// Frames count from 1, the first frame run, and the keys of a frame are those the frame ran with.
+// Until the first keys line no key is down. The replay ends with the frame of its last line. Empty
+// lines and lines starting with # are ignored.
+
+// VERSION is the version of the replay format written, and the newest that can be played.
+pub const VERSION: u32 = 1;
+
+// MAGIC starts the first line of every replay file.
+const MAGIC: &str = "rust-chip8 replay";
+
+// Header is what a ROM must run with for a replay to play back the same.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Header {
+    pub romHash: u64,
+    pub romSize: usize,
+    pub variant: Variant,
+    pub quirks: Quirks,
+    pub instructionsPerFrame: u32,
+    pub machineCode: MachineCode,
+    pub seed: u32,
+    pub checksumEvery: u64,
+}
+
+impl Header {
+    // of returns the header for recording a ROM on an emulator set up to run it, with the seed it
+    // was given.
+    pub fn of(emul: &Emulator, rom: &[u8], seed: u32, checksumEvery: u64) -> Header {
+        Header {
+            romHash: fnv1a(rom),
+            romSize: rom.len(),
+            variant: emul.variant,
+            quirks: emul.quirks,
+            instructionsPerFrame: emul.instructionsPerFrame,
+            machineCode: emul.machineCode,
+            seed,
+            checksumEvery,
+        }
+    }
+
+    // check returns an error unless the ROM is the one the replay was recorded with.
+    pub fn check(&self, rom: &[u8]) -> Result<(), String> {
+        if fnv1a(rom) != self.romHash || rom.len() != self.romSize {
+            return Err(format!("the replay was recorded with another ROM, of {} bytes with hash {:016X}", self.romSize, self.romHash));
+        }
+        Ok(())
+    }
+
+    // apply sets an emulator up to run like the replay was recorded. It must not have run yet.
+    pub fn apply(&self, emul: &mut Emulator) {
+        emul.variant = self.variant;
+        emul.quirks = self.quirks;
+        emul.instructionsPerFrame = self.instructionsPerFrame;
+        emul.machineCode = self.machineCode;
+        emul.seed(self.seed);
+    }
+
+    // to_text returns the first line and the header lines, each ending with a newline.
+    pub fn to_text(&self) -> String {
+        format!("{} {}\nrom {:016X} {}\nvariant {}\nquirks {}\ninstructions-per-frame {}\nmachine-code {}\nseed {:08X}\nchecksum-every {}\n",
+            MAGIC, VERSION, self.romHash, self.romSize, self.variant.spec(), self.quirks.spec(), self.instructionsPerFrame,
+            self.machineCode.name(), self.seed, self.checksumEvery)
+    }
+}
+
+// Entry is a line of a replay after the header.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Entry {
+    Keys { frame: u64, keys: KeyState },
+    Check { frame: u64, checksum: u64 },
+}
+
+impl Entry {
+    pub fn frame(&self) -> u64 {
+        match self {
+            Entry::Keys { frame, .. } | Entry::Check { frame, .. } => *frame,
+        }
+    }
+
+    // to_line returns the entry as a line of the replay, ending with a newline.
+    pub fn to_line(&self) -> String {
+        match self {
+            Entry::Keys { frame, keys } => format!("keys {} {:04X}\n", frame, keys.0),
+            Entry::Check { frame, checksum } => format!("check {} {:016X}\n", frame, checksum),
+        }
+    }
+}
+
+// Replay is a replay file as read by parse.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Replay {
+    pub header: Header,
+    pub entries: Vec<Entry>,
+}
+
+impl Replay {
+    // parse reads a replay file, with errors saying which line is wrong.
+    pub fn parse(text: &str) -> Result<Replay, String> {
+        let mut lines = text.lines().enumerate().map(|(i, l)| (i + 1, l.trim())).filter(|(_, l)| !l.is_empty() && !l.starts_with('#'));
+        let version = match lines.next() {
+            Some((_, line)) => line.strip_prefix(MAGIC).and_then(|v| v.trim().parse::<u32>().ok()),
+            None => None,
+        };
+        match version {
+            Some(version) if version > VERSION => return Err(format!("replay version {} is newer than this emulator plays ({})", version, VERSION)),
+            Some(_) => {}
+            None => return Err(format!("not a replay file, expected it to start with \"{} <version>\"", MAGIC)),
+        }
+
+        let (mut rom, mut variant, mut quirks, mut instructionsPerFrame, mut machineCode, mut seed, mut checksumEvery) = (None, None, None, None, None, None, None);
+        let mut entries: Vec<Entry> = Vec::new();
+        for (n, line) in lines {
+            let error = |e: String| format!("line {}: {}", n, e);
+            let (key, value) = line.split_once(' ').map(|(k, v)| (k, v.trim())).unwrap_or((line, ""));
+            match key {
+                "rom" => {
+                    let (hash, size) = value.split_once(' ').ok_or_else(|| error("expected rom <hash> <size>".to_string()))?;
+                    rom = Some((hex(hash).map_err(error)?, size.trim().parse::<usize>().map_err(|_| error(format!("invalid ROM size '{}'", size)))?));
+                }
+                "variant" => variant = Some(Variant::parse(value).map_err(error)?),
+                "quirks" => quirks = Some(Quirks::parse(value).map_err(error)?),
+                "instructions-per-frame" => instructionsPerFrame = Some(value.parse::<u32>().map_err(|_| error(format!("invalid number '{}'", value)))?),
+                "machine-code" => machineCode = Some(MachineCode::parse(value).map_err(error)?),
+                "seed" => seed = Some(hex(value).map_err(error)? as u32),
+                "checksum-every" => checksumEvery = Some(value.parse::<u64>().map_err(|_| error(format!("invalid number '{}'", value)))?),
+                "keys" | "check" => {
+                    let (frame, data) = value.split_once(' ').ok_or_else(|| error(format!("expected {} <frame> <hex>", key)))?;
+                    let frame = frame.parse::<u64>().map_err(|_| error(format!("invalid frame '{}'", frame)))?;
+                    let data = hex(data.trim()).map_err(error)?;
+                    let entry = if key == "keys" { Entry::Keys { frame, keys: KeyState(data as u16) } } else { Entry::Check { frame, checksum: data } };
+                    if entries.last().is_some_and(|last| last.frame() > frame) {
+                        return Err(error(format!("frame {} comes after frame {}", frame, entries.last().unwrap().frame())));
+                    }
+                    entries.push(entry);
+                }
+                _ if entries.is_empty() => {} // from a newer version, see above
+                _ => return Err(error(format!("unknown line '{}'", line))),
+            }
+        }
+
+        let missing = |name: &str| format!("the replay has no {} line", name);
+        let (romHash, romSize) = rom.ok_or_else(|| missing("rom"))?;
+        let header = Header {
+            romHash,
+            romSize,
+            variant: variant.ok_or_else(|| missing("variant"))?,
+            quirks: quirks.ok_or_else(|| missing("quirks"))?,
+            instructionsPerFrame: instructionsPerFrame.ok_or_else(|| missing("instructions-per-frame"))?,
+            machineCode: machineCode.ok_or_else(|| missing("machine-code"))?,
+            seed: seed.ok_or_else(|| missing("seed"))?,
+            checksumEvery: checksumEvery.ok_or_else(|| missing("checksum-every"))?,
+        };
+        Ok(Replay { header, entries })
+    }
+
+    // frames returns how many frames the replay covers.
+    pub fn frames(&self) -> u64 {
+        self.entries.last().map(|e| e.frame()).unwrap_or(0)
+    }
+}
+
+// checksum hashes everything of the state of an emulator that decides how the ROM goes on: memory,
+// the registers, I, the program counter, the stack, the timers, the display and the random
+// generator.
+pub fn checksum(emul: &Emulator) -> u64 {
+    let mut state = Vec::with_capacity(emul.memory.len() + emul.framebuffer().len() + 64);
+    state.extend_from_slice(emul.memory.bytes());
+    state.extend_from_slice(&emul.registers);
+    state.extend_from_slice(&emul.I.to_be_bytes());
+    state.extend_from_slice(&emul.pc.to_be_bytes());
+    for address in emul.stack.iter() {
+        state.extend_from_slice(&address.to_be_bytes());
+    }
+    state.extend_from_slice(&[emul.delayTimer, emul.soundTimer, emul.hires as u8]);
+    state.extend_from_slice(emul.framebuffer());
+    state.extend_from_slice(&emul.random_state().to_be_bytes());
+    fnv1a(&state)
+}
+
+// hex parses a hexadecimal number, with or without 0x, as replays and save states write them.
+pub fn hex(value: &str) -> Result<u64, String> {
+    let digits = value.strip_prefix("0x").unwrap_or(value);
+    u64::from_str_radix(digits, 16).map_err(|_| format!("invalid hex number '{}'", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROM: [u8; 4] = [0x70, 0x01, 0x12, 0x00];
+
+    fn replay() -> Replay {
+        let mut emul = Emulator::new();
+        emul.load_rom(&ROM).unwrap();
+        let entries = vec![
+            Entry::Keys { frame: 1, keys: KeyState(0x0010) },
+            Entry::Check { frame: 60, checksum: 0x9C3E0F2A11D4B7E8 },
+            Entry::Keys { frame: 61, keys: KeyState(0) },
+        ];
+        Replay { header: Header::of(&emul, &ROM, 0x1B2C3D4E, 60), entries }
+    }
+
+    fn text(replay: &Replay) -> String {
+        replay.header.to_text() + &replay.entries.iter().map(|e| e.to_line()).collect::<String>()
+    }
+
+    #[test]
+    fn readsWhatItWrites() {
+        let replay = replay();
+        assert_eq!(Replay::parse(&text(&replay)), Ok(replay.clone()));
+        assert_eq!(replay.frames(), 61);
+        assert!(replay.header.check(&ROM).is_ok());
+        assert!(replay.header.check(&[0x70, 0x02, 0x12, 0x00]).is_err());
+    }
+
+    #[test]
+    fn skipsCommentsAndNewerHeaderLines() {
+        let replay = replay();
+        let text = text(&replay).replacen("seed", "# a comment\n\nrecorded-by 0.9\nseed", 1);
+        assert_eq!(Replay::parse(&text), Ok(replay));
+    }
+
+    #[test]
+    fn explainsMalformedReplays() {
+        let text = text(&replay());
+        let without = |key: &str| text.lines().filter(|l| !l.starts_with(key)).map(|l| format!("{}\n", l)).collect::<String>();
+        let errors = [
+            ("rust-chip8 state 1\n".to_string(), "not a replay file, expected it to start with \"rust-chip8 replay <version>\""),
+            (text.replacen("replay 1", "replay 2", 1), "replay version 2 is newer than this emulator plays (1)"),
+            (without("seed"), "the replay has no seed line"),
+            (text.clone() + "keys 5 0010\n", "line 12: frame 5 comes after frame 61"),
+            (text.clone() + "keys x 0010\n", "line 12: invalid frame 'x'"),
+            (text.clone() + "check 70\n", "line 12: expected check <frame> <hex>"),
+            (text.clone() + "frobnicate 1\n", "line 12: unknown line 'frobnicate 1'"),
+        ];
+        for (text, error) in errors {
+            assert_eq!(Replay::parse(&text), Err(error.to_string()), "{}", text);
+        }
+    }
+
+    #[test]
+    fn checksumsTheWholeState() {
+        let mut emul = Emulator::new();
+        emul.load_rom(&ROM).unwrap();
+        let before = checksum(&emul);
+        assert_eq!(checksum(&emul.clone()), before);
+        let changes: [fn(&mut Emulator); 5] = [
+            |emul| emul.memory.bytes_mut()[0xFFF] ^= 1,
+            |emul| emul.registers[0xF] = 1,
+            |emul| emul.stack.push(0x202),
+            |emul| emul.delayTimer = 1,
+            |emul| emul.seed(1),
+        ];
+        for (n, change) in changes.iter().enumerate() {
+            let mut changed = emul.clone();
+            change(&mut changed);
+            assert_ne!(checksum(&changed), before, "change {}", n);
+        }
+    }
+}
@@ -0,0 +1,221 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rust_chip8::emulator::{Emulator, KeyState};
+use rust_chip8::memory::MEMORY_SIZE;
+use rust_chip8::replay;
+use rust_chip8::savestate::State;
+
+use crate::bundle;
+use crate::config;
+use crate::paths;
+use crate::saves;
+
+// A bug report is a zip the emulator writes to the reports directory when a ROM stops on an error
+// or the emulator crashes running it, for attaching to a bug report as it is:
+//
+//   report.toml     what happened: the version, the ROM, the frame and the error, the checksum of
+//                   the state it stopped in (see replay::checksum), the frame of start.txt and
+//                   how many frames inputs.txt has
+//   <rom>.ch8       the ROM, as loaded
+//   state.txt       the save state of the first core where it stopped, see savestate
+//   start.txt       a save state from one to two seconds before that, see Rewind
+//   inputs.txt      the keys of every frame from start.txt on, the fault's too, as the keys lines
+//                   of a replay with the frames counted from there
+//   trace.txt       the last instructions it ran, as many as --history keeps
+//
+// "rust-chip8 repro <report>.zip" runs the ROM from start.txt with the keys of inputs.txt, checking
+// that it stops on the same error at the same frame in the same state, so the fault plays out
+// exactly as it did. Without a start.txt, that of a fault right after the ROM was loaded or the
+// state changed, it runs on from state.txt instead, which stops right away at the failing
+// instruction again.
+
+const REPORT: &str = "report.toml";
+const STATE: &str = "state.txt";
+const TRACE: &str = "trace.txt";
+const START: &str = "start.txt";
+const INPUTS: &str = "inputs.txt";
+
+// CHECKPOINT_FRAMES is how often Rewind saves the state, a second at 60hz.
+const CHECKPOINT_FRAMES: u64 = 60;
+
+// Rewind keeps what it takes to run up to a fault again from a while before it: a save state of the
+// first core from between CHECKPOINT_FRAMES and twice as many frames ago, and the keys of every
+// frame since. It starts over whenever the ROM is made to go on differently than the keys do, by
+// a reset, a quirk toggled or such.
+#[derive(Default)]
+pub struct Rewind {
+    start: Option<(State, u64)>, // the state and the frames run until then
+    next: Option<(State, u64)>,  // the start to be, once the start is two checkpoints ago
+    keys: Vec<KeyState>,         // of the frames from start on
+}
+
+impl Rewind {
+    // frame takes in a frame about to run, the frames run so far and the keys it runs with.
+    pub fn frame(&mut self, emul: &Emulator, frame: u64, keys: KeyState) {
+        match &self.start {
+            None => self.start = Some((emul.save_state(), frame)),
+            Some((_, start)) if frame.is_multiple_of(CHECKPOINT_FRAMES) => {
+                if let Some(next) = self.next.take() {
+                    self.keys.drain(..(next.1 - start) as usize);
+                    self.start = Some(next);
+                }
+                self.next = Some((emul.save_state(), frame));
+            }
+            Some(_) => {}
+        }
+        self.keys.push(keys);
+    }
+}
+
+// Report is a bug report as read back by read.
+pub struct Report {
+    pub romFile: String,
+    pub rom: Vec<u8>,
+    pub frame: u64,
+    pub error: String, // as flatten left it
+    pub version: String, // of the emulator that wrote it
+    pub state: State,
+    pub checksum: Option<u64>, // of the state it stopped in
+    pub start: Option<(State, u64)>, // start.txt and its frame
+    pub inputs: Vec<KeyState>, // of the frames from start on
+    pub trace: String,
+}
+
+// write writes a report of a ROM that stopped on an error at a frame, with the state of its first
+// core and how it got there, and returns the file.
+pub fn write(romPath: &str, emul: &Emulator, frame: u64, error: &str, rewind: &Rewind) -> Result<PathBuf, String> {
+    fs::create_dir_all(paths::reports()).map_err(|e| format!("Unable to create {}: {}", paths::reports().display(), e))?;
+    let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let path = paths::reports().join(format!("{}-{}.zip", saves::romName(romPath), seconds));
+    fs::write(&path, zip(romPath, emul, frame, error, rewind)).map_err(|e| format!("Unable to write {}: {}", path.display(), e))?;
+    Ok(path)
+}
+
+// zip returns the zip write writes.
+fn zip(romPath: &str, emul: &Emulator, frame: u64, error: &str, rewind: &Rewind) -> Vec<u8> {
+    let romName = saves::romName(romPath);
+    // the ROM is stored under the name the summary has for it.
+    let romFile = flatten(&Path::new(romPath).file_name().map(|f| f.to_string_lossy().to_string()).unwrap_or_else(|| format!("{}.ch8", romName)));
+    let mut summary = format!("version = \"{}\"\nrom = \"{}\"\nframe = {}\nerror = \"{}\"\nchecksum = \"{:016X}\"\n",
+        env!("CARGO_PKG_VERSION"), romFile, frame, flatten(error), replay::checksum(emul));
+    let trace: String = emul.history().map(|h| h.iter().map(|entry| entry.describe(emul.variant) + "\n").collect()).unwrap_or_default();
+    let mut files = vec![
+        (romFile, emul.rom().to_vec()),
+        (STATE.to_string(), emul.save_state().to_text().into_bytes()),
+        (TRACE.to_string(), trace.into_bytes()),
+    ];
+    if let Some((start, startFrame)) = &rewind.start {
+        summary += &format!("start_frame = {}\ninput_frames = {}\n", startFrame, rewind.keys.len());
+        let mut inputs = String::new();
+        for (n, keys) in rewind.keys.iter().enumerate() {
+            if n == 0 || rewind.keys[n - 1] != *keys {
+                inputs += &replay::Entry::Keys { frame: n as u64 + 1, keys: *keys }.to_line();
+            }
+        }
+        files.push((START.to_string(), start.to_text().into_bytes()));
+        files.push((INPUTS.to_string(), inputs.into_bytes()));
+    }
+    files.insert(0, (REPORT.to_string(), summary.into_bytes()));
+    bundle::zip(&files)
+}
+
+// flatten makes a text fit in a string of report.toml: the config format reads a line at a time
+// and has no escapes, so quotes become single quotes and newlines and other control characters
+// spaces. The messages of assert_eq! and the like span several lines.
+pub fn flatten(text: &str) -> String {
+    text.chars().map(|c| match c {
+        '"' => '\'',
+        c if c.is_control() => ' ',
+        c => c,
+    }).collect()
+}
+
+// read reads a report written by write.
+pub fn read(path: &str) -> Result<Report, String> {
+    let data = fs::read(path).map_err(|e| format!("Unable to read {}: {}", path, e))?;
+    parse(path, &data)
+}
+
+// parse reads the zip of a report, the file path for the errors.
+fn parse(path: &str, data: &[u8]) -> Result<Report, String> {
+    let files = bundle::unzip(data).map_err(|e| format!("{}: {}", path, e))?;
+    let file = |name: &str| files.iter().find(|(n, _)| n == name).map(|(_, data)| String::from_utf8_lossy(data).to_string());
+    let summary = config::parse(&file(REPORT).ok_or_else(|| format!("{} is not a bug report, it has no {}", path, REPORT))?)
+        .map_err(|e| format!("{}: {}: {}", path, REPORT, e))?;
+    let value = |key: &str| summary.value("", key).unwrap_or_default().to_string();
+    let romFile = value("rom");
+    let rom = files.iter().find(|(n, _)| *n == romFile).map(|(_, data)| data.clone()).ok_or_else(|| format!("{} has no ROM {}", path, romFile))?;
+    let state = State::parse(&file(STATE).ok_or_else(|| format!("{} has no {}", path, STATE))?, MEMORY_SIZE).map_err(|e| format!("{}: {}: {}", path, STATE, e))?;
+    let start = match (file(START), value("start_frame").parse::<u64>()) {
+        (Some(text), Ok(frame)) => Some((State::parse(&text, MEMORY_SIZE).map_err(|e| format!("{}: {}: {}", path, START, e))?, frame)),
+        _ => None,
+    };
+    let inputs = match (&start, file(INPUTS)) {
+        (Some(_), Some(text)) => readInputs(&text, value("input_frames").parse().unwrap_or(0)).map_err(|e| format!("{}: {}: {}", path, INPUTS, e))?,
+        _ => Vec::new(),
+    };
+    Ok(Report {
+        romFile,
+        rom,
+        frame: value("frame").parse().unwrap_or(0),
+        error: value("error"),
+        version: value("version"),
+        state,
+        checksum: u64::from_str_radix(&value("checksum"), 16).ok(),
+        start,
+        inputs,
+        trace: file(TRACE).unwrap_or_default(),
+    })
+}
+
+// readInputs reads the keys of a number of frames from the keys lines of inputs.txt, each holding
+// until the next one.
+fn readInputs(text: &str, frames: u64) -> Result<Vec<KeyState>, String> {
+    let mut changes = Vec::new();
+    for (n, line) in text.lines().enumerate().filter(|(_, l)| !l.trim().is_empty()) {
+        let parsed = line.strip_prefix("keys ").and_then(|l| l.split_once(' '))
+            .and_then(|(frame, keys)| Some((frame.parse::<u64>().ok()?, u16::from_str_radix(keys.trim(), 16).ok()?)));
+        changes.push(parsed.ok_or_else(|| format!("line {}: expected keys <frame> <hex>", n + 1))?);
+    }
+    let mut keys = KeyState::default();
+    Ok((1..=frames).map(|frame| {
+        if let Some((_, k)) = changes.iter().find(|(f, _)| *f == frame) {
+            keys = KeyState(*k);
+        }
+        keys
+    }).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A crash of an assert_eq! has quotes and newlines in its message, and the report it's written
+    // to still has to be read back by repro.
+    #[test]
+    fn readsWhatItWrites() {
+        let mut emul = Emulator::new();
+        emul.load_rom(&[0x60, 0x01, 0x70, 0x01, 0x12, 0x02]).unwrap();
+        emul.record_history(8);
+        let mut rewind = Rewind::default();
+        for frame in 0..3 {
+            let keys = KeyState(if frame == 1 { 0x0010 } else { 0 });
+            rewind.frame(&emul, frame, keys);
+            emul.tick_frame(keys).unwrap();
+        }
+        let error = "crashed: assertion `left == right` failed: \"score\"\n  left: 1\n right: 2";
+
+        let report = parse("report.zip", &zip("roms/test \"rom\".ch8", &emul, 3, error, &rewind)).unwrap();
+        assert_eq!(report.error, "crashed: assertion `left == right` failed: 'score'   left: 1  right: 2");
+        assert_eq!(report.romFile, "test 'rom'.ch8");
+        assert_eq!(report.rom, emul.rom());
+        assert_eq!(report.frame, 3);
+        assert_eq!(report.state, emul.save_state());
+        assert_eq!(report.checksum, Some(replay::checksum(&emul)));
+        assert_eq!(report.start.as_ref().map(|(_, frame)| *frame), Some(0));
+        assert_eq!(report.inputs, vec![KeyState(0), KeyState(0x0010), KeyState(0)]);
+        assert_eq!(report.trace.lines().count(), 8);
+    }
+}
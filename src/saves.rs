@@ -0,0 +1,66 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::atomicfile;
+use crate::paths;
+
+// romName returns the file name of a ROM without directory and extension, which is what per-ROM
+// settings and saved data are keyed on.
+pub fn romName(romPath: &str) -> &str {
+    Path::new(romPath).file_stem().and_then(|s| s.to_str()).unwrap_or("rom")
+}
+
+// savePath returns the file holding one kind of persisted data for a ROM, named after the ROM file,
+// in paths::saves, e.g. spaceinvaders.rpl for the RPL flags of ./roms/spaceinvaders.ch8.
+pub fn savePath(romPath: &str, extension: &str) -> PathBuf {
+    paths::saves().join(format!("{}.{}", romName(romPath), extension))
+}
+
+// slotPath returns the file of a save state slot, counting from 0, in paths::saves, e.g. slot1.state
+// for the first. Unlike the other saves they're not of a ROM, each has the ROM it was saved with.
+pub fn slotPath(n: usize) -> PathBuf {
+    paths::saves().join(format!("slot{}.state", n + 1))
+}
+
+// loadRplFlags reads the SCHIP RPL flags saved by a previous session. A ROM that has never saved
+// any starts out with all flags cleared.
+pub fn loadRplFlags(romPath: &str) -> [u8; 8] {
+    let path = savePath(romPath, "rpl");
+    match atomicfile::read(&path, |data| data.try_into().ok()) {
+        Ok(flags) => flags,
+        Err(e) => {
+            if e.kind() == io::ErrorKind::InvalidData {
+                println!("Ignoring {}, expected 8 bytes", path.display());
+            }
+            [0; 8]
+        }
+    }
+}
+
+// saveRplFlags writes the SCHIP RPL flags so that e.g. high scores survive a restart.
+pub fn saveRplFlags(romPath: &str, flags: &[u8; 8]) -> io::Result<()> {
+    fs::create_dir_all(paths::saves())?;
+    atomicfile::write(&savePath(romPath, "rpl"), flags)
+}
+
+// loadMemory reads the persisted memory range saved by a previous session, if there is one and it
+// has the expected size.
+pub fn loadMemory(romPath: &str, size: usize) -> Option<Vec<u8>> {
+    let path = savePath(romPath, "mem");
+    match atomicfile::read(&path, |data| (data.len() == size).then(|| data.to_vec())) {
+        Ok(data) => Some(data),
+        Err(e) => {
+            if e.kind() == io::ErrorKind::InvalidData {
+                println!("Ignoring {}, expected {} bytes", path.display(), size);
+            }
+            None
+        }
+    }
+}
+
+// saveMemory writes the contents of the persisted memory range, the "battery-backed RAM" of a ROM.
+pub fn saveMemory(romPath: &str, data: &[u8]) -> io::Result<()> {
+    fs::create_dir_all(paths::saves())?;
+    atomicfile::write(&savePath(romPath, "mem"), data)
+}
@@ -0,0 +1,327 @@
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::emulator::{KeyState, MachineCode, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+use crate::hash::fnv1a;
+use crate::quirks::Quirks;
+use crate::replay::hex;
+use crate::variant::Variant;
+
+// A save state is everything of an emulator that decides how the ROM goes on, see
+// Emulator::save_state, so that it can go on somewhere else, e.g. from a bug report. It's a text
+// file like a replay:
+//
+//   rust-chip8 state 1
+//   rom 5A1F3C0D9E2B7746 3232
+//   variant schip
+//   quirks vfreset=off,shift=off,loadstore=off,jump=on,indexoverflow=off,clip=on,rowcollisions=on,halfscroll=on,stack=16
+//   instructions-per-frame 500
+//   machine-code skip
+//   halt-on-unknown-opcode off
+//   pc 02A4
+//   i 03F0
+//   v 00112233445566778899AABBCCDDEEFF
+//   timers 00 0A
+//   stack 0202 0246
+//   hires off
+//   keys 0010
+//   random 1B2C3D4E
+//   held-key none
+//   rpl 0000000000000000
+//   memory 0200 6A02600C...
+//   display 12 00000000F0000000...
+//   shown 12 00000000F0000000...
+//
+// The first line names the format and its version, like that of a replay. rom is the hash and
+// size of the ROM the state is of, see hash::fnv1a, the next lines how it runs, then come the
+// registers V0-VF, the delay and sound timers, the return addresses on the stack from the bottom
+// up, the keys held as KeyState, the state of the random generator, the key FX0A waits to be
+// released and the RPL flags, all in hex. memory lines have the address and 32 bytes from there,
+// display lines a row of the display as drawn so far and shown lines as of the end of the last
+// frame, a bit per pixel. Lines of memory and rows of the display that are all zeros are left out.
+
+// VERSION is the version of the save state format written, and the newest that can be loaded.
+pub const VERSION: u32 = 1;
+
+// MAGIC starts the first line of every save state.
+pub const MAGIC: &str = "rust-chip8 state";
+
+// MEMORY_LINE is how many bytes of memory each memory line has.
+const MEMORY_LINE: usize = 32;
+
+// State is a save state as Emulator::save_state returns it and Emulator::load_state takes it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct State {
+    pub romHash: u64,
+    pub romSize: usize,
+    pub variant: Variant,
+    pub quirks: Quirks,
+    pub instructionsPerFrame: u32,
+    pub machineCode: MachineCode,
+    pub haltOnUnknownOpcode: bool,
+    pub pc: u16,
+    pub I: u16,
+    pub registers: [u8; 16],
+    pub delayTimer: u8,
+    pub soundTimer: u8,
+    pub stack: Vec<u16>,
+    pub hires: bool,
+    pub keys: KeyState,
+    pub random: u32,
+    pub heldKey: Option<u8>,
+    pub rplFlags: [u8; 8],
+    pub memory: Vec<u8>,
+    pub display: Vec<u8>, // one byte per pixel like Emulator::display
+    pub shown: Vec<u8>,   // the same for Emulator::framebuffer
+}
+
+impl State {
+    // check returns an error unless the ROM is the one the state is of.
+    pub fn check(&self, rom: &[u8]) -> Result<(), String> {
+        if fnv1a(rom) != self.romHash || rom.len() != self.romSize {
+            return Err(format!("the state is of another ROM, of {} bytes with hash {:016X}", self.romSize, self.romHash));
+        }
+        Ok(())
+    }
+
+    // to_text returns the state as the lines of a save state file, each ending with a newline.
+    pub fn to_text(&self) -> String {
+        let onOff = |on: bool| if on { "on" } else { "off" };
+        let mut text = format!("{} {}\nrom {:016X} {}\nvariant {}\nquirks {}\ninstructions-per-frame {}\nmachine-code {}\nhalt-on-unknown-opcode {}\n",
+            MAGIC, VERSION, self.romHash, self.romSize, self.variant.spec(), self.quirks.spec(), self.instructionsPerFrame,
+            self.machineCode.name(), onOff(self.haltOnUnknownOpcode));
+        let stack: Vec<String> = self.stack.iter().map(|a| format!("{:04X}", a)).collect();
+        let heldKey = self.heldKey.map_or("none".to_string(), |k| format!("{:X}", k));
+        text += &format!("pc {:04X}\ni {:04X}\nv {}\ntimers {:02X} {:02X}\nstack {}\nhires {}\nkeys {:04X}\nrandom {:08X}\nheld-key {}\nrpl {}\n",
+            self.pc, self.I, hexBytes(&self.registers), self.delayTimer, self.soundTimer, stack.join(" "), onOff(self.hires),
+            self.keys.0, self.random, heldKey, hexBytes(&self.rplFlags));
+        for (n, line) in self.memory.chunks(MEMORY_LINE).enumerate().filter(|(_, l)| l.iter().any(|b| *b != 0)) {
+            text += &format!("memory {:04X} {}\n", n * MEMORY_LINE, hexBytes(line));
+        }
+        for (key, pixels) in [("display", &self.display), ("shown", &self.shown)] {
+            for (y, row) in pixels.chunks(DISPLAY_WIDTH).enumerate().filter(|(_, r)| r.contains(&1)) {
+                let bits: Vec<u8> = row.chunks(8).map(|byte| byte.iter().fold(0, |bits, p| bits << 1 | (*p & 1))).collect();
+                text += &format!("{} {} {}\n", key, y, hexBytes(&bits));
+            }
+        }
+        text
+    }
+
+    // parse reads a save state file, with errors saying which line is wrong. The memory is
+    // memorySize bytes, that of the emulator, and a memory line beyond it is an error, as are a pc
+    // or return address outside of it, more return addresses than the stack depth of the quirks
+    // and numbers too large for their register, so that a damaged file can't be loaded.
+    pub fn parse(text: &str, memorySize: usize) -> Result<State, String> {
+        let mut lines = text.lines().enumerate().map(|(i, l)| (i + 1, l.trim())).filter(|(_, l)| !l.is_empty() && !l.starts_with('#'));
+        let version = match lines.next() {
+            Some((_, line)) => line.strip_prefix(MAGIC).and_then(|v| v.trim().parse::<u32>().ok()),
+            None => None,
+        };
+        match version {
+            Some(version) if version > VERSION => return Err(format!("save state version {} is newer than this emulator loads ({})", version, VERSION)),
+            Some(_) => {}
+            None => return Err(format!("not a save state, expected it to start with \"{} <version>\"", MAGIC)),
+        }
+
+        let mut state = State {
+            romHash: 0,
+            romSize: 0,
+            variant: Variant::Chip8,
+            quirks: Quirks::default(),
+            instructionsPerFrame: 0,
+            machineCode: MachineCode::Skip,
+            haltOnUnknownOpcode: false,
+            pc: 0,
+            I: 0,
+            registers: [0; 16],
+            delayTimer: 0,
+            soundTimer: 0,
+            stack: Vec::new(),
+            hires: false,
+            keys: KeyState::default(),
+            random: 0,
+            heldKey: None,
+            rplFlags: [0; 8],
+            memory: vec![0; memorySize],
+            display: vec![0; DISPLAY_WIDTH * DISPLAY_HEIGHT],
+            shown: vec![0; DISPLAY_WIDTH * DISPLAY_HEIGHT],
+        };
+        let mut seen = Vec::new();
+        let mut stackLine = 0;
+        for (n, line) in lines {
+            let error = |e: String| format!("line {}: {}", n, e);
+            let (key, value) = line.split_once(' ').map(|(k, v)| (k, v.trim())).unwrap_or((line, ""));
+            match key {
+                "rom" => {
+                    let (hash, size) = value.split_once(' ').ok_or_else(|| error("expected rom <hash> <size>".to_string()))?;
+                    state.romHash = hex(hash).map_err(error)?;
+                    state.romSize = size.trim().parse().map_err(|_| error(format!("invalid ROM size '{}'", size)))?;
+                }
+                "variant" => state.variant = Variant::parse(value).map_err(error)?,
+                "quirks" => state.quirks = Quirks::parse(value).map_err(error)?,
+                "instructions-per-frame" => {
+                    state.instructionsPerFrame = value.parse().ok().filter(|n| *n > 0).ok_or_else(|| error(format!("invalid number of instructions '{}'", value)))?;
+                }
+                "machine-code" => state.machineCode = MachineCode::parse(value).map_err(error)?,
+                "halt-on-unknown-opcode" => state.haltOnUnknownOpcode = onOff(value).map_err(error)?,
+                "pc" => state.pc = address(value, memorySize).map_err(error)?,
+                "i" => state.I = sized(value).map_err(error)?,
+                "v" => state.registers = fixed(value).map_err(error)?,
+                "timers" => {
+                    let (delay, sound) = value.split_once(' ').ok_or_else(|| error("expected timers <delay> <sound>".to_string()))?;
+                    state.delayTimer = sized(delay).map_err(error)?;
+                    state.soundTimer = sized(sound.trim()).map_err(error)?;
+                }
+                "stack" => {
+                    state.stack = value.split_whitespace().map(|a| address(a, memorySize)).collect::<Result<_, _>>().map_err(error)?;
+                    stackLine = n;
+                }
+                "hires" => state.hires = onOff(value).map_err(error)?,
+                "keys" => state.keys = KeyState(sized(value).map_err(error)?),
+                "random" => state.random = sized(value).map_err(error)?,
+                "held-key" if value == "none" => state.heldKey = None,
+                "held-key" => state.heldKey = Some(sized(value).ok().filter(|k| *k <= 0xF).ok_or_else(|| error(format!("invalid key '{}'", value)))?),
+                "rpl" => state.rplFlags = fixed(value).map_err(error)?,
+                "memory" => {
+                    let (address, data) = value.split_once(' ').ok_or_else(|| error("expected memory <address> <hex>".to_string()))?;
+                    let (start, data) = (hex(address).map_err(error)?, bytes(data.trim()).map_err(error)?);
+                    let end = usize::try_from(start).ok().and_then(|start| start.checked_add(data.len())).filter(|end| *end <= memorySize)
+                        .ok_or_else(|| error(format!("memory {} is beyond the {} bytes of memory", address, memorySize)))?;
+                    state.memory[end - data.len()..end].copy_from_slice(&data);
+                }
+                "display" | "shown" => {
+                    let (y, data) = value.split_once(' ').ok_or_else(|| error(format!("expected {} <row> <hex>", key)))?;
+                    let y = y.parse::<usize>().ok().filter(|y| *y < DISPLAY_HEIGHT).ok_or_else(|| error(format!("invalid row '{}'", y)))?;
+                    let bits: [u8; DISPLAY_WIDTH / 8] = fixed(data.trim()).map_err(error)?;
+                    let pixels = if key == "display" { &mut state.display } else { &mut state.shown };
+                    for (x, pixel) in pixels[y * DISPLAY_WIDTH..(y + 1) * DISPLAY_WIDTH].iter_mut().enumerate() {
+                        *pixel = bits[x / 8] >> (7 - x % 8) & 1;
+                    }
+                }
+                _ => {} // from a newer version, like the header lines of a replay
+            }
+            seen.push(key);
+        }
+        let required = ["rom", "variant", "quirks", "instructions-per-frame", "machine-code", "pc", "i", "v", "timers", "random"];
+        if let Some(missing) = required.iter().find(|key| !seen.contains(key)) {
+            return Err(format!("the save state has no {} line", missing));
+        }
+        if state.stack.len() > state.quirks.stackDepth {
+            return Err(format!("line {}: {} return addresses are more than the stack depth of {}", stackLine, state.stack.len(), state.quirks.stackDepth));
+        }
+        Ok(state)
+    }
+}
+
+// sized parses a hex number that has to fit in a T, like the 16 bits of I.
+fn sized<T: TryFrom<u64>>(value: &str) -> Result<T, String> {
+    hex(value)?.try_into().map_err(|_| format!("'{}' doesn't fit in {} bits", value, 8 * core::mem::size_of::<T>()))
+}
+
+// address parses a hex address that has to be in the memorySize bytes of memory.
+fn address(value: &str, memorySize: usize) -> Result<u16, String> {
+    let address: u16 = sized(value)?;
+    if address as usize >= memorySize {
+        return Err(format!("address {} is beyond the {} bytes of memory", value, memorySize));
+    }
+    Ok(address)
+}
+
+fn hexBytes(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+// bytes parses bytes written as two hex digits each, like hexBytes writes them.
+fn bytes(value: &str) -> Result<Vec<u8>, String> {
+    if !value.len().is_multiple_of(2) || !value.is_ascii() {
+        return Err(format!("invalid hex bytes '{}'", value));
+    }
+    (0..value.len()).step_by(2).map(|i| u8::from_str_radix(&value[i..i + 2], 16).map_err(|_| format!("invalid hex bytes '{}'", value))).collect()
+}
+
+// fixed parses a given number of hex bytes.
+fn fixed<const N: usize>(value: &str) -> Result<[u8; N], String> {
+    bytes(value)?.try_into().map_err(|_| format!("expected {} hex bytes, got '{}'", N, value))
+}
+
+fn onOff(value: &str) -> Result<bool, String> {
+    match value {
+        "on" => Ok(true),
+        "off" => Ok(false),
+        _ => Err(format!("expected on or off, got '{}'", value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::{Emulator, MEM_OFFSET};
+    use crate::memory::MEMORY_SIZE;
+
+    // ROM calls a subroutine that draws the font's 0 with the delay timer set and then waits for a
+    // key with FX0A, for a state with a bit of everything.
+    const ROM: [u8; 16] = [0x22, 0x04, 0x12, 0x02, 0xA0, 0x50, 0x60, 0x05, 0x6F, 0x3C, 0xFF, 0x15, 0xD0, 0x05, 0xF1, 0x0A];
+
+    fn started() -> Emulator {
+        let mut emul = Emulator::new();
+        emul.load_rom(&ROM).unwrap();
+        emul.seed(0x1234);
+        for keys in [0, 0, 0x0010] {
+            emul.tick_frame(KeyState(keys)).unwrap();
+        }
+        emul
+    }
+
+    // with replaces the line of a key of a save state.
+    fn with(text: &str, key: &str, line: &str) -> String {
+        text.lines().map(|l| if l.split(' ').next() == Some(key) { format!("{}\n", line) } else { format!("{}\n", l) }).collect()
+    }
+
+    #[test]
+    fn goesOnWhereItWasSaved() {
+        let mut emul = started();
+        let state = State::parse(&emul.save_state().to_text(), MEMORY_SIZE).unwrap();
+        assert_eq!(state, emul.save_state());
+        assert_eq!((state.stack.as_slice(), state.heldKey, state.delayTimer), ([MEM_OFFSET as u16 + 2].as_slice(), Some(4), 0x39));
+
+        let mut loaded = Emulator::new();
+        loaded.load_rom(&ROM).unwrap();
+        loaded.load_state(&state);
+        for keys in [0x0010, 0, 0] {
+            emul.tick_frame(KeyState(keys)).unwrap();
+            loaded.tick_frame(KeyState(keys)).unwrap();
+        }
+        assert_eq!(loaded.save_state(), emul.save_state());
+        assert_eq!(loaded.registers[1], 4);
+    }
+
+    #[test]
+    fn leavesOutZeros() {
+        let text = started().save_state().to_text();
+        let memory: Vec<&str> = text.lines().filter(|l| l.starts_with("memory ")).map(|l| &l[..11]).collect();
+        assert_eq!(memory, ["memory 0040", "memory 0060", "memory 0080", "memory 00A0", "memory 00C0", "memory 00E0", "memory 0100", "memory 0120", "memory 0200"]);
+        assert_eq!(text.lines().filter(|l| l.starts_with("display ")).count(), 10);
+    }
+
+    #[test]
+    fn refusesValuesOutOfRange() {
+        let text = started().save_state().to_text();
+        let line = |key: &str| text.lines().position(|l| l.split(' ').next() == Some(key)).unwrap() + 1;
+        let parse = |key: &str, replacement: &str| State::parse(&with(&text, key, replacement), MEMORY_SIZE).unwrap_err();
+
+        assert_eq!(parse("pc", "pc 1000"), format!("line {}: address 1000 is beyond the 4096 bytes of memory", line("pc")));
+        assert_eq!(parse("pc", "pc 10200"), format!("line {}: '10200' doesn't fit in 16 bits", line("pc")));
+        assert_eq!(parse("i", "i 10000"), format!("line {}: '10000' doesn't fit in 16 bits", line("i")));
+        assert_eq!(parse("timers", "timers 100 00"), format!("line {}: '100' doesn't fit in 8 bits", line("timers")));
+        assert_eq!(parse("stack", "stack 0202 F000"), format!("line {}: address F000 is beyond the 4096 bytes of memory", line("stack")));
+        assert_eq!(parse("held-key", "held-key 10"), format!("line {}: invalid key '10'", line("held-key")));
+        assert_eq!(parse("instructions-per-frame", "instructions-per-frame 0"), format!("line {}: invalid number of instructions '0'", line("instructions-per-frame")));
+        assert_eq!(parse("memory", "memory 0FF0 00000000000000000000000000000000000000"), format!("line {}: memory 0FF0 is beyond the 4096 bytes of memory", line("memory")));
+
+        let deep = with(&text, "quirks", &format!("quirks {}", Quirks { stackDepth: 2, ..Quirks::default() }.spec()));
+        let deep = with(&deep, "stack", "stack 0202 0202 0202");
+        assert_eq!(State::parse(&deep, MEMORY_SIZE).unwrap_err(), format!("line {}: 3 return addresses are more than the stack depth of 2", line("stack")));
+    }
+}
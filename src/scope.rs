@@ -0,0 +1,85 @@
+use std::collections::VecDeque;
+
+// SCOPE_HEIGHT is the number of rows the sound scope adds below the display and keypad.
+pub const SCOPE_HEIGHT: usize = 16;
+
+// BUZZER_PATTERN is the buzzer as an XO-CHIP audio pattern: 128 one-bit samples, played at 4000
+// samples a second at the default pitch. XO-CHIP ROMs load their own pattern with F002, which the
+// core doesn't run, so for now it's always this square wave of 500hz, near enough the 440hz the
+// sdl2 frontend beeps at.
+pub const BUZZER_PATTERN: [u8; 16] = [0xF0; 16];
+
+// a second of sound timer fills the height of the activity graph.
+const FULL_SCALE: usize = 60;
+const WAVE_HIGH: usize = 3;
+const WAVE_LOW: usize = SCOPE_HEIGHT - 4;
+
+const ACTIVE_COLOR: [u8; 4] = [0xFF, 0xC0, 0x30, 0xff];
+const IDLE_COLOR: [u8; 4] = [0x50, 0x50, 0x50, 0xff];
+const BACKGROUND: [u8; 4] = [0x10, 0x10, 0x10, 0xff];
+
+// SoundScope shows what the sound is doing, so audio can be checked without listening: on the left
+// a scrolling graph of the sound timer, one column per frame, and on the right the waveform of
+// the audio pattern, lit while the sound plays.
+pub struct SoundScope {
+    timers: VecDeque<u8>, // the sound timer at the end of each of the latest frames
+    waveWidth: usize,
+    graphWidth: usize,
+}
+
+impl SoundScope {
+    // new returns a scope for a frame width pixels wide, with a column between graph and waveform.
+    pub fn new(width: usize) -> SoundScope {
+        let waveWidth = (width / 2).min(64);
+        let graphWidth = width - waveWidth - 1;
+        SoundScope { timers: VecDeque::with_capacity(graphWidth), waveWidth, graphWidth }
+    }
+
+    pub fn push(&mut self, soundTimer: u8) {
+        if self.timers.len() == self.graphWidth {
+            self.timers.pop_front();
+        }
+        self.timers.push_back(soundTimer);
+    }
+
+    // draw paints the scope over the SCOPE_HEIGHT rows from top of the frame, which is width pixels
+    // wide like the one the scope was made for.
+    pub fn draw(&self, px: &mut [u8], width: usize, top: usize, pattern: &[u8; 16]) {
+        let mut set = |x: usize, row: usize, color: &[u8; 4]| {
+            let index = ((top + row) * width + x) * 4;
+            px[index..index + 4].copy_from_slice(color);
+        };
+        let (waveWidth, graphWidth) = (self.waveWidth, self.graphWidth);
+        let playing = self.timers.back().is_some_and(|t| *t > 0);
+
+        // the newest frame is always in the rightmost column of the graph.
+        let offset = graphWidth - self.timers.len().min(graphWidth);
+        for x in 0..graphWidth {
+            let timer = x.checked_sub(offset).and_then(|i| self.timers.get(i));
+            let rows = timer.map(|t| (*t as usize * SCOPE_HEIGHT).div_ceil(FULL_SCALE).min(SCOPE_HEIGHT)).unwrap_or(0);
+            for row in 0..SCOPE_HEIGHT {
+                // row 0 is the bottom of the graph
+                set(x, SCOPE_HEIGHT - 1 - row, if row < rows { &ACTIVE_COLOR } else { &BACKGROUND });
+            }
+        }
+        for row in 0..SCOPE_HEIGHT {
+            set(graphWidth, row, &IDLE_COLOR);
+        }
+
+        // the 128 samples of the pattern squeezed into the columns left, with the edges drawn in.
+        let color = if playing { &ACTIVE_COLOR } else { &IDLE_COLOR };
+        let sample = |x: usize| {
+            let s = x * 128 / waveWidth;
+            pattern[s / 8] >> (7 - s % 8) & 1 == 1
+        };
+        for x in 0..waveWidth {
+            let column = graphWidth + 1 + x;
+            let (high, previous) = (sample(x), sample(x.saturating_sub(1)));
+            for row in 0..SCOPE_HEIGHT {
+                let edge = high != previous && (WAVE_HIGH..=WAVE_LOW).contains(&row);
+                let level = row == if high { WAVE_HIGH } else { WAVE_LOW };
+                set(column, row, if edge || level { color } else { &BACKGROUND });
+            }
+        }
+    }
+}
@@ -0,0 +1,744 @@
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rust_chip8::emulator::{Emulator, FrameOutput, KeyState, Region, UnknownOpcode};
+use rust_chip8::error::Error;
+use rust_chip8::hash::fnv1a;
+use rust_chip8::hooks::{Event, Hooks};
+use rust_chip8::octo;
+use rust_chip8::quirks::Quirks;
+use rust_chip8::replay;
+use rust_chip8::savestate::{self, State};
+use rust_chip8::sprites;
+
+use crate::atomicfile;
+use crate::attract::Attract;
+use crate::bundle;
+use crate::cli::{Command, Options};
+use crate::commands::snapshot;
+use crate::inject::Injection;
+use crate::memfile;
+use crate::overlay;
+use crate::pacing::Pacing;
+use crate::paths;
+use crate::recording::{self, Divergence, Player, Recorder};
+use crate::report::{self, Rewind};
+use crate::saves;
+use crate::status::Status;
+
+// Control is what a frontend asks of a session besides running frames.
+pub enum Control {
+    Reset,
+    Load(String, Vec<u8>), // path and contents of another ROM to run
+    ToggleQuirk(&'static str), // a quirk of QUIRK_NAMES, of the first core
+    SaveState(usize), // to a slot of STATE_SLOTS
+    LoadState(usize),
+    Screenshot,
+    ExportSprites, // the display as Octo sprite data, see octo::declare
+    Speed(Option<u32>), // instructions per second, None for the default of the variant
+}
+
+// STATE_SLOTS is how many states can be saved, each to a slot of its own.
+pub const STATE_SLOTS: usize = 8;
+
+// SavedState is a state of the session saved to a slot: the save states of the cores, the ROM and
+// the frame. The slots are files in the saves directory, see saves::slotPath, so they're there
+// after a restart and the same in every tab. A slot file has a line with the ROM's path, the
+// frame and when it was saved as seconds since 1970, followed by the save state of every core, see
+// savestate:
+//
+//   slot 46 1760000000 roms/pong.ch8
+//   rust-chip8 state 1
+//   ...
+struct SavedState {
+    states: Vec<State>,
+    romPath: String,
+    frame: u64,
+    slot: StateSlot,
+}
+
+impl SavedState {
+    fn to_text(&self) -> String {
+        let at = self.slot.at.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        let mut text = format!("slot {} {} {}\n", self.frame, at, self.romPath);
+        for state in &self.states {
+            text += &state.to_text();
+        }
+        text
+    }
+
+    // parse reads a slot file, of cores with memorySize bytes of memory.
+    fn parse(text: &str, memorySize: usize) -> Result<SavedState, String> {
+        let (header, states) = text.split_once('\n').unwrap_or((text, ""));
+        let mut fields = header.trim_end().strip_prefix("slot ").map(|h| h.splitn(3, ' ')).ok_or("not a save state slot, expected it to start with \"slot\"")?;
+        let (frame, at, romPath) = match (fields.next().map(str::parse), fields.next().map(str::parse), fields.next()) {
+            (Some(Ok(frame)), Some(Ok(at)), Some(romPath)) => (frame, at, romPath.to_string()),
+            _ => return Err("expected slot <frame> <seconds> <rom>".to_string()),
+        };
+        // each state starts with the line naming the format.
+        let mut starts: Vec<usize> = states.match_indices(savestate::MAGIC).map(|(i, _)| i).filter(|i| *i == 0 || states[..*i].ends_with('\n')).collect();
+        if starts.is_empty() {
+            return Err("the slot has no save state".to_string());
+        }
+        starts.push(states.len());
+        let states = starts.windows(2).map(|w| State::parse(&states[w[0]..w[1]], memorySize)).collect::<Result<Vec<_>, _>>()?;
+        let slot = StateSlot {
+            romName: saves::romName(&romPath).to_string(),
+            frame,
+            at: UNIX_EPOCH + Duration::from_secs(at),
+            thumbnail: overlay::shrink(&states[0].shown),
+        };
+        Ok(SavedState { states, romPath, frame, slot })
+    }
+}
+
+// StateSlot is what the state browser shows of a saved state: the ROM, the frame, when it was
+// saved and a thumbnail of the display, see overlay::shrink.
+#[derive(Clone)]
+pub struct StateSlot {
+    pub romName: String,
+    pub frame: u64,
+    pub at: SystemTime,
+    pub thumbnail: Vec<u8>,
+}
+
+// UnknownOpcodePolicy is what a session does when a ROM runs an opcode the variant doesn't know,
+// from --unknown-opcodes. Whatever the policy, the opcodes are listed when the session ends.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnknownOpcodePolicy {
+    Ignore,   // skip them silently
+    WarnOnce, // skip them, printing each opcode the first time it runs
+    Pause,    // skip them, printing each one and pausing the first time it runs
+    Halt,     // stop with an error
+}
+
+impl UnknownOpcodePolicy {
+    pub fn parse(name: &str) -> Result<UnknownOpcodePolicy, String> {
+        match name {
+            "ignore" => Ok(UnknownOpcodePolicy::Ignore),
+            "warn-once" => Ok(UnknownOpcodePolicy::WarnOnce),
+            "pause" => Ok(UnknownOpcodePolicy::Pause),
+            "halt" => Ok(UnknownOpcodePolicy::Halt),
+            _ => Err(format!("unknown opcode policy '{}', expected ignore, warn-once, pause or halt", name)),
+        }
+    }
+}
+
+// Session is a ROM running on one or more cores, independent of the frontend showing it. In
+// compare mode a second core runs the very same ROM with other quirks. It takes care of the saved
+// data: the RPL flags and the persisted memory range of the first core. The game over and score
+// hooks of the ROM watch the first core too. With the record and play commands it records or
+// plays back a replay, which starts from power-on without the saved data.
+pub struct Session {
+    pub cores: Vec<Emulator>,
+    pub romPath: String,
+    pub frame: u64,
+    pub hooks: Hooks,
+    timerHz: u32, // frames per second, as the timers tick once per frame
+    breakOnSmc: bool,
+    unknownOpcodes: UnknownOpcodePolicy,
+    breakRequested: bool, // set when the session wants the frontend to pause, see take_break
+    persistRange: Option<Range<usize>>,
+    persisted: Vec<u8>, // the persisted memory range as last saved
+    seed: u32, // of the random generators of the cores
+    recorder: Option<Recorder>,
+    player: Option<Player>, // while playing, the keys come from here rather than the frontend
+    injection: Option<Injection>, // keys pressed by an --inject-input file, besides the frontend's
+    attract: Option<Attract>,
+    frameHashes: Option<(String, File)>, // the path and file of --hash-frames
+    loadMemory: Vec<(String, usize)>,    // the files of --load-memory and where they go
+    dumpMemory: Vec<(String, Range<usize>)>, // the files of --dump-memory and what goes in them
+    pub divergence: Option<Divergence>, // where the state first differed from the replay played
+    reported: bool, // a bug report was written, see report
+    rewind: Rewind, // for the bug report
+}
+
+impl Session {
+    pub fn new(options: &Options, data: &[u8]) -> Result<Session, String> {
+        let mut session = Session::open(options, &options.romPath, data, Hooks::new(options.gameOver.clone(), options.score.clone()))?;
+        session.injection = options.injectInput.as_deref().map(Injection::load).transpose()?;
+        if let Some(path) = &options.hashFrames {
+            let file = File::create(path).map_err(|e| format!("Unable to create {}: {}", path, e))?;
+            session.frameHashes = Some((path.clone(), file));
+        }
+        if let (Some(seconds), Command::Run) = (options.attract, &options.command) {
+            let playlist = match &options.playlist {
+                Some(playlist) => PathBuf::from(playlist),
+                None => Path::new(&options.romPath).parent().filter(|d| !d.as_os_str().is_empty()).unwrap_or(Path::new(".")).to_path_buf(),
+            };
+            session.attract = Some(Attract::new(seconds * options.timerHz as u64, &playlist, &options.romPath)?);
+        }
+        let path = options.replay.as_ref().map(PathBuf::from).unwrap_or_else(|| recording::replayPath(&options.romPath));
+        match options.command {
+            Command::Record => {
+                let header = replay::Header::of(&session.cores[0], data, session.seed, options.checksumEvery);
+                let recorder = Recorder::create(&path, &header)?;
+                session.recorder = Some(recorder);
+            }
+            Command::Play => {
+                let player = Player::open(&path, data)?;
+                // a core compared with keeps its own quirks.
+                for (i, emul) in session.cores.iter_mut().enumerate() {
+                    let quirks = emul.quirks;
+                    player.replay.header.apply(emul);
+                    if i > 0 {
+                        emul.quirks = quirks;
+                    }
+                }
+                session.player = Some(player);
+            }
+            _ => {}
+        }
+        Ok(session)
+    }
+
+    // open starts a session with another ROM than that of the options, like the window does for
+    // the ROMs it opens in tabs, with its own hooks.
+    pub fn open(options: &Options, romPath: &str, data: &[u8], hooks: Hooks) -> Result<Session, String> {
+        let mut cores: Vec<Emulator> = Vec::new();
+        for quirks in Some(options.quirks).into_iter().chain(options.compare) {
+            let mut emul = Emulator::new();
+            emul.load_rom(data).map_err(|e| format!("Unable to load {}: {}", romPath, e))?;
+            emul.quirks = quirks;
+            emul.variant = options.variant;
+            emul.instructionsPerFrame = options.instructionsPerFrame;
+            emul.haltOnUnknownOpcode = options.unknownOpcodes == UnknownOpcodePolicy::Halt;
+            emul.machineCode = options.machineCode;
+            cores.push(emul);
+        }
+
+        // every core must draw the same random numbers, otherwise the displays are bound to diverge.
+        let seed = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_micros() as u32).unwrap_or(0);
+        for emul in cores.iter_mut() {
+            emul.seed(seed);
+        }
+
+        let mut session = Session {
+            cores,
+            romPath: romPath.to_string(),
+            frame: 0,
+            hooks,
+            timerHz: options.timerHz,
+            breakOnSmc: options.breakOnSmc,
+            unknownOpcodes: options.unknownOpcodes,
+            breakRequested: false,
+            persistRange: options.persistRange.clone(),
+            persisted: Vec::new(),
+            seed,
+            recorder: None,
+            player: None,
+            divergence: None,
+            injection: None,
+            attract: None,
+            frameHashes: None,
+            loadMemory: options.loadMemory.clone(),
+            dumpMemory: options.dumpMemory.clone(),
+            reported: false,
+            rewind: Rewind::default(),
+        };
+        session.hooks.attach(&mut session.cores[0]);
+        session.cores[0].track_self_modification(options.warnSmc || options.breakOnSmc);
+        session.cores[0].record_history(options.history);
+        // a replay must start from the same state whatever was saved since.
+        if matches!(options.command, Command::Record | Command::Play) {
+            session.persistRange = None;
+        } else {
+            session.restore();
+        }
+        session.load_memory()?;
+        Ok(session)
+    }
+
+    // rom_name returns the file name of the ROM for display.
+    pub fn rom_name(&self) -> String {
+        Path::new(&self.romPath).file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default()
+    }
+
+    // load_info describes where the ROM was loaded, warns about padding that was left out and
+    // tells the replay being recorded or played.
+    pub fn load_info(&self) -> Vec<String> {
+        let emul = &self.cores[0];
+        let range = emul.rom_range();
+        let mut lines = vec![format!("Loaded {}: {} bytes at {:#05X}-{:#05X}", self.rom_name(), range.len(), range.start, range.end - 1)];
+        if emul.trimmed_padding() > 0 {
+            lines.push(format!("Left out {} bytes of zero padding at the end of the ROM that don't fit in memory", emul.trimmed_padding()));
+        }
+        if let Some(recorder) = &self.recorder {
+            lines.push(format!("Recording to {}", recorder.path.display()));
+        }
+        if let Some(player) = &self.player {
+            lines.push(format!("Playing {}, {} frames", player.path.display(), player.replay.frames()));
+        }
+        lines
+    }
+
+    // slots returns what's saved to each slot, for the state browser.
+    pub fn slots(&self) -> Vec<Option<StateSlot>> {
+        (0..STATE_SLOTS).map(|n| self.saved(n).ok().map(|s| s.slot)).collect()
+    }
+
+    // saved reads the state saved to a slot.
+    fn saved(&self, n: usize) -> Result<SavedState, String> {
+        let path = saves::slotPath(n);
+        let memorySize = self.cores[0].memory.len();
+        atomicfile::read(&path, |data| SavedState::parse(&String::from_utf8_lossy(data), memorySize).ok()).map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => format!("There's no state saved to slot {}", n + 1),
+            _ => format!("Unable to read {}: {}", path.display(), e),
+        })
+    }
+
+    // status returns the status to show for the session.
+    pub fn status(&self, variant: &'static str, paused: bool, pacing: &Pacing) -> Status {
+        Status {
+            romName: self.rom_name(),
+            variant,
+            ips: self.cores[0].instructionsPerFrame * self.timerHz,
+            paused,
+            turbo: pacing.turbo(),
+            slowMotion: pacing.slow_motion(),
+            comparing: self.cores.len() > 1,
+            demo: self.in_demo(),
+            score: self.hooks.score(),
+            gameOver: self.hooks.is_over(),
+        }
+    }
+
+    // control does what the frontend asks and returns a message saying what was done. When loading
+    // another ROM fails, the current one keeps running. The hooks came with the ROM's config, so
+    // another ROM goes without. The saved states are kept in STATE_SLOTS slots, see SavedState,
+    // and load with the ROM they were saved with.
+    pub fn control(&mut self, control: Control) -> Result<String, String> {
+        // what's asked is meant for the ROM the demos interrupted.
+        self.end_demo();
+        self.save_persisted();
+        // anything but a screenshot, an export or saving the state changes how the ROM goes on, which a
+        // replay can't tell, nor the rewind of a bug report.
+        if !matches!(control, Control::Screenshot | Control::ExportSprites | Control::SaveState(_)) {
+            self.rewind = Rewind::default();
+            if let Some(recorder) = self.recorder.take() {
+                println!("Stopped recording at frame {}, {} has the frames up to there", self.frame, recorder.path.display());
+            }
+            if let Some(player) = self.player.take() {
+                println!("Stopped playing {} at frame {}", player.path.display(), self.frame);
+            }
+        }
+        match control {
+            Control::Reset => self.cores.iter_mut().for_each(|emul| emul.reset()),
+            Control::ToggleQuirk(name) => {
+                // the core reads its quirks for every instruction, so this takes effect right away.
+                let quirks = &mut self.cores[0].quirks;
+                let on = !quirks.get(name)?;
+                quirks.set(name, on)?;
+                return Ok(quirkToggled(name, on, self.frame, self.frame > 0, quirks));
+            }
+            Control::SaveState(n) => {
+                if n >= STATE_SLOTS {
+                    return Err("There's no such slot".to_string());
+                }
+                let slot = StateSlot {
+                    romName: saves::romName(&self.romPath).to_string(),
+                    frame: self.frame,
+                    at: SystemTime::now(),
+                    thumbnail: overlay::shrink(self.cores[0].framebuffer()),
+                };
+                let states = self.cores.iter().map(|emul| emul.save_state()).collect();
+                let state = SavedState { states, romPath: self.romPath.clone(), frame: self.frame, slot };
+                let path = saves::slotPath(n);
+                fs::create_dir_all(paths::saves()).and_then(|_| atomicfile::write(&path, state.to_text().as_bytes()))
+                    .map_err(|e| format!("Unable to write {}: {}", path.display(), e))?;
+                return Ok(format!("Saved the state at frame {} to slot {}", self.frame, n + 1));
+            }
+            Control::LoadState(n) => {
+                let state = self.saved(n)?;
+                // the cores take over the settings of the current ones, the history and such.
+                let data = match state.romPath == self.romPath {
+                    true => self.cores[0].rom().to_vec(),
+                    false => bundle::readRom(&state.romPath)?,
+                };
+                let mut cores = Vec::new();
+                for (i, saved) in state.states.iter().enumerate() {
+                    let mut emul = self.cores.get(i).unwrap_or(&self.cores[0]).clone();
+                    emul.load_rom(&data).map_err(|e| format!("Unable to load {}: {}", state.romPath, e))?;
+                    saved.check(emul.rom()).map_err(|e| format!("Unable to load slot {}: {}", n + 1, e))?;
+                    emul.load_state(saved);
+                    cores.push(emul);
+                }
+                (self.cores, self.romPath, self.frame) = (cores, state.romPath, state.frame);
+                return Ok(format!("Loaded the state of frame {} from slot {}", self.frame, n + 1));
+            }
+            Control::Screenshot => {
+                fs::create_dir_all(paths::screenshots()).map_err(|e| format!("Unable to create {}: {}", paths::screenshots().display(), e))?;
+                let path = paths::screenshots().join(format!("{}-{}.png", saves::romName(&self.romPath), self.frame));
+                snapshot::writePng(&path, &snapshot::image(&self.cores[0])).map_err(|e| format!("Unable to write {}: {}", path.display(), e))?;
+                return Ok(format!("Wrote {}", path.display()));
+            }
+            Control::ExportSprites => {
+                fs::create_dir_all(paths::screenshots()).map_err(|e| format!("Unable to create {}: {}", paths::screenshots().display(), e))?;
+                let (romName, emul) = (saves::romName(&self.romPath), &self.cores[0]);
+                let path = paths::screenshots().join(format!("{}-{}.8o", romName, self.frame));
+                let (width, height) = emul.resolution();
+                let tiles = sprites::tiles(emul.framebuffer(), emul.hires, Region { x: 0, y: 0, width, height });
+                let text = octo::declare(&tiles, romName, &format!("the {}x{} display of {} at frame {}", width, height, romName, self.frame));
+                fs::write(&path, text).map_err(|e| format!("Unable to write {}: {}", path.display(), e))?;
+                return Ok(format!("Wrote {} sprites to {}", tiles.len(), path.display()));
+            }
+            Control::Speed(ips) => {
+                for emul in self.cores.iter_mut() {
+                    emul.instructionsPerFrame = match ips {
+                        Some(ips) => (ips / self.timerHz).max(1),
+                        None => emul.variant.instructions_per_frame(),
+                    };
+                }
+                return Ok(format!("Running at {} ips", self.cores[0].instructionsPerFrame * self.timerHz));
+            }
+            Control::Load(path, data) => {
+                // all cores take the same ROM, so checking the first one is enough.
+                self.cores[0].load_rom(&data).map_err(|e| format!("Unable to load {}: {}", path, e))?;
+                for emul in self.cores.iter_mut().skip(1) {
+                    emul.load_rom(&data).expect("Unable to load ROM");
+                }
+                self.romPath = path;
+                self.loadMemory.clear();
+                self.cores[0].clear_pc_watches();
+                self.hooks = Hooks::default();
+                self.restore();
+                self.hooks.reset(&self.cores[0]);
+                return Ok(self.load_info().join("\n"));
+            }
+        }
+        self.restore();
+        self.load_memory()?;
+        self.hooks.reset(&self.cores[0]);
+        Ok(format!("Started {}", self.romPath))
+    }
+
+    // tick_frame runs a frame on every core with the same keys, then saves whatever the first
+    // core changed of the saved data. What the hooks see is printed, one line per event, for
+    // scripts to pick up. The keys of --inject-input are held on top of those given, and while
+    // a demo or a replay plays the keys are those of the demo or replay instead.
+    pub fn tick_frame(&mut self, keys: KeyState) -> Vec<Result<FrameOutput, Error>> {
+        let keys = match self.injection.as_mut() {
+            Some(injection) => KeyState(keys.0 | injection.keys(self.frame + 1).0),
+            None => keys,
+        };
+        let keys = self.attract_keys(keys);
+        let keys = match self.player.as_mut() {
+            Some(player) => player.keys(self.frame + 1),
+            None => keys,
+        };
+        // a demo doesn't get reported, and the ROM it interrupted goes on from another state.
+        if self.in_demo() {
+            self.rewind = Rewind::default();
+        } else if !self.reported {
+            self.rewind.frame(&self.cores[0], self.frame, keys);
+        }
+        let outputs = self.cores.iter_mut().map(|emul| emul.tick_frame(keys)).collect();
+        self.frame += 1;
+
+        // one line per frame with the frame and the hash of each core's display, e.g.
+        // "120 5A1F3C0D9E2B7746", so two runs can be diffed frame by frame. It's written out line
+        // by line like a replay, complete however the emulator is closed.
+        if let Some((path, file)) = self.frameHashes.as_mut() {
+            let hashes: Vec<String> = self.cores.iter().map(|emul| format!("{:016X}", displayHash(emul))).collect();
+            if let Err(e) = writeln!(file, "{} {}", self.frame, hashes.join(" ")) {
+                println!("Stopped hashing frames at frame {}: unable to write {}: {}", self.frame, path, e);
+                self.frameHashes = None;
+            }
+        }
+        if let Some(recorder) = self.recorder.as_mut() {
+            if let Err(e) = recorder.record(self.frame, keys, &self.cores[0]) {
+                println!("Stopped recording at frame {}: {}", self.frame, e);
+                self.recorder = None;
+            }
+        }
+        if let Some(player) = self.player.as_mut().filter(|_| self.divergence.is_none()) {
+            if let Err(divergence) = player.check(self.frame, &self.cores[0]) {
+                println!("Frame {}: {}", self.frame, divergence.describe());
+                self.divergence = Some(divergence);
+                self.breakRequested = true;
+            }
+        }
+        if let Some(player) = self.player.take_if(|p| p.finished(self.frame)) {
+            let checks = match player.checked {
+                _ if self.divergence.is_some() => String::new(),
+                0 => ", it has no checksums".to_string(),
+                n => format!(", all {} checksums matched", n),
+            };
+            println!("The replay ended at frame {}{}, the keys are yours", self.frame, checks);
+            self.breakRequested = true;
+        }
+
+        for smc in self.cores[0].take_self_modifications() {
+            println!("Frame {}: running code at {:#05X} that the ROM wrote itself at {:#05X}", self.frame, smc.pc, smc.writer);
+            self.breakRequested |= self.breakOnSmc;
+        }
+        for unknown in self.cores[0].take_new_unknown_opcodes() {
+            if matches!(self.unknownOpcodes, UnknownOpcodePolicy::WarnOnce | UnknownOpcodePolicy::Pause) {
+                println!("Frame {}: skipped unknown opcode {:04X} at {:#05X}{}", self.frame, unknown.opcode, unknown.pc, hint(&unknown));
+            }
+            self.breakRequested |= self.unknownOpcodes == UnknownOpcodePolicy::Pause;
+        }
+        for event in self.hooks.check(&mut self.cores[0]) {
+            match event {
+                Event::ScoreChanged { from, to } => println!("Frame {}: score {} -> {}", self.frame, from, to),
+                Event::GameOver => println!("Frame {}: game over", self.frame),
+            }
+        }
+
+        // in compare mode only the first core gets to persist anything, and a demo nothing at all.
+        let demo = self.in_demo();
+        if self.cores[0].take_rpl_changed() && !demo {
+            if let Err(e) = saves::saveRplFlags(&self.romPath, &self.cores[0].rplFlags) {
+                println!("Unable to save RPL flags: {}", e);
+            }
+        }
+
+        // persist the memory range, at most once a second to go easy on the disk.
+        if self.frame.is_multiple_of(self.timerHz as u64) {
+            self.save_persisted();
+        }
+        outputs
+    }
+
+    // save_persisted saves the persisted memory range of the first core if it changed since it was
+    // last saved. Besides once a second, that's before anything replaces the memory and when the
+    // session ends, so that a high score written right before isn't lost.
+    pub fn save_persisted(&mut self) {
+        let Some(range) = self.persistRange.as_ref().filter(|_| !self.in_demo()) else { return };
+        let memory = &self.cores[0].memory.bytes()[range.clone()];
+        if memory != &self.persisted[..] {
+            self.persisted = memory.to_vec();
+            if let Err(e) = saves::saveMemory(&self.romPath, &self.persisted) {
+                println!("Unable to save memory: {}", e);
+            }
+        }
+    }
+
+    // in_demo returns whether the attract mode is playing a demo.
+    pub fn in_demo(&self) -> bool {
+        self.attract.as_ref().is_some_and(|a| a.demo.is_some())
+    }
+
+    // attract_keys runs the attract mode, if it's on, before a frame: it starts the demos once no
+    // key has been pressed for long enough, moves on to the next demo when one is over, and ends
+    // them at a key press. It returns the keys to run the frame with, those of the demo while one
+    // plays.
+    fn attract_keys(&mut self, keys: KeyState) -> KeyState {
+        let Some(attract) = self.attract.as_mut() else { return keys };
+        if keys != KeyState::default() {
+            attract.idle = 0;
+            self.end_demo();
+            return keys;
+        }
+        attract.idle += 1;
+        let start = match &attract.demo {
+            Some(demo) => demo.frame >= demo.frames,
+            None => attract.idle >= attract.idleFrames,
+        };
+        if start {
+            self.save_persisted();
+            self.next_demo();
+        }
+        match self.attract.as_mut() {
+            Some(attract) if attract.demo.is_some() => attract.keys(),
+            _ => keys,
+        }
+    }
+
+    // next_demo loads the next ROM of the playlist that loads, with the cores and ROM running now
+    // kept to return to. The demo plays without saved data and doesn't save any.
+    fn next_demo(&mut self) {
+        let Some(attract) = self.attract.as_mut() else { return };
+        let home = match attract.demo.take() {
+            Some(demo) => demo.home,
+            None => (self.cores.clone(), self.romPath.clone(), self.frame, self.hooks.clone()),
+        };
+        for _ in 0..attract.playlist_len() {
+            let Some(path) = attract.next_rom() else { break };
+            let data = match bundle::readRom(&path.to_string_lossy()) {
+                Ok(data) => data,
+                Err(e) => {
+                    println!("{}", e);
+                    continue;
+                }
+            };
+            let mut cores = home.0.clone();
+            if let Err(e) = cores.iter_mut().try_for_each(|emul| emul.load_rom(&data)) {
+                println!("Unable to load {}: {}", path.display(), e);
+                continue;
+            }
+            let demo = Attract::demo(&path, &data, home, self.timerHz);
+            for emul in cores.iter_mut() {
+                emul.rplFlags = [0; 8];
+                if let Some(player) = &demo.player {
+                    player.replay.header.apply(emul);
+                }
+            }
+            println!("Attract mode: {} with {}", path.display(), if demo.player.is_some() { "its replay" } else { "random keys" });
+            (self.cores, self.romPath, self.hooks) = (cores, path.to_string_lossy().to_string(), Hooks::default());
+            attract.demo = Some(demo);
+            return;
+        }
+        // none of them loads, so it's back to where the demos started.
+        (self.cores, self.romPath, self.frame, self.hooks) = home;
+        attract.idle = 0;
+    }
+
+    // end_demo goes back to the ROM the demos interrupted, as it was, if a demo is playing.
+    fn end_demo(&mut self) {
+        if let Some(demo) = self.attract.as_mut().and_then(|a| a.demo.take()) {
+            (self.cores, self.romPath, self.frame, self.hooks) = demo.home;
+            println!("Attract mode: back to {}", self.rom_name());
+        }
+    }
+
+    // playing returns whether a replay is being played.
+    pub fn playing(&self) -> bool {
+        self.player.is_some()
+    }
+
+    // take_break returns whether something happened during the last frames that the frontend
+    // should pause for, like self-modifying code with --break-on-smc.
+    pub fn take_break(&mut self) -> bool {
+        std::mem::take(&mut self.breakRequested)
+    }
+
+    // load_memory loads the files of --load-memory into every core, as on a fresh start. Another
+    // ROM loaded later on goes without them.
+    fn load_memory(&mut self) -> Result<(), String> {
+        for (path, address) in &self.loadMemory {
+            for emul in self.cores.iter_mut() {
+                memfile::load(emul.memory.bytes_mut(), path, *address)?;
+            }
+        }
+        Ok(())
+    }
+
+    // dump_memory writes the ranges of --dump-memory of the first core, for when the session ends.
+    pub fn dump_memory(&self) {
+        for (path, range) in &self.dumpMemory {
+            match memfile::dump(self.cores[0].memory.bytes(), path, range.clone()) {
+                Ok(()) => println!("Wrote {:#05X}..{:#05X} to {}", range.start, range.end, path),
+                Err(e) => println!("{}", e),
+            }
+        }
+    }
+
+    // unknown_opcodes_summary lists the unknown opcodes the first core ran, with where each ran
+    // first and how often, for when the session ends. It's empty if there were none.
+    pub fn unknown_opcodes_summary(&self) -> Vec<String> {
+        let unknown = self.cores[0].unknown_opcodes();
+        if unknown.is_empty() {
+            return Vec::new();
+        }
+        let mut lines = vec![format!("Unknown opcodes {} ran:", self.rom_name())];
+        for u in unknown {
+            let times = if u.count == 1 { "once".to_string() } else { format!("{} times", u.count) };
+            lines.push(format!("  {:04X} at {:#05X}, {}{}", u.opcode, u.pc, times, hint(u)));
+        }
+        lines
+    }
+
+    // dump_history prints the instructions the first core ran last, for when it crashed. The
+    // other cores don't keep a history.
+    pub fn dump_history(&self) {
+        dumpHistory(&self.cores[0]);
+    }
+
+    // report writes a bug report of the first core stopping on an error, or of a crash, see
+    // report. There's one a session at most, so a ROM that keeps failing in kiosk mode doesn't fill
+    // the disk, and none while a replay or a demo plays, which have theirs already.
+    pub fn report(&mut self, error: &str) {
+        if self.reported || self.player.is_some() || self.in_demo() {
+            return;
+        }
+        self.reported = true;
+        match report::write(&self.romPath, &self.cores[0], self.frame, error, &self.rewind) {
+            Ok(path) => println!("Wrote a bug report to {}, \"rust-chip8 repro {}\" runs it again", path.display(), path.display()),
+            Err(e) => println!("Unable to write a bug report: {}", e),
+        }
+    }
+
+    // restore loads the RPL flags and persisted memory saved for the ROM into freshly loaded cores.
+    fn restore(&mut self) {
+        for emul in self.cores.iter_mut() {
+            emul.rplFlags = saves::loadRplFlags(&self.romPath);
+            if let Some(range) = &self.persistRange {
+                if let Some(saved) = saves::loadMemory(&self.romPath, range.len()) {
+                    emul.memory.bytes_mut()[range.clone()].copy_from_slice(&saved);
+                }
+            }
+        }
+        self.persisted = self.persistRange.clone().map(|r| self.cores[0].memory.bytes()[r].to_vec()).unwrap_or_default();
+    }
+}
+
+// quirkToggled says that a quirk was switched as the ROM runs, with the --quirks that runs it that
+// way from the start, and warns that what the ROM did so far ran with the old setting, if it ran.
+pub fn quirkToggled(name: &str, on: bool, frame: u64, ran: bool, quirks: &Quirks) -> String {
+    let state = |on: bool| if on { "on" } else { "off" };
+    let mut message = format!("Quirk {} is {} from frame {} on, that's --quirks {}", name, state(on), frame, quirks.spec());
+    if ran {
+        message.push_str(&format!("\nThe ROM ran with it {} until now, what it computed and drew since may not be what it would be with it {}, reset to start over", state(!on), state(on)));
+    }
+    message
+}
+
+// displayHash hashes what a core shows: the display and whether it's in hires mode.
+fn displayHash(emul: &Emulator) -> u64 {
+    let mut display = emul.framebuffer().to_vec();
+    display.push(emul.hires as u8);
+    fnv1a(&display)
+}
+
+// hint tells what an unknown opcode may be about: an SCHIP instruction, as running a SCHIP ROM as
+// chip8 is the usual reason for them, or a machine code call of the VIP.
+fn hint(unknown: &UnknownOpcode) -> &'static str {
+    if unknown.schip {
+        " (an SCHIP instruction, try --variant schip)"
+    } else if unknown.opcode & 0xF000 == 0 {
+        " (a machine code call, see --machine-code)"
+    } else {
+        ""
+    }
+}
+
+// dumpHistory prints the instructions an emulator ran last, if it keeps a history.
+pub fn dumpHistory(emul: &Emulator) {
+    if let Some(history) = emul.history().filter(|h| !h.is_empty()) {
+        println!("Last {} instructions:", history.len());
+        for entry in history.iter() {
+            println!("  {}", entry.describe(emul.variant));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_chip8::memory::MEMORY_SIZE;
+
+    #[test]
+    fn readsSlotsBack() {
+        let mut cores = [Emulator::new(), Emulator::new()];
+        for (emul, quirks) in cores.iter_mut().zip([Quirks::default(), Quirks::vip()]) {
+            emul.load_rom(&[0xA0, 0x50, 0xD0, 0x05, 0x70, 0x01, 0x12, 0x04]).unwrap();
+            emul.quirks = quirks;
+            emul.tick_frame(KeyState::default()).unwrap();
+        }
+        let slot = StateSlot { romName: "zero".to_string(), frame: 1, at: UNIX_EPOCH + Duration::from_secs(1_760_000_000), thumbnail: overlay::shrink(cores[0].framebuffer()) };
+        let states = cores.iter().map(|emul| emul.save_state()).collect();
+        let saved = SavedState { states, romPath: "roms/a zero.ch8".to_string(), frame: 1, slot };
+
+        let text = saved.to_text();
+        assert!(text.starts_with("slot 1 1760000000 roms/a zero.ch8\nrust-chip8 state 1\n"));
+        let read = SavedState::parse(&text, MEMORY_SIZE).unwrap();
+        assert_eq!(read.states, saved.states);
+        assert_eq!((read.romPath.as_str(), read.frame, read.slot.at), ("roms/a zero.ch8", 1, saved.slot.at));
+        assert_eq!((read.slot.romName.as_str(), &read.slot.thumbnail), ("a zero", &saved.slot.thumbnail));
+        assert!(SavedState::parse("slot 1 1760000000 roms/zero.ch8\n", MEMORY_SIZE).is_err());
+    }
+}
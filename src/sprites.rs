@@ -0,0 +1,106 @@
+use alloc::vec::Vec;
+
+use crate::emulator::{decode, Region, DISPLAY_WIDTH};
+use crate::variant::Variant;
+
+// LOOKAHEAD is how many instructions after an ANNN find looks for the DXYN drawing from it.
+const LOOKAHEAD: usize = 8;
+
+// Sprite is sprite data in memory: height rows of one byte, or two bytes for the 16x16 sprites of
+// SCHIP.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Sprite {
+    pub address: u16,
+    pub height: usize,
+    pub wide: bool,
+}
+
+impl Sprite {
+    pub fn width(&self) -> usize {
+        if self.wide { 16 } else { 8 }
+    }
+
+    // bytes returns the data of the sprite, wrapping around at the end of memory.
+    pub fn bytes(&self, memory: &[u8]) -> Vec<u8> {
+        (0..self.height * self.width() / 8).map(|i| memory[(self.address as usize + i) % memory.len()]).collect()
+    }
+
+    // pixels returns the sprite as rows of pixels, true = lit, the way DXYN draws it.
+    pub fn pixels(&self, memory: &[u8]) -> Vec<Vec<bool>> {
+        let bytes = self.bytes(memory);
+        bytes.chunks(self.width() / 8)
+            .map(|row| row.iter().flat_map(|b| (0..8).rev().map(move |bit| b >> bit & 1 == 1)).collect())
+            .collect()
+    }
+}
+
+// find scans memory from one address to another for the sprites code draws: an ANNN followed
+// within a few instructions by a DXYN, before anything else changes I or control goes elsewhere,
+// is taken as a sprite of N rows at NNN. That misses sprites picked from a table with FX1E, and
+// can be fooled by data that happens to look like such code, but it's what's plausible without
+// running the ROM. Sprites drawn with different heights are kept once, at the largest.
+pub fn find(memory: &[u8], from: u16, to: u16, variant: Variant) -> Vec<Sprite> {
+    let word = |address: usize| (memory[address % memory.len()] as u16) << 8 | memory[(address + 1) % memory.len()] as u16;
+    let mut sprites: Vec<Sprite> = Vec::new();
+    for at in (from as usize..to as usize).step_by(2) {
+        let load = decode(word(at));
+        if load.instr != 0xA {
+            continue;
+        }
+        for next in (1..=LOOKAHEAD).map(|i| word(at + 2 * i)) {
+            let op = decode(next);
+            match (op.instr, op.NN) {
+                (0xD, _) => {
+                    let wide = op.N == 0 && variant == Variant::Schip;
+                    let height = if op.N == 0 { if wide { 16 } else { 0 } } else { op.N as usize };
+                    if height > 0 {
+                        let sprite = Sprite { address: load.NNN, height, wide };
+                        match sprites.iter_mut().find(|s| s.address == sprite.address) {
+                            Some(s) if (s.height * s.width()) < (height * sprite.width()) => *s = sprite,
+                            Some(_) => {}
+                            None => sprites.push(sprite),
+                        }
+                    }
+                    break;
+                }
+                // I changes, or the code goes elsewhere.
+                (0xA | 0x1 | 0x2 | 0xB, _) | (0xF, 0x1E | 0x29 | 0x30) => break,
+                (0x0, 0xEE) => break,
+                _ => {}
+            }
+        }
+    }
+    sprites.sort_by_key(|s| s.address);
+    sprites
+}
+
+// TILE_ROWS is the height of the tiles of a display, the most rows DXYN draws at once.
+pub const TILE_ROWS: usize = 15;
+
+// Tile is part of the display as a sprite that draws it at x,y, 8 pixels wide and a byte per row.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Tile {
+    pub x: usize,
+    pub y: usize,
+    pub rows: Vec<u8>,
+}
+
+// tiles cuts a region of the display into 8 x TILE_ROWS tiles, fewer at its right and bottom
+// edges, leaving out those with nothing lit. The region is in pixels of the mode, so a lores
+// pixel is one pixel even though it covers 2x2 of the display.
+pub fn tiles(display: &[u8], hires: bool, region: Region) -> Vec<Tile> {
+    let scale = if hires { 1 } else { 2 };
+    let lit = |x: usize, y: usize| display.get(y * scale * DISPLAY_WIDTH + x * scale).is_some_and(|p| *p != 0);
+    let mut tiles = Vec::new();
+    for y in (region.y..region.y + region.height).step_by(TILE_ROWS) {
+        for x in (region.x..region.x + region.width).step_by(8) {
+            let rows: Vec<u8> = (y..(y + TILE_ROWS).min(region.y + region.height))
+                .map(|y| (0..8).filter(|i| x + i < region.x + region.width && lit(x + i, y)).fold(0, |row, i| row | 0x80 >> i))
+                .collect();
+            if rows.iter().any(|row| *row != 0) {
+                tiles.push(Tile { x, y, rows });
+            }
+        }
+    }
+    tiles
+}
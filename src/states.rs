@@ -0,0 +1,76 @@
+use std::time::SystemTime;
+
+use crate::overlay::{self, Overlay, Tile};
+use crate::session::{StateSlot, STATE_SLOTS};
+
+// the lines above the grid: the keys and two of the selected slot.
+const LINES: usize = 3;
+
+// StateBrowser is the browser of the save state slots, on F7 or from the command palette: a grid
+// of the slots with a thumbnail of the display each state was saved with, and the ROM, frame and
+// age of the selected one. S saves to the selected slot, Enter loads it, either closing the
+// browser, and Esc closes it.
+pub struct StateBrowser {
+    selected: usize,
+    columns: usize,
+    rows: usize,
+}
+
+impl StateBrowser {
+    pub fn new(width: usize, height: usize) -> StateBrowser {
+        let (columns, rows) = overlay::grid(width, height, LINES);
+        StateBrowser { selected: 0, columns: columns.max(1), rows: rows.max(1) }
+    }
+
+    // left, right, up and down move the selection through the grid, stopping at its ends.
+    pub fn left(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    pub fn right(&mut self) {
+        self.selected = (self.selected + 1).min(STATE_SLOTS - 1);
+    }
+
+    pub fn up(&mut self) {
+        self.selected = self.selected.checked_sub(self.columns).unwrap_or(self.selected);
+    }
+
+    pub fn down(&mut self) {
+        if self.selected + self.columns < STATE_SLOTS {
+            self.selected += self.columns;
+        }
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    // overlay shows the slots as the session last reported them, see Session::slots, with the
+    // page of the grid the selected slot is on.
+    pub fn overlay(&self, slots: &[Option<StateSlot>]) -> Overlay {
+        let (selected, saved) = match slots.get(self.selected).cloned().flatten() {
+            Some(slot) => (format!("SLOT {} {} FRAME {}", self.selected + 1, slot.romName, slot.frame), format!("SAVED {}", age(slot.at))),
+            None => (format!("SLOT {} EMPTY", self.selected + 1), String::new()),
+        };
+        let first = (self.selected / self.columns).saturating_sub(self.rows - 1) * self.columns;
+        let tiles = (first..STATE_SLOTS.min(first + self.columns * self.rows)).map(|n| {
+            let slot = slots.get(n).cloned().flatten();
+            Tile {
+                thumbnail: slot.as_ref().map(|s| s.thumbnail.clone()).unwrap_or_default(),
+                name: format!("{} {}", n + 1, slot.map_or("EMPTY".to_string(), |s| s.romName)),
+                selected: n == self.selected,
+            }
+        }).collect();
+        Overlay { lines: vec!["STATES S:SAVE ENTER:LOAD".to_string(), selected, saved], debug: false, heatmap: false, card: false, tiles }
+    }
+}
+
+// age returns how long ago a state was saved.
+fn age(at: SystemTime) -> String {
+    let seconds = at.elapsed().map(|d| d.as_secs()).unwrap_or(0);
+    match seconds {
+        0..=59 => "JUST NOW".to_string(),
+        60..=3599 => format!("{}M AGO", seconds / 60),
+        _ => format!("{}H{:02}M AGO", seconds / 3600, seconds / 60 % 60),
+    }
+}
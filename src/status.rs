@@ -0,0 +1,71 @@
+use crate::i18n::Messages;
+
+// Status is what the interpreter thread reports to the event-loop whenever it changes, so the
+// window title can show the active settings.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Status {
+    pub romName: String,
+    pub variant: &'static str,
+    pub ips: u32,       // instructions per second
+    pub paused: bool,
+    pub turbo: bool,
+    pub slowMotion: Option<u32>, // slow-motion speed in percent
+    pub comparing: bool,
+    pub demo: bool, // the attract mode is playing a demo
+    pub score: Option<i64>, // as watched by the hooks of the ROM
+    pub gameOver: bool,
+}
+
+impl Status {
+    // title formats the status as a window title, e.g. "Rust-CHIP8 — pong.ch8 [CHIP-8, 960 ips] (PAUSED)"
+    // in English, or in the language of the messages.
+    pub fn title(&self, messages: &Messages) -> String {
+        let mut title = messages.format("title", &[&self.romName, &self.variant, &self.ips]);
+        if let Some(score) = self.score {
+            title.push_str(&format!(" {}", messages.format("title.score", &[&score])));
+        }
+        let mut flag = |key: &str, values: &[&dyn std::fmt::Display]| title.push_str(&format!(" ({})", messages.format(key, values)));
+        if self.gameOver {
+            flag("title.game_over", &[]);
+        }
+        if self.comparing {
+            flag("title.comparing", &[]);
+        }
+        if self.demo {
+            flag("title.demo", &[]);
+        }
+        if self.turbo {
+            flag("title.turbo", &[]);
+        } else if let Some(percent) = self.slowMotion {
+            flag("title.slow_motion", &[&percent]);
+        }
+        if self.paused {
+            flag("title.paused", &[]);
+        }
+        title
+    }
+
+    // announcements describes what changed since the previous status, a line each, for
+    // --announce to print for screen readers. Errors are printed anyway.
+    pub fn announcements(&self, previous: Option<&Status>, messages: &Messages) -> Vec<String> {
+        let mut lines = Vec::new();
+        let changed = |field: fn(&Status) -> bool| previous.is_none_or(|p| field(p) != field(self));
+        if previous.is_none_or(|p| p.romName != self.romName) {
+            lines.push(messages.format("announce.loaded", &[&self.romName]));
+        }
+        if changed(|s| s.paused) && (previous.is_some() || self.paused) {
+            lines.push(messages.get(if self.paused { "announce.paused" } else { "announce.running" }).to_string());
+        }
+        if previous.is_some_and(|p| p.turbo != self.turbo || p.slowMotion != self.slowMotion) {
+            lines.push(match (self.turbo, self.slowMotion) {
+                (true, _) => messages.get("announce.turbo").to_string(),
+                (false, Some(percent)) => messages.format("announce.slow_motion", &[&percent]),
+                (false, None) => messages.get("announce.normal_speed").to_string(),
+            });
+        }
+        if self.gameOver && changed(|s| s.gameOver) {
+            lines.push(messages.get("announce.game_over").to_string());
+        }
+        lines
+    }
+}
@@ -0,0 +1,119 @@
+// Upscaler is how the window scales the frame up before pixels scales it to the window, by the
+// largest whole factor that fits: as it is, in sharp squares, or smoothed in one of a few ways for
+// those who'd rather not look at pixels the size of a thumbnail. It all runs on the CPU, on the
+// frame of the tab as it's presented, which is small enough even at four times the size.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Upscaler {
+    Nearest,
+    Scale2x, // the edge-directed Scale2x of AdvanceMAME, which rounds off diagonals
+    Scale4x, // Scale2x twice
+    Lcd,     // each pixel a cell of an LCD, with a darker gap between the cells
+}
+
+// UPSCALER_NAMES are the upscalers parse takes, in the order F8 goes through them.
+pub const UPSCALER_NAMES: [&str; 4] = ["none", "scale2x", "scale4x", "lcd"];
+
+// LCD_FACTOR is how many pixels a side each pixel takes with the LCD upscaler, the last of them
+// the gap.
+const LCD_FACTOR: usize = 4;
+
+impl Upscaler {
+    pub fn parse(name: &str) -> Result<Upscaler, String> {
+        match name {
+            "none" => Ok(Upscaler::Nearest),
+            "scale2x" => Ok(Upscaler::Scale2x),
+            "scale4x" => Ok(Upscaler::Scale4x),
+            "lcd" => Ok(Upscaler::Lcd),
+            _ => Err(format!("invalid upscaler '{}', expected one of {}", name, UPSCALER_NAMES.join(", "))),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Upscaler::Nearest => "none",
+            Upscaler::Scale2x => "scale2x",
+            Upscaler::Scale4x => "scale4x",
+            Upscaler::Lcd => "lcd",
+        }
+    }
+
+    // next returns the upscaler after this one in UPSCALER_NAMES, for F8.
+    pub fn next(&self) -> Upscaler {
+        let index = UPSCALER_NAMES.iter().position(|n| *n == self.name()).unwrap_or(0);
+        Upscaler::parse(UPSCALER_NAMES[(index + 1) % UPSCALER_NAMES.len()]).unwrap_or(Upscaler::Nearest)
+    }
+
+    // factor returns how many times as wide and high the frame becomes.
+    pub fn factor(&self) -> usize {
+        match self {
+            Upscaler::Nearest => 1,
+            Upscaler::Scale2x => 2,
+            Upscaler::Scale4x => 4,
+            Upscaler::Lcd => LCD_FACTOR,
+        }
+    }
+
+    // apply scales a frame of width x height RGBA pixels up into one factor times the size.
+    pub fn apply(&self, frame: &[u8], width: usize, height: usize, scaled: &mut [u8]) {
+        match self {
+            Upscaler::Nearest => scaled.copy_from_slice(frame),
+            Upscaler::Scale2x => scale2x(frame, width, height, scaled),
+            Upscaler::Scale4x => {
+                let mut double = vec![0; frame.len() * 4];
+                scale2x(frame, width, height, &mut double);
+                scale2x(&double, width * 2, height * 2, scaled);
+            }
+            Upscaler::Lcd => lcd(frame, width, scaled),
+        }
+    }
+}
+
+// scale2x makes each pixel four, taking the color of the neighbors on either side of a corner
+// where those two are alike and not like the others, so that edges go diagonally rather than in
+// steps.
+fn scale2x(frame: &[u8], width: usize, height: usize, scaled: &mut [u8]) {
+    let pixel = |x: usize, y: usize| -> [u8; 4] {
+        let i = (y * width + x) * 4;
+        [frame[i], frame[i + 1], frame[i + 2], frame[i + 3]]
+    };
+    for y in 0..height {
+        for x in 0..width {
+            let p = pixel(x, y);
+            let up = pixel(x, y.saturating_sub(1));
+            let down = pixel(x, (y + 1).min(height - 1));
+            let left = pixel(x.saturating_sub(1), y);
+            let right = pixel((x + 1).min(width - 1), y);
+            let corners = if up != down && left != right {
+                [
+                    if left == up { left } else { p },
+                    if up == right { right } else { p },
+                    if down == left { left } else { p },
+                    if right == down { right } else { p },
+                ]
+            } else {
+                [p; 4]
+            };
+            for (n, color) in corners.iter().enumerate() {
+                let i = ((y * 2 + n / 2) * width * 2 + x * 2 + n % 2) * 4;
+                scaled[i..i + 4].copy_from_slice(color);
+            }
+        }
+    }
+}
+
+// lcd makes each pixel a cell of LCD_FACTOR x LCD_FACTOR pixels, its last row and column a darker
+// shade of it, like the grid between the cells of a handheld's LCD.
+fn lcd(frame: &[u8], width: usize, scaled: &mut [u8]) {
+    let scaledWidth = width * LCD_FACTOR;
+    for (n, color) in frame.chunks(4).enumerate() {
+        let (x, y) = (n % width * LCD_FACTOR, n / width * LCD_FACTOR);
+        let gap = [color[0] / 8 * 5, color[1] / 8 * 5, color[2] / 8 * 5, color[3]];
+        for dy in 0..LCD_FACTOR {
+            for dx in 0..LCD_FACTOR {
+                let i = ((y + dy) * scaledWidth + x + dx) * 4;
+                let edge = dx == LCD_FACTOR - 1 || dy == LCD_FACTOR - 1;
+                scaled[i..i + 4].copy_from_slice(if edge { &gap } else { color });
+            }
+        }
+    }
+}
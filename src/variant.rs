@@ -0,0 +1,56 @@
+use alloc::format;
+use alloc::string::String;
+
+use crate::emulator::INSTRUCTIONS_PER_FRAME;
+use crate::quirks::Quirks;
+
+// Variant is the CHIP8 dialect a ROM was written for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Variant {
+    Chip8,
+    Schip,
+}
+
+impl Variant {
+    pub fn parse(name: &str) -> Result<Variant, String> {
+        match name.to_lowercase().as_str() {
+            "chip8" | "chip-8" => Ok(Variant::Chip8),
+            "schip" | "superchip" => Ok(Variant::Schip),
+            _ => Err(format!("unknown variant '{}', expected chip8 or schip", name)),
+        }
+    }
+
+    // spec returns the variant as parse takes it, for files and the command line.
+    pub fn spec(&self) -> &'static str {
+        match self {
+            Variant::Chip8 => "chip8",
+            Variant::Schip => "schip",
+        }
+    }
+
+    // name returns the name of the variant as it's usually written.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Variant::Chip8 => "CHIP-8",
+            Variant::Schip => "SCHIP",
+        }
+    }
+
+    // quirks returns the quirks a ROM of this variant most likely expects.
+    pub fn quirks(&self) -> Quirks {
+        match self {
+            Variant::Chip8 => Quirks::default(),
+            Variant::Schip => Quirks::schip(),
+        }
+    }
+
+    // instructions_per_frame returns the speed a ROM of this variant most likely expects, in
+    // instructions per 60hz frame. SCHIP ran on the HP48, which was a lot faster than the COSMAC
+    // VIP, and its games were written to match: about 30000 instructions a second.
+    pub fn instructions_per_frame(&self) -> u32 {
+        match self {
+            Variant::Chip8 => INSTRUCTIONS_PER_FRAME,
+            Variant::Schip => 500,
+        }
+    }
+}
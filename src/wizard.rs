@@ -0,0 +1,136 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use rust_chip8::render::PALETTE_NAMES;
+
+use crate::actions::ROM_EXTENSIONS;
+use crate::commands::batch::romFiles;
+use crate::config;
+use crate::paths;
+use crate::input::KEYMAP_NAMES;
+use crate::overlay::Overlay;
+
+// MAX_DIRS is how many ROM directories the setup offers, as many as fit in the overlay.
+const MAX_DIRS: usize = 6;
+
+// Step is a page of the setup.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Step {
+    RomDir,
+    Keymap,
+    Palette,
+    Sound,
+}
+
+const STEPS: [Step; 4] = [Step::RomDir, Step::Keymap, Step::Palette, Step::Sound];
+
+// Wizard is the setup the window walks through on the first start, when there's no config file
+// yet, or with --setup: the ROM directory, the keymap, the palette and the sound scope, which it
+// then writes to the config file. It's drawn as an overlay and takes the keyboard while it's open:
+// Up and Down pick, Enter goes on and Esc skips it all.
+pub struct Wizard {
+    step: usize,
+    selected: usize,
+    romDirs: Vec<(String, usize)>, // directories with ROMs and how many
+    answers: Vec<(&'static str, String)>,
+}
+
+impl Wizard {
+    pub fn new(romPath: &str) -> Wizard {
+        Wizard { step: 0, selected: 0, romDirs: romDirs(romPath), answers: Vec::new() }
+    }
+
+    // choices returns what can be picked on the current page, as shown.
+    fn choices(&self) -> Vec<String> {
+        match STEPS[self.step] {
+            Step::RomDir => self.romDirs.iter().map(|(dir, n)| format!("{} ({})", dir, n)).collect(),
+            Step::Keymap => KEYMAP_NAMES.iter().map(|k| k.to_string()).collect(),
+            Step::Palette => PALETTE_NAMES.iter().map(|p| p.to_string()).collect(),
+            Step::Sound => vec!["scope on".to_string(), "scope off".to_string()],
+        }
+    }
+
+    pub fn up(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    pub fn down(&mut self) {
+        self.selected = (self.selected + 1).min(self.choices().len().saturating_sub(1));
+    }
+
+    // enter takes the choice of the current page and goes on to the next, returning whether that
+    // was the last one.
+    pub fn enter(&mut self) -> bool {
+        let answer = match STEPS[self.step] {
+            Step::RomDir => self.romDirs.get(self.selected).map(|(dir, _)| ("rom_dir", dir.clone())),
+            Step::Keymap => Some(("keymap", KEYMAP_NAMES[self.selected].to_string())),
+            Step::Palette => Some(("palette", PALETTE_NAMES[self.selected].to_string())),
+            Step::Sound => Some(("sound_scope", (self.selected == 0).to_string())),
+        };
+        self.answers.extend(answer);
+        self.step += 1;
+        self.selected = 0;
+        self.step == STEPS.len()
+    }
+
+    // overlay returns the current page: its title, the choices with the selected one marked and
+    // the keys.
+    pub fn overlay(&self) -> Overlay {
+        let title = match STEPS[self.step] {
+            Step::RomDir => "ROM DIRECTORY",
+            Step::Keymap => "KEYMAP",
+            Step::Palette => "PALETTE",
+            Step::Sound => "SOUND SCOPE",
+        };
+        let mut lines = vec![format!("SETUP {}/{} {}", self.step + 1, STEPS.len(), title)];
+        match STEPS[self.step] {
+            Step::RomDir if self.romDirs.is_empty() => lines.push(" NONE FOUND, ENTER SKIPS".to_string()),
+            // the window has no audio out, the scope draws what the buzzer plays instead.
+            Step::Sound => lines.push(" NO AUDIO, DRAW THE BUZZER".to_string()),
+            _ => {}
+        }
+        for (i, choice) in self.choices().iter().enumerate() {
+            lines.push(format!("{}{}", if i == self.selected { '>' } else { ' ' }, choice));
+        }
+        lines.push("ENTER NEXT  ESC SKIPS".to_string());
+        Overlay { lines, debug: false, heatmap: false, card: false, tiles: Vec::new() }
+    }
+
+    // skip leaves the setup without any of the choices made, writing the config file all the same
+    // so the setup isn't shown again.
+    pub fn skip(&mut self) -> Result<String, String> {
+        self.answers.clear();
+        self.save()
+    }
+
+    // save writes the choices to the config file, to take effect the next time the emulator
+    // starts.
+    pub fn save(&self) -> Result<String, String> {
+        config::setKeys(paths::config(), "", &self.answers)?;
+        Ok(match self.answers.is_empty() {
+            true => format!("Skipped the setup and wrote an empty {}, run with --setup to go through it later", paths::config().display()),
+            false => format!("Saved the setup to {}, it takes effect the next time the emulator starts", paths::config().display()),
+        })
+    }
+}
+
+// romDirs returns the directories that have ROMs in them, with how many: ./roms, that of the ROM
+// and those next to ./roms.
+fn romDirs(romPath: &str) -> Vec<(String, usize)> {
+    let mut candidates: Vec<PathBuf> = vec![PathBuf::from("./roms")];
+    candidates.extend(Path::new(romPath).parent().filter(|d| !d.as_os_str().is_empty()).map(Path::to_path_buf));
+    if let Ok(entries) = fs::read_dir(".") {
+        let mut dirs: Vec<PathBuf> = entries.filter_map(|e| e.ok()).map(|e| e.path()).filter(|p| p.is_dir()).collect();
+        dirs.sort();
+        candidates.extend(dirs);
+    }
+    let mut found: Vec<(String, usize)> = Vec::new();
+    for dir in candidates {
+        let name = dir.to_string_lossy().to_string();
+        let roms = romFiles(&dir).map(|files| files.iter().filter(|f| f.extension().is_some_and(|e| ROM_EXTENSIONS.iter().any(|r| e.eq_ignore_ascii_case(r)))).count()).unwrap_or(0);
+        if dir.is_dir() && roms > 0 && !found.iter().any(|(d, _)| Path::new(d) == dir) && found.len() < MAX_DIRS {
+            found.push((name, roms));
+        }
+    }
+    found
+}
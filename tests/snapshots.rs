@@ -0,0 +1,41 @@
+#![allow(non_snake_case)]
+
+// Runs "rust-chip8 snapshot" on the test ROMs in tests/roms, so that "cargo test" fails when a
+// change to the core alters what they draw. The golden snapshots are in tests/snapshots, written
+// with --update-snapshots and checked by hand. The ROMs:
+//
+//   font.ch8  draws the 16 font characters in two rows, with FX29 and DXY5
+//
+//   bcd.ch8   stores the BCD of 231 with FX33, loads it back with FX65 and draws the digits
+//             0x200  60E7  V0 = 231
+//             0x202  A300  I = 0x300
+//             0x204  F033  BCD of V0
+//             0x206  F265  V0-V2 = the digits
+//             0x208  6300  V3 = 0, x
+//             0x20A  6400  V4 = 0, y
+//             0x20C  F029  ... then each digit is drawn 5 pixels to the right of the last
+//
+//   wrap.ch8  draws an 8 at 62,30, so that it wraps around both edges, and the VF that 8XY5 of
+//             two equal registers leaves, 1, at 10,10
+
+use std::path::Path;
+use std::process::Command;
+
+#[test]
+fn testRomsMatchTheirSnapshots() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    // a data directory of its own, so that the config file of whoever runs the tests doesn't
+    // change the quirks.
+    let dataDir = std::env::temp_dir().join("rust-chip8-snapshot-test");
+    let output = Command::new(env!("CARGO_BIN_EXE_rust-chip8"))
+        .arg("snapshot")
+        .arg("--data-dir").arg(&dataDir)
+        .arg("--snapshots").arg(root.join("tests/snapshots"))
+        .arg("--frames").arg("60")
+        .arg(root.join("tests/roms"))
+        .output()
+        .expect("Unable to run rust-chip8");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}{}", stdout, String::from_utf8_lossy(&output.stderr));
+    assert!(stdout.contains("3 ROMs, 0 failed"), "{}", stdout);
+}